@@ -19,10 +19,25 @@
 //! writeln!(&mut file, "{}", render_rules_to_pest(rules).pretty(80))?;
 //! ```
 //!
+//! Instead of changing the rules in code, the changes can be listed in an overrides file (see
+//! [`overrides`](overrides/index.html) for the format):
+//! ```
+//! let overrides = abnf_to_pest::parse_overrides(&read_to_string(overrides_path)?)?;
+//! let hand_written_rules = overrides.apply(&mut rules)?;
+//!
+//! let mut file = File::create(pest_path)?;
+//! writeln!(&mut file, "{}", hand_written_rules)?;
+//! writeln!(&mut file, "{}", render_rules_to_pest(rules).pretty(80))?;
+//! ```
+//!
 //! [pest]: https://pest.rs
 
-use abnf::types::{Node, Repeat, Rule, TerminalValues};
-use indexmap::map::IndexMap;
+pub mod overrides;
+mod parse;
+pub use overrides::{parse_overrides, Overrides};
+pub use parse::parse_abnf;
+
+use abnf::types::{Node, Repeat, TerminalValues};
 use itertools::Itertools;
 use pretty::BoxDoc;
 
@@ -42,6 +57,10 @@ impl Pretty for Node {
                 nodes.iter().map(|x| x.pretty()),
                 BoxDoc::space().append(BoxDoc::text("~ ")),
             ),
+            // Pest can't repeat zero times, but that just matches the empty string.
+            Repetition(rep) if rep.repeat().max() == Some(0) => {
+                BoxDoc::text("\"\"")
+            }
             Repetition(rep) => rep
                 .node()
                 .pretty()
@@ -57,10 +76,10 @@ impl Pretty for Node {
                 .append(BoxDoc::text(")?")),
             String(s) => BoxDoc::text(format!(
                 "^\"{}\"",
-                s.replace("\"", "\\\"").replace("\\", "\\\\")
+                s.replace("\\", "\\\\").replace("\"", "\\\"")
             )),
             TerminalValues(r) => r.pretty(),
-            Prose(_) => unimplemented!(),
+            Prose(s) => BoxDoc::text(escape_rulename(&prose_rulename(s))),
         }
     }
 }
@@ -83,7 +102,17 @@ impl Pretty for TerminalValues {
         use TerminalValues::*;
         BoxDoc::text(match self {
             Range(x, y) => {
-                format!("'{}'..'{}'", format_char(*x), format_char(*y))
+                // Pest ranges are between chars, which can't be surrogates or above U+10FFFF.
+                let x = if (0xD800..=0xDFFF).contains(x) {
+                    0xE000
+                } else {
+                    *x
+                };
+                let y = match *y {
+                    0xD800..=0xDFFF => 0xD7FF,
+                    y => y.min(0x10FFFF),
+                };
+                format!("'{}'..'{}'", format_char(x), format_char(y))
             }
             Concatenation(v) => {
                 format!("\"{}\"", v.iter().map(|x| format_char(*x)).join(""))
@@ -92,6 +121,20 @@ impl Pretty for TerminalValues {
     }
 }
 
+/// The names pest doesn't accept for a rule, or gives a special meaning to.
+#[rustfmt::skip]
+const RESERVED_RULENAMES: &[&str] = &[
+    // Rust keywords
+    "abstract", "alignof", "as", "become", "box", "break", "const", "continue", "crate", "do",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "offsetof", "override", "priv", "proc", "pure", "pub",
+    "ref", "return", "Self", "self", "sizeof", "static", "struct", "super", "trait", "true",
+    "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+    // Pest keywords and implicit rules
+    "ANY", "DROP", "EOI", "PEEK", "PEEK_ALL", "POP", "POP_ALL", "PUSH", "SOI", "WHITESPACE",
+    "COMMENT",
+];
+
 /// Escape the rule name to be a valid Rust identifier.
 ///
 /// Replaces e.g. `if` with `if_`, and `rule-name` with `rule_name`.
@@ -99,12 +142,7 @@ impl Pretty for TerminalValues {
 /// Also changes `Some` and `None` to `Some_` and `None_`, because it was such a pain to work around.
 pub fn escape_rulename(x: &str) -> String {
     let x = x.replace("-", "_");
-    if x == "if"
-        || x == "else"
-        || x == "as"
-        || x == "let"
-        || x == "in"
-        || x == "fn"
+    if RESERVED_RULENAMES.contains(&x.as_str())
         // Not required but such a pain
         || x == "Some"
         || x == "None"
@@ -117,6 +155,20 @@ pub fn escape_rulename(x: &str) -> String {
     }
 }
 
+/// The name of the rule a prose value refers to: `<some description>` refers to
+/// `some_description`.
+fn prose_rulename(prose: &str) -> String {
+    let name: String = prose
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .join("_");
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("prose_{}", name)
+    }
+}
+
 fn format_char(x: u32) -> String {
     if x <= u32::from(u8::max_value()) {
         let x: u8 = x as u8;
@@ -130,10 +182,34 @@ fn format_char(x: u32) -> String {
     format!("\\u{{{:02X}}}", x)
 }
 
+/// The pest modifier of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    /// `rule = { ... }`
+    Normal,
+    /// `rule = _{ ... }`
+    Silent,
+    /// `rule = @{ ... }`
+    Atomic,
+    /// `rule = ${ ... }`
+    CompoundAtomic,
+}
+
+impl Modifier {
+    fn to_pest(self) -> &'static str {
+        match self {
+            Modifier::Normal => "",
+            Modifier::Silent => "_",
+            Modifier::Atomic => "@",
+            Modifier::CompoundAtomic => "$",
+        }
+    }
+}
+
 /// Allow control over some of the pest properties of the outputted rule
 #[derive(Debug, Clone)]
 pub struct PestyRule {
-    pub silent: bool,
+    pub modifier: Modifier,
     pub node: Node,
 }
 
@@ -143,7 +219,7 @@ impl Pretty for (String, PestyRule) {
         BoxDoc::nil()
             .append(BoxDoc::text(name.clone()))
             .append(BoxDoc::text(" = "))
-            .append(BoxDoc::text(if rule.silent { "_" } else { "" }))
+            .append(BoxDoc::text(rule.modifier.to_pest()))
             .append(BoxDoc::text("{"))
             .append(BoxDoc::space().append(rule.node.pretty()).nest(2))
             .append(BoxDoc::space())
@@ -152,27 +228,6 @@ impl Pretty for (String, PestyRule) {
     }
 }
 
-/// Parse an abnf file. Returns a map of rules.
-pub fn parse_abnf(
-    data: &str,
-) -> Result<IndexMap<String, PestyRule>, std::io::Error> {
-    let make_err =
-        |e| std::io::Error::new(std::io::ErrorKind::Other, format!("{}", e));
-    let rules: Vec<Rule> = abnf::rulelist(data).map_err(make_err)?;
-    Ok(rules
-        .into_iter()
-        .map(|rule| {
-            (
-                escape_rulename(rule.name()),
-                PestyRule {
-                    silent: false,
-                    node: rule.node().clone(),
-                },
-            )
-        })
-        .collect())
-}
-
 pub fn render_rules_to_pest<I>(rules: I) -> BoxDoc<'static>
 where
     I: IntoIterator<Item = (String, PestyRule)>,
@@ -0,0 +1,291 @@
+//! Declarative changes to a grammar converted from ABNF.
+//!
+//! An overrides file lists changes to make to the converted rules, one directive per line. Lines
+//! starting with `//` are comments. Rule names are the escaped ones (see [`escape_rulename`]).
+//!
+//! ```text
+//! // Set the pest modifier of some rules.
+//! silent whsp whsp1
+//! atomic simple_label
+//! compound_atomic double_quote_literal
+//!
+//! // Remove some rules.
+//! remove braced_escape braced_codepoint
+//!
+//! // Replace a rule with a hand-written pest rule. The rule can span several lines.
+//! missing = { "missing" ~ !simple_label_next_char }
+//!
+//! // Add a rule that is not in the ABNF grammar.
+//! add bool_or = { "||" }
+//! ```
+//!
+//! Every rule a directive mentions must exist in the grammar, and a rule that is added must not,
+//! so that the overrides don't silently go stale when the grammar changes.
+//!
+//! [`escape_rulename`]: ../fn.escape_rulename.html
+
+use indexmap::map::IndexMap;
+use std::io::{Error, ErrorKind};
+
+use crate::{Modifier, PestyRule};
+
+#[derive(Debug, Clone)]
+enum Directive {
+    SetModifier(Modifier, Vec<String>),
+    Remove(Vec<String>),
+    Replace { name: String, definition: String },
+    Add { name: String, definition: String },
+}
+
+/// A list of changes to make to a grammar, parsed with [`parse_overrides`].
+///
+/// [`parse_overrides`]: fn.parse_overrides.html
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// The directives, with the line they start on.
+    directives: Vec<(usize, Directive)>,
+}
+
+fn error(line: usize, msg: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+fn is_rulename(x: &str) -> bool {
+    !x.is_empty()
+        && !x.starts_with(|c: char| c.is_ascii_digit())
+        && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Counts the braces that are opened and not closed in some pest syntax, ignoring the ones in
+/// string and character literals and in comments.
+fn open_braces(text: &str) -> isize {
+    let mut depth = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' | '\'' => {
+                while let Some(d) = chars.next() {
+                    match d {
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ if d == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.as_str().starts_with('/') => {
+                chars.find(|&d| d == '\n');
+            }
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Parse an overrides file.
+pub fn parse_overrides(data: &str) -> Result<Overrides, Error> {
+    let mut directives = Vec::new();
+    let mut lines = data.lines().enumerate().map(|(i, line)| (i + 1, line));
+    while let Some((line_nb, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap();
+        // A rule can be named like a directive.
+        let keyword = if line[keyword.len()..].trim_start().starts_with('=') {
+            ""
+        } else {
+            keyword
+        };
+        let modifier = match keyword {
+            "silent" => Some(Modifier::Silent),
+            "atomic" => Some(Modifier::Atomic),
+            "compound_atomic" => Some(Modifier::CompoundAtomic),
+            _ => None,
+        };
+        if modifier.is_some() || keyword == "remove" {
+            let names: Vec<String> = words.map(str::to_owned).collect();
+            if names.is_empty() {
+                return Err(error(
+                    line_nb,
+                    format!("`{}` needs rules", keyword),
+                ));
+            }
+            if let Some(name) = names.iter().find(|name| !is_rulename(name)) {
+                return Err(error(
+                    line_nb,
+                    format!("`{}` is not a rule name", name),
+                ));
+            }
+            directives.push((
+                line_nb,
+                match modifier {
+                    Some(modifier) => Directive::SetModifier(modifier, names),
+                    None => Directive::Remove(names),
+                },
+            ));
+            continue;
+        }
+
+        let (add, mut definition) = match keyword {
+            "add" => (true, line["add".len()..].trim_start().to_owned()),
+            _ => (false, line.to_owned()),
+        };
+        let name = match definition.find('=') {
+            Some(i) if is_rulename(definition[..i].trim()) => {
+                definition[..i].trim().to_owned()
+            }
+            _ => {
+                return Err(error(
+                    line_nb,
+                    "expected a directive or a rule definition",
+                ))
+            }
+        };
+        // The definition goes on until its braces are closed.
+        while !definition.contains('{') || open_braces(&definition) > 0 {
+            match lines.next() {
+                Some((_, line)) => {
+                    definition.push('\n');
+                    definition.push_str(line);
+                }
+                None => {
+                    return Err(error(
+                        line_nb,
+                        format!("unterminated definition of `{}`", name),
+                    ))
+                }
+            }
+        }
+        directives.push((
+            line_nb,
+            if add {
+                Directive::Add { name, definition }
+            } else {
+                Directive::Replace { name, definition }
+            },
+        ));
+    }
+    Ok(Overrides { directives })
+}
+
+impl Overrides {
+    /// Apply the overrides to the given rules. The replaced rules are removed from `rules`, and
+    /// the hand-written rules are returned as pest syntax, to be output along with `rules`.
+    pub fn apply(
+        &self,
+        rules: &mut IndexMap<String, PestyRule>,
+    ) -> Result<String, Error> {
+        let mut hand_written = Vec::new();
+        for (line_nb, directive) in &self.directives {
+            let missing = |name: &str| {
+                error(*line_nb, format!("rule `{}` does not exist", name))
+            };
+            match directive {
+                Directive::SetModifier(modifier, names) => {
+                    for name in names {
+                        let rule =
+                            rules.get_mut(name).ok_or_else(|| missing(name))?;
+                        rule.modifier = *modifier;
+                    }
+                }
+                Directive::Remove(names) => {
+                    for name in names {
+                        rules
+                            .shift_remove(name)
+                            .ok_or_else(|| missing(name))?;
+                    }
+                }
+                Directive::Replace { name, definition } => {
+                    rules.shift_remove(name).ok_or_else(|| missing(name))?;
+                    hand_written.push(definition.as_str());
+                }
+                Directive::Add { name, definition } => {
+                    if rules.contains_key(name) {
+                        return Err(error(
+                            *line_nb,
+                            format!("rule `{}` already exists", name),
+                        ));
+                    }
+                    hand_written.push(definition.as_str());
+                }
+            }
+        }
+        Ok(hand_written.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_abnf;
+
+    const GRAMMAR: &str = "a = b / c\nb = \"b\"\nc = \"c\"\nd = \"d\"\n";
+
+    fn apply(overrides: &str) -> Result<(String, Vec<String>), Error> {
+        let mut rules = parse_abnf(GRAMMAR)?;
+        let hand_written = parse_overrides(overrides)?.apply(&mut rules)?;
+        let rules = rules
+            .iter()
+            .map(|(name, rule)| format!("{} {:?}", name, rule.modifier))
+            .collect();
+        Ok((hand_written, rules))
+    }
+
+    #[test]
+    fn overrides() {
+        let (hand_written, rules) = apply(
+            r#"
+            // A comment
+            silent b
+            compound_atomic c
+            remove d
+            a = {
+                "{" ~ b ~ '}' // }
+                | c
+            }
+            add e = @{ "e"{2} }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            hand_written,
+            "a = {\n                \"{\" ~ b ~ '}' // }\n                | c\n            }\n\
+             e = @{ \"e\"{2} }"
+        );
+        assert_eq!(rules, vec!["b Silent", "c CompoundAtomic"]);
+
+        let mut rules = parse_abnf("add = \"+\"\nremove = \"-\"\n").unwrap();
+        let hand_written = parse_overrides("add = { \"+\" }\nremove remove")
+            .unwrap()
+            .apply(&mut rules)
+            .unwrap();
+        assert_eq!(hand_written, "add = { \"+\" }");
+        assert!(rules.is_empty());
+    }
+
+    #[test]
+    fn overridden_rules_must_exist() {
+        let err = |overrides| apply(overrides).unwrap_err().to_string();
+        assert_eq!(err("silent b x"), "line 1: rule `x` does not exist");
+        assert_eq!(
+            err("remove a\na = { b }"),
+            "line 2: rule `a` does not exist"
+        );
+        assert_eq!(err("add b = { c }"), "line 1: rule `b` already exists");
+        assert_eq!(
+            err("a = { b\n  | c"),
+            "line 1: unterminated definition of `a`"
+        );
+        assert_eq!(err("silent"), "line 1: `silent` needs rules");
+        assert_eq!(
+            err("a b = { c }"),
+            "line 1: expected a directive or a rule definition"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Parsing of ABNF grammars, as defined in [RFC 5234] and [RFC 7405].
+//!
+//! [RFC 5234]: https://tools.ietf.org/html/rfc5234
+//! [RFC 7405]: https://tools.ietf.org/html/rfc7405
+
+use abnf::types::{Kind, Node, Repetition};
+use indexmap::map::IndexMap;
+use std::io::{Error, ErrorKind};
+
+use crate::{escape_rulename, Modifier, PestyRule};
+
+/// The core rules of RFC 5234 (appendix B.1). They are added to a grammar that uses them without
+/// defining them.
+const CORE_RULES: &str = r#"ALPHA = %x41-5A / %x61-7A
+BIT = "0" / "1"
+CHAR = %x01-7F
+CR = %x0D
+CRLF = CR LF
+CTL = %x00-1F / %x7F
+DIGIT = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"
+HTAB = %x09
+LF = %x0A
+LWSP = *(WSP / CRLF WSP)
+OCTET = %x00-FF
+SP = %x20
+VCHAR = %x21-7E
+WSP = SP / HTAB
+"#;
+
+fn error(msg: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Rewrites the case-sensitive strings of RFC 7405, which the `abnf` crate doesn't know about, to
+/// RFC 5234 syntax: `%s"ab"` becomes `%x61.62`, and `%i"ab"` becomes `"ab"`. Also makes sure the
+/// input ends with a newline.
+fn desugar_string_sensitivity(data: &str) -> String {
+    let mut out = String::with_capacity(data.len() + 1);
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                out.push(c);
+                while let Some(&d) = chars.peek() {
+                    if d == '\n' {
+                        break;
+                    }
+                    out.push(d);
+                    chars.next();
+                }
+            }
+            '"' | '<' => {
+                let end = if c == '"' { '"' } else { '>' };
+                out.push(c);
+                for d in chars.by_ref() {
+                    out.push(d);
+                    if d == end {
+                        break;
+                    }
+                }
+            }
+            '%' => match chars.peek() {
+                Some('i') | Some('I') => {
+                    chars.next();
+                }
+                Some('s') | Some('S') => {
+                    chars.next();
+                    if chars.peek() != Some(&'"') {
+                        out.push_str("%s");
+                        continue;
+                    }
+                    chars.next();
+                    let mut values = Vec::new();
+                    for d in chars.by_ref() {
+                        if d == '"' {
+                            break;
+                        }
+                        values.push(format!("{:02X}", d as u32));
+                    }
+                    if values.is_empty() {
+                        out.push_str("\"\"");
+                    } else {
+                        out.push_str("%x");
+                        out.push_str(&values.join("."));
+                    }
+                }
+                _ => out.push(c),
+            },
+            _ => out.push(c),
+        }
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Calls `f` on every rule name the node refers to.
+fn rulenames_mut(node: &mut Node, f: &mut impl FnMut(&mut String)) {
+    match node {
+        Node::Alternation(nodes) | Node::Concatenation(nodes) => {
+            for node in nodes {
+                rulenames_mut(node, f)
+            }
+        }
+        Node::Repetition(rep) => {
+            let mut node = rep.node().clone();
+            rulenames_mut(&mut node, f);
+            *rep = Repetition::new(rep.repeat().clone(), node);
+        }
+        Node::Group(node) | Node::Optional(node) => rulenames_mut(node, f),
+        Node::Rulename(name) => f(name),
+        Node::String(_) | Node::TerminalValues(_) | Node::Prose(_) => {}
+    }
+}
+
+/// A rule, with the alternatives added to it with `=/`.
+#[derive(Clone)]
+struct Definition {
+    /// The name the rule is defined with, if it has been.
+    name: Option<String>,
+    /// The name the rule is first mentioned with.
+    first_name: String,
+    alternatives: Vec<Node>,
+}
+
+/// Parses rules, and merges the incremental alternatives (`=/`) into the rules they extend. Rules
+/// are indexed by their lowercase name, since rule names are case-insensitive.
+fn parse_definitions(
+    data: &str,
+) -> Result<IndexMap<String, Definition>, Error> {
+    let rules =
+        abnf::rulelist(&desugar_string_sensitivity(data)).map_err(error)?;
+    let mut definitions: IndexMap<String, Definition> = IndexMap::new();
+    for rule in rules {
+        let def = definitions
+            .entry(rule.name().to_ascii_lowercase())
+            .or_insert_with(|| Definition {
+                name: None,
+                first_name: rule.name().to_owned(),
+                alternatives: Vec::new(),
+            });
+        let alternatives = match rule.node() {
+            Node::Alternation(nodes) => nodes.clone(),
+            node => vec![node.clone()],
+        };
+        match rule.kind() {
+            Kind::Basic => {
+                if def.name.is_some() {
+                    return Err(error(format!(
+                        "rule `{}` is defined more than once",
+                        rule.name()
+                    )));
+                }
+                def.name = Some(rule.name().to_owned());
+                // The alternatives given with `=/` come after the ones given with `=`, even if
+                // they appear first in the file.
+                def.alternatives.splice(0..0, alternatives);
+            }
+            Kind::Incremental => def.alternatives.extend(alternatives),
+        }
+    }
+    Ok(definitions)
+}
+
+/// Parse an abnf file. Returns a map of rules.
+///
+/// Alternatives added to a rule with `=/` are merged into that rule, and rule names refer to rules
+/// regardless of case, as in ABNF. The core rules of RFC 5234 (`ALPHA`, `DIGIT`, ...) are added
+/// if the grammar uses them without defining them.
+///
+/// Pest has no equivalent of prose values (`<some description>`), so a prose value becomes a
+/// reference to a rule named after it (`some_description`), which must be provided separately,
+/// e.g. with an [overrides file](overrides/index.html).
+pub fn parse_abnf(
+    data: &str,
+) -> Result<IndexMap<String, PestyRule>, std::io::Error> {
+    let mut definitions = parse_definitions(data)?;
+    let core_rules = parse_definitions(CORE_RULES)?;
+
+    // Add the core rules the grammar needs, and the ones they need.
+    let mut i = 0;
+    while let Some((_, def)) = definitions.get_index_mut(i) {
+        let mut used = Vec::new();
+        for node in &mut def.alternatives {
+            rulenames_mut(node, &mut |name| {
+                used.push(name.to_ascii_lowercase())
+            });
+        }
+        for name in used {
+            if !definitions.contains_key(&name) {
+                if let Some(core_rule) = core_rules.get(&name) {
+                    definitions.insert(name, core_rule.clone());
+                }
+            }
+        }
+        i += 1;
+    }
+
+    let names: IndexMap<String, String> = definitions
+        .iter()
+        .filter_map(|(key, def)| Some((key.clone(), def.name.clone()?)))
+        .collect();
+    let mut rules = IndexMap::new();
+    for (_, def) in definitions {
+        let name = match def.name {
+            Some(name) => name,
+            None => {
+                return Err(error(format!(
+                    "rule `{}` is extended with `=/` but never defined",
+                    def.first_name
+                )))
+            }
+        };
+        let mut node = match def.alternatives.len() {
+            1 => def.alternatives.into_iter().next().unwrap(),
+            _ => Node::Alternation(def.alternatives),
+        };
+        // Refer to rules with the case they are defined with.
+        rulenames_mut(&mut node, &mut |name| {
+            if let Some(defined) = names.get(&name.to_ascii_lowercase()) {
+                *name = defined.clone();
+            }
+        });
+        rules.insert(
+            escape_rulename(&name),
+            PestyRule {
+                modifier: Modifier::Normal,
+                node,
+            },
+        );
+    }
+    Ok(rules)
+}
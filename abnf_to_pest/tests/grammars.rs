@@ -0,0 +1,78 @@
+//! Converts ABNF grammars to pest, and checks that pest accepts the result.
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+
+use abnf_to_pest::{parse_abnf, render_rules_to_pest};
+use pest_meta::parser::{self, Rule};
+
+/// Converts an ABNF grammar to pest, and checks that pest accepts it.
+fn convert(abnf: &str) -> Result<String, String> {
+    let rules = parse_abnf(abnf).map_err(|e| e.to_string())?;
+    let pest = render_rules_to_pest(rules).pretty(80).to_string();
+    let report = |errors: &[String]| format!("{}\n{}", errors.join("\n"), pest);
+    let to_strings = |errors: Vec<_>| -> Vec<String> {
+        errors.iter().map(ToString::to_string).collect()
+    };
+    let pairs = parser::parse(Rule::grammar_rules, &pest)
+        .map_err(|e| report(&[e.to_string()]))?;
+    pest_meta::validator::validate_pairs(pairs.clone())
+        .map_err(|e| report(&to_strings(e)))?;
+    parser::consume_rules(pairs).map_err(|e| report(&to_strings(e)))?;
+    Ok(pest.clone())
+}
+
+#[test]
+fn grammars_convert_to_valid_pest() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<_> = read_dir(dir.join("tests/grammars"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths.push(dir.join("../dhall/src/syntax/text/dhall.abnf"));
+    for path in paths {
+        let abnf = read_to_string(&path).unwrap();
+        if let Err(e) = convert(&abnf) {
+            panic!("{}: {}", path.display(), e)
+        }
+    }
+}
+
+#[test]
+fn constructs() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let abnf =
+        read_to_string(dir.join("tests/grammars/constructs.abnf")).unwrap();
+    let pest = convert(&abnf).unwrap();
+    let expected = r#"greeting = { "Hello" ~ SP ~ name | ^"hi" ~ SP ~ name | ^"hey" ~ SP ~ name }
+name = { ALPHA+ | any_name }
+any_name = { VCHAR+ }
+bits = { "\u{05}\u{06}" | '\u{00}'..'\u{01}' }
+decimals = { "\u{0D}\u{0A}" | '0'..'9' }
+repeats = { BIT{2,3} ~ BIT{2,} ~ BIT{0,2} ~ BIT{3} ~ BIT? ~ BIT+ ~ BIT* }
+keywords = { true_ | type_ | match_ | COMMENT_ }
+true_ = { ^"true" }
+type_ = { "type" }
+match_ = { ^"match" }
+COMMENT_ = { ^";" ~ VCHAR* }
+escapes = { ^"\\" | "\u{5C}" | ^"'" | "a;b" | ^"" }
+characters = { '\u{D000}'..'\u{E000}' | '\u{10000}'..'\u{10FFFF}' }
+SP = { "\u{20}" }
+ALPHA = { 'A'..'Z' | 'a'..'z' }
+VCHAR = { '!'..'~' }
+BIT = { ^"0" | ^"1" }"#;
+    assert_eq!(pest, expected);
+}
+
+#[test]
+fn invalid_grammars() {
+    let err = |abnf| parse_abnf(abnf).unwrap_err().to_string();
+    assert_eq!(
+        err("a = \"a\"\nA = \"b\"\n"),
+        "rule `A` is defined more than once"
+    );
+    assert_eq!(
+        err("a =/ \"a\"\n"),
+        "rule `a` is extended with `=/` but never defined"
+    );
+}
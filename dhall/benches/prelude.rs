@@ -0,0 +1,122 @@
+//! Benchmarks of folds over large inputs, written with functions from the Prelude. Each one is
+//! run a few times, and the fastest run is reported.
+//!
+//! Run with `cargo bench -p dhall --bench prelude`.
+
+use std::time::{Duration, Instant};
+
+use dhall::{Ctxt, Parsed};
+
+/// The Prelude functions used below, copied as they are in the Prelude.
+const PRELUDE: &str = r#"
+let List/replicate
+    : Natural → ∀(a : Type) → a → List a
+    = λ(n : Natural) →
+      λ(a : Type) →
+      λ(x : a) →
+        List/build
+          a
+          ( λ(list : Type) →
+            λ(cons : a → list → list) →
+              Natural/fold n list (cons x)
+          )
+
+let Natural/enumerate
+    : Natural → List Natural
+    = λ(n : Natural) →
+        List/build
+          Natural
+          ( λ(list : Type) →
+            λ(cons : Natural → list → list) →
+              List/fold
+                { index : Natural, value : {} }
+                (List/indexed {} (List/replicate n {} {=}))
+                list
+                (λ(x : { index : Natural, value : {} }) → cons x.index)
+          )
+
+let List/map
+    : ∀(a : Type) → ∀(b : Type) → (a → b) → List a → List b
+    = λ(a : Type) →
+      λ(b : Type) →
+      λ(f : a → b) →
+      λ(xs : List a) →
+        List/build
+          b
+          ( λ(list : Type) →
+            λ(cons : b → list → list) →
+              List/fold a xs list (λ(x : a) → cons (f x))
+          )
+
+let Natural/sum
+    : List Natural → Natural
+    = λ(xs : List Natural) →
+        List/fold Natural xs Natural (λ(l : Natural) → λ(r : Natural) → l + r) 0
+
+let Text/replicate
+    : Natural → Text → Text
+    = λ(num : Natural) →
+      λ(text : Text) →
+        Natural/fold num Text (λ(t : Text) → text ++ t) ""
+
+in
+"#;
+
+const BENCHES: &[(&str, &str)] = &[
+    ("Natural/fold", "Natural/fold {} Natural (λ(x : Natural) → x + 2) 0"),
+    ("List/replicate", "List/replicate {} Bool True"),
+    ("Natural/enumerate", "Natural/enumerate {}"),
+    (
+        "List/map",
+        "List/map Natural Natural (λ(x : Natural) → x * 2) (Natural/enumerate {})",
+    ),
+    ("Natural/sum", "Natural/sum (Natural/enumerate {})"),
+    ("Text/replicate", "Text/replicate {} \"ab\""),
+];
+
+/// Parses, typechecks and fully normalizes an expression.
+fn normalize(src: &str) -> String {
+    Ctxt::with_new(|cx| {
+        let typed = Parsed::parse_str(src)
+            .unwrap()
+            .skip_resolve(cx)
+            .unwrap()
+            .typecheck(cx)
+            .unwrap();
+        typed.normalize(cx).to_expr(cx).to_string()
+    })
+}
+
+/// How many times each benchmark is run.
+const SAMPLES: usize = 10;
+
+/// The fastest of `SAMPLES` runs of `f`.
+fn time(f: impl Fn()) -> Duration {
+    (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:<20} {:>8} {:>12}", "benchmark", "size", "time (ms)");
+    for (name, expr) in BENCHES {
+        for &size in &[100, 1000, 10_000] {
+            let src =
+                format!("{}{}", PRELUDE, expr.replace("{}", &size.to_string()));
+            let time = time(|| {
+                normalize(&src);
+            });
+            println!(
+                "{:<20} {:>8} {:>12.1}",
+                name,
+                size,
+                time.as_secs_f64() * 1000.0
+            );
+        }
+    }
+}
@@ -3,15 +3,18 @@ use std::fs::{read_to_string, File};
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 
-use abnf_to_pest::render_rules_to_pest;
+use abnf_to_pest::{render_rules_to_pest, Modifier};
+use proc_macro2::{Group, Ident, Span, TokenStream, TokenTree};
 
 fn convert_abnf_to_pest() -> std::io::Result<()> {
     let out_dir = env::var("OUT_DIR").unwrap();
     let abnf_path = "src/syntax/text/dhall.abnf";
     let visibility_path = "src/syntax/text/dhall.pest.visibility";
+    let overrides_path = "src/syntax/text/dhall.pest.overrides";
     let grammar_path = Path::new(&out_dir).join("dhall.pest");
     println!("cargo:rerun-if-changed={}", abnf_path);
     println!("cargo:rerun-if-changed={}", visibility_path);
+    println!("cargo:rerun-if-changed={}", overrides_path);
 
     let mut data = read_to_string(abnf_path)?;
     data.push('\n');
@@ -21,88 +24,24 @@ fn convert_abnf_to_pest() -> std::io::Result<()> {
         let line = line?;
         if line.len() >= 2 && &line[0..2] == "# " {
             if let Some(x) = rules.get_mut(&line[2..]) {
-                x.silent = true;
+                x.modifier = Modifier::Silent;
             }
         }
     }
 
+    // Hand-written changes to the grammar.
+    let in_overrides = |e: std::io::Error| {
+        std::io::Error::new(e.kind(), format!("{}: {}", overrides_path, e))
+    };
+    let overrides =
+        abnf_to_pest::parse_overrides(&read_to_string(overrides_path)?)
+            .map_err(in_overrides)?;
+    let hand_written_rules =
+        overrides.apply(&mut rules).map_err(in_overrides)?;
+
     let mut file = File::create(grammar_path)?;
     writeln!(&mut file, "// AUTO-GENERATED FILE. See build.rs.")?;
-
-    // Work around some greediness issue in the grammar.
-    rules.remove("missing");
-    writeln!(
-        &mut file,
-        r#"missing = {{ "missing" ~ !simple_label_next_char }}"#
-    )?;
-
-    // Prefer my nice error message to illegible parse errors.
-    rules.remove("unicode_escape");
-    rules.remove("unbraced_escape");
-    rules.remove("braced_escape");
-    rules.remove("braced_codepoint");
-    rules.remove("unicode_suffix");
-    writeln!(
-        &mut file,
-        r#"unicode_escape = _{{ HEXDIG{{4}} | "{{" ~ HEXDIG+ ~ "}}" }}"#
-    )?;
-
-    rules.remove("simple_label");
-    writeln!(
-        &mut file,
-        "simple_label = {{
-              keyword ~ simple_label_next_char+
-            | !keyword ~ simple_label_first_char ~ simple_label_next_char*
-    }}"
-    )?;
-
-    rules.remove("nonreserved_label");
-    writeln!(
-        &mut file,
-        "nonreserved_label = _{{
-            !(builtin ~ !simple_label_next_char) ~ label
-    }}"
-    )?;
-
-    // Setup grammar for precedence climbing
-    rules.remove("operator_expression");
-    writeln!(
-        &mut file,
-        r##"
-        import_alt = {{ "?" ~ whsp1 }}
-        bool_or = {{ "||" }}
-        natural_plus = {{ "+" ~ whsp1 }}
-        text_append = {{ "++" }}
-        list_append = {{ "#" }}
-        bool_and = {{ "&&" }}
-        natural_times = {{ "*" }}
-        bool_eq = {{ "==" }}
-        bool_ne = {{ "!=" }}
-
-        operator = _{{
-            equivalent |
-            bool_ne |
-            bool_eq |
-            natural_times |
-            combine_types |
-            prefer |
-            combine |
-            bool_and |
-            list_append |
-            text_append |
-            natural_plus |
-            bool_or |
-            import_alt
-        }}
-        operator_expression = {{ application_expression ~ (whsp ~ operator ~ whsp ~ application_expression)* }}
-    "##
-    )?;
-
-    writeln!(
-        &mut file,
-        "final_expression = ${{ SOI ~ complete_expression ~ EOI }}"
-    )?;
-
+    writeln!(&mut file, "{}", hand_written_rules)?;
     writeln!(&mut file)?;
     writeln!(&mut file, "{}", render_rules_to_pest(rules).pretty(80))?;
 
@@ -122,15 +61,68 @@ fn generate_pest_parser() -> std::io::Result<()> {
         struct DhallParser;
     );
     let derived = pest_generator::derive_parser(pest, false);
+    // Every level of nesting in the input goes through the `expression` rule, so grow the stack
+    // there to parse deeply nested expressions.
+    let mut guarded = 0;
+    let derived = guard_rule(derived, "expression", &mut guarded);
+    assert_eq!(guarded, 1, "expected one generated `expression` function");
     let file_contents = quote::quote!(
         struct DhallParser;
         #derived
-    );
+    )
+    .to_string();
 
     let mut file = File::create(output_path)?;
     writeln!(file, "{}", file_contents)
 }
 
+/// Renames the function that pest generates for `rule` to `<rule>_unguarded`, and adds a function
+/// with the original name next to it that calls it on a large enough stack. The other rules call
+/// `rule` by name, so they all go through the new function. This walks the tokens rather than the
+/// generated text so that it doesn't depend on how they're printed.
+fn guard_rule(
+    tokens: TokenStream,
+    rule: &str,
+    guarded: &mut usize,
+) -> TokenStream {
+    let name = Ident::new(rule, Span::call_site());
+    let unguarded =
+        Ident::new(&format!("{}_unguarded", rule), Span::call_site());
+    let mut found = false;
+    let mut prev_is_fn = false;
+    let mut out: Vec<TokenTree> = Vec::new();
+    for tt in tokens {
+        let tt = match tt {
+            TokenTree::Ident(ref id) if prev_is_fn && *id == rule => {
+                found = true;
+                TokenTree::Ident(unguarded.clone())
+            }
+            TokenTree::Group(g) => {
+                let stream = guard_rule(g.stream(), rule, guarded);
+                let mut new = Group::new(g.delimiter(), stream);
+                new.set_span(g.span());
+                TokenTree::Group(new)
+            }
+            tt => tt,
+        };
+        prev_is_fn = matches!(&tt, TokenTree::Ident(id) if *id == "fn");
+        out.push(tt);
+    }
+    let mut out: TokenStream = out.into_iter().collect();
+    if found {
+        *guarded += 1;
+        out.extend(quote::quote!(
+            #[allow(non_snake_case)]
+            pub fn #name(
+                state: Box<::pest::ParserState<Rule>>,
+            ) -> ::pest::ParseResult<Box<::pest::ParserState<Rule>>> {
+                crate::utils::grow_stack(|| #unguarded(state))
+            }
+        ));
+    }
+    out
+}
+
 fn main() -> std::io::Result<()> {
     convert_abnf_to_pest()?;
     generate_pest_parser()?;
@@ -0,0 +1,60 @@
+//! Compares two Dhall files.
+//!
+//! ```text
+//! cargo run --example diff -- [--color] <old> <new>
+//! ```
+//!
+//! Normalizes both files, and prints the difference between the results. Exits with an error if
+//! they differ.
+
+use std::path::PathBuf;
+use std::process::exit;
+
+use dhall::error::Error;
+use dhall::{diff, Ctxt, Parsed};
+
+fn main() {
+    let mut color = false;
+    let mut files = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--color" => color = true,
+            _ if files.len() < 2 && !arg.starts_with("--") => {
+                files.push(PathBuf::from(arg))
+            }
+            _ => usage(),
+        }
+    }
+    if files.len() != 2 {
+        usage()
+    }
+
+    let res = Ctxt::with_new(|cx| {
+        let normalize = |file: &PathBuf| -> Result<_, Error> {
+            Ok(Parsed::parse_file(file)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx))
+        };
+        let diff =
+            diff::diff(cx, &normalize(&files[0])?, &normalize(&files[1])?);
+        Ok::<_, Error>((diff.render(color), diff.is_same()))
+    });
+    match res {
+        Ok((rendered, same)) => {
+            println!("{}", rendered);
+            if !same {
+                exit(1)
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1)
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: diff [--color] <old> <new>");
+    exit(2)
+}
@@ -0,0 +1,61 @@
+//! Checks a Dhall file with the linter.
+//!
+//! ```text
+//! cargo run --example lint -- [--fix] <file>
+//! ```
+//!
+//! Prints the problems found in the file, and exits with an error if there are any. With `--fix`,
+//! first rewrites the file with the automatic fixes applied, then prints the problems that remain.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::exit;
+
+use dhall::error::Error;
+use dhall::{lint, Parsed};
+
+fn main() {
+    let mut fix = false;
+    let mut file = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            _ if file.is_none() && !arg.starts_with("--") => {
+                file = Some(PathBuf::from(arg))
+            }
+            _ => usage(),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage());
+
+    let res = (|| {
+        let mut source = fs::read_to_string(&file)?;
+        if fix {
+            let fixed = lint::fix(&source)?;
+            if fixed != source {
+                fs::write(&file, &fixed)?;
+                source = fixed;
+            }
+        }
+        Ok::<_, Error>(Parsed::parse_str(&source)?.lint())
+    })();
+    match res {
+        Ok(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}\n", diagnostic);
+            }
+            if !diagnostics.is_empty() {
+                exit(1)
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1)
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: lint [--fix] <file>");
+    exit(2)
+}
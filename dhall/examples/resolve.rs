@@ -0,0 +1,62 @@
+//! Prints the imports of a Dhall file.
+//!
+//! ```text
+//! cargo run --example resolve -- [--dot | --json] <file>
+//! ```
+//!
+//! Without options, prints one imported location per line. With `--dot`, prints the import graph
+//! in the DOT format, e.g. to be piped into `dot -Tsvg`. With `--json`, prints it as JSON.
+
+use std::path::PathBuf;
+use std::process::exit;
+
+use dhall::{Ctxt, ImportGraph, Parsed};
+
+enum Format {
+    List,
+    Dot,
+    Json,
+}
+
+fn main() {
+    let mut format = Format::List;
+    let mut file = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--dot" => format = Format::Dot,
+            "--json" => format = Format::Json,
+            _ if file.is_none() && !arg.starts_with("--") => {
+                file = Some(PathBuf::from(arg))
+            }
+            _ => usage(),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage());
+
+    let res = Ctxt::with_new(|cx| {
+        Parsed::parse_file(&file)?.resolve(cx)?;
+        let graph = ImportGraph::new(cx);
+        Ok::<_, dhall::error::Error>(match format {
+            Format::List => graph
+                .nodes()
+                .iter()
+                .skip(1)
+                .map(|node| format!("{}\n", node.location))
+                .collect(),
+            Format::Dot => graph.to_dot(),
+            Format::Json => format!("{}\n", graph.to_json()),
+        })
+    });
+    match res {
+        Ok(out) => print!("{}", out),
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1)
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: resolve [--dot | --json] <file>");
+    exit(2)
+}
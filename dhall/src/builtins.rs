@@ -2,12 +2,13 @@ use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 
 use crate::operations::{BinOp, OpKind};
-use crate::semantics::{nze, Hir, HirKind, Nir, NirKind, NzEnv, VarEnv};
+use crate::semantics::{nze, Hir, HirKind, Nir, NirKind, NzEnv, NzVar, VarEnv};
 use crate::syntax::Const::Type;
 use crate::syntax::{
     Const, Expr, ExprKind, InterpolatedText, InterpolatedTextContents, Label,
     NaiveDouble, NumKind, Span, UnspannedExpr, V,
 };
+use crate::utils::grow_stack;
 use crate::{Ctxt, Parsed};
 
 /// Built-ins
@@ -103,6 +104,16 @@ impl<'cx> BuiltinClosure<'cx> {
         let args = self.args.iter().cloned().chain(once(a)).collect();
         apply_builtin(self.b, args, self.env.clone())
     }
+    /// Counts an evaluation step against the limits of the context. Returns whether the builtin
+    /// may be applied.
+    pub fn step(&self) -> bool {
+        self.env.cx().limits_state().step()
+    }
+    /// Removes the values this closure holds on to that aren't shared, and passes them to `f`.
+    pub fn pop_unique_values(&mut self, mut f: impl FnMut(Nir<'cx>)) {
+        self.env.pop_unique_values(&mut f);
+        self.args.drain(..).for_each(f);
+    }
     pub fn to_hirkind(&self, venv: VarEnv) -> HirKind<'cx> {
         HirKind::Expr(self.args.iter().fold(
             ExprKind::Builtin(self.b),
@@ -252,7 +263,7 @@ pub fn type_of_builtin<'cx>(cx: Ctxt<'cx>, b: Builtin) -> Hir<'cx> {
         ),
     };
     Parsed::from_expr_without_imports(expr)
-        .resolve(cx)
+        .skip_resolve(cx)
         .unwrap()
         .0
 }
@@ -325,7 +336,7 @@ fn apply_builtin<'cx>(
     }
     let make_closure = |e| {
         Parsed::from_expr_without_imports(e)
-            .resolve(cx)
+            .skip_resolve(cx)
             .unwrap()
             .typecheck(cx)
             .unwrap()
@@ -544,11 +555,21 @@ fn apply_builtin<'cx>(
         (Builtin::ListFold, [_, l, _, cons, nil]) => match &*l.kind() {
             EmptyListLit(_) => Ret::Nir(nil.clone()),
             NEListLit(xs) => {
+                // Elements to prepend to `v`, in reverse order. Building a list by prepending to
+                // it one element at a time would copy it every time.
+                let mut prepended = Vec::new();
                 let mut v = nil.clone();
                 for x in xs.iter().cloned().rev() {
-                    v = cons.app(x).app(v);
+                    let succ = cons.app(x);
+                    if is_list_literal(&v) {
+                        if let Some(prefix) = as_prepend(&succ) {
+                            prepended.extend(prefix.into_iter().rev());
+                            continue;
+                        }
+                    }
+                    v = succ.app(prepend_rev(&mut prepended, v));
                 }
-                Ret::Nir(v)
+                Ret::Nir(prepend_rev(&mut prepended, v))
             }
             _ => Ret::DoneAsIs,
         },
@@ -562,23 +583,175 @@ fn apply_builtin<'cx>(
         ),
 
         (Builtin::NaturalFold, [n, t, succ, zero]) => match &*n.kind() {
-            Num(Natural(0)) => Ret::Nir(zero.clone()),
-            Num(Natural(n)) => {
-                let fold = Nir::from_builtin(cx, Builtin::NaturalFold)
-                    .app(Num(Natural(n - 1)).into_nir())
-                    .app(t.clone())
-                    .app(succ.clone())
-                    .app(zero.clone());
-                Ret::Nir(succ.app(fold))
-            }
+            Num(Natural(n)) => match fold_natively(cx, *n, succ, zero) {
+                (0, acc) => Ret::Nir(acc),
+                (n, zero) => {
+                    let fold = Nir::from_builtin(cx, Builtin::NaturalFold)
+                        .app(Num(Natural(n - 1)).into_nir())
+                        .app(t.clone())
+                        .app(succ.clone())
+                        .app(zero);
+                    Ret::Nir(succ.app(fold))
+                }
+            },
             _ => Ret::DoneAsIs,
         },
         _ => Ret::DoneAsIs,
     };
-    match ret {
+    let kind = match ret {
         Ret::NirKind(v) => v,
         Ret::Nir(v) => v.kind().clone(),
         Ret::DoneAsIs => AppliedBuiltin(BuiltinClosure { b, args, env }),
+    };
+    nze::check_size(cx, &kind);
+    kind
+}
+
+/// Computes `Natural/fold n _ succ zero` without unrolling it into nested applications, which
+/// recurses `n` levels deep. When `succ` ignores its argument, it is applied once. When it
+/// prepends to a list, the list is built in one go. Otherwise it is applied in a loop as long as
+/// the accumulator is a literal, which is when unrolling wouldn't have left work undone.
+/// Returns the number of applications left to do and the accumulator so far.
+fn fold_natively<'cx>(
+    cx: Ctxt<'cx>,
+    mut n: u64,
+    succ: &Nir<'cx>,
+    zero: &Nir<'cx>,
+) -> (u64, Nir<'cx>) {
+    use NirKind::*;
+    let mut acc = zero.clone();
+    match succ.kind() {
+        LamClosure { closure, .. } if !closure.uses_argument() && n > 0 => {
+            return (0, succ.app(acc));
+        }
+        LamClosure { .. } | AppliedBuiltin(_) => {}
+        _ => return (n, acc),
+    }
+    if is_list_literal(&acc) {
+        if let Some(prefix) = as_prepend(succ) {
+            let len = match acc.kind() {
+                NEListLit(xs) => xs.len(),
+                _ => 0,
+            };
+            let len = (n as usize)
+                .saturating_mul(prefix.len())
+                .saturating_add(len);
+            if !cx.limits_state().list_length(len) {
+                return (n, acc);
+            }
+            let rev_prefix: Vec<_> = prefix.into_iter().rev().collect();
+            let mut prepended = Vec::new();
+            for _ in 0..n {
+                prepended.extend(rev_prefix.iter().cloned());
+            }
+            return (0, prepend_rev(&mut prepended, acc));
+        }
+    }
+    while n > 0 && is_literal(acc.kind()) && cx.check_limits().is_ok() {
+        let next = succ.app(acc.clone());
+        n -= 1;
+        // Once a number stops changing, it won't change anymore.
+        if matches!(next.kind(), Num(_)) && next == acc {
+            return (0, next);
+        }
+        acc = next;
+    }
+    (n, acc)
+}
+
+/// If applying `f` to any list `l` gives `prefix # l`, returns `prefix`. This is how `List/build`
+/// builds lists.
+fn as_prepend<'cx>(f: &Nir<'cx>) -> Option<Vec<Nir<'cx>>> {
+    use NirKind::*;
+    let var = NzVar::fresh();
+    let result = f.app(Nir::from_kind(Var(var)));
+    let prefix = match result.kind() {
+        Op(OpKind::BinOp(BinOp::ListAppend, prefix, l)) => match l.kind() {
+            Var(v) if *v == var => prefix,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match prefix.kind() {
+        NEListLit(xs) if !xs.iter().any(|x| mentions_var(x, var)) => {
+            Some(xs.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether `var` may occur in the value. Values under binders are assumed to mention it.
+fn mentions_var(v: &Nir<'_>, var: NzVar) -> bool {
+    use NirKind::*;
+    fn any<'a, 'cx: 'a>(
+        mut xs: impl Iterator<Item = &'a Nir<'cx>>,
+        var: NzVar,
+    ) -> bool {
+        xs.any(|x| mentions_var(x, var))
+    }
+    grow_stack(|| match v.kind() {
+        LamClosure { .. } | PiClosure { .. } => true,
+        AppliedBuiltin(closure) => any(closure.args.iter(), var),
+        Var(v) => *v == var,
+        Const(_) | Num(_) | BuiltinType(_) => false,
+        TextLit(txt) => {
+            let exprs = txt.iter().filter_map(|contents| match contents {
+                InterpolatedTextContents::Text(_) => None,
+                InterpolatedTextContents::Expr(e) => Some(e),
+            });
+            any(exprs, var)
+        }
+        EmptyOptionalLit(x) | NEOptionalLit(x) | OptionalType(x)
+        | EmptyListLit(x) | ListType(x) | Assert(x) => mentions_var(x, var),
+        NEListLit(xs) => any(xs.iter(), var),
+        RecordLit(kvs) | RecordType(kvs) => any(kvs.values(), var),
+        UnionConstructor(_, kts) | UnionType(kts) => {
+            any(kts.values().flatten(), var)
+        }
+        UnionLit(_, x, kts) => {
+            mentions_var(x, var) || any(kts.values().flatten(), var)
+        }
+        Equivalence(x, y) => mentions_var(x, var) || mentions_var(y, var),
+        Op(op) => op
+            .traverse_ref(|x| {
+                if mentions_var(x, var) {
+                    Err(())
+                } else {
+                    Ok(())
+                }
+            })
+            .is_err(),
+    })
+}
+
+fn is_list_literal(v: &Nir<'_>) -> bool {
+    matches!(v.kind(), NirKind::EmptyListLit(_) | NirKind::NEListLit(_))
+}
+
+/// Prepends the elements of `rev_prefix`, in reverse order, to the list literal `l`, and empties
+/// `rev_prefix`.
+fn prepend_rev<'cx>(rev_prefix: &mut Vec<Nir<'cx>>, l: Nir<'cx>) -> Nir<'cx> {
+    if rev_prefix.is_empty() {
+        return l;
+    }
+    let mut xs: Vec<_> = rev_prefix.drain(..).rev().collect();
+    match l.kind() {
+        NirKind::NEListLit(ys) => xs.extend(ys.iter().cloned()),
+        NirKind::EmptyListLit(_) => {}
+        _ => unreachable!(),
+    }
+    Nir::from_kind(NirKind::NEListLit(xs))
+}
+
+/// Whether the value is a literal, i.e. doesn't need to be evaluated any further to pattern-match
+/// on it.
+fn is_literal(kind: &NirKind<'_>) -> bool {
+    use NirKind::*;
+    match kind {
+        Num(_) | EmptyOptionalLit(_) | NEOptionalLit(_) | EmptyListLit(_)
+        | NEListLit(_) | RecordLit(_) | UnionLit(..) => true,
+        TextLit(txt) => txt.as_text().is_some(),
+        _ => false,
     }
 }
 
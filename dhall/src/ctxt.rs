@@ -1,21 +1,77 @@
-use elsa::vec::FrozenVec;
-use once_cell::sync::OnceCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, Index};
 
+use crate::error::LimitError;
+use crate::limits::{Limits, LimitsState};
 use crate::semantics::{Import, ImportLocation, ImportNode};
+use crate::sync::{BoxFuture, OnceCell};
 use crate::syntax::Span;
 use crate::Typed;
 
+/// An append-only vector that can be pushed to through a shared reference. Elements are boxed so
+/// that references to them stay valid when the vector grows.
+struct FrozenVec<T> {
+    #[cfg(not(feature = "sync"))]
+    vec: elsa::vec::FrozenVec<Box<T>>,
+    #[cfg(feature = "sync")]
+    vec: std::sync::RwLock<Vec<Box<T>>>,
+}
+
+impl<T> Default for FrozenVec<T> {
+    fn default() -> Self {
+        FrozenVec {
+            vec: Default::default(),
+        }
+    }
+}
+
+#[cfg(not(feature = "sync"))]
+impl<T> FrozenVec<T> {
+    /// Pushes a value and returns its index.
+    fn push(&self, x: T) -> usize {
+        let id = self.vec.len();
+        self.vec.push(Box::new(x));
+        id
+    }
+    fn get(&self, id: usize) -> &T {
+        &self.vec[id]
+    }
+    fn len(&self) -> usize {
+        self.vec.len()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> FrozenVec<T> {
+    /// Pushes a value and returns its index.
+    fn push(&self, x: T) -> usize {
+        let mut vec = self.vec.write().unwrap();
+        vec.push(Box::new(x));
+        vec.len() - 1
+    }
+    fn get(&self, id: usize) -> &T {
+        let vec = self.vec.read().unwrap();
+        let ptr: *const T = &*vec[id];
+        // Safety: elements are boxed and never removed or mutated, so the pointee stays valid and
+        // unaliased for as long as `self` lives.
+        unsafe { &*ptr }
+    }
+    fn len(&self) -> usize {
+        self.vec.read().unwrap().len()
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////////////////////////////
 // Ctxt
 
 /// Implementation detail. Made public for the `Index` instances.
 #[derive(Default)]
 pub struct CtxtS<'cx> {
-    imports: FrozenVec<Box<StoredImport<'cx>>>,
-    import_alternatives: FrozenVec<Box<StoredImportAlternative<'cx>>>,
-    import_results: FrozenVec<Box<StoredImportResult<'cx>>>,
+    imports: FrozenVec<StoredImport<'cx>>,
+    import_alternatives: FrozenVec<StoredImportAlternative<'cx>>,
+    import_results: FrozenVec<StoredImportResult<'cx>>,
+    roots: FrozenVec<ImportLocation>,
+    limits: LimitsState,
 }
 
 /// Context for the dhall compiler. Stores various global maps.
@@ -25,11 +81,110 @@ pub struct Ctxt<'cx>(&'cx CtxtS<'cx>);
 
 impl Ctxt<'_> {
     pub fn with_new<T>(f: impl for<'cx> FnOnce(Ctxt<'cx>) -> T) -> T {
-        let cx = CtxtS::default();
-        let cx = Ctxt(&cx);
-        f(cx)
+        Self::with_limits(Limits::default(), f)
+    }
+
+    /// Like `with_new`, for asynchronous code such as `Parsed::resolve_async`. The closure should
+    /// return a boxed future, e.g. `Box::pin(async move { ... })`.
+    pub async fn with_new_async<T>(
+        f: impl for<'cx> FnOnce(Ctxt<'cx>) -> BoxFuture<'cx, T>,
+    ) -> T {
+        Self::with_limits_async(Limits::default(), f).await
+    }
+
+    /// Like `with_new`, with limits on the work that can be done in the context. See `Limits`.
+    pub fn with_limits<T>(
+        limits: Limits,
+        f: impl for<'cx> FnOnce(Ctxt<'cx>) -> T,
+    ) -> T {
+        let cx = CtxtOwner::new(limits);
+        f(cx.ctxt())
+    }
+
+    /// Like `with_new_async`, with limits on the work that can be done in the context.
+    pub async fn with_limits_async<T>(
+        limits: Limits,
+        f: impl for<'cx> FnOnce(Ctxt<'cx>) -> BoxFuture<'cx, T>,
+    ) -> T {
+        let cx = CtxtOwner::new(limits);
+        f(cx.ctxt()).await
+    }
+}
+
+impl<'cx> Ctxt<'cx> {
+    /// The limits this context was created with.
+    pub fn limits(self) -> &'cx Limits {
+        self.0.limits.limits()
+    }
+    /// Returns the first limit that was exceeded in this context, if any. Evaluation stops making
+    /// progress once a limit is exceeded, so the values computed since then may be incomplete.
+    pub fn check_limits(self) -> Result<(), LimitError> {
+        match self.0.limits.exceeded() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+    pub(crate) fn limits_state(self) -> &'cx LimitsState {
+        &self.0.limits
+    }
+}
+
+/// Owns a `CtxtS`. The values stored in a context borrow the context itself, so if it was a local
+/// variable the borrow checker wouldn't let these values implement `Drop` (as `Hir` does). It is
+/// kept behind a raw pointer instead, and freed when the owner is dropped.
+///
+/// This is sound as long as the following holds:
+/// - the pointer comes from `Box::into_raw` and is only freed in `Drop`, so it is valid and not
+///   aliased mutably for the whole life of the owner;
+/// - every `Ctxt<'cx>` handed out borrows the owner for `'cx`, and everything that mentions `'cx`
+///   is bounded by it. `with_limits` and friends take a closure that is generic over `'cx`, so no
+///   `Ctxt` nor any value that borrows the context can escape it;
+/// - `CtxtS<'cx>` is invariant in `'cx`, so the `'static` in the pointer type is never observed:
+///   the context is only ever accessed as `CtxtS<'cx>` for the lifetime of a borrow of the owner;
+/// - the values stored in the context can hold a `Ctxt` pointing back to the context itself, which
+///   dangles while the context is being dropped. So no `Drop` impl of a value that can be stored in
+///   the context may use a `Ctxt`. Currently only `Hir`, `Nir` and the environments implement
+///   `Drop`, and they only move out and drop their own contents.
+///
+/// The tests at the end of this file exercise this, and are meant to be run under Miri too.
+struct CtxtOwner(*mut CtxtS<'static>);
+
+impl CtxtOwner {
+    fn new(limits: Limits) -> Self {
+        let cx = CtxtS {
+            limits: LimitsState::new(limits),
+            ..CtxtS::default()
+        };
+        CtxtOwner(Box::into_raw(Box::new(cx)))
+    }
+    fn ctxt<'cx>(&'cx self) -> Ctxt<'cx> {
+        // Safety: the pointer stays valid until `self` is dropped, which can't happen while the
+        // returned `Ctxt` is in use. The lifetime parameter of `CtxtS` is only used for values
+        // that borrow the context itself, so it can be shortened to `'cx`.
+        Ctxt(unsafe { &*(self.0 as *const CtxtS<'cx>) })
     }
 }
+
+// Safety: `CtxtOwner` behaves like a `Box<CtxtS>`, and with the `sync` feature `CtxtS` is `Send`
+// and `Sync` (checked below).
+#[cfg(feature = "sync")]
+unsafe impl Send for CtxtOwner {}
+#[cfg(feature = "sync")]
+unsafe impl Sync for CtxtOwner {}
+#[cfg(feature = "sync")]
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    let _ = assert_send_sync::<CtxtS<'static>>;
+};
+
+impl Drop for CtxtOwner {
+    fn drop(&mut self) {
+        // Safety: the pointer came from `Box::into_raw` and the context isn't borrowed anymore.
+        // The values it contains don't access it when they get dropped.
+        unsafe { drop(Box::from_raw(self.0)) }
+    }
+}
+
 impl<'cx> Deref for Ctxt<'cx> {
     type Target = &'cx CtxtS<'cx>;
     fn deref(&self) -> &&'cx CtxtS<'cx> {
@@ -117,15 +272,18 @@ impl<'cx> Ctxt<'cx> {
             span,
             result: OnceCell::new(),
         };
-        let id = self.0.imports.len();
-        self.0.imports.push(Box::new(stored));
+        let id = self.0.imports.push(stored);
         ImportId(id, PhantomData)
     }
+    /// All the imports stored so far, in the order they were found.
+    pub fn imports(self) -> impl Iterator<Item = ImportId<'cx>> {
+        (0..self.0.imports.len()).map(|id| ImportId(id, PhantomData))
+    }
 }
 impl<'cx> Index<ImportId<'cx>> for CtxtS<'cx> {
     type Output = StoredImport<'cx>;
     fn index(&self, id: ImportId<'cx>) -> &StoredImport<'cx> {
-        &self.imports[id.0]
+        self.imports.get(id.0)
     }
 }
 
@@ -169,10 +327,16 @@ impl<'cx> Ctxt<'cx> {
             right_imports,
             selected: OnceCell::new(),
         };
-        let id = self.0.import_alternatives.len();
-        self.0.import_alternatives.push(Box::new(stored));
+        let id = self.0.import_alternatives.push(stored);
         ImportAlternativeId(id, PhantomData)
     }
+    /// All the import alternatives stored so far.
+    pub fn import_alternatives(
+        self,
+    ) -> impl Iterator<Item = ImportAlternativeId<'cx>> {
+        (0..self.0.import_alternatives.len())
+            .map(|id| ImportAlternativeId(id, PhantomData))
+    }
 }
 impl<'cx> Index<ImportAlternativeId<'cx>> for CtxtS<'cx> {
     type Output = StoredImportAlternative<'cx>;
@@ -180,7 +344,21 @@ impl<'cx> Index<ImportAlternativeId<'cx>> for CtxtS<'cx> {
         &self,
         id: ImportAlternativeId<'cx>,
     ) -> &StoredImportAlternative<'cx> {
-        &self.import_alternatives[id.0]
+        self.import_alternatives.get(id.0)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////////////////////////////
+// Roots
+
+impl<'cx> Ctxt<'cx> {
+    /// Records the location of an expression that was resolved without being imported.
+    pub(crate) fn push_root(self, location: ImportLocation) {
+        self.0.roots.push(location);
+    }
+    /// The locations of the expressions that were resolved without being imported, in order.
+    pub(crate) fn roots(self) -> impl Iterator<Item = &'cx ImportLocation> {
+        (0..self.0.roots.len()).map(move |id| self.0.roots.get(id))
     }
 }
 
@@ -198,14 +376,34 @@ impl<'cx> Ctxt<'cx> {
         self,
         res: StoredImportResult<'cx>,
     ) -> ImportResultId<'cx> {
-        let id = self.0.import_results.len();
-        self.0.import_results.push(Box::new(res));
+        let id = self.0.import_results.push(res);
         ImportResultId(id, PhantomData)
     }
 }
 impl<'cx> Index<ImportResultId<'cx>> for CtxtS<'cx> {
     type Output = StoredImportResult<'cx>;
     fn index(&self, id: ImportResultId<'cx>) -> &StoredImportResult<'cx> {
-        &self.import_results[id.0]
+        self.import_results.get(id.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Parsed;
+
+    /// Stores values that borrow the context inside it, so that dropping the context drops them.
+    #[test]
+    fn drop_values_stored_in_context() {
+        let n = Ctxt::with_new(|cx| {
+            let src = "let f = λ(x : Natural) → x + 1 in missing ? f 1";
+            let parsed = Parsed::parse_str(src).unwrap();
+            let typed = parsed.resolve(cx).unwrap().typecheck(cx).unwrap();
+            let id = cx.push_import_result(typed.clone());
+            let nf = cx[id].normalize(cx).to_expr(cx).to_string();
+            drop(typed);
+            nf
+        });
+        assert_eq!(n, "2");
     }
 }
@@ -0,0 +1,530 @@
+//! Semantic differences between Dhall expressions, like the `dhall diff` command of the reference
+//! implementation.
+//!
+//! [`diff`] compares two normalized expressions: records and record types by field, union types
+//! by alternative, union values by constructor, lists by index, and functions up to the names of
+//! their variables. The result is a tree of [`Diff`]s, which can be flattened into a list of
+//! changes with [`Diff::changes`], or rendered like a Dhall expression where the unchanged parts
+//! are elided:
+//!
+//! ```
+//! # use dhall::{Ctxt, Parsed};
+//! # fn main() -> Result<(), dhall::error::Error> {
+//! Ctxt::with_new(|cx| {
+//!     let normalize = |src: &str| -> Result<_, dhall::error::Error> {
+//!         Ok(Parsed::parse_str(src)?.resolve(cx)?.typecheck(cx)?.normalize(cx))
+//!     };
+//!     let old = normalize("{ port = 80, host = \"localhost\" }")?;
+//!     let new = normalize("{ port = 8000 + 80, host = \"localhost\" }")?;
+//!     let diff = dhall::diff::diff(cx, &old, &new);
+//!     assert_eq!(diff.to_string(), "{ port = - 80\n         + 8080\n, …\n}");
+//!     Ok(())
+//! })
+//! # }
+//! ```
+//!
+//! [`diff`]: fn.diff.html
+//! [`Diff`]: enum.Diff.html
+//! [`Diff::changes`]: enum.Diff.html#method.changes
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::operations::OpKind;
+use crate::syntax::visitor::{self, Fold, Scope};
+use crate::syntax::{Expr, ExprKind, Label, V};
+use crate::{Ctxt, Normalized};
+
+/// The difference between two expressions.
+#[derive(Debug, Clone)]
+pub enum Diff {
+    /// The expressions are equal, up to the names of bound variables.
+    Same,
+    /// The expressions have nothing in common.
+    Replaced(Expr, Expr),
+    /// The expressions have the same shape, and differ in some of their parts.
+    Changed(Shape, Vec<(PathSegment, Diff)>),
+    /// A part that is only in the new expression: a field of a record, an element of a list, or
+    /// an alternative of a union type, with its type if it has one.
+    Added(Option<Expr>),
+    /// A part that is only in the old expression.
+    Removed(Option<Expr>),
+}
+
+/// The shape of two expressions that are compared part by part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shape {
+    RecordLit,
+    RecordType,
+    UnionType,
+    List,
+    Some,
+    /// A function application
+    App,
+    /// A function, with the name of its variable in the new expression.
+    Lam(Label),
+    /// A function type, with the name of its variable in the new expression.
+    Pi(Label),
+    /// A value of a union type, with its constructor
+    UnionLit(Label),
+}
+
+/// A step in the path from an expression to one of its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A field of a record or of a record type
+    Field(Label),
+    /// An alternative of a union type, or the argument of a union value
+    Alternative(Label),
+    /// The union type of a union value
+    UnionType,
+    /// An element of a list
+    Index(usize),
+    /// The value inside a `Some`
+    Some,
+    /// The function of a function application
+    Function,
+    /// The argument of a function application
+    Argument,
+    /// The type of the variable of a function or function type
+    Input,
+    /// The body of a function or function type
+    Output,
+}
+
+/// Compare two normalized expressions.
+pub fn diff<'cx>(
+    cx: Ctxt<'cx>,
+    old: &Normalized<'cx>,
+    new: &Normalized<'cx>,
+) -> Diff {
+    let (old, new) = (old.to_expr(cx), new.to_expr(cx));
+    let (old_alpha, new_alpha) = (alpha_normalize(&old), alpha_normalize(&new));
+    diff_exprs(Node::new(&old, &old_alpha), Node::new(&new, &new_alpha))
+}
+
+/// Replaces the names of bound variables with `_`, so that expressions that only differ in those
+/// names compare equal.
+fn alpha_normalize(expr: &Expr) -> Expr {
+    struct Alpha;
+    impl Fold for Alpha {
+        fn fold_expr(&mut self, expr: Expr, scope: &mut Scope) -> Expr {
+            if let ExprKind::Var(var) = expr.kind() {
+                if let Some(idx) = scope.lookup(var) {
+                    let var = V("_".into(), idx);
+                    return Expr::new(ExprKind::Var(var), expr.span());
+                }
+            }
+            let mut expr = visitor::walk_fold(self, expr, scope);
+            match expr.kind_mut() {
+                ExprKind::Lam(l, _, _)
+                | ExprKind::Pi(l, _, _)
+                | ExprKind::Let(l, _, _, _) => *l = "_".into(),
+                _ => {}
+            }
+            expr
+        }
+    }
+    Alpha.fold_expr(expr.clone(), &mut Scope::new())
+}
+
+/// A subexpression, along with the same subexpression of the alpha-normalized expression. The
+/// latter is what gets compared, and the former is what gets shown.
+#[derive(Clone, Copy)]
+struct Node<'a> {
+    expr: &'a Expr,
+    alpha: &'a Expr,
+}
+
+impl<'a> Node<'a> {
+    fn new(expr: &'a Expr, alpha: &'a Expr) -> Self {
+        Node { expr, alpha }
+    }
+    fn kind(self) -> &'a ExprKind<Expr> {
+        self.expr.kind()
+    }
+    /// The subexpressions, in the order of `visit_each`. Alpha-normalization only renames
+    /// variables, so both expressions have the same subexpressions.
+    fn subexprs(self) -> Vec<Node<'a>> {
+        let mut exprs = Vec::new();
+        visitor::visit_each(self.expr.kind(), |_, e| exprs.push(e));
+        let mut alphas = Vec::new();
+        visitor::visit_each(self.alpha.kind(), |_, e| alphas.push(e));
+        exprs
+            .into_iter()
+            .zip(alphas)
+            .map(|(expr, alpha)| Node::new(expr, alpha))
+            .collect()
+    }
+    /// The union type, the constructor and the argument of a union value like `<A | B>.A x`.
+    fn as_union_value(self) -> Option<(Node<'a>, &'a Label, Node<'a>)> {
+        if let ExprKind::Op(OpKind::App(f, _)) = self.kind() {
+            if let ExprKind::Op(OpKind::Field(t, l)) = f.kind() {
+                if let ExprKind::UnionType(_) = t.kind() {
+                    let parts = self.subexprs();
+                    return Some((parts[0].subexprs()[0], l, parts[1]));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Compare two maps entry by entry.
+fn diff_maps(
+    old: &BTreeMap<Label, Option<Node<'_>>>,
+    new: &BTreeMap<Label, Option<Node<'_>>>,
+    segment: impl Fn(Label) -> PathSegment,
+) -> Vec<(PathSegment, Diff)> {
+    let mut labels: Vec<&Label> = old.keys().chain(new.keys()).collect();
+    labels.sort();
+    labels.dedup();
+    let expr = |x: &Option<Node>| x.map(|x| x.expr.clone());
+    let mut parts = Vec::new();
+    for label in labels {
+        let seg = || segment(label.clone());
+        match (old.get(label), new.get(label)) {
+            (Some(Some(x)), Some(Some(y))) => {
+                parts.push((seg(), diff_exprs(*x, *y)))
+            }
+            (Some(None), Some(None)) => parts.push((seg(), Diff::Same)),
+            (Some(x), Some(y)) => {
+                // An alternative of a union type gained or lost its type.
+                parts.push((seg(), Diff::Removed(expr(x))));
+                parts.push((seg(), Diff::Added(expr(y))));
+            }
+            (Some(x), None) => parts.push((seg(), Diff::Removed(expr(x)))),
+            (None, Some(y)) => parts.push((seg(), Diff::Added(expr(y)))),
+            (None, None) => unreachable!(),
+        }
+    }
+    parts
+}
+
+/// The fields of a record or the alternatives of a union type, with their values or types.
+fn entries<'a, T>(
+    map: impl Iterator<Item = (&'a Label, Option<T>)>,
+    node: Node<'a>,
+) -> BTreeMap<Label, Option<Node<'a>>> {
+    let mut subexprs = node.subexprs().into_iter();
+    map.map(|(k, v)| (k.clone(), v.and_then(|_| subexprs.next())))
+        .collect()
+}
+
+fn diff_exprs(old: Node<'_>, new: Node<'_>) -> Diff {
+    use ExprKind::*;
+    if old.alpha == new.alpha {
+        return Diff::Same;
+    }
+    if let (Some((t, x, e)), Some((u, y, f))) =
+        (old.as_union_value(), new.as_union_value())
+    {
+        if x != y {
+            return Diff::Replaced(old.expr.clone(), new.expr.clone());
+        }
+        let parts = vec![
+            (PathSegment::UnionType, diff_exprs(t, u)),
+            (PathSegment::Alternative(y.clone()), diff_exprs(e, f)),
+        ];
+        return Diff::Changed(Shape::UnionLit(y.clone()), parts);
+    }
+    let (shape, parts) = match (old.kind(), new.kind()) {
+        (RecordLit(x), RecordLit(y)) | (RecordType(x), RecordType(y)) => {
+            let shape = match old.kind() {
+                RecordLit(_) => Shape::RecordLit,
+                _ => Shape::RecordType,
+            };
+            let x = entries(x.iter().map(|(k, v)| (k, Some(v))), old);
+            let y = entries(y.iter().map(|(k, v)| (k, Some(v))), new);
+            (shape, diff_maps(&x, &y, PathSegment::Field))
+        }
+        (UnionType(x), UnionType(y)) => {
+            let x = entries(x.iter().map(|(k, v)| (k, v.as_ref())), old);
+            let y = entries(y.iter().map(|(k, v)| (k, v.as_ref())), new);
+            (
+                Shape::UnionType,
+                diff_maps(&x, &y, PathSegment::Alternative),
+            )
+        }
+        (NEListLit(_), NEListLit(_)) => {
+            let (xs, ys) = (old.subexprs(), new.subexprs());
+            let mut parts = Vec::new();
+            for i in 0..xs.len().max(ys.len()) {
+                let part = match (xs.get(i), ys.get(i)) {
+                    (Some(x), Some(y)) => diff_exprs(*x, *y),
+                    (Some(x), None) => Diff::Removed(Some(x.expr.clone())),
+                    (None, Some(y)) => Diff::Added(Some(y.expr.clone())),
+                    (None, None) => unreachable!(),
+                };
+                parts.push((PathSegment::Index(i), part));
+            }
+            (Shape::List, parts)
+        }
+        (SomeLit(_), SomeLit(_)) => {
+            let (x, y) = (old.subexprs()[0], new.subexprs()[0]);
+            (Shape::Some, vec![(PathSegment::Some, diff_exprs(x, y))])
+        }
+        (Op(OpKind::App(..)), Op(OpKind::App(..))) => {
+            let (x, y) = (old.subexprs(), new.subexprs());
+            let parts = vec![
+                (PathSegment::Function, diff_exprs(x[0], y[0])),
+                (PathSegment::Argument, diff_exprs(x[1], y[1])),
+            ];
+            (Shape::App, parts)
+        }
+        (Lam(..), Lam(y, _, _)) | (Pi(..), Pi(y, _, _)) => {
+            let shape = match old.kind() {
+                Lam(..) => Shape::Lam(y.clone()),
+                _ => Shape::Pi(y.clone()),
+            };
+            let (x, y) = (old.subexprs(), new.subexprs());
+            let parts = vec![
+                (PathSegment::Input, diff_exprs(x[0], y[0])),
+                (PathSegment::Output, diff_exprs(x[1], y[1])),
+            ];
+            (shape, parts)
+        }
+        _ => return Diff::Replaced(old.expr.clone(), new.expr.clone()),
+    };
+    Diff::Changed(shape, parts)
+}
+
+impl Diff {
+    pub fn is_same(&self) -> bool {
+        matches!(self, Diff::Same)
+    }
+
+    /// The parts that were added, removed or replaced, with the path to them.
+    pub fn changes(&self) -> Vec<(Vec<PathSegment>, &Diff)> {
+        let mut changes = Vec::new();
+        self.collect_changes(&mut Vec::new(), &mut changes);
+        changes
+    }
+    fn collect_changes<'a>(
+        &'a self,
+        path: &mut Vec<PathSegment>,
+        changes: &mut Vec<(Vec<PathSegment>, &'a Diff)>,
+    ) {
+        match self {
+            Diff::Same => {}
+            Diff::Changed(_, parts) => {
+                for (segment, part) in parts {
+                    path.push(segment.clone());
+                    part.collect_changes(path, changes);
+                    path.pop();
+                }
+            }
+            _ => changes.push((path.clone(), self)),
+        }
+    }
+
+    /// Render the difference like a Dhall expression, where the unchanged parts are replaced with
+    /// `…`, and the removed and added parts are prefixed with `-` and `+`. With `color`, they are
+    /// also colored red and green with ANSI escape codes.
+    pub fn render(&self, color: bool) -> String {
+        let lines: Vec<String> = render(self)
+            .into_iter()
+            .map(|line| {
+                line.into_iter()
+                    .map(|(style, text)| match style {
+                        Style::Removed if color => {
+                            format!("\x1b[31m{}\x1b[0m", text)
+                        }
+                        Style::Added if color => {
+                            format!("\x1b[32m{}\x1b[0m", text)
+                        }
+                        _ => text,
+                    })
+                    .collect()
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// Formats a path like `servers[2].port`.
+pub fn format_path(path: &[PathSegment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Field(l) | PathSegment::Alternative(l) => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(&V(l.clone(), 0).to_string());
+            }
+            PathSegment::Index(i) => out.push_str(&format!("[{}]", i)),
+            _ => out.push_str(&format!("({})", segment)),
+        }
+    }
+    out
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathSegment::Field(l) | PathSegment::Alternative(l) => {
+                V(l.clone(), 0).fmt(f)
+            }
+            PathSegment::Index(i) => write!(f, "[{}]", i),
+            PathSegment::Some => f.write_str("Some"),
+            PathSegment::UnionType => f.write_str("type"),
+            PathSegment::Function => f.write_str("function"),
+            PathSegment::Argument => f.write_str("argument"),
+            PathSegment::Input => f.write_str("input"),
+            PathSegment::Output => f.write_str("output"),
+        }
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.render(false))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Removed,
+    Added,
+}
+
+/// Rendered lines, made of pieces of text with a style.
+type Block = Vec<Vec<(Style, String)>>;
+
+fn text(style: Style, s: impl Into<String>) -> Block {
+    vec![vec![(style, s.into())]]
+}
+
+fn width(line: &[(Style, String)]) -> usize {
+    line.iter().map(|(_, s)| s.chars().count()).sum()
+}
+
+/// Puts `y` after the end of `x`, indenting the following lines of `y` to align with its first.
+fn hcat(mut x: Block, y: Block) -> Block {
+    let last = x.pop().unwrap_or_default();
+    let indent = " ".repeat(width(&last));
+    let mut y = y.into_iter();
+    let mut first = last;
+    first.extend(y.next().unwrap_or_default());
+    x.push(first);
+    for mut line in y {
+        line.insert(0, (Style::Plain, indent.clone()));
+        x.push(line);
+    }
+    x
+}
+
+/// Lays out items one per line, like `{ a = 1\n, b = 2\n}`.
+fn render_items(
+    open: &str,
+    sep: &str,
+    close: &str,
+    items: Vec<Block>,
+) -> Block {
+    let mut out = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let prefix = if i == 0 { open } else { sep };
+        out.extend(hcat(text(Style::Plain, prefix), item));
+    }
+    out.push(vec![(Style::Plain, close.to_owned())]);
+    out
+}
+
+fn render(diff: &Diff) -> Block {
+    match diff {
+        Diff::Same => text(Style::Plain, "…"),
+        Diff::Replaced(x, y) => {
+            let mut out = text(Style::Removed, format!("- {}", x));
+            out.extend(text(Style::Added, format!("+ {}", y)));
+            out
+        }
+        // Only inside `Changed`.
+        Diff::Added(_) | Diff::Removed(_) => unreachable!(),
+        Diff::Changed(shape, parts) => render_changed(shape, parts),
+    }
+}
+
+fn render_changed(shape: &Shape, parts: &[(PathSegment, Diff)]) -> Block {
+    let part = |i: usize| render(&parts[i].1);
+    match shape {
+        Shape::RecordLit | Shape::RecordType | Shape::UnionType => {
+            let (open, sep, close, assign) = match shape {
+                Shape::RecordLit => ("{ ", ", ", "}", " = "),
+                Shape::RecordType => ("{ ", ", ", "}", " : "),
+                _ => ("< ", "| ", ">", " : "),
+            };
+            let mut items = Vec::new();
+            let mut any_same = false;
+            for (segment, part) in parts {
+                let entry = |x: &Option<Expr>| match x {
+                    Some(x) => format!("{}{}{}", segment, assign, x),
+                    None => segment.to_string(),
+                };
+                items.push(match part {
+                    Diff::Same => {
+                        any_same = true;
+                        continue;
+                    }
+                    Diff::Added(x) => {
+                        text(Style::Added, format!("+ {}", entry(x)))
+                    }
+                    Diff::Removed(x) => {
+                        text(Style::Removed, format!("- {}", entry(x)))
+                    }
+                    _ => hcat(
+                        text(Style::Plain, format!("{}{}", segment, assign)),
+                        render(part),
+                    ),
+                });
+            }
+            if any_same {
+                items.push(text(Style::Plain, "…"));
+            }
+            render_items(open, sep, close, items)
+        }
+        Shape::List => {
+            let mut items: Vec<Block> = Vec::new();
+            let mut previous_same = false;
+            for (_, part) in parts {
+                // Collapse the runs of unchanged elements.
+                if part.is_same() && previous_same {
+                    continue;
+                }
+                previous_same = part.is_same();
+                items.push(match part {
+                    Diff::Added(Some(x)) => {
+                        text(Style::Added, format!("+ {}", x))
+                    }
+                    Diff::Removed(Some(x)) => {
+                        text(Style::Removed, format!("- {}", x))
+                    }
+                    _ => render(part),
+                });
+            }
+            render_items("[ ", ", ", "]", items)
+        }
+        Shape::Some => hcat(text(Style::Plain, "Some "), part(0)),
+        Shape::UnionLit(l) => {
+            let typ = match &parts[0].1 {
+                Diff::Same => text(Style::Plain, "<…>"),
+                typ => render(typ),
+            };
+            let constructor = format!(".{} ", V(l.clone(), 0));
+            hcat(hcat(typ, text(Style::Plain, constructor)), part(1))
+        }
+        Shape::App => hcat(hcat(part(0), text(Style::Plain, " ")), part(1)),
+        Shape::Lam(l) | Shape::Pi(l) => {
+            let binder = if let Shape::Lam(_) = shape {
+                "λ"
+            } else {
+                "∀"
+            };
+            let open = format!("{}({} : ", binder, V(l.clone(), 0));
+            let input = hcat(text(Style::Plain, open), part(0));
+            let arrow = hcat(input, text(Style::Plain, ") → "));
+            hcat(arrow, part(1))
+        }
+    }
+}
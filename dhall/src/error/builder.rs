@@ -63,6 +63,13 @@ impl ErrorBuilder {
         }
     }
 
+    /// Like `new`, for a warning.
+    pub fn new_warning(message: impl ToString) -> Self {
+        let mut builder = ErrorBuilder::new(message);
+        builder.title.annotation_type = AnnotationType::Warning;
+        builder
+    }
+
     pub fn span_annot(
         &mut self,
         span: Span,
@@ -100,6 +107,13 @@ impl ErrorBuilder {
     ) -> &mut Self {
         self.span_annot(span, message, AnnotationType::Error)
     }
+    pub fn span_warn(
+        &mut self,
+        span: Span,
+        message: impl ToString,
+    ) -> &mut Self {
+        self.span_annot(span, message, AnnotationType::Warning)
+    }
     pub fn span_help(
         &mut self,
         span: Span,
@@ -10,7 +10,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub struct Error {
-    kind: ErrorKind,
+    /// Boxed to keep `Result<T, Error>` small.
+    kind: Box<ErrorKind>,
 }
 
 #[derive(Debug)]
@@ -23,6 +24,7 @@ pub enum ErrorKind {
     Resolve(ImportError),
     Typecheck(TypeError),
     Cache(CacheError),
+    Limit(LimitError),
 }
 
 #[derive(Debug)]
@@ -46,6 +48,23 @@ pub enum EncodeError {
     CBORError(serde_cbor::error::Error),
 }
 
+/// A limit set with `Limits` was exceeded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    /// Evaluation took more than this many steps.
+    Steps(u64),
+    /// Evaluation built a list longer than this.
+    ListLength(usize),
+    /// Evaluation built a text longer than this many bytes.
+    TextSize(usize),
+    /// More than this many imports were fetched.
+    Imports(usize),
+    /// Imports were nested deeper than this.
+    ImportDepth(usize),
+    Timeout,
+    Cancelled,
+}
+
 /// A structured type error
 #[derive(Debug)]
 pub struct TypeError {
@@ -67,7 +86,9 @@ pub enum CacheError {
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Self {
-        Error { kind }
+        Error {
+            kind: Box::new(kind),
+        }
     }
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
@@ -103,9 +124,34 @@ impl std::fmt::Display for EncodeError {
 
 impl std::error::Error for EncodeError {}
 
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use LimitError::*;
+        match self {
+            Steps(n) => {
+                write!(f, "Evaluation exceeded the limit of {} steps", n)
+            }
+            ListLength(n) => {
+                write!(f, "Evaluation built a list longer than {} elements", n)
+            }
+            TextSize(n) => {
+                write!(f, "Evaluation built a text longer than {} bytes", n)
+            }
+            Imports(n) => write!(f, "Exceeded the limit of {} imports", n),
+            ImportDepth(n) => {
+                write!(f, "Imports are nested more than {} levels deep", n)
+            }
+            Timeout => write!(f, "Evaluation timed out"),
+            Cancelled => write!(f, "Evaluation was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match &self.kind {
+        match self.kind() {
             ErrorKind::IO(err) => write!(f, "{}", err),
             ErrorKind::Parse(err) => write!(f, "{}", err),
             ErrorKind::Decode(err) => write!(f, "{:?}", err),
@@ -113,6 +159,7 @@ impl std::fmt::Display for Error {
             ErrorKind::Resolve(err) => write!(f, "{:?}", err),
             ErrorKind::Typecheck(err) => write!(f, "{}", err),
             ErrorKind::Cache(err) => write!(f, "{:?}", err),
+            ErrorKind::Limit(err) => write!(f, "{}", err),
         }
     }
 }
@@ -163,3 +210,8 @@ impl From<CacheError> for Error {
         ErrorKind::Cache(err).into()
     }
 }
+impl From<LimitError> for Error {
+    fn from(err: LimitError) -> Error {
+        ErrorKind::Limit(err).into()
+    }
+}
@@ -12,9 +12,14 @@
 
 pub mod builtins;
 pub mod ctxt;
+pub mod diff;
 pub mod error;
+pub mod limits;
+pub mod lint;
 pub mod operations;
+pub mod program;
 pub mod semantics;
+pub mod sync;
 pub mod syntax;
 pub mod utils;
 
@@ -25,10 +30,15 @@ use crate::error::{Error, TypeError};
 use crate::semantics::parse;
 use crate::semantics::resolve;
 use crate::semantics::resolve::ImportLocation;
-use crate::semantics::{typecheck, typecheck_with, Hir, Nir, Tir, Type};
+use crate::semantics::{
+    typecheck, typecheck_indexed, typecheck_with, Hir, Nir, Tir, Type,
+};
 use crate::syntax::Expr;
 
 pub use ctxt::*;
+pub use limits::{CancellationToken, Limits};
+pub use program::Program;
+pub use semantics::{ImportGraph, Session, TypeIndex};
 
 #[derive(Debug, Clone)]
 pub struct Parsed(Expr, ImportLocation);
@@ -71,6 +81,9 @@ impl Parsed {
     pub fn parse_remote(url: Url) -> Result<Parsed, Error> {
         parse::parse_remote(url)
     }
+    pub async fn parse_remote_async(url: Url) -> Result<Parsed, Error> {
+        parse::parse_remote_async(url).await
+    }
     pub fn parse_str(s: &str) -> Result<Parsed, Error> {
         parse::parse_str(s)
     }
@@ -85,6 +98,17 @@ impl Parsed {
     pub fn resolve<'cx>(self, cx: Ctxt<'cx>) -> Result<Resolved<'cx>, Error> {
         resolve::resolve(cx, self)
     }
+    /// Like `resolve`, but fetches imports asynchronously. Imports found in a same file are fetched
+    /// concurrently.
+    ///
+    /// Remote imports are downloaded with the async `reqwest` client, which requires a Tokio
+    /// runtime.
+    pub async fn resolve_async<'cx>(
+        self,
+        cx: Ctxt<'cx>,
+    ) -> Result<Resolved<'cx>, Error> {
+        resolve::resolve_async(cx, self).await
+    }
     pub fn skip_resolve<'cx>(
         self,
         cx: Ctxt<'cx>,
@@ -92,6 +116,11 @@ impl Parsed {
         resolve::skip_resolve(cx, self)
     }
 
+    /// Check the expression with the linter. See `lint`.
+    pub fn lint(&self) -> Vec<lint::Diagnostic> {
+        lint::lint(&self.0)
+    }
+
     /// Converts a value back to the corresponding AST expression.
     pub fn to_expr(&self) -> Expr {
         self.0.clone()
@@ -99,15 +128,41 @@ impl Parsed {
 }
 
 impl<'cx> Resolved<'cx> {
-    pub fn typecheck(&self, cx: Ctxt<'cx>) -> Result<Typed<'cx>, TypeError> {
-        Ok(Typed::from_tir(typecheck(cx, &self.0)?))
+    /// Typechecks the expression. If a limit of the context is exceeded, this returns an
+    /// `ErrorKind::Limit` error, even if the typechecker found errors.
+    pub fn typecheck(&self, cx: Ctxt<'cx>) -> Result<Typed<'cx>, Error> {
+        Ok(Typed::from_tir(check_limits(cx, typecheck(cx, &self.0))?))
+    }
+    /// Typechecks the expression and returns the types of its subexpressions and bound variables,
+    /// indexed by their location in the source.
+    ///
+    /// ```
+    /// # use dhall::{Ctxt, Parsed};
+    /// # fn main() -> Result<(), dhall::error::Error> {
+    /// Ctxt::with_new(|cx| {
+    ///     let src = "λ(x : Natural) → [x, x + 1]";
+    ///     let index = Parsed::parse_str(src)?.resolve(cx)?.type_index(cx)?;
+    ///     let at = |s: &str| index.at(src.find(s).unwrap()).unwrap();
+    ///     assert_eq!(at("x :").to_expr().to_string(), "Natural");
+    ///     assert_eq!(at("1").to_expr().to_string(), "Natural");
+    ///     assert_eq!(at("[").to_expr().to_string(), "List Natural");
+    ///     Ok(())
+    /// })
+    /// # }
+    /// ```
+    pub fn type_index(&self, cx: Ctxt<'cx>) -> Result<TypeIndex<'cx>, Error> {
+        Ok(check_limits(cx, typecheck_indexed(cx, &self.0))?.1)
     }
+    /// Typechecks the expression against the given type, like `typecheck`.
     pub fn typecheck_with(
         self,
         cx: Ctxt<'cx>,
         ty: &Hir<'cx>,
-    ) -> Result<Typed<'cx>, TypeError> {
-        Ok(Typed::from_tir(typecheck_with(cx, &self.0, ty)?))
+    ) -> Result<Typed<'cx>, Error> {
+        Ok(Typed::from_tir(check_limits(
+            cx,
+            typecheck_with(cx, &self.0, ty),
+        )?))
     }
     /// Converts a value back to the corresponding AST expression.
     pub fn to_expr(&self, cx: Ctxt<'cx>) -> Expr {
@@ -123,9 +178,25 @@ impl<'cx> Typed<'cx> {
         }
     }
     /// Reduce an expression to its normal form, performing beta reduction
+    ///
+    /// The normal form is computed lazily, as it gets inspected. If the context has limits, use
+    /// `normalize_checked` instead: evaluation stops making progress once a limit is exceeded, so
+    /// this could return an incomplete value.
     pub fn normalize(&self, cx: Ctxt<'cx>) -> Normalized<'cx> {
         Normalized(self.hir.eval_closed_expr(cx))
     }
+    /// Like `normalize`, but computes the whole normal form right away, and returns an
+    /// `ErrorKind::Limit` error if that exceeds the limits of the context.
+    pub fn normalize_checked(
+        &self,
+        cx: Ctxt<'cx>,
+    ) -> Result<Normalized<'cx>, Error> {
+        let nf = self.normalize(cx);
+        // Going through the whole value forces its evaluation.
+        nf.to_hir();
+        cx.check_limits()?;
+        Ok(nf)
+    }
 
     /// Converts a value back to the corresponding AST expression.
     fn to_expr(&self, cx: Ctxt<'cx>) -> Expr {
@@ -161,6 +232,13 @@ impl<'cx> Normalized<'cx> {
     }
 }
 
+/// Typechecking evaluates types, and a limit exceeded in the process can leave them incomplete and
+/// cause spurious type errors. The exceeded limit is reported instead.
+fn check_limits<T>(cx: Ctxt, res: Result<T, TypeError>) -> Result<T, Error> {
+    cx.check_limits()?;
+    Ok(res?)
+}
+
 macro_rules! derive_traits_for_wrapper_struct {
     ($ty:ident) => {
         impl std::cmp::PartialEq for $ty {
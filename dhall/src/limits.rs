@@ -0,0 +1,190 @@
+//! Limits on the resources that resolving and evaluating an expression can use.
+//!
+//! Dhall programs always terminate, but they can take arbitrarily long to do so: a config
+//! containing `Natural/fold 1000000000 ...` is perfectly valid. When reading untrusted input, set
+//! some `Limits` on the context with `Ctxt::with_limits`.
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::LimitError;
+use crate::sync::OnceCell;
+
+/// Limits on the work done in a `Ctxt`. Every limit is off by default.
+///
+/// When evaluation exceeds a limit it stops making progress, and the operation that was running
+/// returns an `ErrorKind::Limit` error. Normalization is lazy, so use `Typed::normalize_checked`
+/// to get that error; values computed otherwise may be incomplete, which `Ctxt::check_limits`
+/// tells.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use dhall::error::{ErrorKind, LimitError};
+/// use dhall::{Ctxt, Limits, Parsed};
+///
+/// let limits = Limits {
+///     max_steps: Some(10_000),
+///     timeout: Some(Duration::from_secs(5)),
+///     ..Limits::default()
+/// };
+/// let src = "Natural/fold 1000000000 Natural (λ(x : Natural) → x + 1) 0";
+/// let err = Ctxt::with_limits(limits, |cx| -> Result<(), dhall::error::Error> {
+///     let typed = Parsed::parse_str(src)?.resolve(cx)?.typecheck(cx)?;
+///     typed.normalize_checked(cx)?;
+///     Ok(())
+/// })
+/// .unwrap_err();
+/// assert!(matches!(err.kind(), ErrorKind::Limit(LimitError::Steps(10_000))));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Maximum number of evaluation steps. A step is the application of a function or builtin to
+    /// an argument.
+    pub max_steps: Option<u64>,
+    /// Maximum number of elements of the lists built during evaluation.
+    pub max_list_length: Option<usize>,
+    /// Maximum size in bytes of the texts built during evaluation.
+    pub max_text_size: Option<usize>,
+    /// Maximum number of files, URLs and environment variables to import. With the `sync`
+    /// feature, an import shared by sibling imports may be fetched, and counted, more than once.
+    pub max_imports: Option<usize>,
+    /// Maximum length of a chain of imports, i.e. of a file importing a file importing a file...
+    pub max_import_depth: Option<usize>,
+    /// Maximum time to spend, counted from the creation of the context.
+    pub timeout: Option<Duration>,
+    /// Stops the work when cancelled, e.g. from another thread.
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// A flag that cancels the work done in the contexts that use it.
+///
+/// Clones share the same flag, so a clone can be kept around to cancel evaluation from elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How often to look at the clock and the cancellation token, in steps. A power of two.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Tracks the resources used in a context.
+pub(crate) struct LimitsState {
+    limits: Limits,
+    deadline: Option<Instant>,
+    steps: AtomicU64,
+    imports: AtomicUsize,
+    /// The first limit that was exceeded.
+    exceeded: OnceCell<LimitError>,
+}
+
+impl LimitsState {
+    pub(crate) fn new(limits: Limits) -> Self {
+        LimitsState {
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            limits,
+            steps: AtomicU64::new(0),
+            imports: AtomicUsize::new(0),
+            exceeded: OnceCell::new(),
+        }
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+    pub(crate) fn exceeded(&self) -> Option<&LimitError> {
+        self.exceeded.get()
+    }
+    fn exceed(&self, err: LimitError) {
+        let _ = self.exceeded.set(err);
+    }
+
+    fn check_time(&self) {
+        if let Some(token) = &self.limits.cancellation {
+            if token.is_cancelled() {
+                self.exceed(LimitError::Cancelled);
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                self.exceed(LimitError::Timeout);
+            }
+        }
+    }
+
+    /// Counts an evaluation step. Returns whether evaluation may continue.
+    pub(crate) fn step(&self) -> bool {
+        if self.exceeded().is_some() {
+            return false;
+        }
+        let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max) = self.limits.max_steps {
+            if steps > max {
+                self.exceed(LimitError::Steps(max));
+            }
+        }
+        if steps & (CHECK_INTERVAL - 1) == 0 {
+            self.check_time();
+        }
+        self.exceeded().is_none()
+    }
+
+    /// Counts an import about to be fetched at the given depth.
+    pub(crate) fn import(&self, depth: usize) -> Result<(), LimitError> {
+        let imports = self.imports.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max) = self.limits.max_imports {
+            if imports > max {
+                self.exceed(LimitError::Imports(max));
+            }
+        }
+        if let Some(max) = self.limits.max_import_depth {
+            if depth > max {
+                self.exceed(LimitError::ImportDepth(max));
+            }
+        }
+        self.check_time();
+        match self.exceeded() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the length of a list built during evaluation. Returns whether evaluation may
+    /// continue.
+    pub(crate) fn list_length(&self, len: usize) -> bool {
+        if let Some(max) = self.limits.max_list_length {
+            if len > max {
+                self.exceed(LimitError::ListLength(max));
+            }
+        }
+        self.exceeded().is_none()
+    }
+
+    /// Checks the size of a text built during evaluation. Returns whether evaluation may
+    /// continue.
+    pub(crate) fn text_size(&self, size: usize) -> bool {
+        if let Some(max) = self.limits.max_text_size {
+            if size > max {
+                self.exceed(LimitError::TextSize(max));
+            }
+        }
+        self.exceeded().is_none()
+    }
+}
+
+impl Default for LimitsState {
+    fn default() -> Self {
+        LimitsState::new(Limits::default())
+    }
+}
@@ -0,0 +1,407 @@
+//! Lints for Dhall code, like the `dhall lint` command of the reference implementation.
+//!
+//! [`lint`] checks an expression and returns [`Diagnostic`]s. Most of them come with a [`Fix`],
+//! an edit of the source text that [`apply_fixes`] applies. Since they edit the source, fixes keep
+//! the comments and the formatting of the code around them.
+//!
+//! ```
+//! # fn main() -> Result<(), dhall::error::Error> {
+//! let src = "let x = 1 in let y = 2 in y";
+//! let diagnostics = dhall::Parsed::parse_str(src)?.lint();
+//! assert_eq!(diagnostics[0].lint, dhall::lint::Lint::UnusedLet);
+//! assert_eq!(dhall::lint::fix(src)?, "let y = 2 in y");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`lint`]: fn.lint.html
+//! [`Diagnostic`]: struct.Diagnostic.html
+//! [`Fix`]: struct.Fix.html
+//! [`apply_fixes`]: fn.apply_fixes.html
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+use crate::error::{Error, ErrorBuilder};
+use crate::operations::{BinOp, OpKind};
+use crate::syntax::visitor::{self, Scope, Visitor};
+use crate::syntax::{
+    is_label_char, skip_whitespace, Expr, ExprKind, Label, ParsedSpan, Span, V,
+};
+use crate::Parsed;
+
+/// The rules the linter checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A `let` binding whose variable is never used. Bindings of assertions are kept, since the
+    /// assertions are checked even if they are not used.
+    UnusedLet,
+    /// A use of `Optional/fold` or `Optional/build`, which have been removed from the language.
+    DeprecatedOptional,
+    /// A `let` whose body is another `let`, e.g. `let x = 1 in let y = 2 in x + y`, which can be
+    /// written `let x = 1 let y = 2 in x + y`.
+    NestedLet,
+    /// An assertion that is not of the form `assert : a === b`, or an equivalence bound with
+    /// `let` that is probably meant to be an assertion.
+    MalformedAssert,
+}
+
+/// A problem found by the linter.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub message: String,
+    /// The code the diagnostic is about.
+    pub span: Span,
+    /// An edit of the source that fixes the problem, if it can be fixed automatically.
+    pub fix: Option<Fix>,
+}
+
+/// An edit of the source text: the text in the byte range `range` is replaced with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Lint {
+    /// The name of the rule, e.g. `unused-let`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedLet => "unused-let",
+            Lint::DeprecatedOptional => "deprecated-optional",
+            Lint::NestedLet => "nested-let",
+            Lint::MalformedAssert => "malformed-assert",
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut builder = ErrorBuilder::new_warning(&self.message);
+        builder.span_warn(self.span.clone(), self.lint.name());
+        if self.fix.is_some() {
+            builder.help("this can be fixed automatically");
+        }
+        f.write_str(&builder.format())
+    }
+}
+
+/// Whether `var` refers to the variable bound right outside of the expression that `scope` is
+/// relative to.
+fn refers_to_enclosing(var: &V, label: &Label, scope: &Scope) -> bool {
+    let V(l, n) = var;
+    l == label && *n == scope.binders().iter().filter(|b| *b == l).count()
+}
+
+/// Whether `expr` uses the variable bound right outside of it.
+fn uses_var(expr: &Expr, label: &Label) -> bool {
+    any_var(expr, label, |n| n == 0)
+}
+
+/// Whether `expr` uses a variable bound further out than the one bound right outside of it, with
+/// the same name, e.g. `x@1`. Removing the binder would change what these refer to.
+fn uses_var_beyond(expr: &Expr, label: &Label) -> bool {
+    any_var(expr, label, |n| n > 0)
+}
+
+/// Whether `expr` contains a variable named `label` whose index, as seen from right outside of
+/// `expr`, satisfies `pred`.
+fn any_var(expr: &Expr, label: &Label, pred: fn(usize) -> bool) -> bool {
+    struct AnyVar<'a>(&'a Label, fn(usize) -> bool, bool);
+    impl Visitor for AnyVar<'_> {
+        fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+            match expr.kind() {
+                _ if self.2 => {}
+                ExprKind::Var(V(l, n)) if l == self.0 => {
+                    let inner =
+                        scope.binders().iter().filter(|b| *b == l).count();
+                    self.2 = *n >= inner && (self.1)(n - inner);
+                }
+                _ => visitor::walk(self, expr, scope),
+            }
+        }
+    }
+    let mut visitor = AnyVar(label, pred, false);
+    expr.visit(&mut visitor);
+    visitor.2
+}
+
+fn contains_assert(expr: &Expr) -> bool {
+    struct ContainsAssert(bool);
+    impl Visitor for ContainsAssert {
+        fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+            match expr.kind() {
+                ExprKind::Assert(_) => self.0 = true,
+                _ if self.0 => {}
+                _ => visitor::walk(self, expr, scope),
+            }
+        }
+    }
+    let mut visitor = ContainsAssert(false);
+    expr.visit(&mut visitor);
+    visitor.0
+}
+
+fn is_equivalence(expr: &Expr) -> bool {
+    matches!(
+        expr.kind(),
+        ExprKind::Op(OpKind::BinOp(BinOp::Equivalence, _, _))
+    )
+}
+
+fn parsed(span: Span) -> Option<ParsedSpan> {
+    match span {
+        Span::Parsed(span) => Some(span),
+        _ => None,
+    }
+}
+
+/// The definitions of the removed `Optional` builtins, in terms of the remaining ones.
+const OPTIONAL_FOLD: &str = "(λ(a : Type) → λ(o : Optional a) → \
+    λ(optional : Type) → λ(some : a → optional) → λ(none : optional) → \
+    merge { None = none, Some = some } o)";
+const OPTIONAL_BUILD: &str = "(λ(a : Type) → \
+    λ(build : ∀(optional : Type) → ∀(some : a → optional) → ∀(none : optional) → optional) → \
+    build (Optional a) (λ(x : a) → Some x) (None a))";
+
+/// The parts of the source of a `let` expression.
+struct LetSource {
+    /// Where the `let` keyword starts.
+    start: usize,
+    /// The `in` keyword, if the `let` ends with one.
+    in_keyword: Option<Range<usize>>,
+    /// Where the body starts.
+    body_start: usize,
+}
+
+impl LetSource {
+    fn new(span: &ParsedSpan, val: &Expr, body: &Expr) -> Option<Self> {
+        let val = parsed(val.span())?.range();
+        let body = parsed(body.span())?.range();
+        let input = span.input();
+        // Skip the parentheses around the value, if any.
+        let mut i = skip_whitespace(input, val.end);
+        while input[i..].starts_with(')') {
+            i = skip_whitespace(input, i + 1);
+        }
+        let in_keyword = if input[i..].starts_with("in")
+            && !input[i + 2..].starts_with(is_label_char)
+        {
+            Some(i..i + 2)
+        } else {
+            None
+        };
+        Some(LetSource {
+            start: span.range().start,
+            in_keyword,
+            body_start: body.start,
+        })
+    }
+}
+
+struct Linter {
+    diagnostics: Vec<Diagnostic>,
+    /// The start of the `let`s that continue the bindings of an enclosing `let`, as in
+    /// `let x = 1 let y = 2 in x + y`.
+    continued_lets: HashSet<usize>,
+}
+
+impl Linter {
+    fn push(
+        &mut self,
+        lint: Lint,
+        message: String,
+        span: Span,
+        fix: Option<Fix>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            lint,
+            message,
+            span,
+            fix,
+        })
+    }
+
+    fn lint_let(
+        &mut self,
+        expr: &Expr,
+        label: &Label,
+        annot: &Option<Expr>,
+        val: &Expr,
+        body: &Expr,
+    ) {
+        let span = parsed(expr.span());
+        let source = span
+            .as_ref()
+            .and_then(|span| LetSource::new(span, val, body));
+
+        if !uses_var(body, label) && !contains_assert(val) {
+            // Removing the binding would change what `x@1` etc. refer to in the body.
+            let removable =
+                source.as_ref().filter(|_| !uses_var_beyond(body, label));
+            let fix = removable.map(|source| {
+                let continued = self.continued_lets.contains(&source.start);
+                let end = match &source.in_keyword {
+                    // Keep the `in` of the enclosing `let`.
+                    Some(in_keyword) if continued => in_keyword.start,
+                    Some(in_keyword) => skip_whitespace(
+                        span.as_ref().unwrap().input(),
+                        in_keyword.end,
+                    ),
+                    None => source.body_start,
+                };
+                Fix {
+                    range: source.start..end,
+                    replacement: String::new(),
+                }
+            });
+            let binder_span = span
+                .as_ref()
+                .and_then(|span| span.binder(label))
+                .map(Span::Parsed)
+                .unwrap_or_else(|| expr.span());
+            self.push(
+                Lint::UnusedLet,
+                format!("unused `let` binding `{}`", label),
+                binder_span,
+                fix,
+            );
+        }
+
+        if let (Some(span), Some(source)) = (&span, &source) {
+            match (&source.in_keyword, body.kind()) {
+                (None, _) => {
+                    self.continued_lets.insert(source.body_start);
+                }
+                (Some(in_keyword), ExprKind::Let(..)) => {
+                    // Only when the body is not in parentheses.
+                    let fix = if skip_whitespace(span.input(), in_keyword.end)
+                        == source.body_start
+                    {
+                        Some(Fix {
+                            range: in_keyword.start..source.body_start,
+                            replacement: String::new(),
+                        })
+                    } else {
+                        None
+                    };
+                    self.push(
+                        Lint::NestedLet,
+                        "nested `let` can be merged with the enclosing one"
+                            .to_owned(),
+                        Span::Parsed(span.with_range(in_keyword.clone())),
+                        fix,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if annot.is_none() && is_equivalence(val) {
+            let fix = parsed(val.span()).map(|val| Fix {
+                range: val.range().start..val.range().start,
+                replacement: "assert : ".to_owned(),
+            });
+            self.push(
+                Lint::MalformedAssert,
+                format!(
+                    "`{}` is bound to an equivalence, which is not checked; \
+                     use `assert : ...` to check it",
+                    label
+                ),
+                val.span(),
+                fix,
+            );
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+        match expr.kind() {
+            ExprKind::Let(label, annot, val, body) => {
+                self.lint_let(expr, label, annot, val, body)
+            }
+            ExprKind::Assert(t) if !is_equivalence(t) => self.push(
+                Lint::MalformedAssert,
+                "an assertion must be of the form `assert : a === b`"
+                    .to_owned(),
+                expr.span(),
+                None,
+            ),
+            ExprKind::Var(var) => {
+                let replacement = match String::from(&var.0).as_str() {
+                    "Optional/fold" => OPTIONAL_FOLD,
+                    "Optional/build" => OPTIONAL_BUILD,
+                    _ => "",
+                };
+                if !replacement.is_empty()
+                    && refers_to_enclosing(var, &var.0, scope)
+                {
+                    let fix = parsed(expr.span()).map(|span| Fix {
+                        range: span.range(),
+                        replacement: replacement.to_owned(),
+                    });
+                    self.push(
+                        Lint::DeprecatedOptional,
+                        format!(
+                            "`{}` has been removed from the language",
+                            var.0
+                        ),
+                        expr.span(),
+                        fix,
+                    );
+                }
+            }
+            _ => {}
+        }
+        visitor::walk(self, expr, scope)
+    }
+}
+
+/// Lint an expression. The diagnostics are in the order of the expressions they are about, outer
+/// expressions first.
+pub fn lint(expr: &Expr) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        diagnostics: Vec::new(),
+        continued_lets: HashSet::new(),
+    };
+    expr.visit(&mut linter);
+    linter.diagnostics
+}
+
+/// Apply the fixes of the given diagnostics to `source`, which must be the text they were found
+/// in. When fixes overlap, only the first one is applied: lint the result again to find the
+/// remaining ones.
+pub fn apply_fixes(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut fixes: Vec<&Fix> =
+        diagnostics.iter().filter_map(|d| d.fix.as_ref()).collect();
+    // The sort is stable, so the first of two fixes at the same place stays first.
+    fixes.sort_by_key(|fix| fix.range.start);
+    let mut out = String::with_capacity(source.len());
+    let mut end = 0;
+    for fix in fixes {
+        if fix.range.start < end {
+            continue;
+        }
+        out.push_str(&source[end..fix.range.start]);
+        out.push_str(&fix.replacement);
+        end = fix.range.end;
+    }
+    out.push_str(&source[end..]);
+    out
+}
+
+/// Apply all the fixes the linter can find to the given source, until there are none left.
+pub fn fix(source: &str) -> Result<String, Error> {
+    let mut source = source.to_owned();
+    loop {
+        let diagnostics = Parsed::parse_str(&source)?.lint();
+        if diagnostics.iter().all(|d| d.fix.is_none()) {
+            return Ok(source);
+        }
+        source = apply_fixes(&source, &diagnostics);
+    }
+}
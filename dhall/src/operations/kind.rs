@@ -97,4 +97,34 @@ impl<SE> OpKind<SE> {
     ) -> OpKind<SE2> {
         trivial_result(self.traverse_ref(|x| Ok(f(x))))
     }
+
+    /// Calls `f` on each subexpression.
+    pub fn visit_each_mut(&mut self, mut f: impl FnMut(&mut SE)) {
+        use OpKind::*;
+        match self {
+            App(x, y)
+            | BinOp(_, x, y)
+            | ProjectionByExpr(x, y)
+            | Completion(x, y)
+            | With(x, _, y) => {
+                f(x);
+                f(y);
+            }
+            BoolIf(x, y, z) => {
+                f(x);
+                f(y);
+                f(z);
+            }
+            Merge(x, y, t) => {
+                f(x);
+                f(y);
+                t.iter_mut().for_each(f);
+            }
+            ToMap(x, t) => {
+                f(x);
+                t.iter_mut().for_each(f);
+            }
+            Field(x, _) | Projection(x, _) => f(x),
+        }
+    }
 }
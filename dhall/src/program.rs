@@ -0,0 +1,195 @@
+use std::path::Path;
+
+use crate::error::Error;
+use crate::operations::{typecheck_operation, OpKind};
+use crate::semantics::{Hir, Nir, Tir, TyEnv, Type, Universe};
+use crate::syntax::{Expr, ExprKind, Label, Span};
+use crate::{Ctxt, Parsed, Typed};
+
+/// A well-typed Dhall expression that does not borrow a `Ctxt`.
+///
+/// `Resolved`, `Typed` and `Normalized` all borrow the context created by `Ctxt::with_new`,
+/// which only lives for the duration of a closure. A `Program` can instead be stored in a struct,
+/// returned from a function, or kept around for the lifetime of the process.
+///
+/// The context is only needed to resolve imports. Once they are resolved, they are inlined into
+/// the expression, which is then closed. The type of the expression is kept along with it, so
+/// operations only typecheck what they add.
+///
+/// # Cost
+///
+/// A `Program` is not a cheap handle to an evaluated value: it only stores the syntax tree. Each
+/// of [`with_typed`], [`normalize`], [`apply`] and [`field`] creates a fresh context and rebuilds
+/// the whole expression and its type in it, which takes time linear in their size. Nothing is
+/// evaluated ahead of time either, and nothing is shared between calls, so evaluation is redone
+/// on every call. To run many operations on a large expression, run them all in a single
+/// [`with_typed`] closure instead.
+///
+/// [`with_typed`]: Program::with_typed
+/// [`normalize`]: Program::normalize
+/// [`apply`]: Program::apply
+/// [`field`]: Program::field
+///
+/// # Example
+///
+/// ```rust
+/// # fn main() -> Result<(), dhall::error::Error> {
+/// use dhall::Program;
+///
+/// let config = Program::parse_str(
+///     "λ(env : Text) → { host = \"${env}.example.com\", port = 80 }",
+/// )?;
+/// let env = Program::parse_str("\"dev\"")?;
+/// let host = config.apply(&env)?.field("host")?.normalize()?;
+/// assert_eq!(host.to_string(), "\"dev.example.com\"");
+/// assert_eq!(host.ty().to_string(), "Text");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// Closed and well-typed.
+    expr: Expr,
+    /// The normal form of the type of `expr`.
+    ty: Expr,
+    /// The type of `ty`.
+    univ: Universe,
+}
+
+impl Program {
+    /// Resolves the imports of the parsed expression and typechecks it.
+    pub fn new(parsed: Parsed) -> Result<Program, Error> {
+        Ctxt::with_new(|cx| {
+            let typed = parsed.resolve(cx)?.typecheck(cx)?;
+            Ok(Program::from_typed(cx, &typed))
+        })
+    }
+    pub fn parse_str(s: &str) -> Result<Program, Error> {
+        Program::new(Parsed::parse_str(s)?)
+    }
+    pub fn parse_file(f: &Path) -> Result<Program, Error> {
+        Program::new(Parsed::parse_file(f)?)
+    }
+
+    /// Typechecks a closed expression without imports.
+    pub fn from_expr(expr: Expr) -> Result<Program, Error> {
+        Program::new(Parsed::from_expr_without_imports(expr))
+    }
+
+    /// Builds a program from a closed value and its type, both in normal form. The value is not
+    /// typechecked again.
+    pub fn from_nir<'cx>(
+        cx: Ctxt<'cx>,
+        val: &Nir<'cx>,
+        ty: &Nir<'cx>,
+    ) -> Result<Program, Error> {
+        let ty = Type::new_infer_universe(&TyEnv::new(cx), ty.clone())?;
+        let expr = val.to_hir_noenv().to_expr(cx, Default::default());
+        Ok(Program::from_parts(cx, expr, &ty))
+    }
+
+    pub(crate) fn from_typed<'cx>(
+        cx: Ctxt<'cx>,
+        typed: &Typed<'cx>,
+    ) -> Program {
+        Program::from_parts(cx, typed.to_expr(cx), typed.ty())
+    }
+
+    fn from_parts<'cx>(cx: Ctxt<'cx>, expr: Expr, ty: &Type<'cx>) -> Program {
+        Program {
+            expr,
+            ty: ty.as_nir().to_expr(cx, Default::default()),
+            univ: ty.ty(),
+        }
+    }
+
+    /// Rebuilds the typed expression in the given context, without typechecking it again.
+    fn to_typed<'cx>(&self, cx: Ctxt<'cx>) -> Result<Typed<'cx>, Error> {
+        let ty = resolve_closed(cx, &self.ty)?.eval_closed_expr(cx);
+        Ok(Typed {
+            hir: resolve_closed(cx, &self.expr)?,
+            ty: Type::new(ty, self.univ),
+        })
+    }
+
+    /// Runs `f` on the typed expression, in a fresh context. The expression is rebuilt in that
+    /// context first.
+    pub fn with_typed<T>(
+        &self,
+        f: impl for<'cx> FnOnce(Ctxt<'cx>, Typed<'cx>) -> T,
+    ) -> Result<T, Error> {
+        Ctxt::with_new(|cx| Ok(f(cx, self.to_typed(cx)?)))
+    }
+
+    /// Reduces the expression to its normal form. This evaluates it from scratch, in a fresh
+    /// context.
+    pub fn normalize(&self) -> Result<Program, Error> {
+        Ctxt::with_new(|cx| {
+            let typed = self.to_typed(cx)?;
+            Ok(Program {
+                expr: typed.normalize(cx).to_expr(cx),
+                ty: self.ty.clone(),
+                univ: self.univ,
+            })
+        })
+    }
+
+    /// Typechecks an operation on programs. Only the operation itself is typechecked: the types of
+    /// the operands are already known. The operands are still rebuilt in a fresh context.
+    fn operation(op: OpKind<&Program>) -> Result<Program, Error> {
+        Ctxt::with_new(|cx| {
+            let typed = op.traverse_ref(|p| p.to_typed(cx))?;
+            let tir = typed.map_ref(|t| Tir::from_hir(&t.hir, t.ty.clone()));
+            let ty =
+                typecheck_operation(&TyEnv::new(cx), Span::Artificial, tir)?;
+            let expr = mkexpr(op.map_ref(|p| p.expr.clone()));
+            Ok(Program::from_parts(cx, expr, &ty))
+        })
+    }
+
+    /// Applies this function to an argument.
+    pub fn apply(&self, arg: &Program) -> Result<Program, Error> {
+        Program::operation(OpKind::App(self, arg))
+    }
+
+    /// Selects a field of this record, or an alternative of this union type.
+    pub fn field(&self, name: &str) -> Result<Program, Error> {
+        Program::operation(OpKind::Field(self, Label::from(name)))
+    }
+
+    /// The normal form of the type of this expression.
+    pub fn ty(&self) -> &Expr {
+        &self.ty
+    }
+    pub fn as_expr(&self) -> &Expr {
+        &self.expr
+    }
+    /// Converts back to the corresponding AST expression.
+    pub fn to_expr(&self) -> Expr {
+        self.expr.clone()
+    }
+}
+
+/// Resolves a closed expression without imports.
+fn resolve_closed<'cx>(cx: Ctxt<'cx>, expr: &Expr) -> Result<Hir<'cx>, Error> {
+    Ok(Parsed::from_expr_without_imports(expr.clone())
+        .skip_resolve(cx)?
+        .0)
+}
+
+fn mkexpr(op: OpKind<Expr>) -> Expr {
+    Expr::new(ExprKind::Op(op), Span::Artificial)
+}
+
+impl std::cmp::PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+impl std::cmp::Eq for Program {}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.expr.fmt(f)
+    }
+}
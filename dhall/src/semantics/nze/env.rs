@@ -1,4 +1,5 @@
 use crate::semantics::{AlphaVar, Nir, NirKind};
+use crate::utils::Stack;
 use crate::Ctxt;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,7 +21,7 @@ enum EnvItem<'cx, T> {
 #[derive(Debug, Clone)]
 pub struct ValEnv<'cx, T> {
     cx: Ctxt<'cx>,
-    items: Vec<EnvItem<'cx, T>>,
+    items: Stack<EnvItem<'cx, T>>,
 }
 
 pub type NzEnv<'cx> = ValEnv<'cx, ()>;
@@ -52,44 +53,42 @@ impl<'cx, T: Clone> ValEnv<'cx, T> {
     pub fn new(cx: Ctxt<'cx>) -> Self {
         ValEnv {
             cx,
-            items: Vec::new(),
+            items: Stack::new(),
         }
     }
     pub fn cx(&self) -> Ctxt<'cx> {
         self.cx
     }
-    pub fn discard_types(&self) -> ValEnv<'cx, ()> {
-        let items = self
-            .items
-            .iter()
-            .map(|i| match i {
-                EnvItem::Kept(_) => EnvItem::Kept(()),
-                EnvItem::Replaced(val, _) => EnvItem::Replaced(val.clone(), ()),
-            })
-            .collect();
-        ValEnv { cx: self.cx, items }
+    /// Removes the values that aren't shared with other environments, and passes them to `f`.
+    pub fn pop_unique_values(&mut self, mut f: impl FnMut(Nir<'cx>)) {
+        while let Some(item) = self.items.pop_unique() {
+            if let EnvItem::Replaced(val, _) = item {
+                f(val)
+            }
+        }
     }
 
     pub fn insert_type(&self, ty: T) -> Self {
-        let mut env = self.clone();
-        env.items.push(EnvItem::Kept(ty));
-        env
+        ValEnv {
+            cx: self.cx,
+            items: self.items.push(EnvItem::Kept(ty)),
+        }
     }
     pub fn insert_value(&self, e: Nir<'cx>, ty: T) -> Self {
-        let mut env = self.clone();
-        env.items.push(EnvItem::Replaced(e, ty));
-        env
+        ValEnv {
+            cx: self.cx,
+            items: self.items.push(EnvItem::Replaced(e, ty)),
+        }
     }
     pub fn lookup_val(&self, var: AlphaVar) -> NirKind<'cx> {
         let idx = self.items.len() - 1 - var.idx();
-        match &self.items[idx] {
+        match self.items.get(var.idx()).unwrap() {
             EnvItem::Kept(_) => NirKind::Var(NzVar::new(idx)),
             EnvItem::Replaced(x, _) => x.kind().clone(),
         }
     }
     pub fn lookup_ty(&self, var: AlphaVar) -> T {
-        let idx = self.items.len() - 1 - var.idx();
-        match &self.items[idx] {
+        match self.items.get(var.idx()).unwrap() {
             EnvItem::Kept(ty) | EnvItem::Replaced(_, ty) => ty.clone(),
         }
     }
@@ -1,8 +1,9 @@
-use once_cell::unsync::OnceCell;
-use std::cell::Cell;
 use std::fmt::Debug;
 use std::ops::Deref;
 
+use crate::sync::{OnceCell, TakeCell};
+use crate::utils::grow_stack;
+
 pub trait Eval<Tgt> {
     fn eval(self) -> Tgt;
 }
@@ -11,7 +12,7 @@ pub trait Eval<Tgt> {
 pub struct Lazy<Src, Tgt> {
     /// Exactly one of `src` of `tgt` must be set at a given time.
     /// Once `src` is unset and `tgt` is set, we never go back.
-    src: Cell<Option<Src>>,
+    src: TakeCell<Src>,
     tgt: OnceCell<Tgt>,
 }
 
@@ -22,14 +23,14 @@ where
     /// Creates a new lazy value with the given initializing value.
     pub fn new(src: Src) -> Self {
         Lazy {
-            src: Cell::new(Some(src)),
+            src: TakeCell::new(Some(src)),
             tgt: OnceCell::new(),
         }
     }
     /// Creates a new lazy value with the given already-initialized value.
     pub fn new_completed(tgt: Tgt) -> Self {
         let lazy = Lazy {
-            src: Cell::new(None),
+            src: TakeCell::new(None),
             tgt: OnceCell::new(),
         };
         let _ = lazy.tgt.set(tgt);
@@ -39,7 +40,8 @@ where
     pub fn force(&self) -> &Tgt {
         self.tgt.get_or_init(|| {
             let src = self.src.take().unwrap();
-            src.eval()
+            // Evaluation can force other lazy values recursively.
+            grow_stack(|| src.eval())
         })
     }
 
@@ -53,6 +55,14 @@ where
     }
 }
 
+impl<Src, Tgt> Lazy<Src, Tgt> {
+    /// Moves out the contents, leaving the value uninitialized. Used to drop the contents
+    /// somewhere else.
+    pub fn take_contents(&mut self) -> (Option<Src>, Option<Tgt>) {
+        (self.src.get_mut().take(), self.tgt.take())
+    }
+}
+
 impl<Src, Tgt> Deref for Lazy<Src, Tgt>
 where
     Src: Eval<Tgt>,
@@ -63,7 +73,8 @@ where
     }
 }
 
-/// This implementation evaluates before cloning, because we can't clone the contents of a `Cell`.
+/// This implementation evaluates before cloning, because we can't clone the contents of a
+/// `TakeCell`.
 impl<Src, Tgt> Clone for Lazy<Src, Tgt>
 where
     Src: Eval<Tgt>,
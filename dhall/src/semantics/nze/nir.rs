@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::rc::Rc;
 
 use crate::builtins::{Builtin, BuiltinClosure};
 use crate::operations::{BinOp, OpKind};
@@ -8,9 +7,12 @@ use crate::semantics::{
     apply_any, normalize_hir, normalize_one_layer, squash_textlit, Binder, Hir,
     HirKind, NzEnv, NzVar, TyEnv, Type, Universe, VarEnv,
 };
+use crate::sync::Rc;
+use crate::syntax::visitor::visit_each_mut;
 use crate::syntax::{
     Const, Expr, ExprKind, InterpolatedTextContents, Label, NumKind, Span,
 };
+use crate::utils::grow_stack;
 use crate::{Ctxt, ToExprOptions};
 
 /// Stores a possibly unevaluated value. Gets (partially) normalized on-demand, sharing computation
@@ -137,10 +139,12 @@ impl<'cx> Nir<'cx> {
         Rc::make_mut(&mut self.0).get_mut()
     }
     /// If we are the sole owner of this Nir, we can avoid a clone.
-    pub fn into_kind(self) -> NirKind<'cx> {
-        match Rc::try_unwrap(self.0) {
-            Ok(lazy) => lazy.into_inner(),
-            Err(rc) => (**rc).clone(),
+    pub fn into_kind(mut self) -> NirKind<'cx> {
+        match Rc::get_mut(&mut self.0) {
+            Some(lazy) => {
+                std::mem::replace(lazy.get_mut(), NirKind::Const(Const::Type))
+            }
+            None => self.kind().clone(),
         }
     }
 
@@ -176,7 +180,7 @@ impl<'cx> Nir<'cx> {
         let builtin =
             |b| Hir::new(HirKind::Expr(ExprKind::Builtin(b)), Span::Artificial);
 
-        let hir = match self.kind() {
+        let hir = grow_stack(|| match self.kind() {
             NirKind::Var(v) => HirKind::Var(venv.lookup(*v)),
             NirKind::AppliedBuiltin(closure) => closure.to_hirkind(venv),
             self_kind => HirKind::Expr(match self_kind {
@@ -273,7 +277,7 @@ impl<'cx> Nir<'cx> {
                 NirKind::Assert(x) => ExprKind::Assert(x.to_hir(venv)),
                 NirKind::Op(e) => ExprKind::Op(e.map_ref(|v| v.to_hir(venv))),
             }),
-        };
+        });
 
         Hir::new(hir, Span::Artificial)
     }
@@ -339,6 +343,23 @@ impl<'cx> Closure<'cx> {
         }
     }
 
+    /// Whether the body of the closure may depend on its argument.
+    pub fn uses_argument(&self) -> bool {
+        match self {
+            Closure::Closure { body, .. } => body.uses_var(0),
+            Closure::ConstantClosure { .. } => false,
+        }
+    }
+
+    /// Counts an evaluation step against the limits of the context. Returns whether the closure
+    /// may be applied.
+    pub fn step(&self) -> bool {
+        match self {
+            Closure::Closure { env, .. } => env.cx().limits_state().step(),
+            Closure::ConstantClosure { .. } => true,
+        }
+    }
+
     /// Convert this closure to a Hir expression
     pub fn to_hir(&self, venv: VarEnv) -> Hir<'cx> {
         self.apply_var(NzVar::new(venv.size()))
@@ -420,6 +441,88 @@ impl<'cx> std::cmp::PartialEq for Nir<'cx> {
 }
 impl<'cx> std::cmp::Eq for Nir<'cx> {}
 
+/// The contents of a `Nir`, moved out of it.
+type Contents<'cx> = (Option<Thunk<'cx>>, Option<NirKind<'cx>>);
+
+/// Dropping a deep value recursively could overflow the stack, so the subvalues that aren't shared
+/// are moved out and dropped one at a time.
+impl<'cx> Drop for Nir<'cx> {
+    fn drop(&mut self) {
+        let mut contents = Vec::new();
+        if let Some(lazy) = Rc::get_mut(&mut self.0) {
+            take_subvalues(&mut lazy.take_contents(), &mut contents);
+        }
+        while let Some(mut c) = contents.pop() {
+            take_subvalues(&mut c, &mut contents);
+        }
+    }
+}
+
+/// Moves out the contents of the subvalues that aren't shared, leaving them empty.
+fn take_subvalues<'cx>(
+    (thunk, kind): &mut Contents<'cx>,
+    contents: &mut Vec<Contents<'cx>>,
+) {
+    use NirKind::*;
+    let mut take = |nir: &mut Nir<'cx>| {
+        if let Some(lazy) = Rc::get_mut(&mut nir.0) {
+            contents.push(lazy.take_contents());
+        }
+    };
+    match thunk {
+        Some(Thunk::Thunk { env, .. }) => {
+            env.pop_unique_values(|mut x| take(&mut x))
+        }
+        Some(Thunk::PartialExpr { expr }) => {
+            visit_each_mut(expr, |_, x| take(x))
+        }
+        None => {}
+    }
+    let kind = match kind {
+        Some(kind) => kind,
+        None => return,
+    };
+    match kind {
+        LamClosure { annot, closure, .. }
+        | PiClosure { annot, closure, .. } => {
+            take(annot);
+            match closure {
+                Closure::Closure { env, .. } => {
+                    env.pop_unique_values(|mut x| take(&mut x))
+                }
+                Closure::ConstantClosure { body } => take(body),
+            }
+        }
+        AppliedBuiltin(closure) => {
+            closure.pop_unique_values(|mut x| take(&mut x))
+        }
+        Var(_) | Const(_) | Num(_) | BuiltinType(_) => {}
+        TextLit(t) => t.0.iter_mut().for_each(|x| {
+            if let InterpolatedTextContents::Expr(x) = x {
+                take(x)
+            }
+        }),
+        EmptyOptionalLit(x) | NEOptionalLit(x) | OptionalType(x)
+        | EmptyListLit(x) | ListType(x) | Assert(x) => take(x),
+        NEListLit(xs) => xs.iter_mut().for_each(&mut take),
+        RecordLit(kvs) | RecordType(kvs) => {
+            kvs.values_mut().for_each(&mut take)
+        }
+        UnionConstructor(_, kts) | UnionType(kts) => {
+            kts.values_mut().flatten().for_each(&mut take)
+        }
+        UnionLit(_, x, kts) => {
+            take(x);
+            kts.values_mut().flatten().for_each(&mut take)
+        }
+        Equivalence(x, y) => {
+            take(x);
+            take(y);
+        }
+        Op(op) => op.visit_each_mut(take),
+    }
+}
+
 impl<'cx> std::cmp::PartialEq for Thunk<'cx> {
     fn eq(&self, _other: &Self) -> bool {
         unreachable!(
@@ -4,11 +4,22 @@ use crate::operations::{normalize_operation, OpKind};
 use crate::semantics::NzEnv;
 use crate::semantics::{Binder, Closure, Hir, HirKind, Nir, NirKind, TextLit};
 use crate::syntax::{ExprKind, InterpolatedTextContents};
+use crate::utils::grow_stack;
+use crate::Ctxt;
 
 pub fn apply_any<'cx>(f: &Nir<'cx>, a: Nir<'cx>) -> NirKind<'cx> {
+    // Builtins like `Natural/fold` apply functions recursively.
+    grow_stack(|| apply_any_unguarded(f, a))
+}
+
+fn apply_any_unguarded<'cx>(f: &Nir<'cx>, a: Nir<'cx>) -> NirKind<'cx> {
+    // Applying functions is how evaluation can run for a long time, so this is where the limits
+    // of the context are enforced. Once they are exceeded, applications are left as they are.
     match f.kind() {
-        NirKind::LamClosure { closure, .. } => closure.apply(a).kind().clone(),
-        NirKind::AppliedBuiltin(closure) => closure.apply(a),
+        NirKind::LamClosure { closure, .. } if closure.step() => {
+            closure.apply(a).kind().clone()
+        }
+        NirKind::AppliedBuiltin(closure) if closure.step() => closure.apply(a),
         NirKind::UnionConstructor(l, kts) => {
             NirKind::UnionLit(l.clone(), a, kts.clone())
         }
@@ -193,7 +204,38 @@ pub fn normalize_hir<'cx>(env: &NzEnv<'cx>, hir: &Hir<'cx>) -> NirKind<'cx> {
         }
         HirKind::Expr(e) => {
             let e = e.map_ref(|hir| hir.eval(env));
-            normalize_one_layer(e)
+            match e {
+                // Once a limit is exceeded, operations are left unevaluated.
+                ExprKind::Op(op) if env.cx().check_limits().is_err() => {
+                    NirKind::Op(op)
+                }
+                e => {
+                    let kind = normalize_one_layer(e);
+                    check_size(env.cx(), &kind);
+                    kind
+                }
+            }
         }
     }
 }
+
+/// Checks the lists and texts built during evaluation against the limits of the context.
+pub fn check_size(cx: Ctxt<'_>, kind: &NirKind<'_>) {
+    let limits = cx.limits_state();
+    match kind {
+        NirKind::NEListLit(xs) => {
+            limits.list_length(xs.len());
+        }
+        NirKind::TextLit(txt) => {
+            let size = txt
+                .iter()
+                .map(|contents| match contents {
+                    InterpolatedTextContents::Text(s) => s.len(),
+                    InterpolatedTextContents::Expr(_) => 0,
+                })
+                .sum();
+            limits.text_size(size);
+        }
+        _ => {}
+    }
+}
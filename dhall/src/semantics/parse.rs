@@ -2,7 +2,9 @@ use std::path::Path;
 use url::Url;
 
 use crate::error::Error;
-use crate::semantics::resolve::{download_http_text, ImportLocation};
+use crate::semantics::resolve::{
+    download_http_text, download_http_text_async, ImportLocation,
+};
 use crate::syntax::{binary, parse_expr};
 use crate::Parsed;
 
@@ -20,6 +22,13 @@ pub fn parse_remote(url: Url) -> Result<Parsed, Error> {
     Ok(Parsed(expr, root))
 }
 
+pub async fn parse_remote_async(url: Url) -> Result<Parsed, Error> {
+    let body = download_http_text_async(url.clone()).await?;
+    let expr = parse_expr(&body)?;
+    let root = ImportLocation::remote_dhall_code(url);
+    Ok(Parsed(expr, root))
+}
+
 pub fn parse_str(s: &str) -> Result<Parsed, Error> {
     let expr = parse_expr(s)?;
     let root = ImportLocation::dhall_code_of_unknown_origin();
@@ -33,6 +42,7 @@ pub fn parse_binary(data: &[u8]) -> Result<Parsed, Error> {
 }
 
 pub fn parse_binary_file(f: &Path) -> Result<Parsed, Error> {
+    // Read it whole so that decoding errors can show the offending value.
     let data = crate::utils::read_binary_file(f)?;
     let expr = binary::decode(&data)?;
     let root = ImportLocation::local_dhall_code(f.to_owned());
@@ -1,5 +1,5 @@
 use std::env;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 use crate::error::{CacheError, Error};
@@ -94,7 +94,7 @@ fn read_cache_file<'cx>(
         }
     }
 
-    Ok(parse_binary(&data)?.resolve(cx)?.typecheck(cx)?)
+    parse_binary(&data)?.skip_resolve(cx)?.typecheck(cx)
 }
 
 /// Write a file to the cache.
@@ -103,8 +103,9 @@ fn write_cache_file<'cx>(
     path: &Path,
     expr: &Typed<'cx>,
 ) -> Result<(), Error> {
-    let data = binary::encode(&expr.to_expr(cx))?;
-    File::create(path)?.write_all(data.as_slice())?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    binary::encode_to_writer(&expr.to_expr(cx), &mut writer)?;
+    writer.flush()?;
     Ok(())
 }
 
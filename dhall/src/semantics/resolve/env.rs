@@ -1,14 +1,17 @@
 use std::collections::HashMap;
 
 use crate::error::{Error, ImportError};
-use crate::semantics::{check_hash, AlphaVar, Cache, ImportLocation, VarEnv};
+use crate::semantics::{
+    check_hash, AlphaVar, Cache, ImportLocation, SessionCache, VarEnv,
+};
 use crate::syntax::{Hash, Label, V};
+use crate::utils::Stack;
 use crate::{Ctxt, ImportId, ImportResultId, Typed};
 
 /// Environment for resolving names.
 #[derive(Debug, Clone, Default)]
 pub struct NameEnv {
-    names: Vec<Label>,
+    names: Stack<Label>,
 }
 
 pub type CyclesStack = Vec<ImportLocation>;
@@ -18,6 +21,8 @@ pub struct ImportEnv<'cx> {
     cx: Ctxt<'cx>,
     disk_cache: Option<Cache>, // `None` if it failed to initialize
     mem_cache: HashMap<ImportLocation, ImportResultId<'cx>>,
+    /// Results of previous runs, when resolving within a `Session`.
+    session: Option<SessionCache>,
     stack: CyclesStack,
 }
 
@@ -35,10 +40,10 @@ impl NameEnv {
         env
     }
     pub fn insert_mut(&mut self, x: &Label) {
-        self.names.push(x.clone())
+        self.names = self.names.push(x.clone())
     }
     pub fn remove_mut(&mut self) {
-        self.names.pop();
+        self.names = self.names.pop();
     }
 
     pub fn unlabel_var(&self, var: &V) -> Option<AlphaVar> {
@@ -46,18 +51,16 @@ impl NameEnv {
         let (idx, _) = self
             .names
             .iter()
-            .rev()
             .enumerate()
             .filter(|(_, n)| *n == name)
             .nth(*idx)?;
         Some(AlphaVar::new(idx))
     }
     pub fn label_var(&self, var: AlphaVar) -> V {
-        let name = &self.names[self.names.len() - 1 - var.idx()];
+        let name = self.names.get(var.idx()).unwrap();
         let idx = self
             .names
             .iter()
-            .rev()
             .take(var.idx())
             .filter(|n| *n == name)
             .count();
@@ -71,19 +74,68 @@ impl<'cx> ImportEnv<'cx> {
             cx,
             disk_cache: Cache::new().ok(),
             mem_cache: Default::default(),
+            session: None,
             stack: Default::default(),
         }
     }
 
+    /// An environment that reuses and extends the results of previous runs.
+    pub fn with_session(cx: Ctxt<'cx>, session: SessionCache) -> Self {
+        ImportEnv {
+            session: Some(session),
+            ..ImportEnv::new(cx)
+        }
+    }
+    pub fn into_session_cache(self) -> SessionCache {
+        self.session.unwrap_or_default()
+    }
+
     pub fn cx(&self) -> Ctxt<'cx> {
         self.cx
     }
 
+    /// Makes a copy of this environment, e.g. to resolve imports on another thread.
+    pub fn fork(&self) -> Self {
+        ImportEnv {
+            cx: self.cx,
+            disk_cache: self.disk_cache.clone(),
+            mem_cache: self.mem_cache.clone(),
+            session: self.session.as_ref().map(SessionCache::fork),
+            stack: self.stack.clone(),
+        }
+    }
+
+    /// Adds to the in-memory cache the results that were found in a forked environment.
+    pub fn merge_mem_cache(&mut self, other: Self) {
+        for (location, result) in other.mem_cache {
+            self.mem_cache.entry(location).or_insert(result);
+        }
+        if let (Some(session), Some(other)) = (&mut self.session, other.session)
+        {
+            session.merge(other);
+        }
+    }
+
+    /// Whether the import at `location` can be found in the in-memory cache or the session.
+    pub fn is_cached(&self, location: &ImportLocation) -> bool {
+        self.mem_cache.contains_key(location)
+            || self
+                .session
+                .as_ref()
+                .map_or(false, |s| s.contains(location))
+    }
+
     pub fn get_from_mem_cache(
-        &self,
+        &mut self,
         location: &ImportLocation,
     ) -> Option<ImportResultId<'cx>> {
-        Some(*self.mem_cache.get(location)?)
+        if let Some(res_id) = self.mem_cache.get(location) {
+            return Some(*res_id);
+        }
+        let typed = self.session.as_ref()?.get(self.cx, location)?;
+        let res_id = self.cx.push_import_result(typed);
+        self.mem_cache.insert(location.clone(), res_id);
+        Some(res_id)
     }
 
     pub fn get_from_disk_cache(
@@ -108,9 +160,24 @@ impl<'cx> ImportEnv<'cx> {
         location: ImportLocation,
         result: ImportResultId<'cx>,
     ) {
+        if let Some(session) = &mut self.session {
+            session.finish_fetch(self.cx, location.clone(), &self.cx[result]);
+        }
         self.mem_cache.insert(location, result);
     }
 
+    /// Records that `importer` imports `location`, so that the session can tell which imports
+    /// are affected when a file changes.
+    pub fn add_dependency(
+        &mut self,
+        importer: &ImportLocation,
+        location: &ImportLocation,
+    ) {
+        if let Some(session) = &mut self.session {
+            session.add_dependency(importer, location);
+        }
+    }
+
     pub fn write_to_disk_cache(
         &self,
         hash: &Option<Hash>,
@@ -129,18 +196,35 @@ impl<'cx> ImportEnv<'cx> {
         location: ImportLocation,
         do_resolve: impl FnOnce(&mut Self) -> Result<Typed<'cx>, Error>,
     ) -> Result<Typed<'cx>, Error> {
+        self.enter_import(location)?;
+        // Resolve the import recursively
+        // WARNING: do not propagate errors here or the stack will get messed up.
+        let result = do_resolve(self);
+        self.exit_import();
+        result
+    }
+
+    /// Pushes `location` on the stack of imports being resolved, or errors if that would create a
+    /// cycle or exceed the limits of the context. Must be followed by a call to `exit_import`.
+    pub fn enter_import(
+        &mut self,
+        location: ImportLocation,
+    ) -> Result<(), Error> {
         if self.stack.contains(&location) {
             return Err(
                 ImportError::ImportCycle(self.stack.clone(), location).into()
             );
         }
-        // Push the current location on the stack
+        self.cx.limits_state().import(self.stack.len() + 1)?;
+        if let Some(session) = &mut self.session {
+            session.start_fetch(&location);
+        }
         self.stack.push(location);
-        // Resolve the import recursively
-        // WARNING: do not propagate errors here or the stack will get messed up.
-        let result = do_resolve(self);
-        // Remove location from the stack.
+        Ok(())
+    }
+
+    /// Removes from the stack the location pushed by the last `enter_import`.
+    pub fn exit_import(&mut self) {
         self.stack.pop().unwrap();
-        result
     }
 }
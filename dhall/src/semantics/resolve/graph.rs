@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::semantics::{ImportLocation, ImportNode};
+use crate::syntax::{Hash, ImportMode, Span};
+use crate::{Ctxt, ImportId};
+
+/// The files, URLs and environment variables that an expression depends on, and which of them
+/// import which.
+///
+/// The graph is built from the imports resolved in a `Ctxt`. Its roots are the expressions that
+/// were resolved without being imported, e.g. the file passed to `Parsed::parse_file`, even if
+/// they don't import anything. Imports in
+/// the unselected branch of a `?` are left out, as are the imports of files that failed to load.
+///
+/// Imports that were found in the on-disk cache or reused from a `Session` aren't read again, so
+/// their own imports don't appear in the graph.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), dhall::error::Error> {
+/// use dhall::{Ctxt, ImportGraph, Parsed};
+///
+/// let dot = Ctxt::with_new(|cx| {
+///     Parsed::parse_file("config.dhall".as_ref())?.resolve(cx)?;
+///     Ok::<_, dhall::error::Error>(ImportGraph::new(cx).to_dot())
+/// })?;
+/// println!("{}", dot);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImportGraph {
+    nodes: Vec<ImportGraphNode>,
+    edges: Vec<ImportGraphEdge>,
+}
+
+/// Something that was imported, or a root of the graph.
+#[derive(Debug, Clone)]
+pub struct ImportGraphNode {
+    pub location: ImportLocation,
+    /// The integrity check of the import, if it had one.
+    pub hash: Option<Hash>,
+    /// Where it was first imported. `None` for the roots.
+    pub span: Option<Span>,
+}
+
+/// An import of the node at index `to` in the node at index `from`.
+#[derive(Debug, Clone)]
+pub struct ImportGraphEdge {
+    pub from: usize,
+    pub to: usize,
+    /// The import expression.
+    pub span: Span,
+}
+
+/// A successful import, as found in the `Ctxt`.
+struct FoundImport {
+    target: ImportLocation,
+    hash: Option<Hash>,
+    span: Span,
+}
+
+impl ImportGraph {
+    /// Collects the imports resolved so far in `cx`.
+    pub fn new(cx: Ctxt<'_>) -> Self {
+        let excluded = unselected_imports(cx);
+        let mut found: HashMap<ImportLocation, Vec<FoundImport>> =
+            HashMap::new();
+        for id in cx.imports() {
+            let stored = &cx[id];
+            let target = match stored.base_location.chain(&stored.import) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            if stored.get_resultid().is_none() || excluded.contains(&id) {
+                continue;
+            }
+            found.entry(stored.base_location.clone()).or_default().push(
+                FoundImport {
+                    target,
+                    hash: stored.import.hash.clone(),
+                    span: stored.span.clone(),
+                },
+            );
+        }
+
+        let mut graph = ImportGraph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut indices = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in cx.roots() {
+            if !indices.contains_key(root) {
+                graph.add_node(&mut indices, root.clone(), None, None);
+                queue.push_back(root.clone());
+            }
+        }
+        // Breadth-first, so that nodes are numbered by distance from the roots.
+        while let Some(location) = queue.pop_front() {
+            let from = indices[&location];
+            for import in found.get(&location).into_iter().flatten() {
+                let to = match indices.get(&import.target) {
+                    Some(&to) => to,
+                    None => {
+                        queue.push_back(import.target.clone());
+                        graph.add_node(
+                            &mut indices,
+                            import.target.clone(),
+                            import.hash.clone(),
+                            Some(import.span.clone()),
+                        )
+                    }
+                };
+                if graph.nodes[to].hash.is_none() {
+                    graph.nodes[to].hash = import.hash.clone();
+                }
+                graph.edges.push(ImportGraphEdge {
+                    from,
+                    to,
+                    span: import.span.clone(),
+                });
+            }
+        }
+        graph
+    }
+
+    fn add_node(
+        &mut self,
+        indices: &mut HashMap<ImportLocation, usize>,
+        location: ImportLocation,
+        hash: Option<Hash>,
+        span: Option<Span>,
+    ) -> usize {
+        let idx = self.nodes.len();
+        indices.insert(location.clone(), idx);
+        self.nodes.push(ImportGraphNode {
+            location,
+            hash,
+            span,
+        });
+        idx
+    }
+
+    pub fn nodes(&self) -> &[ImportGraphNode] {
+        &self.nodes
+    }
+    pub fn edges(&self) -> &[ImportGraphEdge] {
+        &self.edges
+    }
+
+    /// Renders the graph in the DOT format of Graphviz.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph imports {\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = node.location.to_string();
+            if let Some(hash) = &node.hash {
+                label = format!("{}\n{}", label, hash);
+            }
+            out += &format!("    {} [label=\"{}\"];\n", i, escape_dot(&label));
+        }
+        for edge in &self.edges {
+            out += &format!("    {} -> {};\n", edge.from, edge.to);
+        }
+        out += "}\n";
+        out
+    }
+
+    /// Renders the graph as JSON, with a list of nodes and a list of edges. Edges refer to nodes
+    /// by their `id`.
+    pub fn to_json(&self) -> String {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let mode = match node.location.mode() {
+                    ImportMode::Code => "code",
+                    ImportMode::RawText => "text",
+                    ImportMode::Location => "location",
+                };
+                let hash = node.hash.as_ref().map(|h| json_string(&h.to_string()));
+                format!(
+                    r#"{{"hash":{},"id":{},"location":{},"mode":"{}","span":{}}}"#,
+                    hash.as_deref().unwrap_or("null"),
+                    i,
+                    json_string(&node.location.to_string()),
+                    mode,
+                    node.span.as_ref().map_or("null".to_owned(), span_to_json),
+                )
+            })
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                format!(
+                    r#"{{"from":{},"span":{},"to":{}}}"#,
+                    edge.from,
+                    span_to_json(&edge.span),
+                    edge.to,
+                )
+            })
+            .collect();
+        format!(
+            r#"{{"edges":[{}],"nodes":[{}]}}"#,
+            edges.join(","),
+            nodes.join(",")
+        )
+    }
+}
+
+/// The imports in the branches of `?` that were not selected, including in nested `?`.
+fn unselected_imports(cx: Ctxt<'_>) -> HashSet<ImportId<'_>> {
+    fn exclude<'cx>(
+        cx: Ctxt<'cx>,
+        nodes: &[ImportNode<'cx>],
+        excluded: &mut HashSet<ImportId<'cx>>,
+    ) {
+        for node in nodes {
+            match *node {
+                ImportNode::Import(id) => {
+                    excluded.insert(id);
+                }
+                ImportNode::Alternative(id) => {
+                    let alt = &cx[id];
+                    exclude(cx, &alt.left_imports, excluded);
+                    exclude(cx, &alt.right_imports, excluded);
+                }
+            }
+        }
+    }
+
+    let mut excluded = HashSet::new();
+    for id in cx.import_alternatives() {
+        let alt = &cx[id];
+        match alt.get_selected() {
+            Some(true) => exclude(cx, &alt.right_imports, &mut excluded),
+            Some(false) => exclude(cx, &alt.left_imports, &mut excluded),
+            None => {}
+        }
+    }
+    excluded
+}
+
+fn span_to_json(span: &Span) -> String {
+    match span {
+        Span::Parsed(span) => {
+            let (line, column) = span.line_col();
+            format!(
+                r#"{{"column":{},"line":{},"text":{}}}"#,
+                column,
+                line,
+                json_string(span.as_str())
+            )
+        }
+        _ => "null".to_owned(),
+    }
+}
+
+/// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if c < ' ' => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
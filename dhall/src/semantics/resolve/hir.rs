@@ -1,6 +1,9 @@
 use crate::error::TypeError;
 use crate::semantics::{type_with, typecheck, NameEnv, Nir, NzEnv, Tir, TyEnv};
+use crate::sync::Rc;
+use crate::syntax::visitor::visit_each_mut;
 use crate::syntax::{Expr, ExprKind, Span, V};
+use crate::utils::grow_stack;
 use crate::{Ctxt, ImportAlternativeId, ImportId, ToExprOptions};
 
 /// Stores an alpha-normalized variable.
@@ -23,10 +26,11 @@ pub enum HirKind<'cx> {
     Expr(ExprKind<Hir<'cx>>),
 }
 
-// An expression with resolved variables and imports.
-#[derive(Debug, Clone)]
+// An expression with resolved variables and imports. Evaluation keeps subexpressions around in
+// thunks and closures, so they are shared rather than copied.
+#[derive(Debug)]
 pub struct Hir<'cx> {
-    kind: Box<HirKind<'cx>>,
+    kind: Rc<HirKind<'cx>>,
     span: Span,
 }
 
@@ -42,7 +46,7 @@ impl AlphaVar {
 impl<'cx> Hir<'cx> {
     pub fn new(kind: HirKind<'cx>, span: Span) -> Self {
         Hir {
-            kind: Box::new(kind),
+            kind: Rc::new(kind),
             span,
         }
     }
@@ -68,10 +72,13 @@ impl<'cx> Hir<'cx> {
         self.to_expr(cx, opts)
     }
     pub fn to_expr_tyenv(&self, env: &TyEnv<'cx>) -> Expr {
+        self.to_expr_nameenv(env.cx(), env.as_nameenv())
+    }
+    /// Converts a Hir expr whose free variables are named in `env` back to the corresponding AST
+    /// expression.
+    pub fn to_expr_nameenv(&self, cx: Ctxt<'cx>, env: &NameEnv) -> Expr {
         let opts = ToExprOptions { alpha: false };
-        let cx = env.cx();
-        let mut env = env.as_nameenv().clone();
-        hir_to_expr(cx, self, opts, &mut env)
+        hir_to_expr(cx, self, opts, &mut env.clone())
     }
 
     /// Typecheck the Hir.
@@ -97,6 +104,28 @@ impl<'cx> Hir<'cx> {
     pub fn eval_closed_expr(&self, cx: Ctxt<'cx>) -> Nir<'cx> {
         self.eval(NzEnv::new(cx))
     }
+
+    /// Whether the variable with DeBruijn index `idx` occurs in the expression.
+    pub fn uses_var(&self, idx: usize) -> bool {
+        grow_stack(|| match self.kind() {
+            HirKind::Var(v) => v.idx() == idx,
+            // Imports are closed expressions.
+            HirKind::MissingVar(_) | HirKind::Import(_) => false,
+            HirKind::ImportAlternative(_, left, right) => {
+                left.uses_var(idx) || right.uses_var(idx)
+            }
+            HirKind::Expr(e) => e
+                .traverse_ref_maybe_binder(|l, e| {
+                    let idx = if l.is_some() { idx + 1 } else { idx };
+                    if e.uses_var(idx) {
+                        Err(())
+                    } else {
+                        Ok(())
+                    }
+                })
+                .is_err(),
+        })
+    }
 }
 
 fn hir_to_expr<'cx>(
@@ -126,7 +155,7 @@ fn hir_to_expr<'cx>(
                 if let Some(l) = l {
                     env.insert_mut(l);
                 }
-                let e = hir_to_expr(cx, hir, opts, env);
+                let e = grow_stack(|| hir_to_expr(cx, hir, opts, env));
                 if l.is_some() {
                     env.remove_mut();
                 }
@@ -147,9 +176,50 @@ fn hir_to_expr<'cx>(
     Expr::new(kind, hir.span())
 }
 
+impl<'cx> Clone for Hir<'cx> {
+    fn clone(&self) -> Self {
+        Hir {
+            kind: self.kind.clone(),
+            span: self.span.clone(),
+        }
+    }
+}
+
+/// Dropping a deep expression recursively could overflow the stack, so the subexpressions that
+/// aren't shared are moved out and dropped one at a time.
+impl<'cx> Drop for Hir<'cx> {
+    fn drop(&mut self) {
+        let mut kinds = Vec::new();
+        if let Some(kind) = Rc::get_mut(&mut self.kind) {
+            take_subexprs(kind, &mut kinds);
+        }
+        while let Some(mut kind) = kinds.pop() {
+            take_subexprs(&mut kind, &mut kinds);
+        }
+    }
+}
+
+/// Moves out the contents of the subexpressions that aren't shared, leaving variables behind.
+fn take_subexprs<'cx>(kind: &mut HirKind<'cx>, kinds: &mut Vec<HirKind<'cx>>) {
+    let mut take = |hir: &mut Hir<'cx>| {
+        if let Some(kind) = Rc::get_mut(&mut hir.kind) {
+            let leaf = HirKind::Var(AlphaVar::new(0));
+            kinds.push(std::mem::replace(kind, leaf));
+        }
+    };
+    match kind {
+        HirKind::ImportAlternative(_, x, y) => {
+            take(x);
+            take(y);
+        }
+        HirKind::Expr(e) => visit_each_mut(e, |_, x| take(x)),
+        _ => {}
+    }
+}
+
 impl<'cx> std::cmp::PartialEq for Hir<'cx> {
     fn eq(&self, other: &Self) -> bool {
-        self.kind == other.kind
+        Rc::ptr_eq(&self.kind, &other.kind) || self.kind == other.kind
     }
 }
 impl<'cx> std::cmp::Eq for Hir<'cx> {}
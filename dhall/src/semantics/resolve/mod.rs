@@ -1,8 +1,12 @@
 pub mod cache;
 pub mod env;
+pub mod graph;
 pub mod hir;
 pub mod resolve;
+pub mod session;
 pub use cache::*;
 pub use env::*;
+pub use graph::*;
 pub use hir::*;
 pub use resolve::*;
+pub use session::*;
@@ -2,19 +2,21 @@ use itertools::Itertools;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use url::Url;
 
 use crate::builtins::Builtin;
 use crate::error::ErrorBuilder;
 use crate::error::{Error, ImportError};
 use crate::operations::{BinOp, OpKind};
-use crate::semantics::{mkerr, Hir, HirKind, ImportEnv, NameEnv, Type};
+use crate::semantics::{mkerr, Hir, HirKind, ImportEnv, NameEnv, Stamp, Type};
+use crate::sync::BoxFuture;
 use crate::syntax;
 use crate::syntax::{
     Expr, ExprKind, FilePath, FilePrefix, Hash, ImportMode, ImportTarget, Span,
     UnspannedExpr, URL,
 };
+use crate::utils::grow_stack;
 use crate::{
     Ctxt, ImportAlternativeId, ImportId, ImportResultId, Parsed, Resolved,
     Typed,
@@ -145,6 +147,26 @@ impl ImportLocationKind {
         })
     }
 
+    /// Like `fetch_dhall`, but downloads remote files asynchronously.
+    async fn fetch_dhall_async(&self) -> Result<Parsed, Error> {
+        match self {
+            ImportLocationKind::Remote(url) => {
+                Ok(Parsed::parse_remote_async(url.clone()).await?)
+            }
+            _ => self.fetch_dhall(),
+        }
+    }
+
+    /// Like `fetch_text`, but downloads remote files asynchronously.
+    async fn fetch_text_async(&self) -> Result<String, Error> {
+        match self {
+            ImportLocationKind::Remote(url) => {
+                Ok(download_http_text_async(url.clone()).await?)
+            }
+            _ => self.fetch_text(),
+        }
+    }
+
     fn to_location(&self) -> Expr {
         let (field_name, arg) = match self {
             ImportLocationKind::Local(path) => {
@@ -203,7 +225,10 @@ impl ImportLocation {
     /// location, or error if not allowed.
     /// `sanity_check` indicates whether to check if that location is allowed to be referenced,
     /// for example to prevent a remote file from reading an environment variable.
-    fn chain(&self, import: &Import) -> Result<ImportLocation, Error> {
+    pub(crate) fn chain(
+        &self,
+        import: &Import,
+    ) -> Result<ImportLocation, Error> {
         // Makes no sense to chain an import if the current file is not a dhall file.
         assert!(matches!(self.mode, ImportMode::Code));
         if matches!(self.kind, ImportLocationKind::NoImport) {
@@ -245,6 +270,51 @@ impl ImportLocation {
         })
     }
 
+    /// How the contents of this location are interpreted.
+    pub fn mode(&self) -> ImportMode {
+        self.mode
+    }
+
+    /// The path of the file, if this is a local import.
+    pub fn local_path(&self) -> Option<&Path> {
+        match &self.kind {
+            ImportLocationKind::Local(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Takes a stamp of the source of this import, to detect changes later. Returns `None` for
+    /// imports that can't change. If `previous` has the same modification time, it is returned
+    /// without reading the source again.
+    pub(crate) fn stamp(&self, previous: Option<&Stamp>) -> Option<Stamp> {
+        if matches!(self.mode, ImportMode::Location) {
+            return None;
+        }
+        match &self.kind {
+            ImportLocationKind::Local(path) => {
+                let modified = std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                match previous {
+                    Some(stamp) if stamp.is_current(modified) => {
+                        Some(stamp.clone())
+                    }
+                    _ => {
+                        let contents = std::fs::read(path).unwrap_or_default();
+                        Some(Stamp::new(modified, &contents))
+                    }
+                }
+            }
+            ImportLocationKind::Env(var_name) => {
+                let value = env::var(var_name).unwrap_or_default();
+                Some(Stamp::new(None, value.as_bytes()))
+            }
+            ImportLocationKind::Remote(..)
+            | ImportLocationKind::Missing
+            | ImportLocationKind::NoImport => None,
+        }
+    }
+
     /// Fetches the expression corresponding to this location.
     fn fetch<'cx>(
         &self,
@@ -255,34 +325,85 @@ impl ImportLocation {
         let typed = match self.mode {
             ImportMode::Code => {
                 let parsed = self.kind.fetch_dhall()?;
-                let typed = parsed.resolve_with_env(env)?.typecheck(cx)?;
-                Typed {
-                    // TODO: manage to keep the Nir around. Will need fixing variables.
-                    hir: typed.normalize(cx).to_hir(),
-                    ty: typed.ty,
-                }
+                code_import(cx, resolve_with_env(env, parsed)?)?
             }
             ImportMode::RawText => {
-                let text = self.kind.fetch_text()?;
-                Typed {
-                    hir: Hir::new(
-                        HirKind::Expr(ExprKind::TextLit(text.into())),
-                        span,
-                    ),
-                    ty: Type::from_builtin(cx, Builtin::Text),
-                }
-            }
-            ImportMode::Location => {
-                let expr = self.kind.to_location();
-                Parsed::from_expr_without_imports(expr)
-                    .resolve(cx)
-                    .unwrap()
-                    .typecheck(cx)
-                    .unwrap()
+                text_import(cx, self.kind.fetch_text()?, span)
             }
+            ImportMode::Location => self.location_import(cx),
         };
         Ok(typed)
     }
+
+    /// Like `fetch`, but fetches remote files and nested imports asynchronously.
+    fn fetch_async<'a, 'cx: 'a>(
+        &'a self,
+        env: &'a mut ImportEnv<'cx>,
+        span: Span,
+    ) -> BoxFuture<'a, Result<Typed<'cx>, Error>> {
+        Box::pin(async move {
+            let cx = env.cx();
+            let typed = match self.mode {
+                ImportMode::Code => {
+                    let parsed = self.kind.fetch_dhall_async().await?;
+                    code_import(cx, resolve_with_env_async(env, parsed).await?)?
+                }
+                ImportMode::RawText => {
+                    text_import(cx, self.kind.fetch_text_async().await?, span)
+                }
+                ImportMode::Location => self.location_import(cx),
+            };
+            Ok(typed)
+        })
+    }
+
+    fn location_import<'cx>(&self, cx: Ctxt<'cx>) -> Typed<'cx> {
+        let expr = self.kind.to_location();
+        Parsed::from_expr_without_imports(expr)
+            .skip_resolve(cx)
+            .unwrap()
+            .typecheck(cx)
+            .unwrap()
+    }
+}
+
+/// The result of importing some resolved Dhall code.
+fn code_import<'cx>(
+    cx: Ctxt<'cx>,
+    resolved: Resolved<'cx>,
+) -> Result<Typed<'cx>, Error> {
+    let typed = resolved.typecheck(cx)?;
+    Ok(Typed {
+        // TODO: manage to keep the Nir around. Will need fixing variables.
+        hir: typed.normalize(cx).to_hir(),
+        ty: typed.ty,
+    })
+}
+
+/// The result of importing some text with `as Text`.
+fn text_import<'cx>(cx: Ctxt<'cx>, text: String, span: Span) -> Typed<'cx> {
+    Typed {
+        hir: Hir::new(HirKind::Expr(ExprKind::TextLit(text.into())), span),
+        ty: Type::from_builtin(cx, Builtin::Text),
+    }
+}
+
+impl std::fmt::Display for ImportLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.kind {
+            ImportLocationKind::Local(path) => write!(f, "{}", path.display())?,
+            ImportLocationKind::Remote(url) => write!(f, "{}", url)?,
+            ImportLocationKind::Env(var_name) => write!(f, "env:{}", var_name)?,
+            ImportLocationKind::Missing | ImportLocationKind::NoImport => {
+                write!(f, "missing")?
+            }
+        }
+        match self.mode {
+            ImportMode::Code => Ok(()),
+            ImportMode::RawText => write!(f, " as Text"),
+            ImportMode::Location => write!(f, " as Location"),
+        }
+    }
 }
 
 fn mkexpr(kind: UnspannedExpr) -> Expr {
@@ -303,6 +424,23 @@ pub(crate) fn download_http_text(_url: Url) -> Result<String, Error> {
     panic!("Remote imports are not supported on wasm yet")
 }
 
+/// Downloads without blocking the current thread. This uses the async `reqwest` client, which needs
+/// to run within a Tokio runtime.
+#[cfg(all(not(target_arch = "wasm32"), feature = "reqwest"))]
+pub(crate) async fn download_http_text_async(
+    url: Url,
+) -> Result<String, Error> {
+    let other = |e| std::io::Error::new(std::io::ErrorKind::Other, e);
+    let response = reqwest::get(url).await.map_err(other)?;
+    Ok(response.text().await.map_err(other)?)
+}
+#[cfg(any(target_arch = "wasm32", not(feature = "reqwest")))]
+pub(crate) async fn download_http_text_async(
+    url: Url,
+) -> Result<String, Error> {
+    download_http_text(url)
+}
+
 fn make_aslocation_uniontype() -> Expr {
     let text_type = mkexpr(ExprKind::Builtin(Builtin::Text));
     let mut union = BTreeMap::default();
@@ -375,21 +513,12 @@ fn fetch_import<'cx>(
     import_id: ImportId<'cx>,
 ) -> Result<ImportResultId<'cx>, Error> {
     let cx = env.cx();
-    let import = &cx[import_id].import;
     let span = cx[import_id].span.clone();
-    let location = cx[import_id].base_location.chain(import)?;
-
-    // If the hash is in the on-disk cache, return
-    // the cached contents.
-    if let Some(typed) = env.get_from_disk_cache(&import.hash) {
-        // No need to check the hash, it was checked before reading the file.
-        // We also don't write to the in-memory cache, because the location might be completely
-        // unrelated to the cached file (e.g. `missing sha256:...` is valid).
-        // This actually means that importing many times a same hashed import will take
-        // longer than importing many times a same non-hashed import.
-        let res_id = cx.push_import_result(typed);
+    let location = cx[import_id].base_location.chain(&cx[import_id].import)?;
+    if let Some(res_id) = get_from_disk_cache(env, import_id) {
         return Ok(res_id);
     }
+    env.add_dependency(&cx[import_id].base_location, &location);
 
     // If the import is in the in-memory cache return the cached contents. Otherwise fetch the
     // import.
@@ -401,25 +530,80 @@ fn fetch_import<'cx>(
         let res = env.with_cycle_detection(location.clone(), |env| {
             location.fetch(env, span.clone())
         });
-        let typed = match res {
-            Ok(typed) => typed,
-            Err(e) => mkerr(
-                ErrorBuilder::new("error")
-                    .span_err(span.clone(), e.to_string())
-                    .format(),
-            )?,
-        };
+        store_fetched_import(env, location, span, res)?
+    };
+    check_and_cache_import(env, import_id, res_id)
+}
 
-        let res_id = cx.push_import_result(typed);
-        // Cache the mapping from this location to the result.
-        env.write_to_mem_cache(location, res_id);
+/// Like `fetch_import`, but fetches asynchronously.
+async fn fetch_import_async<'cx>(
+    env: &mut ImportEnv<'cx>,
+    import_id: ImportId<'cx>,
+) -> Result<ImportResultId<'cx>, Error> {
+    let cx = env.cx();
+    let span = cx[import_id].span.clone();
+    let location = cx[import_id].base_location.chain(&cx[import_id].import)?;
+    if let Some(res_id) = get_from_disk_cache(env, import_id) {
+        return Ok(res_id);
+    }
+    env.add_dependency(&cx[import_id].base_location, &location);
+
+    let res_id = if let Some(res_id) = env.get_from_mem_cache(&location) {
         res_id
+    } else {
+        env.enter_import(location.clone())?;
+        // WARNING: do not propagate errors here or the stack will get messed up.
+        let res = location.fetch_async(env, span.clone()).await;
+        env.exit_import();
+        store_fetched_import(env, location, span, res)?
+    };
+    check_and_cache_import(env, import_id, res_id)
+}
+
+/// If the hash is in the on-disk cache, return the cached contents.
+fn get_from_disk_cache<'cx>(
+    env: &ImportEnv<'cx>,
+    import_id: ImportId<'cx>,
+) -> Option<ImportResultId<'cx>> {
+    let typed = env.get_from_disk_cache(&env.cx()[import_id].import.hash)?;
+    // No need to check the hash, it was checked before reading the file.
+    // We also don't write to the in-memory cache, because the location might be completely
+    // unrelated to the cached file (e.g. `missing sha256:...` is valid).
+    // This actually means that importing many times a same hashed import will take
+    // longer than importing many times a same non-hashed import.
+    Some(env.cx().push_import_result(typed))
+}
+
+/// Store the result of fetching `location` in the global context and the in-memory cache.
+fn store_fetched_import<'cx>(
+    env: &mut ImportEnv<'cx>,
+    location: ImportLocation,
+    span: Span,
+    res: Result<Typed<'cx>, Error>,
+) -> Result<ImportResultId<'cx>, Error> {
+    let typed = match res {
+        Ok(typed) => typed,
+        Err(e) => mkerr(
+            ErrorBuilder::new("error")
+                .span_err(span, e.to_string())
+                .format(),
+        )?,
     };
 
-    // Add the resolved import to the on-disk cache if the hash matches.
-    env.check_hash(import_id, res_id)?;
-    env.write_to_disk_cache(&import.hash, res_id);
+    let res_id = env.cx().push_import_result(typed);
+    // Cache the mapping from this location to the result.
+    env.write_to_mem_cache(location, res_id);
+    Ok(res_id)
+}
 
+/// Add the resolved import to the on-disk cache if the hash matches.
+fn check_and_cache_import<'cx>(
+    env: &ImportEnv<'cx>,
+    import_id: ImportId<'cx>,
+    res_id: ImportResultId<'cx>,
+) -> Result<ImportResultId<'cx>, Error> {
+    env.check_hash(import_id, res_id)?;
+    env.write_to_disk_cache(&env.cx()[import_id].import.hash, res_id);
     Ok(res_id)
 }
 
@@ -473,8 +657,9 @@ fn traverse_accumulate<'cx>(
                 if let Some(l) = l {
                     name_env.insert_mut(l);
                 }
-                let hir =
-                    traverse_accumulate(env, name_env, nodes, base_location, e);
+                let hir = grow_stack(|| {
+                    traverse_accumulate(env, name_env, nodes, base_location, e)
+                });
                 if l.is_some() {
                     name_env.remove_mut();
                 }
@@ -499,11 +684,98 @@ fn traverse_accumulate<'cx>(
     Hir::new(kind, expr.span())
 }
 
+/// The locations of the imports among `nodes` that are worth fetching ahead of time, i.e. that are
+/// not cached already. Each location appears once.
+fn imports_to_prefetch<'cx>(
+    env: &ImportEnv<'cx>,
+    nodes: &[ImportNode<'cx>],
+) -> Vec<(ImportLocation, Span)> {
+    let cx = env.cx();
+    nodes
+        .iter()
+        .filter_map(|node| match node {
+            ImportNode::Import(import) => Some(&cx[import]),
+            ImportNode::Alternative(_) => None,
+        })
+        .filter(|stored| env.get_from_disk_cache(&stored.import.hash).is_none())
+        .filter_map(|stored| {
+            let location = stored.base_location.chain(&stored.import).ok()?;
+            Some((location, stored.span.clone()))
+        })
+        .filter(|(location, _)| !env.is_cached(location))
+        .unique_by(|(location, _)| location.clone())
+        .collect()
+}
+
+/// Fetches the imports among `nodes` in parallel, and stores the results in the in-memory cache.
+/// This only warms the cache: `resolve_nodes` then goes through the nodes in order as usual, so
+/// errors, cycle detection and hash checks behave exactly as in the sequential case.
+#[cfg(feature = "sync")]
+fn prefetch_imports<'cx>(env: &mut ImportEnv<'cx>, nodes: &[ImportNode<'cx>]) {
+    use rayon::prelude::*;
+    let cx = env.cx();
+    let to_fetch = imports_to_prefetch(env, nodes);
+    if to_fetch.len() < 2 {
+        return;
+    }
+
+    let parent = &*env;
+    let children: Vec<ImportEnv<'cx>> = to_fetch
+        .into_par_iter()
+        .map(|(location, span)| {
+            let mut env = parent.fork();
+            let res = env.with_cycle_detection(location.clone(), |env| {
+                location.fetch(env, span)
+            });
+            if let Ok(typed) = res {
+                let res_id = cx.push_import_result(typed);
+                env.write_to_mem_cache(location, res_id);
+            }
+            env
+        })
+        .collect();
+    for child in children {
+        env.merge_mem_cache(child);
+    }
+}
+
+/// Like `prefetch_imports`, but fetches the imports concurrently on the current task.
+async fn prefetch_imports_async<'cx>(
+    env: &mut ImportEnv<'cx>,
+    nodes: &[ImportNode<'cx>],
+) {
+    let cx = env.cx();
+    let to_fetch = imports_to_prefetch(env, nodes);
+    if to_fetch.len() < 2 {
+        return;
+    }
+
+    let children = to_fetch.into_iter().map(|(location, span)| {
+        let mut env = env.fork();
+        async move {
+            if env.enter_import(location.clone()).is_ok() {
+                let res = location.fetch_async(&mut env, span).await;
+                env.exit_import();
+                if let Ok(typed) = res {
+                    let res_id = cx.push_import_result(typed);
+                    env.write_to_mem_cache(location, res_id);
+                }
+            }
+            env
+        }
+    });
+    for child in futures::future::join_all(children).await {
+        env.merge_mem_cache(child);
+    }
+}
+
 /// Take a list of nodes and recursively resolve them.
 fn resolve_nodes<'cx>(
     env: &mut ImportEnv<'cx>,
     nodes: &[ImportNode<'cx>],
 ) -> Result<(), Error> {
+    #[cfg(feature = "sync")]
+    prefetch_imports(env, nodes);
     for &node in nodes {
         match node {
             ImportNode::Import(import) => {
@@ -539,10 +811,82 @@ fn resolve_with_env<'cx>(
         &expr,
     );
     // Then we resolve them and choose sides for the alternatives.
-    resolve_nodes(env, &nodes)?;
+    let res = resolve_nodes(env, &nodes);
+    // Exceeding a limit fails the whole resolution, even if an alternative was found.
+    env.cx().check_limits()?;
+    res?;
     Ok(Resolved(resolved))
 }
 
+/// Like `resolve_nodes`, but fetches sibling imports concurrently.
+fn resolve_nodes_async<'a, 'cx: 'a>(
+    env: &'a mut ImportEnv<'cx>,
+    nodes: &'a [ImportNode<'cx>],
+) -> BoxFuture<'a, Result<(), Error>> {
+    Box::pin(async move {
+        prefetch_imports_async(env, nodes).await;
+        for &node in nodes {
+            match node {
+                ImportNode::Import(import) => {
+                    let res_id = fetch_import_async(env, import).await?;
+                    env.cx()[import].set_resultid(res_id);
+                }
+                ImportNode::Alternative(alt) => {
+                    let alt = &env.cx()[alt];
+                    if resolve_nodes_async(env, &alt.left_imports).await.is_ok()
+                    {
+                        alt.set_selected(true);
+                    } else {
+                        resolve_nodes_async(env, &alt.right_imports).await?;
+                        alt.set_selected(false);
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+async fn resolve_with_env_async<'cx>(
+    env: &mut ImportEnv<'cx>,
+    parsed: Parsed,
+) -> Result<Resolved<'cx>, Error> {
+    let Parsed(expr, base_location) = parsed;
+    let mut nodes = Vec::new();
+    let resolved = traverse_accumulate(
+        env,
+        &mut NameEnv::new(),
+        &mut nodes,
+        &base_location,
+        &expr,
+    );
+    let res = resolve_nodes_async(env, &nodes).await;
+    env.cx().check_limits()?;
+    res?;
+    Ok(Resolved(resolved))
+}
+
+/// Resolves a file as if it was imported, so that the result is stored in the session of `env`.
+pub(crate) fn resolve_file<'cx>(
+    env: &mut ImportEnv<'cx>,
+    path: &Path,
+) -> Result<Typed<'cx>, Error> {
+    let cx = env.cx();
+    let location = ImportLocation::local_dhall_code(path.to_owned());
+    cx.push_root(location.clone());
+    if let Some(res_id) = env.get_from_mem_cache(&location) {
+        return Ok(cx[res_id].clone());
+    }
+    let typed = env.with_cycle_detection(location.clone(), |env| {
+        location.fetch(env, Span::Artificial)
+    });
+    cx.check_limits()?;
+    let typed = typed?;
+    let res_id = cx.push_import_result(typed);
+    env.write_to_mem_cache(location, res_id);
+    Ok(cx[res_id].clone())
+}
+
 /// Resolves all imports and names. Returns errors if importing failed. Name errors are deferred to
 /// typechecking.
 pub fn resolve<'cx>(
@@ -552,20 +896,31 @@ pub fn resolve<'cx>(
     parsed.resolve_with_env(&mut ImportEnv::new(cx))
 }
 
+/// Like `resolve`, but fetches imports asynchronously, and fetches sibling imports concurrently.
+pub async fn resolve_async<'cx>(
+    cx: Ctxt<'cx>,
+    parsed: Parsed,
+) -> Result<Resolved<'cx>, Error> {
+    cx.push_root(parsed.1.clone());
+    resolve_with_env_async(&mut ImportEnv::new(cx), parsed).await
+}
+
 /// Resolves names, and errors if we find any imports.
 pub fn skip_resolve<'cx>(
     cx: Ctxt<'cx>,
     parsed: Parsed,
 ) -> Result<Resolved<'cx>, Error> {
     let parsed = Parsed::from_expr_without_imports(parsed.0);
-    Ok(resolve(cx, parsed)?)
+    resolve_with_env(&mut ImportEnv::new(cx), parsed)
 }
 
 impl Parsed {
-    fn resolve_with_env<'cx>(
+    /// Resolves an expression that wasn't imported, which makes it a root of the `ImportGraph`.
+    pub(crate) fn resolve_with_env<'cx>(
         self,
         env: &mut ImportEnv<'cx>,
     ) -> Result<Resolved<'cx>, Error> {
+        env.cx().push_root(self.1.clone());
         resolve_with_env(env, self)
     }
 }
@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::Error;
+use crate::semantics::{
+    resolve_file, ImportEnv, ImportLocation, Type, Universe,
+};
+use crate::sync::Rc;
+use crate::syntax::Expr;
+use crate::{Ctxt, Parsed, Program, Resolved, Typed};
+
+/// Resolves imports across several runs, reusing the results of previous runs.
+///
+/// Each run of `Ctxt::with_new` normally starts from scratch, so imports shared between runs
+/// (e.g. a Prelude) get parsed and typechecked again every time. A `Session` instead remembers
+/// every import it resolved, along with the imports it depends on. At the start of each run, the
+/// files that changed since they were read are detected by their modification time and
+/// contents; these and the imports that depend on them are dropped, and everything else is reused
+/// without being typechecked again.
+///
+/// Remote imports are assumed not to change during the lifetime of the session.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), dhall::error::Error> {
+/// use dhall::Session;
+///
+/// let mut session = Session::new();
+/// let config = session.load_file("config.dhall".as_ref())?;
+/// // ... `config.dhall` or one of its imports gets modified ...
+/// // Only the modified file and the files that import it are loaded again.
+/// let config = session.load_file("config.dhall".as_ref())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Session {
+    cache: SessionCache,
+}
+
+/// The results of the imports resolved by a `Session`. Lives in an `ImportEnv` during a run.
+#[derive(Debug, Default, Clone)]
+pub struct SessionCache {
+    entries: HashMap<ImportLocation, Rc<CachedImport>>,
+    /// Imports being fetched in the current run.
+    pending: HashMap<ImportLocation, PendingImport>,
+    /// Sources that were read but whose result isn't stored in `entries`, i.e. the root files
+    /// and the imports that failed, along with the imports they depend on. They're kept to know
+    /// when they change: until then, an import that failed is assumed to fail again, and the
+    /// imports that fell back on an alternative are kept.
+    uncached: HashMap<ImportLocation, PendingImport>,
+}
+
+/// An import resolved in a previous run, stored independently of any `Ctxt`.
+#[derive(Debug, Clone)]
+struct CachedImport {
+    /// The normal form of the import, which is closed.
+    expr: Expr,
+    /// The normal form of its type.
+    ty: Expr,
+    univ: Universe,
+    /// `None` if the import can't change during the session.
+    stamp: Option<Stamp>,
+    /// The imports it refers to, whose results are inlined into `expr`.
+    deps: Vec<ImportLocation>,
+}
+
+#[derive(Debug, Clone)]
+struct PendingImport {
+    stamp: Option<Stamp>,
+    deps: Vec<ImportLocation>,
+}
+
+/// The state of some source when it was read, to detect changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    modified: Option<SystemTime>,
+    hash: Box<[u8]>,
+    taken: SystemTime,
+}
+
+/// Modification times have a coarse granularity on some filesystems, so a file modified shortly
+/// before being stamped could be modified again without its modification time changing. The
+/// contents of such files are always compared.
+const MODIFICATION_TIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+impl Stamp {
+    pub fn new(modified: Option<SystemTime>, contents: &[u8]) -> Self {
+        Stamp {
+            modified,
+            hash: crate::utils::sha256_hash(contents),
+            taken: SystemTime::now(),
+        }
+    }
+    /// Whether the source was not modified since `self` was taken, as far as we can tell from
+    /// its modification time.
+    pub fn is_current(&self, modified: Option<SystemTime>) -> bool {
+        match self.modified {
+            Some(old) if Some(old) == modified => self
+                .taken
+                .duration_since(old)
+                .map(|age| age >= MODIFICATION_TIME_GRANULARITY)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+    fn same_contents(&self, other: &Stamp) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// Resolves the imports of `parsed`, reusing the imports resolved in previous runs that are
+    /// still up to date.
+    pub fn resolve<'cx>(
+        &mut self,
+        cx: Ctxt<'cx>,
+        parsed: Parsed,
+    ) -> Result<Resolved<'cx>, Error> {
+        self.with_env(cx, |env| parsed.resolve_with_env(env))
+    }
+
+    /// Loads a file, typechecks it and normalizes it. The file itself is remembered like its
+    /// imports, so loading it again is cheap if nothing changed.
+    pub fn load_file(&mut self, path: &Path) -> Result<Program, Error> {
+        Ctxt::with_new(|cx| {
+            self.with_env(cx, |env| {
+                let typed = resolve_file(env, path)?;
+                Ok(Program::from_typed(cx, &typed))
+            })
+        })
+    }
+
+    fn with_env<'cx, T>(
+        &mut self,
+        cx: Ctxt<'cx>,
+        f: impl FnOnce(&mut ImportEnv<'cx>) -> T,
+    ) -> T {
+        self.refresh();
+        let cache = std::mem::take(&mut self.cache);
+        let mut env = ImportEnv::with_session(cx, cache);
+        let res = f(&mut env);
+        self.cache = env.into_session_cache();
+        self.cache.end_run();
+        res
+    }
+
+    /// Parses a file. The file is tracked along with the imports, so that `refresh` can tell when
+    /// it changes.
+    pub fn parse_file(&mut self, path: &Path) -> Result<Parsed, Error> {
+        let location = ImportLocation::local_dhall_code(path.to_owned());
+        // Stamp before reading, so that changes made while reading are detected.
+        let pending = PendingImport {
+            stamp: location.stamp(None),
+            deps: Vec::new(),
+        };
+        self.cache.uncached.insert(location, pending);
+        Parsed::parse_file(path)
+    }
+
+    /// The local files read by the session: imports, files passed to `parse_file` or
+    /// `load_file`, and imports that failed. A change to any of them will be picked up by
+    /// `refresh`.
+    pub fn local_files(&self) -> Vec<PathBuf> {
+        let locations =
+            self.cache.entries.keys().chain(self.cache.uncached.keys());
+        let mut files: Vec<PathBuf> = locations
+            .filter_map(|location| location.local_path())
+            .map(Path::to_owned)
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Drops the imports whose source changed since it was read, along with the imports that
+    /// depend on them. Returns how many sources changed or were dropped; if nonzero, the next run
+    /// will give a different result. This is done automatically at the start of each run.
+    pub fn refresh(&mut self) -> usize {
+        self.cache.refresh()
+    }
+
+    /// The number of imports remembered by the session.
+    pub fn len(&self) -> usize {
+        self.cache.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cache.entries.is_empty()
+    }
+    /// Forgets all the imports.
+    pub fn clear(&mut self) {
+        self.cache = SessionCache::default();
+    }
+}
+
+impl SessionCache {
+    /// Copies this cache for a forked `ImportEnv`.
+    pub fn fork(&self) -> Self {
+        SessionCache {
+            entries: self.entries.clone(),
+            pending: HashMap::new(),
+            uncached: HashMap::new(),
+        }
+    }
+    /// Adds the imports resolved in a forked `ImportEnv`, and the ones that failed there.
+    pub fn merge(&mut self, other: Self) {
+        for (location, entry) in other.entries {
+            self.entries.entry(location).or_insert(entry);
+        }
+        for (location, pending) in other.pending {
+            self.pending.entry(location).or_insert(pending);
+        }
+    }
+
+    /// Remembers the imports that were started but not finished during the run, i.e. that
+    /// failed.
+    fn end_run(&mut self) {
+        for (location, pending) in self.pending.drain() {
+            self.uncached.insert(location, pending);
+        }
+    }
+
+    pub fn contains(&self, location: &ImportLocation) -> bool {
+        self.entries.contains_key(location)
+    }
+
+    /// Rebuilds a cached import in the given context, without typechecking it again.
+    pub fn get<'cx>(
+        &self,
+        cx: Ctxt<'cx>,
+        location: &ImportLocation,
+    ) -> Option<Typed<'cx>> {
+        let entry = self.entries.get(location)?;
+        let resolve = |expr: &Expr| {
+            Parsed::from_expr_without_imports(expr.clone())
+                .skip_resolve(cx)
+                .ok()
+                .map(|resolved| resolved.0)
+        };
+        let hir = resolve(&entry.expr)?;
+        let ty = resolve(&entry.ty)?.eval_closed_expr(cx);
+        Some(Typed {
+            hir,
+            ty: Type::new(ty, entry.univ),
+        })
+    }
+
+    /// Starts fetching `location`. Its source is stamped beforehand, so that changes made while
+    /// reading it will be detected on the next run.
+    pub fn start_fetch(&mut self, location: &ImportLocation) {
+        let pending = PendingImport {
+            stamp: location.stamp(None),
+            deps: Vec::new(),
+        };
+        self.pending.insert(location.clone(), pending);
+    }
+
+    /// Records that the import at `location` was found while fetching `importer`.
+    pub fn add_dependency(
+        &mut self,
+        importer: &ImportLocation,
+        location: &ImportLocation,
+    ) {
+        if let Some(pending) = self.pending.get_mut(importer) {
+            pending.deps.push(location.clone());
+        }
+    }
+
+    /// Stores the result of fetching `location`.
+    pub fn finish_fetch<'cx>(
+        &mut self,
+        cx: Ctxt<'cx>,
+        location: ImportLocation,
+        typed: &Typed<'cx>,
+    ) {
+        let pending = match self.pending.remove(&location) {
+            Some(pending) => pending,
+            // Not fetched during this run.
+            None => return,
+        };
+        self.uncached.remove(&location);
+        let entry = CachedImport {
+            expr: typed.hir.to_expr_noopts(cx),
+            ty: typed.ty.as_nir().to_expr(cx, Default::default()),
+            univ: typed.ty.ty(),
+            stamp: pending.stamp,
+            deps: pending.deps,
+        };
+        self.entries.insert(location, Rc::new(entry));
+    }
+
+    fn refresh(&mut self) -> usize {
+        self.pending.clear();
+        // The imports that refer to each import.
+        let mut importers: HashMap<ImportLocation, Vec<ImportLocation>> =
+            HashMap::new();
+        let mut changed = Vec::new();
+        let locations: Vec<_> = self
+            .entries
+            .keys()
+            .chain(self.uncached.keys())
+            .cloned()
+            .collect();
+        for location in locations {
+            let deps = match self.entries.get(&location) {
+                Some(entry) => &entry.deps,
+                None => &self.uncached[&location].deps,
+            };
+            for dep in deps {
+                if !self.entries.contains_key(dep)
+                    && !self.uncached.contains_key(dep)
+                {
+                    // Removed from the cache.
+                    changed.push(dep.clone());
+                }
+                importers
+                    .entry(dep.clone())
+                    .or_default()
+                    .push(location.clone());
+            }
+            if !self.check_stamp(&location) {
+                changed.push(location);
+            }
+        }
+
+        // Drop the sources that changed and the imports that depend on them, directly or not.
+        let mut outdated = HashSet::new();
+        while let Some(location) = changed.pop() {
+            if outdated.insert(location.clone()) {
+                changed.extend(importers.remove(&location).unwrap_or_default());
+            }
+        }
+        outdated
+            .iter()
+            .filter(|location| {
+                self.entries.remove(location).is_some()
+                    | self.uncached.remove(location).is_some()
+            })
+            .count()
+    }
+
+    /// Whether the source at `location` still has the contents it had when it was read. If so,
+    /// its stamp is brought up to date.
+    fn check_stamp(&mut self, location: &ImportLocation) -> bool {
+        let old = match self.entries.get(location) {
+            Some(entry) => &entry.stamp,
+            None => &self.uncached[location].stamp,
+        };
+        let old = match old {
+            Some(stamp) => stamp.clone(),
+            None => return true,
+        };
+        match restamp(location, &old) {
+            Some(new) if new == old => true,
+            Some(new) => {
+                // The contents didn't change; remember the new stamp.
+                match self.entries.get_mut(location) {
+                    Some(entry) => Rc::make_mut(entry).stamp = Some(new),
+                    None => {
+                        self.uncached.get_mut(location).unwrap().stamp =
+                            Some(new)
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Checks whether the source at `location` still has the contents it had when `old` was taken.
+/// If so, returns an up-to-date stamp.
+fn restamp(location: &ImportLocation, old: &Stamp) -> Option<Stamp> {
+    let new = location.stamp(Some(old))?;
+    if new.same_contents(old) {
+        Some(new)
+    } else {
+        None
+    }
+}
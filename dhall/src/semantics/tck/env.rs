@@ -14,6 +14,8 @@ pub struct TyEnv<'cx> {
     cx: Ctxt<'cx>,
     names: NameEnv,
     items: ValEnv<'cx, Type<'cx>>,
+    /// `items` without the types, kept alongside so that it doesn't need to be rebuilt.
+    values: NzEnv<'cx>,
 }
 
 impl VarEnv {
@@ -46,6 +48,7 @@ impl<'cx> TyEnv<'cx> {
             cx,
             names: NameEnv::new(),
             items: ValEnv::new(cx),
+            values: NzEnv::new(cx),
         }
     }
     pub fn cx(&self) -> Ctxt<'cx> {
@@ -55,7 +58,7 @@ impl<'cx> TyEnv<'cx> {
         self.names.as_varenv()
     }
     pub fn to_nzenv(&self) -> NzEnv<'cx> {
-        self.items.discard_types()
+        self.values.clone()
     }
     pub fn as_nameenv(&self) -> &NameEnv {
         &self.names
@@ -66,13 +69,15 @@ impl<'cx> TyEnv<'cx> {
             cx: self.cx,
             names: self.names.insert(x),
             items: self.items.insert_type(ty),
+            values: self.values.insert_type(()),
         }
     }
     pub fn insert_value(&self, x: &Label, e: Nir<'cx>, ty: Type<'cx>) -> Self {
         TyEnv {
             cx: self.cx,
             names: self.names.insert(x),
-            items: self.items.insert_value(e, ty),
+            items: self.items.insert_value(e.clone(), ty),
+            values: self.values.insert_value(e, ()),
         }
     }
     pub fn lookup(&self, var: AlphaVar) -> Type<'cx> {
@@ -0,0 +1,144 @@
+use std::ops::Range;
+
+use crate::semantics::{NameEnv, Type};
+use crate::syntax::{Expr, Label, Span};
+use crate::Ctxt;
+
+/// The types of the subexpressions of a typechecked expression, indexed by their location in the
+/// source. Built with `Resolved::type_index`.
+///
+/// Besides subexpressions, the index contains the variables bound by `λ`, `∀` and `let`, at the
+/// place where they are bound. Subexpressions that don't come from the source text (e.g. the
+/// contents of imports, or parts of desugared expressions) are not in the index.
+#[derive(Debug, Clone)]
+pub struct TypeIndex<'cx> {
+    cx: Ctxt<'cx>,
+    /// The variables bound in the expression, with the scope they are bound in.
+    scopes: Vec<(Option<usize>, Label)>,
+    /// While typechecking, the innermost scope.
+    current_scope: Option<usize>,
+    entries: Vec<Entry<'cx>>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<'cx> {
+    range: Range<usize>,
+    binder: Option<Label>,
+    ty: Type<'cx>,
+    /// The innermost scope the type lives in.
+    scope: Option<usize>,
+}
+
+/// A subexpression or a bound variable in a `TypeIndex`, with its type.
+#[derive(Debug, Clone, Copy)]
+pub struct TypeAt<'a, 'cx> {
+    index: &'a TypeIndex<'cx>,
+    entry: &'a Entry<'cx>,
+}
+
+impl<'cx> TypeIndex<'cx> {
+    pub(crate) fn new(cx: Ctxt<'cx>) -> Self {
+        TypeIndex {
+            cx,
+            scopes: Vec::new(),
+            current_scope: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records the type of the expression at `span`. Subexpressions must be recorded before the
+    /// expressions that contain them.
+    pub(crate) fn record(&mut self, span: &Span, ty: &Type<'cx>) {
+        if let Span::Parsed(span) = span {
+            self.entries.push(Entry {
+                range: span.range(),
+                binder: None,
+                ty: ty.clone(),
+                scope: self.current_scope,
+            })
+        }
+    }
+    /// Records the type of the variable bound by the expression at `span`, and enters the scope of
+    /// that variable.
+    pub(crate) fn enter_binder(
+        &mut self,
+        span: &Span,
+        binder: &Label,
+        ty: &Type<'cx>,
+    ) {
+        if let Span::Parsed(span) = span {
+            if let Some(binder_span) = span.binder(binder) {
+                self.entries.push(Entry {
+                    range: binder_span.range(),
+                    binder: Some(binder.clone()),
+                    ty: ty.clone(),
+                    scope: self.current_scope,
+                })
+            }
+        }
+        self.scopes.push((self.current_scope, binder.clone()));
+        self.current_scope = Some(self.scopes.len() - 1);
+    }
+    /// Leaves the scope of the innermost bound variable.
+    pub(crate) fn exit_binder(&mut self) {
+        let scope = self.current_scope.expect("no scope to exit");
+        self.current_scope = self.scopes[scope].0;
+    }
+
+    /// The innermost subexpression or bound variable at the given byte offset in the source.
+    pub fn at(&self, offset: usize) -> Option<TypeAt<'_, 'cx>> {
+        // When several expressions have the same location, the first one recorded is the
+        // innermost.
+        self.entries
+            .iter()
+            .filter(|entry| entry.range.contains(&offset))
+            .min_by_key(|entry| entry.range.len())
+            .map(|entry| TypeAt { index: self, entry })
+    }
+    /// All the subexpressions and bound variables in the index. Subexpressions come before the
+    /// expressions that contain them.
+    pub fn iter(&self) -> impl Iterator<Item = TypeAt<'_, 'cx>> {
+        self.entries
+            .iter()
+            .map(move |entry| TypeAt { index: self, entry })
+    }
+}
+
+impl<'a, 'cx> TypeAt<'a, 'cx> {
+    /// The byte range of the subexpression or bound variable in the source.
+    pub fn range(&self) -> Range<usize> {
+        self.entry.range.clone()
+    }
+    /// The variable, if this is the place where a variable is bound.
+    pub fn binder(&self) -> Option<&'a Label> {
+        self.entry.binder.as_ref()
+    }
+    pub fn ty(&self) -> &'a Type<'cx> {
+        &self.entry.ty
+    }
+    /// The names of the variables in scope, outermost first.
+    fn names(&self) -> NameEnv {
+        let mut labels = Vec::new();
+        let mut scope = self.entry.scope;
+        while let Some(i) = scope {
+            let (parent, label) = &self.index.scopes[i];
+            labels.push(label);
+            scope = *parent;
+        }
+        let mut names = NameEnv::new();
+        for label in labels.into_iter().rev() {
+            names.insert_mut(label);
+        }
+        names
+    }
+    /// Converts the type back to an AST expression. Its free variables refer to the variables in
+    /// scope at that location.
+    pub fn to_expr(&self) -> Expr {
+        let names = self.names();
+        self.entry
+            .ty
+            .as_nir()
+            .to_hir(names.as_varenv())
+            .to_expr_nameenv(self.index.cx, &names)
+    }
+}
@@ -1,6 +1,8 @@
 pub mod env;
+pub mod index;
 pub mod tir;
 pub mod typecheck;
 pub use env::*;
+pub use index::*;
 pub use tir::*;
 pub use typecheck::*;
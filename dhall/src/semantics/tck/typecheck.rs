@@ -3,8 +3,11 @@ use std::cmp::max;
 use crate::builtins::{type_of_builtin, Builtin};
 use crate::error::{ErrorBuilder, TypeError, TypeMessage};
 use crate::operations::typecheck_operation;
-use crate::semantics::{Hir, HirKind, Nir, NirKind, Tir, TyEnv, Type};
+use crate::semantics::{
+    Hir, HirKind, Nir, NirKind, Tir, TyEnv, Type, TypeIndex,
+};
 use crate::syntax::{Const, ExprKind, InterpolatedTextContents, NumKind, Span};
+use crate::utils::grow_stack;
 use crate::Ctxt;
 
 fn function_check(a: Const, b: Const) -> Const {
@@ -181,6 +184,26 @@ pub fn type_with<'cx, 'hir>(
     env: &TyEnv<'cx>,
     hir: &'hir Hir<'cx>,
     annot: Option<Type<'cx>>,
+) -> Result<Tir<'cx, 'hir>, TypeError> {
+    type_with_index(env, hir, annot, None)
+}
+
+/// Like `type_with`, and records the types of the subexpressions in `index` if there is one.
+fn type_with_index<'cx, 'hir>(
+    env: &TyEnv<'cx>,
+    hir: &'hir Hir<'cx>,
+    annot: Option<Type<'cx>>,
+    index: Option<&mut TypeIndex<'cx>>,
+) -> Result<Tir<'cx, 'hir>, TypeError> {
+    // Typechecking recurses into the subexpressions.
+    grow_stack(|| type_with_unguarded(env, hir, annot, index))
+}
+
+fn type_with_unguarded<'cx, 'hir>(
+    env: &TyEnv<'cx>,
+    hir: &'hir Hir<'cx>,
+    annot: Option<Type<'cx>>,
+    mut index: Option<&mut TypeIndex<'cx>>,
 ) -> Result<Tir<'cx, 'hir>, TypeError> {
     let tir = match hir.kind() {
         HirKind::Var(var) => Tir::from_hir(hir, env.lookup(*var)),
@@ -194,12 +217,17 @@ pub fn type_with<'cx, 'hir>(
             Tir::from_hir(hir, typed.ty.clone())
         }
         HirKind::ImportAlternative(alt, left, right) => {
-            let hir = if env.cx()[alt].unwrap_selected() {
+            let selected = if env.cx()[alt].unwrap_selected() {
                 left
             } else {
                 right
             };
-            return type_with(env, hir, annot);
+            let tir =
+                type_with_index(env, selected, annot, index.as_deref_mut())?;
+            if let Some(index) = index {
+                index.record(&hir.span(), tir.ty());
+            }
+            return Ok(tir);
         }
         HirKind::Expr(ExprKind::Var(_)) => {
             unreachable!("Hir should contain no unresolved variables")
@@ -212,16 +240,25 @@ pub fn type_with<'cx, 'hir>(
                 HirKind::Expr(ExprKind::Const(Const::Sort)) => {
                     Type::from_const(Const::Sort)
                 }
-                _ => type_with(env, t, None)?.eval_to_type(env)?,
+                _ => type_with_index(env, t, None, index.as_deref_mut())?
+                    .eval_to_type(env)?,
             };
-            type_with(env, x, Some(t))?
+            type_with_index(env, x, Some(t), index.as_deref_mut())?
         }
 
         HirKind::Expr(ExprKind::Lam(binder, annot, body)) => {
-            let annot = type_with(env, annot, None)?;
+            let annot =
+                type_with_index(env, annot, None, index.as_deref_mut())?;
             let annot_nf = annot.eval_to_type(env)?;
+            if let Some(index) = index.as_deref_mut() {
+                index.enter_binder(&hir.span(), binder, &annot_nf);
+            }
             let body_env = env.insert_type(binder, annot_nf);
-            let body = type_with(&body_env, body, None)?;
+            let body =
+                type_with_index(&body_env, body, None, index.as_deref_mut())?;
+            if let Some(index) = index.as_deref_mut() {
+                index.exit_binder();
+            }
 
             let u_annot = annot.ty().as_const().unwrap();
             let u_body = match body.ty().ty().as_const() {
@@ -242,11 +279,19 @@ pub fn type_with<'cx, 'hir>(
             Tir::from_hir(hir, ty)
         }
         HirKind::Expr(ExprKind::Pi(binder, annot, body)) => {
-            let annot = type_with(env, annot, None)?;
+            let annot =
+                type_with_index(env, annot, None, index.as_deref_mut())?;
             let annot_val = annot.eval_to_type(env)?;
+            if let Some(index) = index.as_deref_mut() {
+                index.enter_binder(&hir.span(), binder, &annot_val);
+            }
             let body_env = env.insert_type(binder, annot_val);
-            let body = type_with(&body_env, body, None)?;
-            body.ensure_is_type(env)?;
+            let body =
+                type_with_index(&body_env, body, None, index.as_deref_mut())?;
+            body.ensure_is_type(&body_env)?;
+            if let Some(index) = index.as_deref_mut() {
+                index.exit_binder();
+            }
 
             let ks = annot.ty().as_const().unwrap();
             let kt = body.ty().as_const().unwrap();
@@ -254,19 +299,32 @@ pub fn type_with<'cx, 'hir>(
             Tir::from_hir(hir, ty)
         }
         HirKind::Expr(ExprKind::Let(binder, annot, val, body)) => {
-            let val_annot = annot
-                .as_ref()
-                .map(|t| Ok(type_with(env, t, None)?.eval_to_type(env)?))
-                .transpose()?;
-            let val = type_with(env, &val, val_annot)?;
+            let val_annot = match annot {
+                Some(t) => Some(
+                    type_with_index(env, t, None, index.as_deref_mut())?
+                        .eval_to_type(env)?,
+                ),
+                None => None,
+            };
+            let val =
+                type_with_index(env, val, val_annot, index.as_deref_mut())?;
+            if let Some(index) = index.as_deref_mut() {
+                index.enter_binder(&hir.span(), binder, val.ty());
+            }
             let val_nf = val.eval(env);
             let body_env = env.insert_value(&binder, val_nf, val.ty().clone());
-            let body = type_with(&body_env, body, None)?;
+            let body =
+                type_with_index(&body_env, body, None, index.as_deref_mut())?;
+            if let Some(index) = index.as_deref_mut() {
+                index.exit_binder();
+            }
             let ty = body.ty().clone();
             Tir::from_hir(hir, ty)
         }
         HirKind::Expr(ekind) => {
-            let ekind = ekind.traverse_ref(|e| type_with(env, e, None))?;
+            let ekind = ekind.traverse_ref(|e| {
+                type_with_index(env, e, None, index.as_deref_mut())
+            })?;
             let ty = type_one_layer(env, ekind, hir.span())?;
             Tir::from_hir(hir, ty)
         }
@@ -285,6 +343,9 @@ pub fn type_with<'cx, 'hir>(
         }
     }
 
+    if let Some(index) = index {
+        index.record(&hir.span(), tir.ty());
+    }
     Ok(tir)
 }
 
@@ -297,6 +358,16 @@ pub fn typecheck<'cx, 'hir>(
     type_with(&TyEnv::new(cx), hir, None)
 }
 
+/// Like `typecheck`, but additionally records the types of the subexpressions in a `TypeIndex`.
+pub fn typecheck_indexed<'cx, 'hir>(
+    cx: Ctxt<'cx>,
+    hir: &'hir Hir<'cx>,
+) -> Result<(Tir<'cx, 'hir>, TypeIndex<'cx>), TypeError> {
+    let mut index = TypeIndex::new(cx);
+    let tir = type_with_index(&TyEnv::new(cx), hir, None, Some(&mut index))?;
+    Ok((tir, index))
+}
+
 /// Like `typecheck`, but additionally checks that the expression's type matches the provided type.
 pub fn typecheck_with<'cx, 'hir>(
     cx: Ctxt<'cx>,
@@ -0,0 +1,59 @@
+//! Shared-ownership and lazy-initialization primitives used throughout the crate.
+//!
+//! By default these are the cheap single-threaded versions. With the `sync` feature, they are
+//! swapped for their thread-safe counterparts, which makes `Parsed`, `Typed`, `Nir` etc. `Send`
+//! and `Sync`, and lets imports be resolved in parallel.
+
+#[cfg(not(feature = "sync"))]
+pub use futures::future::LocalBoxFuture as BoxFuture;
+#[cfg(not(feature = "sync"))]
+pub use once_cell::unsync::OnceCell;
+#[cfg(not(feature = "sync"))]
+pub use std::rc::Rc;
+
+/// With the `sync` feature, `BoxFuture` is `Send`.
+#[cfg(feature = "sync")]
+pub use futures::future::BoxFuture;
+#[cfg(feature = "sync")]
+pub use once_cell::sync::OnceCell;
+/// With the `sync` feature, `Rc` is actually an `Arc`.
+#[cfg(feature = "sync")]
+pub use std::sync::Arc as Rc;
+
+/// A cell whose contents can be taken out once, through a shared reference.
+pub(crate) struct TakeCell<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: std::cell::Cell<Option<T>>,
+    #[cfg(feature = "sync")]
+    inner: std::sync::Mutex<Option<T>>,
+}
+
+impl<T> TakeCell<T> {
+    pub(crate) fn new(x: Option<T>) -> Self {
+        TakeCell { inner: x.into() }
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn take(&self) -> Option<T> {
+        self.inner.take()
+    }
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn get_mut(&mut self) -> &mut Option<T> {
+        self.inner.get_mut()
+    }
+    #[cfg(feature = "sync")]
+    pub(crate) fn take(&self) -> Option<T> {
+        // A panic while holding the lock can't leave the `Option` in an inconsistent state.
+        match self.inner.lock() {
+            Ok(mut x) => x.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        }
+    }
+    #[cfg(feature = "sync")]
+    pub(crate) fn get_mut(&mut self) -> &mut Option<T> {
+        match self.inner.get_mut() {
+            Ok(x) => x,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
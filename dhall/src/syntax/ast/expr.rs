@@ -166,6 +166,13 @@ impl Expr {
     pub fn kind(&self) -> &UnspannedExpr {
         &self.kind
     }
+    /// Changing the kind of an expression keeps its span.
+    pub fn kind_mut(&mut self) -> &mut UnspannedExpr {
+        &mut self.kind
+    }
+    pub fn into_kind(mut self) -> UnspannedExpr {
+        std::mem::replace(&mut *self.kind, ExprKind::Const(Const::Type))
+    }
     pub fn span(&self) -> Span {
         self.span.clone()
     }
@@ -177,10 +184,25 @@ impl Expr {
         }
     }
 
+    /// Visit this expression and its subexpressions.
+    pub fn visit(&self, visitor: &mut impl visitor::Visitor) {
+        visitor.visit_expr(self, &mut visitor::Scope::new())
+    }
+    /// Visit this expression and its subexpressions, changing them in place.
+    pub fn visit_mut(&mut self, visitor: &mut impl visitor::VisitorMut) {
+        visitor.visit_expr_mut(self, &mut visitor::Scope::new())
+    }
+    /// Rebuild this expression with the given fold.
+    pub fn fold(self, folder: &mut impl visitor::Fold) -> Expr {
+        folder.fold_expr(self, &mut visitor::Scope::new())
+    }
+
     // Compute the sha256 hash of the binary form of the expression.
     pub fn sha256_hash(&self) -> Result<Box<[u8]>, Error> {
-        let data = binary::encode(self)?;
-        Ok(crate::utils::sha256_hash(&data))
+        use sha2::Digest;
+        let mut hasher = sha2::Sha256::new();
+        binary::encode_to_writer(self, &mut hasher)?;
+        Ok(hasher.finalize().as_slice().into())
     }
 }
 
@@ -228,6 +250,26 @@ impl From<Label> for V {
     }
 }
 
+/// Dropping a deep expression recursively could overflow the stack, so the subexpressions are moved
+/// out and dropped one at a time.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut kinds = Vec::new();
+        take_subexprs(&mut self.kind, &mut kinds);
+        while let Some(mut kind) = kinds.pop() {
+            take_subexprs(&mut kind, &mut kinds);
+        }
+    }
+}
+
+/// Moves out the contents of the subexpressions, leaving constants behind.
+fn take_subexprs(kind: &mut ExprKind<Expr>, kinds: &mut Vec<ExprKind<Expr>>) {
+    visitor::visit_each_mut(kind, |_, e| {
+        let leaf = ExprKind::Const(Const::Type);
+        kinds.push(std::mem::replace(&mut *e.kind, leaf));
+    })
+}
+
 impl std::cmp::PartialEq for Expr {
     fn eq(&self, other: &Self) -> bool {
         self.kind == other.kind
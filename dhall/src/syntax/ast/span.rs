@@ -1,4 +1,7 @@
-use std::rc::Rc;
+use std::ops::Range;
+
+use crate::sync::Rc;
+use crate::syntax::Label;
 
 /// A location in the source text
 #[derive(Debug, Clone)]
@@ -32,6 +35,67 @@ impl ParsedSpan {
     pub fn to_input(&self) -> String {
         self.input.to_string()
     }
+    /// The byte range of the span in the input.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+    /// The whole input the span points into.
+    pub(crate) fn input(&self) -> &str {
+        &self.input
+    }
+    /// Another span of the same input.
+    pub(crate) fn with_range(&self, range: Range<usize>) -> Self {
+        assert!(self.input.is_char_boundary(range.start));
+        assert!(self.input.is_char_boundary(range.end));
+        ParsedSpan {
+            input: self.input.clone(),
+            start: range.start,
+            end: range.end,
+        }
+    }
+    /// For the span of a `λ`, `∀` or `let` expression, the span of the variable it binds. Returns
+    /// `None` if the variable is not found where expected.
+    pub(crate) fn binder(&self, label: &Label) -> Option<Self> {
+        let text = self.as_str();
+        let keyword = ["λ", "\\", "∀", "forall", "let"]
+            .iter()
+            .find(|keyword| text.starts_with(*keyword))?;
+        // `forall` and `let` could be the start of a label, in a `∀` without a binder.
+        if text[keyword.len()..].starts_with(is_label_char) {
+            return None;
+        }
+        let mut i = skip_whitespace(text, keyword.len());
+        if text[i..].starts_with('(') {
+            i = skip_whitespace(text, i + 1);
+        }
+        let rest = &text[i..];
+        let (name, len) = if rest.starts_with('`') {
+            let name = rest.split('`').nth(1)?;
+            (name, name.len() + 2)
+        } else {
+            let len =
+                rest.find(|c: char| !is_label_char(c)).unwrap_or(rest.len());
+            (&rest[..len], len)
+        };
+        if name != String::from(label) {
+            return None;
+        }
+        let start = self.start + i;
+        Some(self.with_range(start..start + len))
+    }
+    /// The spanned text.
+    pub fn as_str(&self) -> &str {
+        &self.input[self.start..self.end]
+    }
+    /// The line and column where the span starts, both starting from 1. Columns are counted in
+    /// chars.
+    pub fn line_col(&self) -> (usize, usize) {
+        let before = &self.input[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let col = before[line_start..].chars().count() + 1;
+        (line, col)
+    }
     /// Convert to a char range for consumption by annotate_snippets.
     /// This compensates for  https://github.com/rust-lang/annotate-snippets-rs/issues/24
     pub fn as_char_range(&self) -> (usize, usize) {
@@ -92,3 +156,43 @@ fn char_idx_from_byte_idx(input: &str, idx: usize) -> usize {
         .map(|(char_i, _)| char_i)
         .unwrap()
 }
+
+/// Whether the character can appear in an unquoted label.
+pub(crate) fn is_label_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '/' || c == '_'
+}
+
+/// Skips whitespace and comments, starting at byte `i` of `text`. Returns the index of the next
+/// token.
+pub(crate) fn skip_whitespace(text: &str, mut i: usize) -> usize {
+    loop {
+        let rest = &text[i..];
+        if rest.starts_with("--") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("{-") {
+            // Block comments nest.
+            let mut depth = 0;
+            let mut j = 0;
+            while j < rest.len() {
+                if rest[j..].starts_with("{-") {
+                    depth += 1;
+                    j += 2;
+                } else if rest[j..].starts_with("-}") {
+                    depth -= 1;
+                    j += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    j += rest[j..].chars().next().unwrap().len_utf8();
+                }
+            }
+            i += j;
+        } else {
+            match rest.chars().next() {
+                Some(c) if c.is_whitespace() => i += c.len_utf8(),
+                _ => return i,
+            }
+        }
+    }
+}
@@ -86,6 +86,10 @@ impl<SubExpr> InterpolatedText<SubExpr> {
         &mut self.head
     }
 
+    pub fn tail_mut(&mut self) -> &mut Vec<(SubExpr, String)> {
+        &mut self.tail
+    }
+
     pub fn is_empty(&self) -> bool {
         self.head.is_empty() && self.tail.is_empty()
     }
@@ -1,7 +1,40 @@
+//! Traversals of the syntax tree.
+//!
+//! [`Visitor`], [`VisitorMut`] and [`Fold`] walk an [`Expr`] and its subexpressions, keeping
+//! track in a [`Scope`] of the variables bound around the current subexpression. Override the
+//! method of the trait to act on each expression, and call the matching `walk` function from it
+//! to carry on into the subexpressions.
+//!
+//! ```
+//! # use dhall::syntax::{Expr, ExprKind, Label, V};
+//! # use dhall::syntax::visitor::{self, Scope, Visitor};
+//! /// Collects the free variables of an expression.
+//! struct FreeVars(Vec<V>);
+//!
+//! impl Visitor for FreeVars {
+//!     fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+//!         if let ExprKind::Var(var) = expr.kind() {
+//!             if !scope.is_bound(var) {
+//!                 self.0.push(var.clone());
+//!             }
+//!         }
+//!         visitor::walk(self, expr, scope)
+//!     }
+//! }
+//! ```
+//!
+//! [`Visitor`]: trait.Visitor.html
+//! [`VisitorMut`]: trait.VisitorMut.html
+//! [`Fold`]: trait.Fold.html
+//! [`Scope`]: struct.Scope.html
+//! [`Expr`]: ../struct.Expr.html
+
 use itertools::Itertools;
 use std::iter::FromIterator;
 
+use crate::operations::OpKind;
 use crate::syntax::*;
+use crate::utils::grow_stack;
 
 fn opt<'a, T, U, Err>(
     x: &'a Option<T>,
@@ -71,3 +104,212 @@ where
         Import(i) => Import(i.traverse_ref(expr!())?),
     })
 }
+
+macro_rules! make_visit_each {
+    ($name:ident, $($mut:ident)?, $iter:ident, $values:ident, $tail:ident) => {
+        /// Calls `f` on each subexpression, along with the variable it is under the binder of, if
+        /// any.
+        pub fn $name<'a, F, SE>(input: &'a $($mut)? ExprKind<SE>, mut f: F)
+        where
+            F: FnMut(Option<&'a Label>, &'a $($mut)? SE),
+        {
+            use crate::syntax::ExprKind::*;
+            match input {
+                Var(_) | Const(_) | Num(_) | Builtin(_) => {}
+                Lam(l, t, e) | Pi(l, t, e) => {
+                    f(None, t);
+                    f(Some(l), e);
+                }
+                Let(l, t, a, e) => {
+                    if let Some(t) = t {
+                        f(None, t);
+                    }
+                    f(None, a);
+                    f(Some(l), e);
+                }
+                TextLit(t) => t.$tail().$iter().for_each(|(e, _)| f(None, e)),
+                SomeLit(e) | EmptyListLit(e) | Assert(e) => f(None, e),
+                NEListLit(es) => es.$iter().for_each(|e| f(None, e)),
+                RecordType(kts) | RecordLit(kts) => {
+                    kts.$values().for_each(|e| f(None, e))
+                }
+                UnionType(kts) => {
+                    kts.$values().flatten().for_each(|e| f(None, e))
+                }
+                Op(op) => {
+                    use OpKind::*;
+                    match op {
+                        App(x, y)
+                        | BinOp(_, x, y)
+                        | ProjectionByExpr(x, y)
+                        | Completion(x, y)
+                        | With(x, _, y) => {
+                            f(None, x);
+                            f(None, y);
+                        }
+                        BoolIf(x, y, z) => {
+                            f(None, x);
+                            f(None, y);
+                            f(None, z);
+                        }
+                        Merge(x, y, t) => {
+                            f(None, x);
+                            f(None, y);
+                            if let Some(t) = t {
+                                f(None, t);
+                            }
+                        }
+                        ToMap(x, t) => {
+                            f(None, x);
+                            if let Some(t) = t {
+                                f(None, t);
+                            }
+                        }
+                        Field(x, _) | Projection(x, _) => f(None, x),
+                    }
+                }
+                Annot(x, t) => {
+                    f(None, x);
+                    f(None, t);
+                }
+                Import(i) => {
+                    if let ImportTarget::Remote(url) = &$($mut)? i.location {
+                        if let Some(headers) = &$($mut)? url.headers {
+                            f(None, headers);
+                        }
+                    }
+                }
+            }
+        }
+    };
+}
+
+make_visit_each!(visit_each, , iter, values, tail);
+make_visit_each!(visit_each_mut, mut, iter_mut, values_mut, tail_mut);
+
+/// The variables bound around a subexpression, from the outermost to the innermost. Only the
+/// binders inside the traversed expression are known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scope {
+    binders: Vec<Label>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Scope::default()
+    }
+    pub fn binders(&self) -> &[Label] {
+        &self.binders
+    }
+    /// How many binders there are between the variable and the one it refers to, or `None` if it
+    /// is free.
+    pub fn lookup(&self, var: &V) -> Option<usize> {
+        let V(label, n) = var;
+        self.binders
+            .iter()
+            .rev()
+            .enumerate()
+            .filter(|(_, l)| *l == label)
+            .nth(*n)
+            .map(|(i, _)| i)
+    }
+    pub fn is_bound(&self, var: &V) -> bool {
+        self.lookup(var).is_some()
+    }
+    pub fn push(&mut self, label: Label) {
+        self.binders.push(label)
+    }
+    pub fn pop(&mut self) -> Option<Label> {
+        self.binders.pop()
+    }
+
+    /// Run `f` under the given binder, if any.
+    fn under<T>(
+        &mut self,
+        binder: Option<&Label>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        match binder {
+            None => f(self),
+            Some(label) => {
+                self.push(label.clone());
+                let x = f(self);
+                self.pop();
+                x
+            }
+        }
+    }
+}
+
+/// Walks an expression tree by reference.
+pub trait Visitor {
+    /// Called on each expression, before its subexpressions. The default implementation visits
+    /// the subexpressions with [`walk`](fn.walk.html).
+    fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+        walk(self, expr, scope)
+    }
+}
+
+/// Calls `visitor` on the immediate subexpressions of `expr`.
+pub fn walk<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    expr: &Expr,
+    scope: &mut Scope,
+) {
+    grow_stack(|| {
+        visit_each(expr.kind(), |binder, e| {
+            scope.under(binder, |scope| visitor.visit_expr(e, scope))
+        })
+    })
+}
+
+/// Walks an expression tree by mutable reference, to change it in place. Changing the kind of an
+/// expression with [`Expr::kind_mut`](../struct.Expr.html#method.kind_mut) keeps its span.
+pub trait VisitorMut {
+    /// Called on each expression, before its subexpressions. The default implementation visits
+    /// the subexpressions with [`walk_mut`](fn.walk_mut.html).
+    fn visit_expr_mut(&mut self, expr: &mut Expr, scope: &mut Scope) {
+        walk_mut(self, expr, scope)
+    }
+}
+
+/// Calls `visitor` on the immediate subexpressions of `expr`.
+pub fn walk_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expr: &mut Expr,
+    scope: &mut Scope,
+) {
+    grow_stack(|| {
+        visit_each_mut(expr.kind_mut(), |binder, e| {
+            scope.under(binder, |scope| visitor.visit_expr_mut(e, scope))
+        })
+    })
+}
+
+/// Rebuilds an expression tree.
+pub trait Fold {
+    /// Called on each expression. The default implementation rebuilds it with its subexpressions
+    /// folded, with [`walk_fold`](fn.walk_fold.html).
+    fn fold_expr(&mut self, expr: Expr, scope: &mut Scope) -> Expr {
+        walk_fold(self, expr, scope)
+    }
+}
+
+/// Rebuilds `expr`, with the same span, after calling `folder` on its immediate subexpressions.
+pub fn walk_fold<F: Fold + ?Sized>(
+    folder: &mut F,
+    expr: Expr,
+    scope: &mut Scope,
+) -> Expr {
+    grow_stack(|| {
+        let span = expr.span();
+        let mut kind = expr.into_kind();
+        visit_each_mut(&mut kind, |binder, e| {
+            let placeholder =
+                Expr::new(ExprKind::Const(Const::Type), Span::Artificial);
+            let e_ = std::mem::replace(e, placeholder);
+            *e = scope.under(binder, |scope| folder.fold_expr(e_, scope));
+        });
+        Expr::new(kind, span)
+    })
+}
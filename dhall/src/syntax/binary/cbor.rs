@@ -0,0 +1,469 @@
+//! A CBOR deserializer. `serde_cbor`'s gives up on values nested more than 128 levels deep, which
+//! ordinary Dhall expressions easily are; this one has no limit.
+use serde::de::{self, DeserializeSeed, Visitor};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::utils::grow_stack;
+
+pub type Error = serde_cbor::Error;
+
+/// Where the bytes come from.
+pub trait Input {
+    /// Reads exactly `n` bytes.
+    fn read(&mut self, n: usize) -> Result<&[u8], Error>;
+    /// The next byte, without consuming it.
+    fn peek(&mut self) -> Result<Option<u8>, Error>;
+    /// How many bytes were consumed so far.
+    fn offset(&self) -> usize;
+    /// The input from `offset` on, if it is still available.
+    fn rest_from(&self, offset: usize) -> Option<&[u8]>;
+}
+
+pub struct SliceInput<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceInput<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        SliceInput { data, pos: 0 }
+    }
+}
+
+impl<'a> Input for SliceInput<'a> {
+    fn read(&mut self, n: usize) -> Result<&[u8], Error> {
+        if self.data.len() - self.pos < n {
+            return Err(eof(self.data.len()));
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        Ok(self.data.get(self.pos).copied())
+    }
+    fn offset(&self) -> usize {
+        self.pos
+    }
+    fn rest_from(&self, offset: usize) -> Option<&[u8]> {
+        self.data.get(offset..)
+    }
+}
+
+/// Bytes that were read are dropped, so `rest_from` always returns `None`.
+pub struct ReaderInput<R> {
+    reader: R,
+    peeked: Option<u8>,
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl<R: io::Read> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderInput {
+            reader,
+            peeked: None,
+            buf: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl<R: io::Read> Input for ReaderInput<R> {
+    fn read(&mut self, n: usize) -> Result<&[u8], Error> {
+        self.buf.clear();
+        if n > 0 {
+            self.buf.extend(self.peeked.take());
+        }
+        // Don't trust `n` with an allocation: it comes from the input.
+        let rest = (n - self.buf.len()) as u64;
+        (&mut self.reader).take(rest).read_to_end(&mut self.buf)?;
+        self.offset += self.buf.len();
+        if self.buf.len() < n {
+            return Err(eof(self.offset));
+        }
+        Ok(&self.buf)
+    }
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        if self.peeked.is_none() {
+            let mut byte = [0];
+            loop {
+                match self.reader.read(&mut byte) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => break,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            self.peeked = Some(byte[0]);
+            // `offset` counts the peeked byte only once it is consumed.
+        }
+        Ok(self.peeked)
+    }
+    fn offset(&self) -> usize {
+        self.offset
+    }
+    fn rest_from(&self, _: usize) -> Option<&[u8]> {
+        None
+    }
+}
+
+fn eof(offset: usize) -> Error {
+    de::Error::custom(format!("EOF while parsing a value at offset {}", offset))
+}
+
+pub struct Deserializer<I> {
+    input: I,
+    /// Where the innermost array or map whose visitor failed starts.
+    failed_at: Option<usize>,
+}
+
+impl<I: Input> Deserializer<I> {
+    pub fn new(input: I) -> Self {
+        Deserializer {
+            input,
+            failed_at: None,
+        }
+    }
+
+    /// Checks that the whole input was consumed.
+    pub fn end(&mut self) -> Result<(), Error> {
+        match self.input.peek()? {
+            None => Ok(()),
+            Some(_) => Err(self.error("trailing data")),
+        }
+    }
+
+    /// The innermost array or map that failed to decode, when the input is still available.
+    pub fn failed_value(&self) -> Option<Value> {
+        let data = self.input.rest_from(self.failed_at?)?;
+        let mut de = Deserializer::new(SliceInput::new(data));
+        de::Deserialize::deserialize(&mut de).ok()
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        de::Error::custom(format!("{} at offset {}", msg, self.input.offset()))
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.input.read(1)?[0])
+    }
+
+    /// Reads the argument of an item whose initial byte has `info` in its low bits.
+    fn argument(&mut self, info: u8) -> Result<u64, Error> {
+        let n = match info {
+            0..=23 => return Ok(u64::from(info)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(self.error("unexpected code")),
+        };
+        let bytes = self.input.read(n)?;
+        Ok(bytes.iter().fold(0, |acc, &b| acc << 8 | u64::from(b)))
+    }
+
+    /// Reads the length of an array or map; `None` if it is indefinite.
+    fn length(&mut self, info: u8) -> Result<Option<u64>, Error> {
+        match info {
+            31 => Ok(None),
+            _ => self.argument(info).map(Some),
+        }
+    }
+
+    /// Reads the contents of a byte or text string.
+    fn string(&mut self, major: u8, info: u8) -> Result<Cow<'_, [u8]>, Error> {
+        let len = match self.length(info)? {
+            Some(len) => len,
+            None => {
+                let mut bytes = Vec::new();
+                loop {
+                    let byte = self.byte()?;
+                    if byte == 0xff {
+                        return Ok(Cow::Owned(bytes));
+                    }
+                    if byte >> 5 != major || byte & 0x1f == 31 {
+                        return Err(self.error("invalid string chunk"));
+                    }
+                    let chunk = self.string(major, byte & 0x1f)?;
+                    bytes.extend_from_slice(&chunk);
+                }
+            }
+        };
+        match usize::try_from(len) {
+            Ok(len) => self.input.read(len).map(Cow::Borrowed),
+            Err(_) => Err(self.error("length out of range")),
+        }
+    }
+
+    /// Runs the visitor of an array or map, on a fresh stack if needed since this is where
+    /// decoding recurses.
+    fn container<T>(
+        &mut self,
+        start: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let res = grow_stack(|| f(self));
+        if res.is_err() && self.failed_at.is_none() {
+            self.failed_at = Some(start);
+        }
+        res
+    }
+
+    /// Checks that the visitor consumed the whole array or map.
+    fn finish(&mut self, remaining: Option<u64>) -> Result<(), Error> {
+        match remaining {
+            Some(0) => Ok(()),
+            None if self.byte()? == 0xff => Ok(()),
+            _ => Err(self.error("trailing elements")),
+        }
+    }
+
+    /// Whether there are more elements in an array or map.
+    fn has_next(&mut self, remaining: &mut Option<u64>) -> Result<bool, Error> {
+        match remaining {
+            Some(0) => Ok(false),
+            Some(n) => {
+                *n -= 1;
+                Ok(true)
+            }
+            None => match self.input.peek()? {
+                Some(0xff) => Ok(false),
+                Some(_) => Ok(true),
+                None => Err(eof(self.input.offset())),
+            },
+        }
+    }
+
+    fn parse_value<'de, V: Visitor<'de>>(
+        &mut self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut start = self.input.offset();
+        let mut byte = self.byte()?;
+        // Tags are ignored.
+        while byte >> 5 == 6 {
+            self.argument(byte & 0x1f)?;
+            start = self.input.offset();
+            byte = self.byte()?;
+        }
+        let info = byte & 0x1f;
+        match byte >> 5 {
+            0 => visitor.visit_u64(self.argument(info)?),
+            1 => {
+                let n = self.argument(info)?;
+                match i64::try_from(n) {
+                    Ok(n) => visitor.visit_i64(-1 - n),
+                    Err(_) => visitor.visit_i128(-1 - i128::from(n)),
+                }
+            }
+            2 => visitor.visit_bytes(&self.string(2, info)?),
+            3 => match std::str::from_utf8(&self.string(3, info)?) {
+                Ok(s) => visitor.visit_str(s),
+                Err(_) => Err(de::Error::custom("invalid UTF-8 in a string")),
+            },
+            4 => {
+                let len = self.length(info)?;
+                self.container(start, |de| {
+                    let mut seq = Seq { de, remaining: len };
+                    let value = visitor.visit_seq(&mut seq)?;
+                    seq.de.finish(seq.remaining)?;
+                    Ok(value)
+                })
+            }
+            5 => {
+                let len = self.length(info)?;
+                self.container(start, |de| {
+                    let mut map = Map { de, remaining: len };
+                    let value = visitor.visit_map(&mut map)?;
+                    map.de.finish(map.remaining)?;
+                    Ok(value)
+                })
+            }
+            _ => match info {
+                20 => visitor.visit_bool(false),
+                21 => visitor.visit_bool(true),
+                22 | 23 => visitor.visit_unit(),
+                25 => {
+                    let bits = self.argument(info)? as u16;
+                    visitor.visit_f32(f16_to_f32(bits))
+                }
+                26 => {
+                    let bits = self.argument(info)? as u32;
+                    visitor.visit_f32(f32::from_bits(bits))
+                }
+                27 => visitor.visit_f64(f64::from_bits(self.argument(info)?)),
+                _ => Err(self.error("unexpected code")),
+            },
+        }
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f32::from(bits & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+impl<'de, 'a, I: Input> de::Deserializer<'de> for &'a mut Deserializer<I> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.parse_value(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Seq<'a, I> {
+    de: &'a mut Deserializer<I>,
+    remaining: Option<u64>,
+}
+
+impl<'de, 'a, I: Input> de::SeqAccess<'de> for Seq<'a, I> {
+    type Error = Error;
+
+    fn next_element_seed<T>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if !self.de.has_next(&mut self.remaining)? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining.and_then(|n| usize::try_from(n).ok())
+    }
+}
+
+struct Map<'a, I> {
+    de: &'a mut Deserializer<I>,
+    remaining: Option<u64>,
+}
+
+impl<'de, 'a, I: Input> de::MapAccess<'de> for Map<'a, I> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if !self.de.has_next(&mut self.remaining)? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining.and_then(|n| usize::try_from(n).ok())
+    }
+}
+
+/// An enum that can encode most CBOR values. Only used to show what was wrong with a
+/// badly-formed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+    Bytes(Vec<u8>),
+}
+
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("any valid CBOR value")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+                Ok(Value::String(v.to_owned()))
+            }
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Value, E> {
+                Ok(Value::Bytes(v.to_owned()))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+                Ok(Value::U64(v))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+                Ok(Value::I64(v))
+            }
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+                Ok(Value::Bool(v))
+            }
+            fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+                Ok(Value::F64(v))
+            }
+            fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut vec = Vec::new();
+                while let Some(elem) = seq.next_element()? {
+                    vec.push(elem);
+                }
+                Ok(Value::Array(vec))
+            }
+            fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                let mut values = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry()? {
+                    values.insert(key, value);
+                }
+                Ok(Value::Object(values))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
@@ -1,38 +1,82 @@
-use itertools::Itertools;
 use serde::de;
+use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::fmt;
-use std::iter::FromIterator;
+use std::io;
 
+use crate::builtins::Builtin;
 use crate::error::DecodeError;
-use crate::operations::OpKind;
+use crate::operations::{BinOp, OpKind};
 use crate::syntax;
+use crate::syntax::binary::cbor;
 use crate::syntax::{
-    Expr, ExprKind, FilePath, FilePrefix, Hash, ImportMode, ImportTarget,
-    Integer, InterpolatedText, Label, Natural, NumKind, Scheme, Span,
-    UnspannedExpr, URL, V,
+    Const, Expr, ExprKind, FilePath, FilePrefix, Hash, ImportMode,
+    ImportTarget, Integer, InterpolatedText, Label, Natural, NumKind, Scheme,
+    Span, UnspannedExpr, URL, V,
 };
+
 type DecodedExpr = Expr;
 
 pub fn decode(data: &[u8]) -> Result<DecodedExpr, DecodeError> {
-    match serde_cbor::de::from_slice(data) {
-        Ok(v) => cbor_value_to_dhall(&v),
-        Err(e) => Err(DecodeError::CBORError(e)),
-    }
+    decode_with(cbor::SliceInput::new(data))
 }
 
-/// An enum that can encode most CBOR values.
-#[derive(Clone, Debug, PartialEq)]
-pub enum Value {
-    Null,
-    Bool(bool),
-    U64(u64),
-    I64(i64),
-    F64(f64),
-    String(String),
-    Array(Vec<Value>),
-    Object(BTreeMap<String, Value>),
-    Bytes(Vec<u8>),
+/// Decodes an expression from a reader, without reading the whole input in memory first. The
+/// reader is read in small chunks, so it should be buffered. Since the input isn't kept around,
+/// errors can't show the badly-formed part of it like `decode` does.
+pub fn decode_from_reader(
+    reader: impl io::Read,
+) -> Result<DecodedExpr, DecodeError> {
+    decode_with(cbor::ReaderInput::new(reader))
+}
+
+fn decode_with<I: cbor::Input>(input: I) -> Result<DecodedExpr, DecodeError> {
+    let cx = Decoder::default();
+    let mut de = cbor::Deserializer::new(input);
+    let res = ExprSeed(&cx)
+        .deserialize(&mut de)
+        .and_then(|e| de.end().map(|()| e));
+    res.map_err(|e| match cx.error.take() {
+        Some(Wrong::Message(msg)) => DecodeError::WrongFormatError(msg),
+        Some(Wrong::Shape(what)) => {
+            DecodeError::WrongFormatError(match de.failed_value() {
+                Some(value) => format!("{:?}", value),
+                None => what,
+            })
+        }
+        None => DecodeError::CBORError(e),
+    })
+}
+
+/// State shared by the whole decoding. Serde only lets us return its own error type, so we keep
+/// the actual reason for a badly-formed expression here.
+#[derive(Default)]
+struct Decoder {
+    error: Cell<Option<Wrong>>,
+}
+
+enum Wrong {
+    Message(String),
+    /// The array or map being decoded doesn't have the shape of any expression. The error shows
+    /// it whole when the input is still available, and says what it looked like otherwise.
+    Shape(String),
+}
+
+impl Decoder {
+    fn wrong<E: de::Error>(&self, msg: impl Into<String>) -> E {
+        self.fail(Wrong::Message(msg.into()))
+    }
+    fn shape<E: de::Error>(&self, what: impl Into<String>) -> E {
+        self.fail(Wrong::Shape(what.into()))
+    }
+    fn fail<E: de::Error>(&self, wrong: Wrong) -> E {
+        let err = match &wrong {
+            Wrong::Message(msg) | Wrong::Shape(msg) => E::custom(msg),
+        };
+        self.error.set(Some(wrong));
+        err
+    }
 }
 
 // Should probably rename this
@@ -40,615 +84,893 @@ fn rc(x: UnspannedExpr) -> Expr {
     Expr::new(x, Span::Decoded)
 }
 
-fn cbor_value_to_dhall(data: &Value) -> Result<DecodedExpr, DecodeError> {
-    use crate::builtins::Builtin;
-    use crate::operations::BinOp;
-    use syntax::Const;
-    use ExprKind::*;
-    use OpKind::*;
-    use Value::*;
-    Ok(rc(match data {
-        String(s) => match Builtin::parse(s) {
-            Some(b) => ExprKind::Builtin(b),
-            None => match s.as_str() {
-                "True" => Num(NumKind::Bool(true)),
-                "False" => Num(NumKind::Bool(false)),
-                "Type" => Const(Const::Type),
-                "Kind" => Const(Const::Kind),
-                "Sort" => Const(Const::Sort),
-                _ => {
-                    return Err(DecodeError::WrongFormatError(
-                        "builtin".to_owned(),
-                    ))
-                }
-            },
+fn builtin(b: Builtin) -> Expr {
+    rc(ExprKind::Builtin(b))
+}
+
+fn parse_builtin(s: &str) -> Option<UnspannedExpr> {
+    Some(match Builtin::parse(s) {
+        Some(b) => ExprKind::Builtin(b),
+        None => match s {
+            "True" => ExprKind::Num(NumKind::Bool(true)),
+            "False" => ExprKind::Num(NumKind::Bool(false)),
+            "Type" => ExprKind::Const(Const::Type),
+            "Kind" => ExprKind::Const(Const::Kind),
+            "Sort" => ExprKind::Const(Const::Sort),
+            _ => return None,
         },
-        U64(n) => Var(V(Label::from("_"), *n as usize)),
-        F64(x) => Num(NumKind::Double((*x).into())),
-        Bool(b) => Num(NumKind::Bool(*b)),
-        Array(vec) => match vec.as_slice() {
-            [String(l), U64(n)] => {
-                if l.as_str() == "_" {
-                    return Err(DecodeError::WrongFormatError(
-                        "`_` variable was encoded incorrectly".to_owned(),
-                    ));
-                }
-                let l = Label::from(l.as_str());
-                Var(V(l, *n as usize))
-            }
-            [U64(0), f, args @ ..] => {
-                if args.is_empty() {
-                    return Err(DecodeError::WrongFormatError(
-                        "Function application must have at least one argument"
-                            .to_owned(),
-                    ));
-                }
-                let mut f = cbor_value_to_dhall(&f)?;
-                for a in args {
-                    let a = cbor_value_to_dhall(&a)?;
-                    f = rc(Op(App(f, a)))
-                }
-                return Ok(f);
-            }
-            [U64(1), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Lam(Label::from("_"), x, y)
-            }
-            [U64(1), String(l), x, y] => {
-                if l.as_str() == "_" {
-                    return Err(DecodeError::WrongFormatError(
-                        "`_` variable was encoded incorrectly".to_owned(),
-                    ));
+    })
+}
+
+fn parse_binop(n: u64) -> Option<BinOp> {
+    use BinOp::*;
+    Some(match n {
+        0 => BoolOr,
+        1 => BoolAnd,
+        2 => BoolEQ,
+        3 => BoolNE,
+        4 => NaturalPlus,
+        5 => NaturalTimes,
+        6 => TextAppend,
+        7 => ListAppend,
+        8 => RecursiveRecordMerge,
+        9 => RightBiasedRecordMerge,
+        10 => RecursiveRecordTypeMerge,
+        11 => ImportAlt,
+        12 => Equivalence,
+        _ => return None,
+    })
+}
+
+/// Decodes a whole expression.
+#[derive(Clone, Copy)]
+struct ExprSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for ExprSeed<'a> {
+    type Value = DecodedExpr;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ExprSeed<'a> {
+    type Value = DecodedExpr;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("an encoded dhall expression")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        match parse_builtin(s) {
+            Some(e) => Ok(rc(e)),
+            None => Err(self.0.wrong("builtin")),
+        }
+    }
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Self::Value, E> {
+        Ok(rc(ExprKind::Var(V(Label::from("_"), n as usize))))
+    }
+    fn visit_f64<E: de::Error>(self, x: f64) -> Result<Self::Value, E> {
+        Ok(rc(ExprKind::Num(NumKind::Double(x.into()))))
+    }
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Self::Value, E> {
+        Ok(rc(ExprKind::Num(NumKind::Bool(b))))
+    }
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Self::Value, E> {
+        Err(self.0.wrong(format!("{:?}", cbor::Value::I64(n))))
+    }
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Err(self.0.wrong(format!("{:?}", cbor::Value::Null)))
+    }
+    fn visit_bytes<E: de::Error>(self, b: &[u8]) -> Result<Self::Value, E> {
+        Err(self
+            .0
+            .wrong(format!("{:?}", cbor::Value::Bytes(b.to_owned()))))
+    }
+    fn visit_map<A>(self, _: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        Err(self.0.shape("unexpected map"))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut seq = Elements { cx: self.0, seq };
+        let e = match seq.next::<Atom>()? {
+            Some(Atom::Nat(tag)) => decode_tagged(tag, &mut seq)?,
+            Some(Atom::Str(l)) => {
+                let n = match seq.next::<Atom>()? {
+                    Some(Atom::Nat(n)) => n,
+                    _ => return Err(self.0.shape("variable")),
+                };
+                if l == "_" {
+                    return Err(self
+                        .0
+                        .wrong("`_` variable was encoded incorrectly"));
                 }
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                let l = Label::from(l.as_str());
-                Lam(l, x, y)
-            }
-            [U64(2), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Pi(Label::from("_"), x, y)
+                seq.end("variable")?;
+                rc(ExprKind::Var(V(Label::from(l), n as usize)))
             }
-            [U64(2), String(l), x, y] => {
-                if l.as_str() == "_" {
-                    return Err(DecodeError::WrongFormatError(
-                        "`_` variable was encoded incorrectly".to_owned(),
-                    ));
-                }
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                let l = Label::from(l.as_str());
+            _ => return Err(self.0.shape("unexpected array")),
+        };
+        Ok(e)
+    }
+}
+
+/// Decodes the elements of an array tagged with `tag`, after the tag.
+fn decode_tagged<'de, A>(
+    tag: u64,
+    seq: &mut Elements<'_, A>,
+) -> Result<DecodedExpr, A::Error>
+where
+    A: SeqAccess<'de>,
+{
+    use ExprKind::*;
+    use OpKind::*;
+    let cx = seq.cx;
+    let e = match tag {
+        0 => {
+            let mut f = seq.expr("application")?;
+            let mut has_args = false;
+            while let Some(a) = seq.next_expr()? {
+                f = rc(Op(App(f, a)));
+                has_args = true;
+            }
+            if !has_args {
+                return Err(cx.wrong(
+                    "Function application must have at least one argument",
+                ));
+            }
+            return Ok(f);
+        }
+        1 | 2 => {
+            let what = if tag == 1 { "lambda" } else { "pi" };
+            let first = seq.str_or_expr(what)?;
+            let x = seq.expr(what)?;
+            let (l, x, y) = match seq.next_expr()? {
+                Some(y) => match first {
+                    StrOrExpr::Str(l) if l == "_" => {
+                        return Err(
+                            cx.wrong("`_` variable was encoded incorrectly")
+                        )
+                    }
+                    StrOrExpr::Str(l) => (Label::from(l), x, y),
+                    StrOrExpr::Expr(_) => return Err(cx.shape(what)),
+                },
+                None => (Label::from("_"), cx.to_expr(first)?, x),
+            };
+            seq.end(what)?;
+            if tag == 1 {
+                Lam(l, x, y)
+            } else {
                 Pi(l, x, y)
             }
-            [U64(3), U64(13), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Op(Completion(x, y))
-            }
-            [U64(3), U64(n), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                use BinOp::*;
-                let op = match n {
-                    0 => BoolOr,
-                    1 => BoolAnd,
-                    2 => BoolEQ,
-                    3 => BoolNE,
-                    4 => NaturalPlus,
-                    5 => NaturalTimes,
-                    6 => TextAppend,
-                    7 => ListAppend,
-                    8 => RecursiveRecordMerge,
-                    9 => RightBiasedRecordMerge,
-                    10 => RecursiveRecordTypeMerge,
-                    11 => ImportAlt,
-                    12 => Equivalence,
-                    _ => {
-                        return Err(DecodeError::WrongFormatError(
-                            "binop".to_owned(),
-                        ))
-                    }
-                };
-                Op(BinOp(op, x, y))
+        }
+        3 => {
+            let op = match seq.next::<Atom>()? {
+                Some(Atom::Nat(op)) => op,
+                _ => return Err(cx.shape("binop")),
+            };
+            let x = seq.expr("binop")?;
+            let y = seq.expr("binop")?;
+            seq.end("binop")?;
+            match op {
+                13 => Op(Completion(x, y)),
+                _ => match parse_binop(op) {
+                    Some(op) => Op(BinOp(op, x, y)),
+                    None => return Err(cx.wrong("binop")),
+                },
             }
-            [U64(4), t] => {
-                let t = cbor_value_to_dhall(&t)?;
+        }
+        4 => match seq.opt_expr("list")? {
+            Some(t) => {
+                seq.end("list")?;
                 EmptyListLit(rc(Op(App(
-                    rc(ExprKind::Builtin(Builtin::List)),
+                    builtin(crate::builtins::Builtin::List),
                     t,
                 ))))
             }
-            [U64(4), Null, rest @ ..] => {
-                let rest = rest
-                    .iter()
-                    .map(cbor_value_to_dhall)
-                    .collect::<Result<Vec<_>, _>>()?;
-                NEListLit(rest)
-            }
-            [U64(5), Null, x] => {
-                let x = cbor_value_to_dhall(&x)?;
-                SomeLit(x)
-            }
-            // Old-style optional literals
-            [U64(5), t] => {
-                let t = cbor_value_to_dhall(&t)?;
-                Op(App(rc(ExprKind::Builtin(Builtin::OptionalNone)), t))
-            }
-            [U64(5), t, x] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let t = cbor_value_to_dhall(&t)?;
-                Annot(
+            None => {
+                let mut xs = Vec::new();
+                while let Some(x) = seq.next_expr()? {
+                    xs.push(x);
+                }
+                if xs.is_empty() {
+                    return Err(cx.shape("list"));
+                }
+                NEListLit(xs)
+            }
+        },
+        5 => {
+            let t = seq.opt_expr("optional")?;
+            let x = seq.next_expr()?;
+            seq.end("optional")?;
+            match (t, x) {
+                (None, Some(x)) => SomeLit(x),
+                // Old-style optional literals
+                (Some(t), None) => {
+                    Op(App(builtin(crate::builtins::Builtin::OptionalNone), t))
+                }
+                (Some(t), Some(x)) => Annot(
                     rc(SomeLit(x)),
-                    rc(Op(App(rc(ExprKind::Builtin(Builtin::Optional)), t))),
-                )
-            }
-            [U64(6), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Op(Merge(x, y, None))
-            }
-            [U64(6), x, y, z] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                let z = cbor_value_to_dhall(&z)?;
-                Op(Merge(x, y, Some(z)))
-            }
-            [U64(7), Object(map)] => {
-                let map = cbor_map_to_dhall_map(map)?;
-                RecordType(map)
+                    rc(Op(App(builtin(crate::builtins::Builtin::Optional), t))),
+                ),
+                (None, None) => return Err(cx.shape("optional")),
             }
-            [U64(8), Object(map)] => {
-                let map = cbor_map_to_dhall_map(map)?;
+        }
+        6 => {
+            let x = seq.expr("merge")?;
+            let y = seq.expr("merge")?;
+            let z = seq.next_expr()?;
+            seq.end("merge")?;
+            Op(Merge(x, y, z))
+        }
+        7 | 8 => {
+            let map = seq.required(MapSeed(cx), "record")?;
+            seq.end("record")?;
+            if tag == 7 {
+                RecordType(map)
+            } else {
                 RecordLit(map)
             }
-            [U64(9), x, String(l)] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let l = Label::from(l.as_str());
-                Op(Field(x, l))
-            }
-            [U64(10), x, Array(arr)] => {
-                let x = cbor_value_to_dhall(&x)?;
-                if let [y] = arr.as_slice() {
-                    let y = cbor_value_to_dhall(&y)?;
+        }
+        9 => {
+            let x = seq.expr("field")?;
+            let l = seq.label("field")?;
+            seq.end("field")?;
+            Op(Field(x, l))
+        }
+        10 => {
+            let x = seq.expr("projection")?;
+            match seq.next_seed(ProjectionSeed(cx))? {
+                Some(Projected::Expr(y)) => {
+                    seq.end("projection-by-expr")?;
                     Op(ProjectionByExpr(x, y))
-                } else {
-                    return Err(DecodeError::WrongFormatError(
-                        "projection-by-expr".to_owned(),
-                    ));
                 }
-            }
-            [U64(10), x, rest @ ..] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let labels = rest
-                    .iter()
-                    .map(|s| match s {
-                        String(s) => Ok(Label::from(s.as_str())),
-                        _ => Err(DecodeError::WrongFormatError(
-                            "projection".to_owned(),
-                        )),
-                    })
-                    .collect::<Result<_, _>>()?;
-                Op(Projection(x, labels))
-            }
-            [U64(11), Object(map)] => {
-                let map = cbor_map_to_dhall_opt_map(map)?;
-                UnionType(map)
-            }
-            [U64(12), ..] => {
-                return Err(DecodeError::WrongFormatError(
-                    "Union literals are not supported anymore".to_owned(),
-                ))
-            }
-            [U64(14), x, y, z] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                let z = cbor_value_to_dhall(&z)?;
-                Op(BoolIf(x, y, z))
-            }
-            [U64(15), U64(x)] => Num(NumKind::Natural(*x as Natural)),
-            [U64(16), U64(x)] => Num(NumKind::Integer(*x as Integer)),
-            [U64(16), I64(x)] => Num(NumKind::Integer(*x as Integer)),
-            [U64(18), String(first), rest @ ..] => {
-                TextLit(InterpolatedText::from((
-                    first.clone(),
-                    rest.iter()
-                        .tuples()
-                        .map(|(x, y)| {
-                            let x = cbor_value_to_dhall(&x)?;
-                            let y = match y {
-                                String(s) => s.clone(),
-                                _ => {
-                                    return Err(DecodeError::WrongFormatError(
-                                        "text".to_owned(),
-                                    ))
-                                }
-                            };
-                            Ok((x, y))
-                        })
-                        .collect::<Result<_, _>>()?,
-                )))
-            }
-            [U64(19), t] => {
-                let t = cbor_value_to_dhall(&t)?;
-                Assert(t)
-            }
-            [U64(24), hash, U64(mode), U64(scheme), rest @ ..] => {
-                let mode = match mode {
-                    0 => ImportMode::Code,
-                    1 => ImportMode::RawText,
-                    2 => ImportMode::Location,
-                    _ => {
-                        return Err(DecodeError::WrongFormatError(format!(
-                            "import/mode/unknown_mode: {:?}",
-                            mode
-                        )))
-                    }
-                };
-                let hash = match hash {
-                    Null => None,
-                    Bytes(bytes) => match bytes.as_slice() {
-                        [18, 32, rest @ ..] => {
-                            Some(Hash::SHA256(rest.to_vec().into()))
-                        }
-                        _ => {
-                            return Err(DecodeError::WrongFormatError(format!(
-                                "import/hash/unknown_multihash: {:?}",
-                                bytes
-                            )))
+                Some(Projected::Label(l)) => {
+                    let mut labels = vec![Label::from(l)];
+                    while let Some(l) = seq.next::<Atom>()? {
+                        match l {
+                            Atom::Str(l) => labels.push(Label::from(l)),
+                            _ => return Err(cx.wrong("projection")),
                         }
-                    },
-                    _ => {
-                        return Err(DecodeError::WrongFormatError(
-                            "import/hash/should_be_bytes".to_owned(),
-                        ))
-                    }
-                };
-                let mut rest = rest.iter();
-                let location = match scheme {
-                    0 | 1 => {
-                        let scheme = match scheme {
-                            0 => Scheme::HTTP,
-                            _ => Scheme::HTTPS,
-                        };
-                        let headers = match rest.next() {
-                            Some(Null) => None,
-                            Some(x) => {
-                                let x = cbor_value_to_dhall(&x)?;
-                                Some(x)
-                            }
-                            _ => {
-                                return Err(DecodeError::WrongFormatError(
-                                    "import/remote/headers".to_owned(),
-                                ))
-                            }
-                        };
-                        let authority = match rest.next() {
-                            Some(String(s)) => s.to_owned(),
-                            _ => {
-                                return Err(DecodeError::WrongFormatError(
-                                    "import/remote/authority".to_owned(),
-                                ))
-                            }
-                        };
-                        let query = match rest.next_back() {
-                            Some(Null) => None,
-                            Some(String(s)) => Some(s.to_owned()),
-                            _ => {
-                                return Err(DecodeError::WrongFormatError(
-                                    "import/remote/query".to_owned(),
-                                ))
-                            }
-                        };
-                        let file_path = rest
-                            .map(|s| match s.as_string() {
-                                Some(s) => Ok(s.clone()),
-                                None => Err(DecodeError::WrongFormatError(
-                                    "import/remote/path".to_owned(),
-                                )),
-                            })
-                            .collect::<Result<_, _>>()?;
-                        let path = FilePath { file_path };
-                        ImportTarget::Remote(URL {
-                            scheme,
-                            authority,
-                            path,
-                            query,
-                            headers,
-                        })
-                    }
-                    2 | 3 | 4 | 5 => {
-                        let prefix = match scheme {
-                            2 => FilePrefix::Absolute,
-                            3 => FilePrefix::Here,
-                            4 => FilePrefix::Parent,
-                            5 => FilePrefix::Home,
-                            _ => {
-                                return Err(DecodeError::WrongFormatError(
-                                    "import/local/prefix".to_owned(),
-                                ))
-                            }
-                        };
-                        let file_path = rest
-                            .map(|s| match s.as_string() {
-                                Some(s) => Ok(s.clone()),
-                                None => Err(DecodeError::WrongFormatError(
-                                    "import/local/path".to_owned(),
-                                )),
-                            })
-                            .collect::<Result<_, _>>()?;
-                        let path = FilePath { file_path };
-                        ImportTarget::Local(prefix, path)
                     }
-                    6 => {
-                        let env = match rest.next() {
-                            Some(String(s)) => s.to_owned(),
-                            _ => {
-                                return Err(DecodeError::WrongFormatError(
-                                    "import/env".to_owned(),
-                                ))
-                            }
-                        };
-                        ImportTarget::Env(env)
-                    }
-                    7 => ImportTarget::Missing,
-                    _ => {
-                        return Err(DecodeError::WrongFormatError(
-                            "import/type".to_owned(),
-                        ))
-                    }
-                };
-                Import(syntax::Import {
-                    mode,
-                    hash,
-                    location,
-                })
+                    Op(Projection(x, labels.into_iter().collect()))
+                }
+                None => Op(Projection(x, Default::default())),
             }
-            [U64(25), bindings @ ..] => {
-                let mut tuples = bindings.iter().tuples();
-                let bindings = (&mut tuples)
-                    .map(|(x, t, v)| {
-                        let x = x.as_string().ok_or_else(|| {
-                            DecodeError::WrongFormatError(
-                                "let/label".to_owned(),
-                            )
-                        })?;
-                        let x = Label::from(x.as_str());
-                        let t = match t {
-                            Null => None,
-                            t => Some(cbor_value_to_dhall(&t)?),
-                        };
-                        let v = cbor_value_to_dhall(&v)?;
-                        Ok((x, t, v))
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                let expr = tuples.into_buffer().next().ok_or_else(|| {
-                    DecodeError::WrongFormatError("let/expr".to_owned())
-                })?;
-                let expr = cbor_value_to_dhall(expr)?;
-                return Ok(bindings
-                    .into_iter()
-                    .rev()
-                    .fold(expr, |acc, (x, t, v)| rc(Let(x, t, v, acc))));
-            }
-            [U64(26), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Annot(x, y)
-            }
-            [U64(27), x] => {
-                let x = cbor_value_to_dhall(&x)?;
-                Op(ToMap(x, None))
-            }
-            [U64(27), x, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                Op(ToMap(x, Some(y)))
-            }
-            [U64(28), x] => {
-                let x = cbor_value_to_dhall(&x)?;
-                EmptyListLit(x)
-            }
-            [U64(29), x, labels, y] => {
-                let x = cbor_value_to_dhall(&x)?;
-                let y = cbor_value_to_dhall(&y)?;
-                let labels = match labels {
-                    Array(labels) => labels
-                        .iter()
-                        .map(|s| match s {
-                            String(s) => Ok(Label::from(s.as_str())),
-                            _ => Err(DecodeError::WrongFormatError(
-                                "with".to_owned(),
-                            )),
-                        })
-                        .collect::<Result<_, _>>()?,
-                    _ => {
-                        return Err(DecodeError::WrongFormatError(
-                            "with".to_owned(),
-                        ))
-                    }
-                };
-                Op(With(x, labels, y))
+        }
+        11 => {
+            let map = seq.required(UnionMapSeed(cx), "union")?;
+            seq.end("union")?;
+            UnionType(map)
+        }
+        12 => return Err(cx.wrong("Union literals are not supported anymore")),
+        14 => {
+            let x = seq.expr("if")?;
+            let y = seq.expr("if")?;
+            let z = seq.expr("if")?;
+            seq.end("if")?;
+            Op(BoolIf(x, y, z))
+        }
+        15 => {
+            let n = match seq.next::<Atom>()? {
+                Some(Atom::Nat(n)) => n,
+                _ => return Err(cx.shape("natural")),
+            };
+            seq.end("natural")?;
+            Num(NumKind::Natural(n as Natural))
+        }
+        16 => {
+            let n = match seq.next::<Atom>()? {
+                Some(Atom::Nat(n)) => n as Integer,
+                Some(Atom::Neg(n)) => n as Integer,
+                _ => return Err(cx.shape("integer")),
+            };
+            seq.end("integer")?;
+            Num(NumKind::Integer(n))
+        }
+        18 => {
+            let head = seq.string("text")?;
+            let mut tail = Vec::new();
+            while let Some(x) = seq.next_expr()? {
+                tail.push((x, seq.string("text")?));
             }
-            _ => {
-                return Err(DecodeError::WrongFormatError(format!(
-                    "{:?}",
-                    data
-                )))
+            TextLit(InterpolatedText::from((head, tail)))
+        }
+        19 => {
+            let x = seq.expr("assert")?;
+            seq.end("assert")?;
+            Assert(x)
+        }
+        24 => Import(decode_import(seq)?),
+        25 => {
+            let mut bindings = Vec::new();
+            let body = loop {
+                let first = seq.str_or_expr("let/expr")?;
+                let t = match seq.next_seed(OptExprSeed(cx))? {
+                    Some(t) => t,
+                    None => break cx.to_expr(first)?,
+                };
+                let l = match first {
+                    StrOrExpr::Str(l) => Label::from(l),
+                    StrOrExpr::Expr(_) => return Err(cx.wrong("let/label")),
+                };
+                let v = seq.expr("let/expr")?;
+                bindings.push((l, t, v));
+            };
+            if bindings.is_empty() {
+                return Err(cx.shape("let"));
             }
-        },
-        _ => return Err(DecodeError::WrongFormatError(format!("{:?}", data))),
-    }))
+            return Ok(bindings
+                .into_iter()
+                .rev()
+                .fold(body, |acc, (x, t, v)| rc(Let(x, t, v, acc))));
+        }
+        26 => {
+            let x = seq.expr("annotation")?;
+            let y = seq.expr("annotation")?;
+            seq.end("annotation")?;
+            Annot(x, y)
+        }
+        27 => {
+            let x = seq.expr("toMap")?;
+            let y = seq.next_expr()?;
+            seq.end("toMap")?;
+            Op(ToMap(x, y))
+        }
+        28 => {
+            let x = seq.expr("list")?;
+            seq.end("list")?;
+            EmptyListLit(x)
+        }
+        29 => {
+            let x = seq.expr("with")?;
+            let labels = seq.required(LabelsSeed(cx), "with")?;
+            let y = seq.expr("with")?;
+            seq.end("with")?;
+            Op(With(x, labels, y))
+        }
+        _ => return Err(cx.shape(format!("unknown expression tag: {}", tag))),
+    };
+    Ok(rc(e))
 }
 
-fn cbor_map_to_dhall_map<'a, T>(
-    map: impl IntoIterator<Item = (&'a String, &'a Value)>,
-) -> Result<T, DecodeError>
+fn decode_import<'de, A>(
+    seq: &mut Elements<'_, A>,
+) -> Result<syntax::Import<DecodedExpr>, A::Error>
 where
-    T: FromIterator<(Label, DecodedExpr)>,
+    A: SeqAccess<'de>,
 {
-    map.into_iter()
-        .map(|(k, v)| -> Result<(_, _), _> {
-            let v = cbor_value_to_dhall(v)?;
-            Ok((Label::from(k.as_ref()), v))
-        })
-        .collect::<Result<_, _>>()
+    let cx = seq.cx;
+    let hash = seq.required(HashSeed(cx), "import/hash")?;
+    let mode = match seq.next::<Atom>()? {
+        Some(Atom::Nat(0)) => ImportMode::Code,
+        Some(Atom::Nat(1)) => ImportMode::RawText,
+        Some(Atom::Nat(2)) => ImportMode::Location,
+        mode => {
+            return Err(
+                cx.wrong(format!("import/mode/unknown_mode: {:?}", mode))
+            )
+        }
+    };
+    let scheme = match seq.next::<Atom>()? {
+        Some(Atom::Nat(scheme)) => scheme,
+        _ => return Err(cx.wrong("import/type")),
+    };
+    let location = match scheme {
+        0 | 1 => {
+            let scheme = match scheme {
+                0 => Scheme::HTTP,
+                _ => Scheme::HTTPS,
+            };
+            let headers = seq.opt_expr("import/remote/headers")?;
+            let authority = seq.string("import/remote/authority")?;
+            let mut rest = Vec::new();
+            while let Some(s) = seq.next::<Atom>()? {
+                rest.push(s);
+            }
+            let query = match rest.pop() {
+                Some(Atom::Null) => None,
+                Some(Atom::Str(query)) => Some(query),
+                _ => return Err(cx.wrong("import/remote/query")),
+            };
+            let file_path = rest
+                .into_iter()
+                .map(|s| match s {
+                    Atom::Str(s) => Ok(s),
+                    _ => Err(cx.wrong("import/remote/path")),
+                })
+                .collect::<Result<_, _>>()?;
+            ImportTarget::Remote(URL {
+                scheme,
+                authority,
+                path: FilePath { file_path },
+                query,
+                headers,
+            })
+        }
+        2..=5 => {
+            let prefix = match scheme {
+                2 => FilePrefix::Absolute,
+                3 => FilePrefix::Here,
+                4 => FilePrefix::Parent,
+                _ => FilePrefix::Home,
+            };
+            let mut file_path = Vec::new();
+            while let Some(s) = seq.next::<Atom>()? {
+                match s {
+                    Atom::Str(s) => file_path.push(s),
+                    _ => return Err(cx.wrong("import/local/path")),
+                }
+            }
+            ImportTarget::Local(prefix, FilePath { file_path })
+        }
+        6 => {
+            let env = seq.string("import/env")?;
+            seq.end("import/env")?;
+            ImportTarget::Env(env)
+        }
+        7 => {
+            seq.end("import/missing")?;
+            ImportTarget::Missing
+        }
+        _ => return Err(cx.wrong("import/type")),
+    };
+    Ok(syntax::Import {
+        mode,
+        hash,
+        location,
+    })
 }
 
-fn cbor_map_to_dhall_opt_map<'a, T>(
-    map: impl IntoIterator<Item = (&'a String, &'a Value)>,
-) -> Result<T, DecodeError>
-where
-    T: FromIterator<(Label, Option<DecodedExpr>)>,
-{
-    map.into_iter()
-        .map(|(k, v)| -> Result<(_, _), _> {
-            let v = match v {
-                Value::Null => None,
-                _ => Some(cbor_value_to_dhall(v)?),
-            };
-            Ok((Label::from(k.as_ref()), v))
-        })
-        .collect::<Result<_, _>>()
+/// The elements of an array, read one at a time.
+struct Elements<'a, A> {
+    cx: &'a Decoder,
+    seq: A,
 }
 
-impl Value {
-    pub fn as_string(&self) -> Option<&String> {
-        if let Value::String(v) = self {
-            Some(v)
-        } else {
-            None
+impl<'a, 'de, A: SeqAccess<'de>> Elements<'a, A> {
+    fn next<T: de::Deserialize<'de>>(&mut self) -> Result<Option<T>, A::Error> {
+        self.seq.next_element()
+    }
+    fn next_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, A::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        self.seq.next_element_seed(seed)
+    }
+    fn required<S>(&mut self, seed: S, what: &str) -> Result<S::Value, A::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.next_seed(seed)? {
+            Some(x) => Ok(x),
+            None => Err(self.cx.shape(what)),
+        }
+    }
+    fn next_expr(&mut self) -> Result<Option<DecodedExpr>, A::Error> {
+        self.next_seed(ExprSeed(self.cx))
+    }
+    fn expr(&mut self, what: &str) -> Result<DecodedExpr, A::Error> {
+        self.required(ExprSeed(self.cx), what)
+    }
+    /// An expression, or `null`.
+    fn opt_expr(
+        &mut self,
+        what: &str,
+    ) -> Result<Option<DecodedExpr>, A::Error> {
+        self.required(OptExprSeed(self.cx), what)
+    }
+    /// A string that may be a label, or an expression.
+    fn str_or_expr(&mut self, what: &str) -> Result<StrOrExpr, A::Error> {
+        self.required(StrOrExprSeed(self.cx), what)
+    }
+    fn string(&mut self, what: &str) -> Result<String, A::Error> {
+        match self.next::<Atom>()? {
+            Some(Atom::Str(s)) => Ok(s),
+            _ => Err(self.cx.wrong(what)),
         }
     }
+    fn label(&mut self, what: &str) -> Result<Label, A::Error> {
+        Ok(Label::from(self.string(what)?))
+    }
+    /// Checks that there are no elements left.
+    fn end(&mut self, what: &str) -> Result<(), A::Error> {
+        match self.next::<de::IgnoredAny>()? {
+            Some(_) => Err(self.cx.shape(what)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A value that isn't an expression: a tag, a label, an integer or `null`.
+enum Atom {
+    Nat(u64),
+    Neg(i64),
+    Str(String),
+    Null,
+    Other,
 }
 
-impl<'de> de::Deserialize<'de> for Value {
-    #[inline]
-    fn deserialize<D>(deserializer: D) -> Result<Value, D::Error>
+impl<'de> de::Deserialize<'de> for Atom {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
     where
         D: de::Deserializer<'de>,
     {
-        struct ValueVisitor;
-
-        impl<'de> de::Visitor<'de> for ValueVisitor {
-            type Value = Value;
-
+        struct AtomVisitor;
+        impl<'de> Visitor<'de> for AtomVisitor {
+            type Value = Atom;
             fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-                fmt.write_str("any valid CBOR value")
+                fmt.write_str("an integer or a string")
             }
-
-            #[inline]
-            fn visit_str<E>(self, value: &str) -> Result<Value, E>
-            where
-                E: de::Error,
-            {
-                self.visit_string(String::from(value))
+            fn visit_u64<E: de::Error>(self, n: u64) -> Result<Atom, E> {
+                Ok(Atom::Nat(n))
             }
-
-            #[inline]
-            fn visit_string<E>(self, value: String) -> Result<Value, E>
-            where
-                E: de::Error,
-            {
-                Ok(Value::String(value))
+            fn visit_i64<E: de::Error>(self, n: i64) -> Result<Atom, E> {
+                Ok(Atom::Neg(n))
             }
-            #[inline]
-            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                self.visit_byte_buf(v.to_owned())
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Atom, E> {
+                Ok(Atom::Str(s.to_owned()))
             }
-
-            #[inline]
-            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                Ok(Value::Bytes(v))
+            fn visit_string<E: de::Error>(self, s: String) -> Result<Atom, E> {
+                Ok(Atom::Str(s))
             }
-
-            #[inline]
-            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            fn visit_f64<E: de::Error>(self, _: f64) -> Result<Atom, E> {
+                Ok(Atom::Other)
+            }
+            fn visit_bool<E: de::Error>(self, _: bool) -> Result<Atom, E> {
+                Ok(Atom::Other)
+            }
+            fn visit_unit<E: de::Error>(self) -> Result<Atom, E> {
+                Ok(Atom::Null)
+            }
+            fn visit_bytes<E: de::Error>(self, _: &[u8]) -> Result<Atom, E> {
+                Ok(Atom::Other)
+            }
+            fn visit_seq<A>(self, mut seq: A) -> Result<Atom, A::Error>
             where
-                E: de::Error,
+                A: SeqAccess<'de>,
             {
-                Ok(Value::U64(v))
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(Atom::Other)
             }
-
-            #[inline]
-            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            fn visit_map<A>(self, mut map: A) -> Result<Atom, A::Error>
             where
-                E: de::Error,
+                A: de::MapAccess<'de>,
             {
-                Ok(Value::I64(v))
+                while map
+                    .next_entry::<de::IgnoredAny, de::IgnoredAny>()?
+                    .is_some()
+                {}
+                Ok(Atom::Other)
             }
+        }
+        d.deserialize_any(AtomVisitor)
+    }
+}
 
-            #[inline]
-            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                Ok(Value::Bool(v))
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Atom::Nat(n) => write!(f, "{}", n),
+            Atom::Neg(n) => write!(f, "{}", n),
+            Atom::Str(s) => write!(f, "{:?}", s),
+            Atom::Null => write!(f, "null"),
+            Atom::Other => write!(f, "_"),
+        }
+    }
+}
+
+enum StrOrExpr {
+    Str(String),
+    Expr(DecodedExpr),
+}
+
+impl Decoder {
+    /// Interprets a string that turned out not to be a label as an expression.
+    fn to_expr<E: de::Error>(&self, x: StrOrExpr) -> Result<DecodedExpr, E> {
+        match x {
+            StrOrExpr::Expr(e) => Ok(e),
+            StrOrExpr::Str(s) => match parse_builtin(&s) {
+                Some(e) => Ok(rc(e)),
+                None => Err(self.wrong("builtin")),
+            },
+        }
+    }
+}
+
+/// Decodes a string without interpreting it, or any other expression.
+struct StrOrExprSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for StrOrExprSeed<'a> {
+    type Value = StrOrExpr;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
+
+macro_rules! forward_to_expr {
+    ($($visit:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $visit<E: de::Error>(
+            self,
+            $($arg: $ty),*
+        ) -> Result<Self::Value, E> {
+            ExprSeed(self.0).$visit($($arg),*).map(Self::wrap)
+        }
+    )*};
+}
+
+macro_rules! expr_visitor {
+    ($seed:ident, $value:ty, $expecting:expr) => {
+        impl<'a, 'de> Visitor<'de> for $seed<'a> {
+            type Value = $value;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str($expecting)
             }
 
-            #[inline]
-            fn visit_none<E>(self) -> Result<Self::Value, E>
+            forward_to_expr! {
+                visit_u64(n: u64);
+                visit_i64(n: i64);
+                visit_f64(x: f64);
+                visit_bool(b: bool);
+                visit_bytes(b: &[u8]);
+            }
+            fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
             where
-                E: de::Error,
+                A: SeqAccess<'de>,
             {
-                self.visit_unit()
+                ExprSeed(self.0).visit_seq(seq).map(Self::wrap)
             }
-
-            #[inline]
-            fn visit_unit<E>(self) -> Result<Self::Value, E>
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
             where
-                E: de::Error,
+                A: de::MapAccess<'de>,
             {
-                Ok(Value::Null)
+                ExprSeed(self.0).visit_map(map).map(Self::wrap)
             }
-
-            #[inline]
-            fn visit_seq<V>(
+            fn visit_str<E: de::Error>(
                 self,
-                mut visitor: V,
-            ) -> Result<Self::Value, V::Error>
-            where
-                V: de::SeqAccess<'de>,
-            {
-                let mut vec = Vec::new();
+                s: &str,
+            ) -> Result<Self::Value, E> {
+                self.visit_string(s.to_owned())
+            }
+            fn visit_string<E: de::Error>(
+                self,
+                s: String,
+            ) -> Result<Self::Value, E> {
+                self.string(s)
+            }
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                self.null()
+            }
+        }
+    };
+}
 
-                while let Some(elem) = visitor.next_element()? {
-                    vec.push(elem);
-                }
+impl<'a> StrOrExprSeed<'a> {
+    fn wrap(e: DecodedExpr) -> StrOrExpr {
+        StrOrExpr::Expr(e)
+    }
+    fn string<E: de::Error>(self, s: String) -> Result<StrOrExpr, E> {
+        Ok(StrOrExpr::Str(s))
+    }
+    fn null<E: de::Error>(self) -> Result<StrOrExpr, E> {
+        ExprSeed(self.0).visit_unit().map(Self::wrap)
+    }
+}
 
-                Ok(Value::Array(vec))
-            }
+expr_visitor!(StrOrExprSeed, StrOrExpr, "a string or an expression");
 
-            #[inline]
-            fn visit_map<V>(self, mut visitor: V) -> Result<Value, V::Error>
-            where
-                V: de::MapAccess<'de>,
-            {
-                let mut values = BTreeMap::new();
+/// Decodes `null` or an expression.
+#[derive(Clone, Copy)]
+struct OptExprSeed<'a>(&'a Decoder);
 
-                while let Some((key, value)) = visitor.next_entry()? {
-                    values.insert(key, value);
-                }
+impl<'a, 'de> DeserializeSeed<'de> for OptExprSeed<'a> {
+    type Value = Option<DecodedExpr>;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
 
-                Ok(Value::Object(values))
-            }
+impl<'a> OptExprSeed<'a> {
+    fn wrap(e: DecodedExpr) -> Option<DecodedExpr> {
+        Some(e)
+    }
+    fn string<E: de::Error>(self, s: String) -> Result<Option<DecodedExpr>, E> {
+        ExprSeed(self.0).visit_str(&s).map(Self::wrap)
+    }
+    fn null<E: de::Error>(self) -> Result<Option<DecodedExpr>, E> {
+        Ok(None)
+    }
+}
 
-            #[inline]
-            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                Ok(Value::F64(v))
+expr_visitor!(OptExprSeed, Option<DecodedExpr>, "null or an expression");
+
+enum Projected {
+    Label(String),
+    Expr(DecodedExpr),
+}
+
+/// Decodes the first projected label, or the `[expr]` of a projection by expression.
+struct ProjectionSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for ProjectionSeed<'a> {
+    type Value = Projected;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ProjectionSeed<'a> {
+    type Value = Projected;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a label or an expression in an array")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+        Ok(Projected::Label(s.to_owned()))
+    }
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Self::Value, E> {
+        Ok(Projected::Label(s))
+    }
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut seq = Elements { cx: self.0, seq };
+        let y = seq.expr("projection-by-expr")?;
+        seq.end("projection-by-expr")?;
+        Ok(Projected::Expr(y))
+    }
+}
+
+/// Decodes the fields of a record.
+struct MapSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for MapSeed<'a> {
+    type Value = BTreeMap<Label, DecodedExpr>;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_map(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for MapSeed<'a> {
+    type Value = BTreeMap<Label, DecodedExpr>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a map from labels to expressions")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut fields = BTreeMap::new();
+        while let Some(k) = map.next_key::<String>()? {
+            let v = map.next_value_seed(ExprSeed(self.0))?;
+            fields.insert(Label::from(k), v);
+        }
+        Ok(fields)
+    }
+}
+
+/// Decodes the alternatives of a union type.
+struct UnionMapSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for UnionMapSeed<'a> {
+    type Value = BTreeMap<Label, Option<DecodedExpr>>;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_map(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for UnionMapSeed<'a> {
+    type Value = BTreeMap<Label, Option<DecodedExpr>>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("a map from labels to optional expressions")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut alts = BTreeMap::new();
+        while let Some(k) = map.next_key::<String>()? {
+            let v = map.next_value_seed(OptExprSeed(self.0))?;
+            alts.insert(Label::from(k), v);
+        }
+        Ok(alts)
+    }
+}
+
+/// Decodes the array of labels of a `with` expression.
+struct LabelsSeed<'a>(&'a Decoder);
+
+impl<'a, 'de> DeserializeSeed<'de> for LabelsSeed<'a> {
+    type Value = Vec<Label>;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for LabelsSeed<'a> {
+    type Value = Vec<Label>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("an array of labels")
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut seq = Elements { cx: self.0, seq };
+        let mut labels = Vec::new();
+        while let Some(l) = seq.next::<Atom>()? {
+            match l {
+                Atom::Str(l) => labels.push(Label::from(l)),
+                _ => return Err(self.0.wrong("with")),
             }
         }
+        Ok(labels)
+    }
+}
+
+/// Decodes the hash of an import.
+struct HashSeed<'a>(&'a Decoder);
 
-        deserializer.deserialize_any(ValueVisitor)
+impl<'a, 'de> DeserializeSeed<'de> for HashSeed<'a> {
+    type Value = Option<Hash>;
+    fn deserialize<D>(self, d: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        d.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for HashSeed<'a> {
+    type Value = Option<Hash>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("null or a multihash")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        match bytes {
+            [18, 32, rest @ ..] => Ok(Some(Hash::SHA256(rest.into()))),
+            _ => Err(self
+                .0
+                .wrong(format!("import/hash/unknown_multihash: {:?}", bytes))),
+        }
     }
 }
@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::io;
 use std::vec;
 
 use crate::builtins::Builtin;
@@ -9,12 +10,23 @@ use crate::syntax::{
     Expr, ExprKind, FilePrefix, Hash, Import, ImportMode, ImportTarget, Label,
     Scheme, V,
 };
+use crate::utils::grow_stack;
 
 pub fn encode(expr: &Expr) -> Result<Vec<u8>, EncodeError> {
     serde_cbor::ser::to_vec(&Serialize::Expr(expr))
         .map_err(EncodeError::CBORError)
 }
 
+/// Encodes an expression straight into a writer. The output is written in small pieces, so the
+/// writer should be buffered.
+pub fn encode_to_writer(
+    expr: &Expr,
+    writer: impl io::Write,
+) -> Result<(), EncodeError> {
+    serde_cbor::to_writer(writer, &Serialize::Expr(expr))
+        .map_err(EncodeError::CBORError)
+}
+
 enum Serialize<'a> {
     Null,
     Tag(u64),
@@ -275,7 +287,7 @@ impl<'a> serde::ser::Serialize for Serialize<'a> {
             Text(v) => ser.serialize_str(v),
             Bytes(v) => ser.serialize_bytes(v),
 
-            Expr(e) => serialize_subexpr(ser, e),
+            Expr(e) => grow_stack(|| serialize_subexpr(ser, e)),
             RecordMap(map) => {
                 ser.collect_map(map.iter().map(|(k, v)| (Label(k), Expr(v))))
             }
@@ -286,34 +298,24 @@ impl<'a> serde::ser::Serialize for Serialize<'a> {
     }
 }
 
-fn collect_nested_applications<'a>(e: &'a Expr) -> (&'a Expr, Vec<&'a Expr>) {
-    fn go<'a>(e: &'a Expr, vec: &mut Vec<&'a Expr>) -> &'a Expr {
-        match e.as_ref() {
-            ExprKind::Op(OpKind::App(f, a)) => {
-                vec.push(a);
-                go(f, vec)
-            }
-            _ => e,
-        }
-    }
+fn collect_nested_applications<'a>(
+    mut e: &'a Expr,
+) -> (&'a Expr, Vec<&'a Expr>) {
     let mut vec = vec![];
-    let e = go(e, &mut vec);
+    while let ExprKind::Op(OpKind::App(f, a)) = e.as_ref() {
+        vec.push(a);
+        e = f;
+    }
     (e, vec)
 }
 
 type LetBinding<'a> = (&'a Label, &'a Option<Expr>, &'a Expr);
 
-fn collect_nested_lets<'a>(e: &'a Expr) -> (&'a Expr, Vec<LetBinding<'a>>) {
-    fn go<'a>(e: &'a Expr, vec: &mut Vec<LetBinding<'a>>) -> &'a Expr {
-        match e.as_ref() {
-            ExprKind::Let(l, t, v, e) => {
-                vec.push((l, t, v));
-                go(e, vec)
-            }
-            _ => e,
-        }
-    }
+fn collect_nested_lets<'a>(mut e: &'a Expr) -> (&'a Expr, Vec<LetBinding<'a>>) {
     let mut vec = vec![];
-    let e = go(e, &mut vec);
+    while let ExprKind::Let(l, t, v, body) = e.as_ref() {
+        vec.push((l, t, v));
+        e = body;
+    }
     (e, vec)
 }
@@ -1,4 +1,5 @@
+mod cbor;
 mod decode;
 mod encode;
-pub use decode::decode;
-pub use encode::encode;
+pub use decode::{decode, decode_from_reader};
+pub use encode::{encode, encode_to_writer};
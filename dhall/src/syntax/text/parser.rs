@@ -1,13 +1,17 @@
+// pest_consume fixes the error type of the parsing functions, and pest's errors are large.
+#![allow(clippy::result_large_err)]
+
 use itertools::Itertools;
 use pest::prec_climber as pcl;
 use pest::prec_climber::PrecClimber;
-use std::collections::{BTreeMap, BTreeSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::iter::once;
-use std::rc::Rc;
 
 use pest_consume::{match_nodes, Parser};
 
 use crate::operations::OpKind::*;
+use crate::sync::Rc;
 use crate::syntax::ExprKind::*;
 use crate::syntax::NumKind::*;
 use crate::syntax::{
@@ -23,11 +27,20 @@ use crate::syntax::{
 
 type ParsedText = InterpolatedText<Expr>;
 type ParsedTextContents = InterpolatedTextContents<Expr>;
-type ParseInput<'input> = pest_consume::Node<'input, Rule, Rc<str>>;
+type ParseInput<'input> = pest_consume::Node<'input, Rule, ParseState>;
 
 pub type ParseError = pest::error::Error<Rule>;
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// Data available to every node of the parse tree.
+#[derive(Clone)]
+struct ParseState {
+    input: Rc<str>,
+    /// Expressions that were parsed ahead of time, by their position in the input. See
+    /// `parse_expr`.
+    parsed: std::rc::Rc<RefCell<HashMap<(usize, usize), Expr>>>,
+}
+
 #[derive(Debug)]
 enum Selector {
     Field(Label),
@@ -36,7 +49,7 @@ enum Selector {
 }
 
 fn input_to_span(input: ParseInput) -> Span {
-    Span::make(input.user_data().clone(), input.as_pair().as_span())
+    Span::make(input.user_data().input.clone(), input.as_pair().as_span())
 }
 fn spanned(input: ParseInput, x: UnspannedExpr) -> Expr {
     Expr::new(x, input_to_span(input))
@@ -398,7 +411,7 @@ impl DhallParser {
         }
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn identifier(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [variable(v)] => spanned(input, Var(v)),
@@ -660,6 +673,11 @@ impl DhallParser {
     }
 
     fn expression(input: ParseInput) -> ParseResult<Expr> {
+        let span = input.as_span();
+        let key = (span.start(), span.end());
+        if let Some(e) = input.user_data().parsed.borrow_mut().remove(&key) {
+            return Ok(e);
+        }
         Ok(match_nodes!(input.children();
             [lambda(()), label(l), expression(typ),
                     arrow(()), expression(body)] => {
@@ -715,7 +733,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     #[prec_climb(expression, PRECCLIMBER)]
     fn operator_expression(
         l: Expr,
@@ -750,7 +768,7 @@ impl DhallParser {
         Ok(())
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn with_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [expression(e)] => e,
@@ -775,7 +793,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn application_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [expression(e)] => e,
@@ -794,7 +812,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn first_application_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [Some_(()), expression(e)] => {
@@ -810,7 +828,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn completion_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [expression(e)] => e,
@@ -829,7 +847,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn selector_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [expression(e)] => e,
@@ -878,7 +896,7 @@ impl DhallParser {
         ))
     }
 
-    #[alias(expression, shortcut = true)]
+    #[alias(expression)]
     fn primitive_expression(input: ParseInput) -> ParseResult<Expr> {
         Ok(match_nodes!(input.children();
             [double_literal(n)] => spanned(input, Num(Double(n))),
@@ -1023,12 +1041,28 @@ impl DhallParser {
 }
 
 pub fn parse_expr(input_str: &str) -> ParseResult<Expr> {
-    let rc_input_str = input_str.to_string().into();
+    let state = ParseState {
+        input: input_str.to_string().into(),
+        parsed: Default::default(),
+    };
     let inputs = DhallParser::parse_with_userdata(
         Rule::final_expression,
         input_str,
-        rc_input_str,
+        state.clone(),
     )?;
+    // Consuming an expression consumes its subexpressions recursively, which would overflow the
+    // stack for deeply nested expressions. Instead, parse the innermost expressions first, so
+    // that the subexpressions of each expression are already parsed when we get to it.
+    let pairs = inputs.as_pairs().clone().flatten().collect::<Vec<_>>();
+    for pair in pairs.into_iter().rev() {
+        if pair.as_rule() == Rule::expression {
+            let span = pair.as_span();
+            let key = (span.start(), span.end());
+            let node = ParseInput::new_with_user_data(pair, state.clone());
+            let e = DhallParser::expression(node)?;
+            state.parsed.borrow_mut().insert(key, e);
+        }
+    }
     Ok(match_nodes!(<DhallParser>; inputs;
         [expression(e)] => e,
     ))
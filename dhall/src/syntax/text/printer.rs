@@ -1,6 +1,7 @@
 use crate::builtins::Builtin;
 use crate::operations::{BinOp, OpKind};
 use crate::syntax::*;
+use crate::utils::grow_stack;
 use itertools::Itertools;
 use std::fmt::{self, Display};
 
@@ -61,11 +62,21 @@ impl UnspannedExpr {
                 b.map(|x| x.phase(PrintPhase::App)),
             )),
             Annot(a, b) => Annot(a.phase(Operator), b),
-            Op(OpKind::BinOp(op, a, b)) => Op(OpKind::BinOp(
-                op,
-                a.phase(PrintPhase::BinOp(op)),
-                b.phase(PrintPhase::BinOp(op)),
-            )),
+            Op(OpKind::BinOp(op, a, b)) => {
+                // Operators associate to the left, so a right operand that uses the same operator
+                // needs parentheses.
+                let b_phase = match b.0.kind() {
+                    Op(OpKind::BinOp(op2, _, _)) if *op2 == op => {
+                        PrintPhase::App
+                    }
+                    _ => PrintPhase::BinOp(op),
+                };
+                Op(OpKind::BinOp(
+                    op,
+                    a.phase(PrintPhase::BinOp(op)),
+                    b.phase(b_phase),
+                ))
+            }
             SomeLit(e) => SomeLit(e.phase(PrintPhase::Import)),
             Op(OpKind::App(f, a)) => Op(OpKind::App(
                 f.phase(PrintPhase::App),
@@ -79,6 +90,9 @@ impl UnspannedExpr {
             Op(Completion(a, b)) => {
                 Op(Completion(a.phase(Primitive), b.phase(Primitive)))
             }
+            Op(With(a, ls, b)) => {
+                Op(With(a.phase(PrintPhase::Import), ls, b.phase(Operator)))
+            }
             ExprKind::Import(a) => {
                 ExprKind::Import(a.map_ref(|x| x.phase(PrintPhase::Import)))
             }
@@ -103,6 +117,8 @@ impl UnspannedExpr {
             | Op(BoolIf(_, _, _))
             | Op(Merge(_, _, _))
             | Op(ToMap(_, _))
+            | Op(With(_, _, _))
+            | Assert(_)
             | Annot(_, _) => phase > PrintPhase::Base,
             // Precedence is magically handled by the ordering of BinOps. This is reverse Pratt
             // parsing.
@@ -312,7 +328,8 @@ impl Display for NumKind {
 
 impl<'a> Display for PhasedExpr<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        self.0.as_ref().fmt_phase(f, self.1)
+        // Every subexpression is printed through here.
+        grow_stack(|| self.0.as_ref().fmt_phase(f, self.1))
     }
 }
 
@@ -422,13 +439,32 @@ impl Display for Hash {
     }
 }
 
+/// Whether the character can appear in an unquoted path component.
+fn is_path_character(c: char) -> bool {
+    matches!(
+        c,
+        '\x21'
+            | '\x24'..='\x27'
+            | '\x2A'..='\x2B'
+            | '\x2D'..='\x2E'
+            | '\x30'..='\x3B'
+            | '\x3D'
+            | '\x40'..='\x5A'
+            | '\x5E'..='\x7A'
+            | '\x7C'
+            | '\x7E'
+    )
+}
+
 impl<SubExpr: Display> Display for Import<SubExpr> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         use FilePrefix::*;
         use ImportMode::*;
         use ImportTarget::*;
+        // Quoted components are percent-encoded when parsed, so only quote the ones that can't
+        // be written unquoted.
         let quote_if_needed = |s: &str| -> String {
-            if s.chars().all(|c| c.is_ascii_alphanumeric()) {
+            if s.chars().all(is_path_character) {
                 s.to_string()
             } else {
                 format!("\"{}\"", s)
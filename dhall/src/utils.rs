@@ -1,8 +1,10 @@
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
 use crate::error::Error;
+use crate::sync::Rc;
 
 // Compute the sha256 hash of a bitstring.
 pub fn sha256_hash(data: &[u8]) -> Box<[u8]> {
@@ -15,3 +17,112 @@ pub fn read_binary_file(path: impl AsRef<Path>) -> Result<Box<[u8]>, Error> {
     File::open(path)?.read_to_end(&mut buffer)?;
     Ok(buffer.into())
 }
+
+/// How much stack `grow_stack` leaves for its closure. Must be more than the stack used between two
+/// calls to `grow_stack` in a recursion, which can be a lot in debug builds.
+const STACK_RED_ZONE: usize = 256 * 1024;
+/// The size of the new stack segments.
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs `f`, on a new stack segment if the current one is close to running out.
+///
+/// Recursive functions that walk expressions call this at each level, so that deeply nested
+/// expressions don't overflow the stack.
+#[inline]
+pub fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, f)
+}
+
+/// A stack that shares its lower items with the stacks it was pushed onto, so that pushing
+/// doesn't copy it. Environments are stacks of variables that get pushed onto at every binder.
+pub(crate) struct Stack<T> {
+    top: Option<Rc<StackNode<T>>>,
+    len: usize,
+}
+
+struct StackNode<T> {
+    item: T,
+    below: Option<Rc<StackNode<T>>>,
+}
+
+impl<T> Stack<T> {
+    pub(crate) fn new() -> Self {
+        Stack { top: None, len: 0 }
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+    pub(crate) fn push(&self, item: T) -> Self {
+        let below = self.top.clone();
+        Stack {
+            top: Some(Rc::new(StackNode { item, below })),
+            len: self.len + 1,
+        }
+    }
+    /// The stack without its top item.
+    pub(crate) fn pop(&self) -> Self {
+        match &self.top {
+            Some(node) => Stack {
+                top: node.below.clone(),
+                len: self.len - 1,
+            },
+            None => Stack::new(),
+        }
+    }
+    /// The item `idx` positions from the top. Takes time proportional to `idx`.
+    pub(crate) fn get(&self, idx: usize) -> Option<&T> {
+        self.iter().nth(idx)
+    }
+    /// Iterates from the top.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut node = self.top.as_deref();
+        std::iter::from_fn(move || {
+            let n = node?;
+            node = n.below.as_deref();
+            Some(&n.item)
+        })
+    }
+    /// Removes and returns the top item, if no other stack shares it.
+    pub(crate) fn pop_unique(&mut self) -> Option<T> {
+        let node = self.top.take()?;
+        match Rc::try_unwrap(node) {
+            Ok(node) => {
+                self.top = node.below;
+                self.len -= 1;
+                Some(node.item)
+            }
+            Err(node) => {
+                self.top = Some(node);
+                None
+            }
+        }
+    }
+}
+
+impl<T> Clone for Stack<T> {
+    fn clone(&self) -> Self {
+        Stack {
+            top: self.top.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Stack::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Dropping the nodes recursively could overflow the stack on long stacks.
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        while self.pop_unique().is_some() {}
+    }
+}
@@ -0,0 +1,20 @@
+//! Round-tripping through the binary encoding. Random expressions are covered by the
+//! `encode_then_decode` and `decode_corrupted` properties in `properties.rs`.
+use dhall::error::Error;
+use dhall::syntax::*;
+
+/// Decoding has no limit on nesting, unlike `serde_cbor` which stops at 128 levels.
+#[test]
+fn binary_round_trip_deep() -> Result<(), Error> {
+    let append = vec!["\"a\""; 300].join(" ++ ");
+    let list = format!("{}0{}", "[".repeat(200), "]".repeat(200));
+    for src in &[append, list] {
+        let expr = parse_expr(src)?;
+        let data = binary::encode(&expr)?;
+        let decoded = binary::decode(&data)?;
+        assert_eq!(binary::encode(&decoded)?, data, "round-tripping {}", src);
+        let read = binary::decode_from_reader(data.as_slice())?;
+        assert_eq!(binary::encode(&read)?, data, "round-tripping {}", src);
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Sharing values between threads, and resolving imports in parallel and concurrently.
+mod temp_dir;
+
+use dhall::error::Error;
+use dhall::*;
+use temp_dir::TempDir;
+
+/// With the `sync` feature, values can be shared between threads.
+#[cfg(feature = "sync")]
+#[test]
+fn thread_safety() {
+    use dhall::semantics::*;
+    use dhall::syntax::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Parsed>();
+    assert_send_sync::<Resolved<'static>>();
+    assert_send_sync::<Typed<'static>>();
+    assert_send_sync::<Normalized<'static>>();
+    assert_send_sync::<Ctxt<'static>>();
+    assert_send_sync::<Expr>();
+    // Async resolution can run on a multi-threaded executor.
+    fn assert_send<T: Send>(_: &T) {}
+    let parsed = Parsed::parse_str("1").unwrap();
+    assert_send(&Ctxt::with_new_async(|cx| {
+        Box::pin(async move { parsed.resolve_async(cx).await.map(|_| ()) })
+    }));
+
+    fn run(cx: Ctxt<'_>) -> Result<(), Error> {
+        let f = "\\(x: Natural) -> \\(y: Natural) -> x * y";
+        let f = Parsed::parse_str(f)?
+            .skip_resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx);
+        let apply = |n: u64| {
+            let n = Nir::from_kind(NirKind::Num(NumKind::Natural(n)));
+            let m = f.as_nir().app(n.clone()).app(n);
+            match m.kind() {
+                NirKind::Num(NumKind::Natural(m)) => *m,
+                _ => panic!("`f` was not `Natural -> Natural -> Natural`"),
+            }
+        };
+        // The same lazily-evaluated value is forced from two threads.
+        let (a, b) = rayon::join(|| apply(6), || apply(7));
+        assert_eq!((a, b), (36, 49));
+        Ok(())
+    }
+    Ctxt::with_new(run).unwrap();
+}
+
+/// Sibling imports are resolved in parallel with the `sync` feature, and concurrently with
+/// `resolve_async`; the results must not depend on it.
+#[test]
+fn import_resolution() {
+    let dir = TempDir::new("import-resolution");
+    let files = [
+        ("shared.dhall", "{ x = 1 }"),
+        ("a.dhall", "(./shared.dhall).x + 1"),
+        ("b.dhall", "(./shared.dhall).x + 2"),
+        (
+            "main.dhall",
+            "[ ./a.dhall, ./b.dhall, ./missing.dhall ? ./a.dhall ]",
+        ),
+        ("cycle1.dhall", "./cycle2.dhall"),
+        ("cycle2.dhall", "./cycle1.dhall"),
+        ("cyclic.dhall", "[ ./a.dhall, ./cycle1.dhall ]"),
+        ("text.dhall", "./shared.dhall as Text ++ ./a.dhall as Text"),
+    ];
+    for (name, contents) in &files {
+        dir.write(name, contents);
+    }
+
+    let eval = |name: &str| -> Result<String, Error> {
+        Ctxt::with_new(|cx| {
+            let nf = Parsed::parse_file(&dir.path(name))?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx);
+            Ok(nf.to_expr(cx).to_string())
+        })
+    };
+    let eval_async = |name: &str| -> Result<String, Error> {
+        let parsed = Parsed::parse_file(&dir.path(name))?;
+        futures::executor::block_on(Ctxt::with_new_async(|cx| {
+            Box::pin(async move {
+                let nf = parsed
+                    .resolve_async(cx)
+                    .await?
+                    .typecheck(cx)?
+                    .normalize(cx);
+                Ok(nf.to_expr(cx).to_string())
+            })
+        }))
+    };
+    for eval in &[&eval as &dyn Fn(&str) -> _, &eval_async] {
+        assert_eq!(eval("main.dhall").unwrap(), "[2, 3, 2]");
+        assert_eq!(
+            eval("text.dhall").unwrap(),
+            "\"{ x = 1 }(./shared.dhall).x + 1\""
+        );
+        assert!(eval("cyclic.dhall").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use dhall::diff::{self, format_path, Diff};
+use dhall::error::Error;
+use dhall::{Ctxt, Parsed};
+
+/// Normalizes the two expressions, and renders their difference.
+fn diff_str(old: &str, new: &str) -> Result<String, Error> {
+    Ctxt::with_new(|cx| {
+        let normalize = |src: &str| -> Result<_, Error> {
+            Ok(Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx))
+        };
+        let diff = diff::diff(cx, &normalize(old)?, &normalize(new)?);
+        Ok(diff.to_string())
+    })
+}
+
+/// The paths of the changes between the two expressions, with the kind of change.
+fn changes(old: &str, new: &str) -> Result<Vec<(String, &'static str)>, Error> {
+    Ctxt::with_new(|cx| {
+        let normalize = |src: &str| -> Result<_, Error> {
+            Ok(Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx))
+        };
+        let diff = diff::diff(cx, &normalize(old)?, &normalize(new)?);
+        Ok(diff
+            .changes()
+            .into_iter()
+            .map(|(path, change)| {
+                let kind = match change {
+                    Diff::Added(_) => "added",
+                    Diff::Removed(_) => "removed",
+                    _ => "replaced",
+                };
+                (format_path(&path), kind)
+            })
+            .collect())
+    })
+}
+
+#[test]
+fn same() -> Result<(), Error> {
+    assert_eq!(diff_str("{ a = 1 + 1 }", "{ a = 2 }")?, "…");
+    // Functions are compared up to the names of their variables.
+    assert_eq!(
+        diff_str("λ(x : Natural) → x + 1", "λ(y : Natural) → y + 1")?,
+        "…"
+    );
+    Ok(())
+}
+
+#[test]
+fn records() -> Result<(), Error> {
+    assert_eq!(
+        diff_str(
+            "{ a = 1, b = { c = True, d = \"x\" }, e = 3, f = 4 }",
+            "{ a = 1, b = { c = False, d = \"x\" }, f = 4, g = 5 }"
+        )?,
+        "{ b = { c = - True\n            + False\n      , …\n      }\n\
+         , - e = 3\n\
+         , + g = 5\n\
+         , …\n\
+         }"
+    );
+    assert_eq!(
+        diff_str("{ a : Natural, b : Bool }", "{ a : Integer, b : Bool }")?,
+        "{ a : - Natural\n      + Integer\n, …\n}"
+    );
+    assert_eq!(
+        changes(
+            "{ servers = [{ port = 80 }, { port = 443 }] }",
+            "{ servers = [{ port = 80 }, { port = 8443 }, { port = 1 }] }"
+        )?,
+        vec![
+            ("servers[1].port".to_owned(), "replaced"),
+            ("servers[2]".to_owned(), "added")
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn lists() -> Result<(), Error> {
+    assert_eq!(
+        diff_str("[1, 2, 3, 4]", "[1, 2, 5, 4, 6]")?,
+        "[ …\n, - 3\n  + 5\n, …\n, + 6\n]"
+    );
+    assert_eq!(
+        diff_str("[1]", "[] : List Natural")?,
+        "- [1]\n+ [] : List Natural"
+    );
+    Ok(())
+}
+
+#[test]
+fn unions() -> Result<(), Error> {
+    assert_eq!(
+        diff_str("< A : Natural | B | C >", "< A : Integer | B : Bool | D >")?,
+        "< A : - Natural\n      + Integer\n\
+         | - B\n\
+         | + B : Bool\n\
+         | - C\n\
+         | + D\n\
+         >"
+    );
+    // Values of a union type are compared by constructor.
+    assert_eq!(
+        diff_str("< A : Natural | B >.A 1", "< A : Natural | B >.A 2")?,
+        "<…>.A - 1\n      + 2"
+    );
+    assert_eq!(
+        diff_str("< A : Natural | B >.A 1", "< A : Natural | B >.B")?,
+        "- < A: Natural | B >.A 1\n+ < A: Natural | B >.B"
+    );
+    assert_eq!(
+        diff_str(
+            "< A : Natural | B : Natural >.A 1",
+            "< A : Natural | B : Natural >.B 1"
+        )?,
+        "- < A: Natural | B: Natural >.A 1\n+ < A: Natural | B: Natural >.B 1"
+    );
+    assert_eq!(
+        diff_str("< A : Natural >.A 1", "< A : Natural | B >.A 1")?,
+        "< + B\n| …\n>.A …"
+    );
+    assert_eq!(
+        changes(
+            "{ x = < A : Natural | B >.A 1 }",
+            "{ x = < A : Natural | B >.A 2 }"
+        )?,
+        vec![("x.A".to_owned(), "replaced")]
+    );
+    Ok(())
+}
+
+#[test]
+fn functions() -> Result<(), Error> {
+    assert_eq!(
+        diff_str(
+            "λ(x : Natural) → { a = x }",
+            "λ(y : Natural) → { a = y + 1 }"
+        )?,
+        "λ(y : …) → { a = - x\n                 + y + 1\n           }"
+    );
+    assert_eq!(
+        changes("∀(x : Type) → List x", "∀(y : Type) → Optional y")?,
+        vec![("(output)(function)".to_owned(), "replaced")]
+    );
+    Ok(())
+}
+
+#[test]
+fn color() -> Result<(), Error> {
+    Ctxt::with_new(|cx| {
+        let normalize = |src: &str| -> Result<_, Error> {
+            Ok(Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx))
+        };
+        let diff = diff::diff(cx, &normalize("Some 1")?, &normalize("Some 2")?);
+        assert_eq!(
+            diff.render(true),
+            "Some \u{1b}[31m- 1\u{1b}[0m\n     \u{1b}[32m+ 2\u{1b}[0m"
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,93 @@
+//! The checks run by the fuzz targets in `dhall/fuzz`. They are shared with the regression tests
+//! in `fuzz_regressions.rs`, which replay the inputs that made them fail.
+#![allow(dead_code)]
+
+use dhall::syntax::{binary, parse_expr, Expr};
+use dhall::{Ctxt, Limits, Parsed};
+
+/// Limits for evaluating fuzzed inputs, so that the fuzzer doesn't get stuck on expressions that
+/// take a long time to normalize.
+fn limits() -> Limits {
+    Limits {
+        max_steps: Some(100_000),
+        max_list_length: Some(10_000),
+        max_text_size: Some(100_000),
+        ..Limits::default()
+    }
+}
+
+fn encode(expr: &Expr) -> Vec<u8> {
+    binary::encode(expr).expect("failed to encode")
+}
+
+/// Parsing must not panic. If it succeeds, printing then parsing the expression gives it back,
+/// and so does encoding then decoding it.
+pub fn check_parse(src: &str) {
+    let expr = match parse_expr(src) {
+        Ok(expr) => expr,
+        Err(_) => return,
+    };
+    let data = encode(&expr);
+    let printed = expr.to_string();
+    let reparsed = parse_expr(&printed).unwrap_or_else(|e| {
+        panic!(
+            "failed to parse `{}`, printed from `{}`: {}",
+            printed, src, e
+        )
+    });
+    assert_eq!(
+        encode(&reparsed),
+        data,
+        "printed `{}` as `{}`",
+        src,
+        printed
+    );
+    check_canonical(&data);
+}
+
+/// Decoding must not panic. If it succeeds, decoding then encoding the canonical form of the
+/// expression gives it back, and decoding from a reader gives the same result.
+pub fn check_decode(data: &[u8]) {
+    let from_reader = binary::decode_from_reader(data);
+    let expr = match binary::decode(data) {
+        Ok(expr) => expr,
+        Err(_) => {
+            assert!(from_reader.is_err(), "only decoded from a reader");
+            return;
+        }
+    };
+    let canonical = encode(&expr);
+    let from_reader = from_reader.expect("only decoded from a slice");
+    assert_eq!(encode(&from_reader), canonical);
+    check_canonical(&canonical);
+}
+
+/// Decoding then encoding canonical input is the identity.
+fn check_canonical(data: &[u8]) {
+    let decoded =
+        binary::decode(data).expect("failed to decode a canonical encoding");
+    assert_eq!(encode(&decoded), data, "decoding then encoding changed it");
+}
+
+/// Evaluation must not panic: any input that typechecks can be normalized, and its normal form
+/// and its type can be printed.
+pub fn check_pipeline(src: &str) {
+    let parsed = match Parsed::parse_str(src) {
+        Ok(parsed) => parsed,
+        Err(_) => return,
+    };
+    Ctxt::with_limits(limits(), |cx| {
+        // Imports aren't resolved: fuzzing shouldn't touch the filesystem or the network.
+        let typed = match parsed.skip_resolve(cx) {
+            Ok(resolved) => match resolved.typecheck(cx) {
+                Ok(typed) => typed,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+        let _ = typed.normalize(cx).to_expr(cx).to_string();
+        if let Ok(ty) = typed.get_type() {
+            let _ = ty.to_expr(cx).to_string();
+        }
+    })
+}
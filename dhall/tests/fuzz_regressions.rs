@@ -0,0 +1,26 @@
+//! Inputs that made the fuzz targets in `dhall/fuzz` fail.
+mod fuzz;
+
+use fuzz::{check_parse, check_pipeline};
+
+#[test]
+fn printing_path_components() {
+    // Quoted path components are percent-encoded, so they used to change when printed then
+    // parsed again.
+    check_parse("/ne@5ate +1");
+    check_parse("./\"a b\"/\"c@d\"/e");
+}
+
+#[test]
+fn printing_assert_and_with() {
+    check_parse("showConstructor (assert : 1 + 1 ≡ < A | B : Natural >.A)");
+    check_parse("f ({=} with a = 1) 3");
+    check_parse("Some ({ a = 1 } with a = 2)");
+    check_parse("(({ a = 1 } with a = 2) with b = 3).a");
+}
+
+#[test]
+fn pi_with_a_body_that_is_not_a_type() {
+    // The error message was printed in the wrong environment.
+    check_pipeline("λ(f : ∀(a : Type) → a → List/build a) → f Natural 1");
+}
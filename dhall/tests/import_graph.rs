@@ -0,0 +1,75 @@
+mod temp_dir;
+
+use dhall::error::Error;
+use dhall::*;
+use temp_dir::TempDir;
+
+#[test]
+fn import_graph() -> Result<(), Error> {
+    let dir = TempDir::new("import-graph");
+    dir.write("shared.dhall", "{ x = 1 }");
+    dir.write("a.dhall", "(./shared.dhall).x");
+    dir.write("b.dhall", "./shared.dhall");
+    dir.write("readme.txt", "hello");
+    dir.write(
+        "main.dhall",
+        "[ ./a.dhall, ./a.dhall ] # [ (./nope.dhall ? ./b.dhall).x ] \
+         # [ Text/size ./readme.txt as Text ]",
+    );
+
+    Ctxt::with_new(|cx| -> Result<(), Error> {
+        let parsed = Parsed::parse_file(&dir.path("main.dhall"))?;
+        let _ = parsed.resolve(cx);
+        let graph = ImportGraph::new(cx);
+        let name = |i: usize| {
+            let location = graph.nodes()[i].location.to_string();
+            location.rsplit('/').next().unwrap().to_owned()
+        };
+        let nodes: Vec<_> = (0..graph.nodes().len()).map(name).collect();
+        assert_eq!(
+            nodes,
+            [
+                "main.dhall",
+                "a.dhall",
+                "b.dhall",
+                "readme.txt as Text",
+                "shared.dhall"
+            ]
+        );
+        assert!(graph.nodes()[0].span.is_none());
+        let edges: Vec<_> = graph
+            .edges()
+            .iter()
+            .map(|edge| (name(edge.from), name(edge.to)))
+            .collect();
+        assert_eq!(edges.len(), 6);
+        assert_eq!(edges[0], ("main.dhall".to_owned(), "a.dhall".to_owned()));
+        assert_eq!(edges[1], edges[0]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph imports {\n"));
+        assert!(dot.contains("    0 -> 1;\n"));
+        assert!(!dot.contains("nope"));
+
+        let json = graph.to_json();
+        assert!(json.contains(r#""mode":"text""#));
+        assert!(
+            json.contains(r#""span":{"column":3,"line":1,"text":"./a.dhall"}"#)
+        );
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 5);
+        assert_eq!(json["edges"].as_array().unwrap().len(), 6);
+        Ok(())
+    })?;
+
+    // A file without imports is still in the graph.
+    Ctxt::with_new(|cx| -> Result<(), Error> {
+        Parsed::parse_file(&dir.path("shared.dhall"))?.resolve(cx)?;
+        let graph = ImportGraph::new(cx);
+        assert_eq!(graph.nodes().len(), 1);
+        assert!(graph.edges().is_empty());
+        assert!(graph.to_dot().contains("shared.dhall"));
+        Ok(())
+    })?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+mod temp_dir;
+
+use dhall::error::{Error, ErrorKind, LimitError};
+use dhall::*;
+use temp_dir::TempDir;
+
+#[test]
+fn limits() {
+    let dir = TempDir::new("limits");
+    dir.write("leaf.dhall", "1");
+    dir.write("mid.dhall", "./leaf.dhall + 1");
+    dir.write("top.dhall", "./mid.dhall + 1");
+    dir.write("alt.dhall", "./mid.dhall ? 0");
+
+    let eval = |limits: Limits, src: &str| -> Result<String, Error> {
+        Ctxt::with_limits(limits, |cx| {
+            let nf = Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize_checked(cx)?;
+            Ok(nf.to_expr(cx).to_string())
+        })
+    };
+    let eval_file = |limits: Limits, name: &str| -> Result<String, Error> {
+        Ctxt::with_limits(limits, |cx| {
+            let nf = Parsed::parse_file(&dir.path(name))?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize_checked(cx)?;
+            Ok(nf.to_expr(cx).to_string())
+        })
+    };
+    let limit_err = |res: Result<String, Error>| match res {
+        Err(err) => match err.kind() {
+            ErrorKind::Limit(err) => err.clone(),
+            _ => panic!("unexpected error: {}", err),
+        },
+        Ok(nf) => panic!("no limit was hit: {}", nf),
+    };
+
+    let fold = "Natural/fold 1000000000 Natural (λ(x : Natural) → x + 1) 0";
+    let steps = Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    };
+    assert_eq!(
+        limit_err(eval(steps.clone(), fold)),
+        LimitError::Steps(1000)
+    );
+    assert_eq!(
+        eval(
+            steps.clone(),
+            "Natural/fold 10 Natural (λ(x : Natural) → x + 1) 0"
+        )
+        .unwrap(),
+        "10"
+    );
+    // Typechecking the assertion needs evaluation, which stops before it can check it.
+    let assert = "let f = λ(n : Natural) → \
+                  Natural/fold n Natural (λ(x : Natural) → x + 1) 0 \
+                  in assert : f 5000 ≡ 5000";
+    assert_eq!(
+        limit_err(eval(steps.clone(), assert)),
+        LimitError::Steps(1000)
+    );
+    assert!(eval(Limits::default(), assert).is_ok());
+    // Without inspecting the normal form.
+    let normalize = |limits: Limits, src: &str| -> Result<(), Error> {
+        Ctxt::with_limits(limits, |cx| {
+            Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize_checked(cx)?;
+            Ok(())
+        })
+    };
+    let err = normalize(steps, fold).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::Limit(LimitError::Steps(1000))
+    ));
+
+    let list = Limits {
+        max_list_length: Some(100),
+        ..Limits::default()
+    };
+    let replicate = |n: usize| {
+        format!(
+            "List/build Natural (λ(list : Type) → λ(cons : Natural → list → list) \
+             → λ(nil : list) → Natural/fold {} list (cons 0) nil)",
+            n
+        )
+    };
+    assert_eq!(
+        limit_err(eval(list.clone(), &replicate(1000))),
+        LimitError::ListLength(100)
+    );
+    assert!(eval(list, &replicate(100)).is_ok());
+
+    let text = Limits {
+        max_text_size: Some(100),
+        ..Limits::default()
+    };
+    let src = r#"Natural/fold 60 Text (λ(t : Text) → t ++ "ab") """#;
+    assert_eq!(limit_err(eval(text, src)), LimitError::TextSize(100));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let cancelled = Limits {
+        cancellation: Some(token),
+        ..Limits::default()
+    };
+    assert_eq!(limit_err(eval(cancelled, fold)), LimitError::Cancelled);
+
+    let imports = |max| Limits {
+        max_imports: Some(max),
+        ..Limits::default()
+    };
+    assert_eq!(eval_file(imports(2), "top.dhall").unwrap(), "3");
+    assert_eq!(
+        limit_err(eval_file(imports(1), "top.dhall")),
+        LimitError::Imports(1)
+    );
+    let depth = |max| Limits {
+        max_import_depth: Some(max),
+        ..Limits::default()
+    };
+    assert_eq!(eval_file(depth(2), "top.dhall").unwrap(), "3");
+    assert_eq!(
+        limit_err(eval_file(depth(1), "top.dhall")),
+        LimitError::ImportDepth(1)
+    );
+    // Alternatives don't hide exceeded limits.
+    assert_eq!(
+        limit_err(eval_file(depth(1), "alt.dhall")),
+        LimitError::ImportDepth(1)
+    );
+}
@@ -0,0 +1,141 @@
+use dhall::error::Error;
+use dhall::lint::{self, Lint};
+use dhall::{Ctxt, Parsed};
+
+/// The lints found in `src`, with whether they can be fixed.
+fn lints(src: &str) -> Result<Vec<(Lint, bool)>, Error> {
+    Ok(Parsed::parse_str(src)?
+        .lint()
+        .into_iter()
+        .map(|d| (d.lint, d.fix.is_some()))
+        .collect())
+}
+
+#[test]
+fn unused_let() -> Result<(), Error> {
+    assert_eq!(lint::fix("let x = 1 in 2")?, "2");
+    assert_eq!(lint::fix("let x = 1 in (x)")?, "let x = 1 in (x)");
+    assert_eq!(lint::fix("let x = (1) in (2)")?, "(2)");
+    assert_eq!(
+        lint::fix("let a = 1 let b = 2 let c = 3 in a + c")?,
+        "let a = 1 let c = 3 in a + c"
+    );
+    assert_eq!(lint::fix("let a = 1 let b = 2 in a")?, "let a = 1 in a");
+    assert_eq!(lint::fix("let a = 1 let b = 2 in b")?, "let b = 2 in b");
+    // Removing a binding can make another one unused.
+    assert_eq!(lint::fix("let a = 1 let b = a in 2")?, "2");
+
+    // Shadowed variables
+    assert_eq!(lints("let x = 1 in λ(x : Natural) → x")?.len(), 1);
+    assert_eq!(lints("let x = 1 in λ(x : Natural) → x@1")?, vec![]);
+    assert_eq!(lints("let x = 1 in let x = x in x")?.len(), 1);
+    // Removing the binding would make `x@1` refer to another variable.
+    assert_eq!(
+        lint::fix("λ(x : Natural) → let x = 1 in x@1")?,
+        "λ(x : Natural) → let x = 1 in x@1"
+    );
+    assert_eq!(
+        lints("λ(x : Natural) → let x = 1 in λ(x : Bool) → x@2")?,
+        vec![(Lint::UnusedLet, false)]
+    );
+    assert_eq!(
+        lint::fix("λ(x : Natural) → let x = 1 in λ(x : Bool) → x")?,
+        "λ(x : Natural) → λ(x : Bool) → x"
+    );
+    // Assertions are checked even if they are not used.
+    assert_eq!(lints("let _ = assert : 1 === 1 in 2")?, vec![]);
+    Ok(())
+}
+
+#[test]
+fn nested_let() -> Result<(), Error> {
+    assert_eq!(
+        lint::fix("let a = 1 in let b = 2 in a + b")?,
+        "let a = 1 let b = 2 in a + b"
+    );
+    assert_eq!(
+        lint::fix("let a = 1 -- one\nin  let b = 2 in a + b")?,
+        "let a = 1 -- one\nlet b = 2 in a + b"
+    );
+    assert_eq!(
+        lints("let a = 1 in (let b = 2 in a + b)")?,
+        vec![(Lint::NestedLet, false)]
+    );
+    assert_eq!(lints("let a = 1 let b = 2 in a + b")?, vec![]);
+    Ok(())
+}
+
+#[test]
+fn deprecated_optional() -> Result<(), Error> {
+    let src =
+        "Optional/fold Natural (Some 1) Natural (λ(x : Natural) → x + 1) 0";
+    assert_eq!(lints(src)?, vec![(Lint::DeprecatedOptional, true)]);
+    let fixed = lint::fix(src)?;
+    Ctxt::with_new(|cx| {
+        let normalized = Parsed::parse_str(&fixed)?
+            .resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx);
+        assert_eq!(normalized.to_expr(cx).to_string(), "2");
+        Ok::<_, Error>(())
+    })?;
+
+    let src = "Optional/build Natural (λ(o : Type) → λ(some : Natural → o) → λ(none : o) → some 1)";
+    let fixed = lint::fix(src)?;
+    Ctxt::with_new(|cx| {
+        let normalized = Parsed::parse_str(&fixed)?
+            .resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx);
+        assert_eq!(normalized.to_expr(cx).to_string(), "Some 1");
+        Ok::<_, Error>(())
+    })?;
+
+    assert_eq!(
+        lints("λ(`Optional/fold` : Bool) → `Optional/fold`")?,
+        vec![]
+    );
+    Ok(())
+}
+
+#[test]
+fn malformed_assert() -> Result<(), Error> {
+    assert_eq!(
+        lints("assert : Natural")?,
+        vec![(Lint::MalformedAssert, false)]
+    );
+    assert_eq!(lints("assert : 1 + 1 ≡ 2")?, vec![]);
+    assert_eq!(
+        lint::fix("let example = 1 + 1 === 2 in example")?,
+        "let example = assert : 1 + 1 === 2 in example"
+    );
+    // With an annotation, the binding is probably meant to be a type.
+    assert_eq!(lints("let T : Type = 1 === 1 in T")?, vec![]);
+    Ok(())
+}
+
+#[test]
+fn fixes_keep_the_formatting() -> Result<(), Error> {
+    let src = "{- header -}\nlet x = 1\n-- comment\nlet y = 2\nin  { x = x }\n";
+    assert_eq!(
+        lint::fix(src)?,
+        "{- header -}\nlet x = 1\n-- comment\nin  { x = x }\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn display() -> Result<(), Error> {
+    let diagnostics = Parsed::parse_str("let x = 1\nin  2")?.lint();
+    assert_eq!(
+        diagnostics[0].to_string(),
+        "warning: unused `let` binding `x`\n \
+         --> <current file>:1:5\n  \
+         |\n\
+         1 | let x = 1\n  \
+         |     - unused-let\n  \
+         |\n  \
+         = help: this can be fixed automatically"
+    );
+    Ok(())
+}
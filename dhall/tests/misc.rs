@@ -48,3 +48,261 @@ fn manual_function_application() {
     // The crate uses essentially a global context, created here.
     Ctxt::with_new(run).unwrap();
 }
+
+/// A `Program` can outlive the context it was created in.
+#[test]
+fn program() -> Result<(), Error> {
+    struct Config {
+        mk_server: Program,
+    }
+    fn load() -> Result<Config, Error> {
+        let mk_server = Program::parse_str(
+            "let Server = { host : Text, port : Natural } \
+             in λ(port : Natural) → { host = \"localhost\", port } : Server",
+        )?;
+        Ok(Config { mk_server })
+    }
+
+    let config = load()?;
+    assert_eq!(
+        config.mk_server.ty().to_string(),
+        "∀(port : Natural) → { host : Text, port : Natural }"
+    );
+    let server = config.mk_server.apply(&Program::parse_str("8080")?)?;
+    assert_eq!(
+        server.normalize()?.to_string(),
+        "{ host = \"localhost\", port = 8080 }"
+    );
+    let port = server.field("port")?;
+    assert_eq!(port.ty().to_string(), "Natural");
+    assert_eq!(port.normalize()?, Program::parse_str("8080")?);
+    assert_eq!(
+        port.with_typed(|cx, typed| typed.normalize(cx).to_expr(cx))?,
+        Program::parse_str("8080")?.to_expr()
+    );
+    // Selecting an alternative of a union type.
+    let either = Program::parse_str("< Left : Natural | Right : Text >")?;
+    let left = either.field("Left")?.apply(&Program::parse_str("1")?)?;
+    assert_eq!(left.ty().to_string(), "< Left: Natural | Right: Text >");
+    assert!(either.field("Right")?.apply(&port).is_err());
+
+    assert!(server.field("name").is_err());
+    assert!(config
+        .mk_server
+        .apply(&Program::parse_str("True")?)
+        .is_err());
+    assert!(Program::parse_str("1 + True").is_err());
+    Ok(())
+}
+
+/// The source text an expression was parsed from.
+fn source_of(expr: &Expr) -> String {
+    match expr.span() {
+        Span::Parsed(sp) => sp.as_str().to_owned(),
+        _ => panic!("expected a parsed span"),
+    }
+}
+
+#[test]
+fn visitor() -> Result<(), Error> {
+    use visitor::{Scope, Visitor};
+
+    struct FreeVars(Vec<String>);
+    impl Visitor for FreeVars {
+        fn visit_expr(&mut self, expr: &Expr, scope: &mut Scope) {
+            if let ExprKind::Var(var) = expr.kind() {
+                if !scope.is_bound(var) {
+                    self.0.push(expr.to_string());
+                }
+            }
+            visitor::walk(self, expr, scope)
+        }
+    }
+
+    let expr = Parsed::parse_str(
+        "λ(x : T) → let y = x@1 in λ(x : Bool) → [x, x@1, x@2, y, y@1, z]",
+    )?
+    .to_expr();
+    let mut free_vars = FreeVars(Vec::new());
+    expr.visit(&mut free_vars);
+    assert_eq!(free_vars.0, vec!["T", "x@1", "x@2", "y@1", "z"]);
+    Ok(())
+}
+
+/// Rename a field everywhere, in place.
+#[test]
+fn visitor_mut() -> Result<(), Error> {
+    use dhall::operations::OpKind;
+    use visitor::{Scope, VisitorMut};
+
+    struct RenameField(Label, Label);
+    impl VisitorMut for RenameField {
+        fn visit_expr_mut(&mut self, expr: &mut Expr, scope: &mut Scope) {
+            match expr.kind_mut() {
+                ExprKind::RecordType(kvs) | ExprKind::RecordLit(kvs) => {
+                    if let Some(x) = kvs.remove(&self.0) {
+                        kvs.insert(self.1.clone(), x);
+                    }
+                }
+                ExprKind::Op(OpKind::Field(_, l)) if *l == self.0 => {
+                    *l = self.1.clone()
+                }
+                _ => {}
+            }
+            visitor::walk_mut(self, expr, scope)
+        }
+    }
+
+    let src = "let r : { a : Natural } = { a = 1 } in r.a + r.b";
+    let mut expr = Parsed::parse_str(src)?.to_expr();
+    expr.visit_mut(&mut RenameField("a".into(), "c".into()));
+    assert_eq!(
+        expr.to_string(),
+        "let r : { c : Natural } = { c = 1 } in r.c + r.b"
+    );
+    // The changed expressions keep their span.
+    assert_eq!(source_of(&expr), src);
+    Ok(())
+}
+
+/// Replace an import with another expression, keeping the span of the import.
+#[test]
+fn fold() -> Result<(), Error> {
+    use visitor::{Fold, Scope};
+
+    struct ReplaceImport(Expr, Expr);
+    impl Fold for ReplaceImport {
+        fn fold_expr(&mut self, expr: Expr, scope: &mut Scope) -> Expr {
+            if expr == self.0 {
+                Expr::new(self.1.kind().clone(), expr.span())
+            } else {
+                visitor::walk_fold(self, expr, scope)
+            }
+        }
+    }
+
+    let expr = Parsed::parse_str("./a.dhall + (./b.dhall ? ./a.dhall)")?;
+    let from = Parsed::parse_str("./a.dhall")?.to_expr();
+    let to = Parsed::parse_str("env:A")?.to_expr();
+    let expr = expr.to_expr().fold(&mut ReplaceImport(from, to));
+    assert_eq!(expr.to_string(), "env:A + (./b.dhall ? env:A)");
+    match expr.kind() {
+        ExprKind::Op(op) => {
+            let mut operands = Vec::new();
+            op.map_ref(|e| operands.push(source_of(e)));
+            assert_eq!(operands, vec!["./a.dhall", "./b.dhall ? ./a.dhall"]);
+        }
+        _ => panic!(),
+    }
+    Ok(())
+}
+
+#[test]
+fn type_index() -> Result<(), Error> {
+    Ctxt::with_new(|cx| {
+        let src = "let id = λ(a : Type) → λ(`x` : a) → x\n\
+                   let f = \\(a : Type) -> \\(a : Type) -> {- a -} \\(y : a@1) -> y\n\
+                   in  λ(b : Bool) → id Bool (Natural/even 2)";
+        let index = Parsed::parse_str(src)?.resolve(cx)?.type_index(cx)?;
+        // The type at the first occurrence of `needle` after `after`.
+        let type_at = |after: &str, needle: &str| {
+            let start = src.find(after).unwrap();
+            let offset = start + src[start..].find(needle).unwrap();
+            let at = index.at(offset).unwrap();
+            (at.binder().map(String::from), at.to_expr().to_string())
+        };
+        let binder =
+            |name: &str, ty: &str| (Some(name.to_owned()), ty.to_owned());
+        let expr = |ty: &str| (None, ty.to_owned());
+
+        assert_eq!(
+            type_at("", "id"),
+            binder("id", "∀(a : Type) → ∀(x : a) → a")
+        );
+        assert_eq!(type_at("", "a :"), binder("a", "Type"));
+        assert_eq!(type_at("", "x`"), binder("x", "a"));
+        assert_eq!(type_at("→ x", "x"), expr("a"));
+        assert_eq!(type_at("{- a -}", "y"), binder("y", "a@1"));
+        assert_eq!(type_at("-> y", "y"), expr("a@1"));
+        assert_eq!(
+            type_at("{- a -}", "{"),
+            expr("∀(a : Type) → ∀(y : a@1) → a@1")
+        );
+        assert_eq!(type_at("", "b :"), binder("b", "Bool"));
+        assert_eq!(type_at("id Bool", "2"), expr("Natural"));
+        assert_eq!(type_at("id Bool", "Natural/even"), expr("Natural → Bool"));
+        assert_eq!(type_at("id Bool", "("), expr("Bool"));
+        assert_eq!(type_at("id Bool", " "), expr("∀(x : Bool) → Bool"));
+        assert_eq!(type_at("λ(b", "→"), expr("∀(b : Bool) → Bool"));
+        assert_eq!(type_at("", "let"), expr("∀(b : Bool) → Bool"));
+
+        // Subexpressions come before the expressions that contain them.
+        let ranges: Vec<_> = index.iter().map(|at| at.range()).collect();
+        assert_eq!(ranges.last(), Some(&(0..src.len())));
+        assert_eq!(index.at(src.len()).map(|at| at.range()), None);
+        Ok(())
+    })
+}
+
+/// Nesting depth for the tests below; deep enough to overflow the stack of the test thread if
+/// something recurses without checking.
+const DEPTH: usize = 100_000;
+
+#[test]
+fn deeply_nested_list() -> Result<(), Error> {
+    let src = format!("{}1{}", "[".repeat(DEPTH), "]".repeat(DEPTH));
+    Ctxt::with_new(|cx| {
+        let typed = Parsed::parse_str(&src)?.resolve(cx)?.typecheck(cx)?;
+        let ty = typed.get_type()?.to_expr(cx);
+        assert_eq!(ty.to_string().matches("List").count(), DEPTH);
+        let nf = typed.normalize(cx).to_expr(cx);
+        assert_eq!(nf.to_string(), src);
+        Ok(())
+    })
+}
+
+#[test]
+fn long_operator_chain() -> Result<(), Error> {
+    let src = format!("0{}", " + 1".repeat(DEPTH));
+    Ctxt::with_new(|cx| {
+        let nf = Parsed::parse_str(&src)?
+            .resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx)
+            .to_expr(cx);
+        assert_eq!(nf.to_string(), DEPTH.to_string());
+        Ok(())
+    })
+}
+
+#[test]
+fn long_let_chain() -> Result<(), Error> {
+    let src = format!("let x = 0 {}in x", "let x = x + 1 ".repeat(DEPTH));
+    Ctxt::with_new(|cx| {
+        let nf = Parsed::parse_str(&src)?
+            .resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx)
+            .to_expr(cx);
+        assert_eq!(nf.to_string(), DEPTH.to_string());
+        Ok(())
+    })
+}
+
+#[test]
+fn deep_natural_fold() -> Result<(), Error> {
+    // The argument is a variable, so normalization unrolls the fold into `n + 1 + 1 + ...`.
+    let src = format!(
+        "λ(n : Natural) → Natural/fold {} Natural (λ(x : Natural) → x + 1) n",
+        DEPTH
+    );
+    Ctxt::with_new(|cx| {
+        let nf = Parsed::parse_str(&src)?
+            .resolve(cx)?
+            .typecheck(cx)?
+            .normalize(cx)
+            .to_expr(cx);
+        assert_eq!(nf.to_string().matches("+ 1").count(), DEPTH);
+        Ok(())
+    })
+}
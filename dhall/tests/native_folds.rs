@@ -0,0 +1,74 @@
+use dhall::error::Error;
+use dhall::*;
+
+/// `Natural/fold` and `List/fold` take shortcuts in common cases; check they compute the same
+/// thing.
+#[test]
+fn native_folds() -> Result<(), Error> {
+    let replicate = |n: &str, x: &str| {
+        format!(
+            "List/build Natural (λ(list : Type) → \
+             λ(cons : Natural → list → list) → Natural/fold {} list (cons {}))",
+            n, x
+        )
+    };
+    let cases = [
+        (replicate("3", "7"), "[7, 7, 7]".to_owned()),
+        (
+            format!("λ(x : Natural) → {}", replicate("2", "x")),
+            "λ(x : Natural) → [x, x]".to_owned(),
+        ),
+        (
+            "Natural/fold 3 (List Natural) \
+             (λ(l : List Natural) → [List/length Natural l] # l) \
+             ([] : List Natural)"
+                .to_owned(),
+            "[2, 1, 0]".to_owned(),
+        ),
+        (
+            "List/fold Natural [1, 2, 3] (List Natural) \
+             (λ(x : Natural) → λ(l : List Natural) → [x, x * 10] # l) [0]"
+                .to_owned(),
+            "[1, 10, 2, 20, 3, 30, 0]".to_owned(),
+        ),
+        (
+            "List/fold Natural [1, 2, 3] (List Natural) \
+             (λ(x : Natural) → λ(l : List Natural) → \
+             if Natural/even x then l else [x] # l) ([] : List Natural)"
+                .to_owned(),
+            "[1, 3]".to_owned(),
+        ),
+        (
+            "λ(l : List Natural) → List/fold Natural [1, 2] (List Natural) \
+             (λ(x : Natural) → λ(acc : List Natural) → [x] # acc) l"
+                .to_owned(),
+            "λ(l : List Natural) → [1] # ([2] # l)".to_owned(),
+        ),
+        // Reaches a fixpoint after two steps.
+        (
+            "Natural/fold 1000000000 Bool (λ(b : Bool) → b && False) True"
+                .to_owned(),
+            "False".to_owned(),
+        ),
+        (
+            "Natural/fold 1000000000 Natural (λ(x : Natural) → 5) 0".to_owned(),
+            "5".to_owned(),
+        ),
+        (
+            "Natural/fold 3 Text (λ(t : Text) → \"a\" ++ t) \"b\"".to_owned(),
+            "\"aaab\"".to_owned(),
+        ),
+    ];
+    for (src, expected) in &cases {
+        let nf = Ctxt::with_new(|cx| -> Result<_, Error> {
+            Ok(Parsed::parse_str(src)?
+                .resolve(cx)?
+                .typecheck(cx)?
+                .normalize(cx)
+                .to_expr(cx)
+                .to_string())
+        })?;
+        assert_eq!(&nf, expected, "normalizing {}", src);
+    }
+    Ok(())
+}
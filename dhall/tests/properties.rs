@@ -0,0 +1,635 @@
+//! Property tests: random well-typed expressions must satisfy the invariants that the text and
+//! binary pipelines rely on.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+use dhall::builtins::Builtin;
+use dhall::operations::{BinOp, OpKind};
+use dhall::syntax::{
+    binary, parse_expr, visitor, Expr, ExprKind, InterpolatedText, Label,
+    NumKind, Span, UnspannedExpr, V,
+};
+use dhall::{Ctxt, Parsed};
+
+/// How many expressions each property is checked on.
+const CASES: u64 = 300;
+/// How deeply the generated expressions are nested.
+const DEPTH: usize = 4;
+
+/// Checks `property` on `CASES` random expressions, and reports the first one it fails on.
+fn for_all(property: impl Fn(&Expr) -> Result<(), String>) {
+    for seed in 0..CASES {
+        let expr = Gen::new(seed).expr();
+        if let Err(msg) = property(&expr) {
+            panic!("failed on `{}` (seed {}): {}", expr, seed, msg);
+        }
+    }
+}
+
+/// The types of the generated expressions.
+#[derive(Clone, PartialEq)]
+enum Ty {
+    Bool,
+    Natural,
+    Integer,
+    Double,
+    Text,
+    List(Box<Ty>),
+    Optional(Box<Ty>),
+    Record(BTreeMap<Label, Ty>),
+    Union(BTreeMap<Label, Option<Ty>>),
+    Fun(Box<Ty>, Box<Ty>),
+}
+
+fn mk(kind: UnspannedExpr) -> Expr {
+    Expr::new(kind, Span::Artificial)
+}
+fn builtin(b: Builtin) -> Expr {
+    mk(ExprKind::Builtin(b))
+}
+fn app(f: Expr, args: Vec<Expr>) -> Expr {
+    args.into_iter()
+        .fold(f, |f, a| mk(ExprKind::Op(OpKind::App(f, a))))
+}
+fn binop(op: BinOp, x: Expr, y: Expr) -> Expr {
+    mk(ExprKind::Op(OpKind::BinOp(op, x, y)))
+}
+fn field(x: Expr, l: &Label) -> Expr {
+    mk(ExprKind::Op(OpKind::Field(x, l.clone())))
+}
+
+impl Ty {
+    fn to_expr(&self) -> Expr {
+        match self {
+            Ty::Bool => builtin(Builtin::Bool),
+            Ty::Natural => builtin(Builtin::Natural),
+            Ty::Integer => builtin(Builtin::Integer),
+            Ty::Double => builtin(Builtin::Double),
+            Ty::Text => builtin(Builtin::Text),
+            Ty::List(t) => app(builtin(Builtin::List), vec![t.to_expr()]),
+            Ty::Optional(t) => {
+                app(builtin(Builtin::Optional), vec![t.to_expr()])
+            }
+            Ty::Record(kts) => mk(ExprKind::RecordType(
+                kts.iter().map(|(k, t)| (k.clone(), t.to_expr())).collect(),
+            )),
+            Ty::Union(kts) => mk(ExprKind::UnionType(
+                kts.iter()
+                    .map(|(k, t)| (k.clone(), t.as_ref().map(Ty::to_expr)))
+                    .collect(),
+            )),
+            Ty::Fun(a, b) => {
+                mk(ExprKind::Pi("_".into(), a.to_expr(), b.to_expr()))
+            }
+        }
+    }
+}
+
+/// Generates random well-typed closed expressions, by picking a type and then an expression of
+/// that type.
+struct Gen {
+    rng: StdRng,
+    /// The variables in scope, innermost last.
+    env: Vec<(Label, Ty)>,
+}
+
+impl Gen {
+    fn new(seed: u64) -> Self {
+        Gen {
+            rng: StdRng::seed_from_u64(seed),
+            env: Vec::new(),
+        }
+    }
+
+    fn expr(&mut self) -> Expr {
+        let ty = self.ty(2);
+        self.term(&ty, DEPTH)
+    }
+
+    fn pick<'a, T>(&mut self, xs: &'a [T]) -> &'a T {
+        &xs[self.rng.gen_range(0, xs.len())]
+    }
+
+    fn label(&mut self) -> Label {
+        // Few names, so that variables often shadow each other.
+        Label::from(*self.pick(&["x", "y", "_"]))
+    }
+
+    fn field_labels(&mut self) -> Vec<Label> {
+        let n = self.rng.gen_range(1, 4);
+        ["a", "b", "c"][..n]
+            .iter()
+            .map(|&l| Label::from(l))
+            .collect()
+    }
+
+    fn ty(&mut self, depth: usize) -> Ty {
+        let max = if depth == 0 { 5 } else { 10 };
+        match self.rng.gen_range(0, max) {
+            0 => Ty::Bool,
+            1 => Ty::Natural,
+            2 => Ty::Integer,
+            3 => Ty::Double,
+            4 => Ty::Text,
+            5 => Ty::List(Box::new(self.ty(depth - 1))),
+            6 => Ty::Optional(Box::new(self.ty(depth - 1))),
+            7 => Ty::Record(
+                self.field_labels()
+                    .into_iter()
+                    .map(|l| (l, self.ty(depth - 1)))
+                    .collect(),
+            ),
+            8 => Ty::Union(
+                self.field_labels()
+                    .into_iter()
+                    .map(|l| {
+                        let t = if self.rng.gen() {
+                            Some(self.ty(depth - 1))
+                        } else {
+                            None
+                        };
+                        (l, t)
+                    })
+                    .collect(),
+            ),
+            _ => Ty::Fun(
+                Box::new(self.ty(depth - 1)),
+                Box::new(self.ty(depth - 1)),
+            ),
+        }
+    }
+
+    /// A variable of type `ty` in scope, if there is one.
+    fn var(&mut self, ty: &Ty) -> Option<Expr> {
+        let candidates: Vec<usize> = (0..self.env.len())
+            .filter(|&i| &self.env[i].1 == ty)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let i = *self.pick(&candidates);
+        let label = &self.env[i].0;
+        let idx = self.env[i + 1..].iter().filter(|(l, _)| l == label).count();
+        Some(mk(ExprKind::Var(V(label.clone(), idx))))
+    }
+
+    /// `body` generates an expression with `label` of type `ty` in scope.
+    fn under_binder<T>(
+        &mut self,
+        label: &Label,
+        ty: &Ty,
+        body: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.env.push((label.clone(), ty.clone()));
+        let x = body(self);
+        self.env.pop();
+        x
+    }
+
+    fn lambda(&mut self, a: &Ty, b: &Ty, depth: usize) -> Expr {
+        let label = self.label();
+        let body = self.under_binder(&label, a, |g| g.term(b, depth));
+        mk(ExprKind::Lam(label, a.to_expr(), body))
+    }
+
+    fn term(&mut self, ty: &Ty, depth: usize) -> Expr {
+        if depth == 0 || self.rng.gen_range(0, 10) == 0 {
+            if self.rng.gen() {
+                if let Some(v) = self.var(ty) {
+                    return v;
+                }
+            }
+            return self.leaf(ty);
+        }
+        let d = depth - 1;
+        if self.rng.gen_range(0, 3) == 0 {
+            return self.any_term(ty, d);
+        }
+        match ty {
+            Ty::Bool => match self.rng.gen_range(0, 4) {
+                0 => {
+                    let op = *self.pick(&[
+                        BinOp::BoolAnd,
+                        BinOp::BoolOr,
+                        BinOp::BoolEQ,
+                        BinOp::BoolNE,
+                    ]);
+                    binop(op, self.term(ty, d), self.term(ty, d))
+                }
+                1 => app(
+                    builtin(Builtin::NaturalIsZero),
+                    vec![self.term(&Ty::Natural, d)],
+                ),
+                2 => app(
+                    builtin(Builtin::NaturalEven),
+                    vec![self.term(&Ty::Natural, d)],
+                ),
+                _ => self.leaf(ty),
+            },
+            Ty::Natural => match self.rng.gen_range(0, 4) {
+                0 => {
+                    let op =
+                        *self.pick(&[BinOp::NaturalPlus, BinOp::NaturalTimes]);
+                    binop(op, self.term(ty, d), self.term(ty, d))
+                }
+                1 => {
+                    let n = mk(ExprKind::Num(NumKind::Natural(
+                        self.rng.gen_range(0, 4),
+                    )));
+                    let f = self.term(
+                        &Ty::Fun(ty.clone().into(), ty.clone().into()),
+                        d,
+                    );
+                    app(
+                        builtin(Builtin::NaturalFold),
+                        vec![n, ty.to_expr(), f, self.term(ty, d)],
+                    )
+                }
+                2 => {
+                    let t = self.ty(1);
+                    let xs = self.term(&Ty::List(Box::new(t.clone())), d);
+                    app(builtin(Builtin::ListLength), vec![t.to_expr(), xs])
+                }
+                _ => self.leaf(ty),
+            },
+            Ty::Integer => match self.rng.gen_range(0, 3) {
+                0 => {
+                    app(builtin(Builtin::IntegerNegate), vec![self.term(ty, d)])
+                }
+                1 => app(
+                    builtin(Builtin::NaturalToInteger),
+                    vec![self.term(&Ty::Natural, d)],
+                ),
+                _ => self.leaf(ty),
+            },
+            Ty::Double => app(
+                builtin(Builtin::IntegerToDouble),
+                vec![self.term(&Ty::Integer, d)],
+            ),
+            Ty::Text => match self.rng.gen_range(0, 4) {
+                0 => {
+                    binop(BinOp::TextAppend, self.term(ty, d), self.term(ty, d))
+                }
+                1 => mk(ExprKind::TextLit(InterpolatedText::from((
+                    "a".to_owned(),
+                    vec![(self.term(ty, d), "b".to_owned())],
+                )))),
+                2 => app(
+                    builtin(Builtin::NaturalShow),
+                    vec![self.term(&Ty::Natural, d)],
+                ),
+                _ => app(builtin(Builtin::TextShow), vec![self.term(ty, d)]),
+            },
+            Ty::List(t) => match self.rng.gen_range(0, 3) {
+                0 => {
+                    let n = self.rng.gen_range(1, 4);
+                    mk(ExprKind::NEListLit(
+                        (0..n).map(|_| self.term(t, d)).collect(),
+                    ))
+                }
+                1 => {
+                    binop(BinOp::ListAppend, self.term(ty, d), self.term(ty, d))
+                }
+                _ => app(
+                    builtin(Builtin::ListReverse),
+                    vec![t.to_expr(), self.term(ty, d)],
+                ),
+            },
+            Ty::Optional(t) => match self.rng.gen_range(0, 2) {
+                0 => mk(ExprKind::SomeLit(self.term(t, d))),
+                _ => app(
+                    builtin(Builtin::ListHead),
+                    vec![t.to_expr(), self.term(&Ty::List(t.clone()), d)],
+                ),
+            },
+            Ty::Record(kts) => mk(ExprKind::RecordLit(
+                kts.iter()
+                    .map(|(k, t)| (k.clone(), self.term(t, d)))
+                    .collect(),
+            )),
+            Ty::Union(_) => self.leaf(ty),
+            Ty::Fun(a, b) => self.lambda(a, b, d),
+        }
+    }
+
+    /// An expression of any type built from other expressions.
+    fn any_term(&mut self, ty: &Ty, d: usize) -> Expr {
+        match self.rng.gen_range(0, 6) {
+            0 => {
+                let label = self.label();
+                let a = self.ty(1);
+                let annot = if self.rng.gen() {
+                    Some(a.to_expr())
+                } else {
+                    None
+                };
+                let value = self.term(&a, d);
+                let body = self.under_binder(&label, &a, |g| g.term(ty, d));
+                mk(ExprKind::Let(label, annot, value, body))
+            }
+            1 => {
+                let cond = self.term(&Ty::Bool, d);
+                mk(ExprKind::Op(OpKind::BoolIf(
+                    cond,
+                    self.term(ty, d),
+                    self.term(ty, d),
+                )))
+            }
+            2 => {
+                let a = self.ty(1);
+                let f = self.lambda(&a, ty, d);
+                app(f, vec![self.term(&a, d)])
+            }
+            3 => mk(ExprKind::Annot(self.term(ty, d), ty.to_expr())),
+            4 => {
+                let u = match self.ty(1) {
+                    u @ Ty::Union(_) => u,
+                    _ => Ty::Union(
+                        vec![(Label::from("A"), Some(Ty::Natural))]
+                            .into_iter()
+                            .collect(),
+                    ),
+                };
+                let handlers = match &u {
+                    Ty::Union(kts) => kts
+                        .iter()
+                        .map(|(k, t)| {
+                            let h = match t {
+                                Some(t) => self.lambda(t, ty, d),
+                                None => self.term(ty, d),
+                            };
+                            (k.clone(), h)
+                        })
+                        .collect(),
+                    _ => unreachable!(),
+                };
+                let handlers = mk(ExprKind::RecordLit(handlers));
+                let arg = self.term(&u, d);
+                mk(ExprKind::Op(OpKind::Merge(handlers, arg, None)))
+            }
+            _ => {
+                let other = Label::from("other");
+                let mut kvs = BTreeMap::new();
+                kvs.insert(Label::from("value"), self.term(ty, d));
+                let t = self.ty(1);
+                kvs.insert(other, self.term(&t, d));
+                field(mk(ExprKind::RecordLit(kvs)), &Label::from("value"))
+            }
+        }
+    }
+
+    /// A simple expression of the given type.
+    fn leaf(&mut self, ty: &Ty) -> Expr {
+        match ty {
+            Ty::Bool => mk(ExprKind::Num(NumKind::Bool(self.rng.gen()))),
+            Ty::Natural => {
+                mk(ExprKind::Num(NumKind::Natural(self.rng.gen_range(0, 10))))
+            }
+            Ty::Integer => {
+                mk(ExprKind::Num(NumKind::Integer(self.rng.gen_range(-10, 10))))
+            }
+            Ty::Double => {
+                let x = *self.pick(&[0.0, -0.0, 1.5, -2.25, 1e100, 3.0]);
+                mk(ExprKind::Num(NumKind::Double(x.into())))
+            }
+            Ty::Text => {
+                let s = *self.pick(&["", "a", "\"quoted\"", "${}", "λ\n\t"]);
+                mk(ExprKind::TextLit(InterpolatedText::from(s.to_owned())))
+            }
+            Ty::List(_) => mk(ExprKind::EmptyListLit(ty.to_expr())),
+            Ty::Optional(t) => {
+                app(builtin(Builtin::OptionalNone), vec![t.to_expr()])
+            }
+            Ty::Record(kts) => mk(ExprKind::RecordLit(
+                kts.iter()
+                    .map(|(k, t)| (k.clone(), self.term(t, 0)))
+                    .collect(),
+            )),
+            Ty::Union(kts) => {
+                let kts: Vec<_> = kts.iter().collect();
+                let (k, t) = *self.pick(&kts);
+                let constructor = field(ty.to_expr(), k);
+                match t {
+                    Some(t) => app(constructor, vec![self.term(t, 0)]),
+                    None => constructor,
+                }
+            }
+            Ty::Fun(a, b) => self.lambda(a, b, 0),
+        }
+    }
+}
+
+/// Renames every bound variable with `rename`, and updates the variables that refer to it.
+/// `scope` holds the old and new names of the binders in scope, innermost last.
+fn rename_binders(
+    e: &Expr,
+    scope: &mut Vec<(Label, Label)>,
+    rename: &mut dyn FnMut(&Label) -> Label,
+) -> Expr {
+    let new_label = match e.kind() {
+        ExprKind::Lam(l, _, _)
+        | ExprKind::Pi(l, _, _)
+        | ExprKind::Let(l, _, _, _) => Some(rename(l)),
+        _ => None,
+    };
+    let kind = match e.kind() {
+        ExprKind::Var(V(l, n)) => {
+            let mut count = 0;
+            let mut found = None;
+            for i in (0..scope.len()).rev() {
+                if &scope[i].0 == l {
+                    if count == *n {
+                        found = Some(i);
+                        break;
+                    }
+                    count += 1;
+                }
+            }
+            let i = found.expect("generated expressions are closed");
+            let new = &scope[i].1;
+            let idx = scope[i + 1..].iter().filter(|(_, n)| n == new).count();
+            ExprKind::Var(V(new.clone(), idx))
+        }
+        kind => {
+            let kind = visitor::visit_ref(kind, |binder, sub| {
+                Ok::<_, Infallible>(match binder {
+                    Some(l) => {
+                        let new = new_label.clone().unwrap();
+                        scope.push((l.clone(), new));
+                        let sub = rename_binders(sub, scope, rename);
+                        scope.pop();
+                        sub
+                    }
+                    None => rename_binders(sub, scope, rename),
+                })
+            });
+            match kind {
+                Ok(kind) => kind,
+                Err(e) => match e {},
+            }
+        }
+    };
+    let kind = match (kind, new_label) {
+        (ExprKind::Lam(_, t, b), Some(l)) => ExprKind::Lam(l, t, b),
+        (ExprKind::Pi(_, t, b), Some(l)) => ExprKind::Pi(l, t, b),
+        (ExprKind::Let(_, t, v, b), Some(l)) => ExprKind::Let(l, t, v, b),
+        (kind, _) => kind,
+    };
+    mk(kind)
+}
+
+/// Names every bound variable `_`, so that alpha-equivalent expressions become equal.
+fn alpha_normalize(e: &Expr) -> Expr {
+    rename_binders(e, &mut Vec::new(), &mut |_| Label::from("_"))
+}
+
+/// Typechecks and normalizes an expression, and returns its alpha-normal form.
+fn normalize(e: &Expr, alpha: bool) -> Result<Expr, String> {
+    let data = binary::encode(e).map_err(|e| e.to_string())?;
+    let parsed = Parsed::parse_binary(&data).map_err(|e| e.to_string())?;
+    Ctxt::with_new(|cx| {
+        let typed = parsed
+            .skip_resolve(cx)
+            .map_err(|e| e.to_string())?
+            .typecheck(cx)
+            .map_err(|e| e.to_string())?;
+        let nf = typed.normalize(cx);
+        Ok(if alpha {
+            nf.to_expr_alpha(cx)
+        } else {
+            nf.to_expr(cx)
+        })
+    })
+}
+
+#[test]
+fn generated_expressions_typecheck() {
+    for_all(|e| normalize(e, false).map(|_| ()));
+}
+
+#[test]
+fn print_then_parse() {
+    for_all(|e| {
+        let printed = e.to_string();
+        let parsed = parse_expr(&printed).map_err(|e| e.to_string())?;
+        if alpha_normalize(&parsed) != alpha_normalize(e) {
+            return Err(format!("parsed back as `{}`", parsed));
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn encode_then_decode() {
+    for_all(|e| {
+        let data = binary::encode(e).map_err(|e| e.to_string())?;
+        let mut written = Vec::new();
+        binary::encode_to_writer(e, &mut written).map_err(|e| e.to_string())?;
+        if written != data {
+            return Err("encoded differently to a writer".to_owned());
+        }
+        let decoded = binary::decode(&data).map_err(|e| format!("{:?}", e))?;
+        if &decoded != e {
+            return Err(format!("decoded as `{}`", decoded));
+        }
+        let read = binary::decode_from_reader(data.as_slice())
+            .map_err(|e| format!("{:?}", e))?;
+        if &read != e {
+            return Err(format!("decoded from a reader as `{}`", read));
+        }
+        Ok(())
+    });
+}
+
+/// Decoding corrupted bytes must fail cleanly, and the same way from a reader and from a slice.
+/// Whatever decodes successfully must round-trip.
+#[test]
+fn decode_corrupted() {
+    let rng = RefCell::new(StdRng::seed_from_u64(0));
+    for_all(|e| {
+        let data = binary::encode(e).map_err(|e| e.to_string())?;
+        let rng = &mut *rng.borrow_mut();
+        for _ in 0..10 {
+            let mut corrupted = data.clone();
+            match rng.gen_range(0, 3) {
+                0 => corrupted.truncate(rng.gen_range(0, data.len())),
+                1 => corrupted.push(rng.gen()),
+                _ => {
+                    let i = rng.gen_range(0, data.len());
+                    corrupted[i] = rng.gen();
+                }
+            }
+            let from_slice = binary::decode(&corrupted);
+            let from_reader = binary::decode_from_reader(corrupted.as_slice());
+            match (from_slice, from_reader) {
+                (Ok(x), Ok(y)) => {
+                    if x != y {
+                        let msg = format!(
+                            "`{}` from a slice but `{}` from a reader",
+                            x, y
+                        );
+                        return Err(format!(
+                            "{:?} decoded as {}",
+                            corrupted, msg
+                        ));
+                    }
+                    let data = binary::encode(&x).map_err(|e| e.to_string())?;
+                    let again = binary::decode(&data)
+                        .map_err(|e| format!("{:?}", e))?;
+                    if again != x {
+                        return Err(format!(
+                            "{:?} decoded as `{}`, which doesn't round-trip",
+                            corrupted, x
+                        ));
+                    }
+                }
+                (Err(_), Err(_)) => {}
+                (x, y) => {
+                    let msg = format!(
+                        "{:?} from a slice but {:?} from a reader",
+                        x, y
+                    );
+                    return Err(format!("{:?} decoded as {}", corrupted, msg));
+                }
+            }
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn normalize_is_idempotent() {
+    for_all(|e| {
+        let nf = normalize(e, false)?;
+        let nf2 = normalize(&nf, false)?;
+        if nf2 != nf {
+            return Err(format!("`{}` normalizes to `{}`", nf, nf2));
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn hash_is_stable_under_alpha_renaming() {
+    let hash = |e: &Expr| -> Result<Box<[u8]>, String> {
+        normalize(e, true)?.sha256_hash().map_err(|e| e.to_string())
+    };
+    for_all(|e| {
+        let mut n = 0;
+        let renamed = rename_binders(e, &mut Vec::new(), &mut |l| {
+            n += 1;
+            Label::from(format!("{}{}", l, n))
+        });
+        if alpha_normalize(&renamed) != alpha_normalize(e) {
+            return Err(format!("renamed to `{}`", renamed));
+        }
+        if hash(&renamed)? != hash(e)? {
+            return Err(format!("renamed to `{}`", renamed));
+        }
+        Ok(())
+    });
+}
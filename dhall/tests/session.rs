@@ -0,0 +1,64 @@
+mod temp_dir;
+
+use dhall::error::Error;
+use dhall::*;
+use temp_dir::TempDir;
+
+/// A `Session` only reloads the files that changed and the files that import them.
+#[test]
+fn session() -> Result<(), Error> {
+    let dir = TempDir::new("session");
+    dir.write("shared.dhall", "{ x = 1 }");
+    dir.write("a.dhall", "(./shared.dhall).x + 1");
+    dir.write("b.dhall", "(./shared.dhall).x + 2");
+    dir.write("main.dhall", "[ ./a.dhall, ./b.dhall ]");
+    let main = dir.path("main.dhall");
+
+    let mut session = Session::new();
+    assert_eq!(session.load_file(&main)?.to_string(), "[2, 3]");
+    assert_eq!(session.len(), 4);
+    assert_eq!(session.refresh(), 0);
+    assert_eq!(session.load_file(&main)?.to_string(), "[2, 3]");
+
+    // Only `b.dhall` and `main.dhall` are affected.
+    dir.write("b.dhall", "(./shared.dhall).x + 3");
+    assert_eq!(session.refresh(), 2);
+    assert_eq!(session.len(), 2);
+    assert_eq!(session.load_file(&main)?.to_string(), "[2, 4]");
+    assert_eq!(session.len(), 4);
+
+    // Rewriting a file with the same contents changes nothing.
+    dir.write("b.dhall", "(./shared.dhall).x + 3");
+    assert_eq!(session.refresh(), 0);
+
+    // Everything depends on `shared.dhall`.
+    dir.write("shared.dhall", "{ x = 10 }");
+    assert_eq!(session.load_file(&main)?.to_string(), "[11, 13]");
+
+    // Errors are not remembered.
+    dir.write("a.dhall", "(./shared.dhall).y");
+    assert!(session.load_file(&main).is_err());
+    assert_eq!(session.len(), 2);
+    dir.write("a.dhall", "(./shared.dhall).x");
+    assert_eq!(session.load_file(&main)?.to_string(), "[10, 13]");
+
+    // An import that fell back on an alternative is kept until the one that failed changes.
+    dir.write("c.dhall", "./missing.dhall ? 5");
+    let c = dir.path("c.dhall");
+    assert_eq!(session.load_file(&c)?.to_string(), "5");
+    assert_eq!(session.refresh(), 0);
+    dir.write("missing.dhall", "6");
+    assert_eq!(session.refresh(), 2);
+    assert_eq!(session.load_file(&c)?.to_string(), "6");
+
+    // Imports of expressions resolved in a session are remembered too.
+    session.clear();
+    Ctxt::with_new(|cx| -> Result<(), Error> {
+        let parsed = Parsed::parse_file(&main)?;
+        let nf = session.resolve(cx, parsed)?.typecheck(cx)?.normalize(cx);
+        assert_eq!(nf.to_expr(cx).to_string(), "[10, 13]");
+        Ok(())
+    })?;
+    assert_eq!(session.len(), 3);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::ffi::OsString;
 use std::fmt::{Debug, Display};
@@ -8,6 +9,7 @@ use std::fs::{create_dir_all, read_to_string, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use libtest_mimic::{Arguments, Outcome, Test};
 use walkdir::WalkDir;
@@ -280,9 +282,40 @@ enum SpecTestKind {
     AlphaNormalization,
 }
 
+impl SpecTestKind {
+    /// The section of the standard test suite this kind of test belongs to.
+    fn section(self) -> &'static str {
+        use SpecTestKind::*;
+        match self {
+            ParserSuccess | ParserFailure | Printer | BinaryEncoding => {
+                "parser"
+            }
+            BinaryDecodingSuccess | BinaryDecodingFailure => "binary-decode",
+            ImportSuccess | ImportFailure => "import",
+            Normalization => "normalization",
+            AlphaNormalization => "alpha-normalization",
+            TypeInferenceSuccess | TypeInferenceFailure => "type-inference",
+            SemanticHash => "semantic-hash",
+        }
+    }
+}
+
+/// The sections of the standard test suite, in the order they are reported.
+static SECTIONS: &[&str] = &[
+    "parser",
+    "binary-decode",
+    "import",
+    "normalization",
+    "alpha-normalization",
+    "type-inference",
+    "semantic-hash",
+];
+
 #[derive(Clone)]
 struct SpecTest {
     kind: SpecTestKind,
+    /// Why the test is skipped, if it is.
+    skipped: Option<&'static str>,
     input: TestFile,
     output: TestFile,
 }
@@ -442,7 +475,7 @@ fn discover_tests_for_feature(feature: TestFeature) -> Vec<Test<SpecTest>> {
                 .join(&path)
                 .to_string_lossy()
                 .replace("\\", "/");
-            let is_ignored = ignore_test(feature.variant, &rel_path);
+            let skipped = skip_reason(feature.variant, &rel_path);
 
             // Transform path into a valid Rust identifier
             let name =
@@ -474,12 +507,13 @@ fn discover_tests_for_feature(feature: TestFeature) -> Vec<Test<SpecTest>> {
             tests.push(Test {
                 name: format!("{}::{}", feature.module_name, name),
                 kind: "".into(),
-                is_ignored,
+                is_ignored: skipped.is_some(),
                 is_bench: false,
                 data: SpecTest {
                     input,
                     output,
                     kind: feature.variant,
+                    skipped,
                 },
             });
         }
@@ -487,24 +521,12 @@ fn discover_tests_for_feature(feature: TestFeature) -> Vec<Test<SpecTest>> {
     tests
 }
 
-/// Ignore some tests if they are known to be failing or not meant to pass.
-/// `path` must be relative to the test directorie(s).
+/// Why a test is skipped, if it is. We skip tests that are known to be failing or not meant to
+/// pass. `path` must be relative to the test directorie(s).
 #[allow(clippy::nonminimal_bool)]
-fn ignore_test(variant: SpecTestKind, path: &str) -> bool {
+fn skip_reason(variant: SpecTestKind, path: &str) -> Option<&'static str> {
     use SpecTestKind::*;
 
-    // This will never succeed because of a specificity of dhall-rust.
-    let is_meant_to_fail = false
-        // We don't support bignums
-        || path == "binary-decode/success/unit/IntegerBigNegative"
-        || path == "binary-decode/success/unit/IntegerBigPositive"
-        || path == "binary-decode/success/unit/NaturalBig"
-        || path == "semantic-hash/success/simple/integerToDouble"
-        || path == "normalization/success/simple/integerToDouble"
-        // These don't typecheck but we always tck before normalizing.
-        || path == "alpha-normalization/success/unit/FunctionNestedBindingXXFree"
-        || path == "normalization/success/unit/Sort";
-
     // Fails because of Windows-specific shenanigans.
     let fails_on_windows = false
         // TODO: git changes newlines on windows
@@ -519,37 +541,56 @@ fn ignore_test(variant: SpecTestKind, path: &str) -> bool {
         || path == "import/success/unit/MixImportModes"
         || variant == ImportFailure;
 
-    // Only include in release tests.
-    let is_too_slow = false
-        || path == "parser/success/largeExpression"
-        || path == "normalization/success/remoteSystems";
+    let reason = match path {
+        // These will never succeed because of a specificity of dhall-rust.
+        // We don't support bignums
+        "binary-decode/success/unit/IntegerBigNegative"
+        | "binary-decode/success/unit/IntegerBigPositive"
+        | "binary-decode/success/unit/NaturalBig"
+        | "semantic-hash/success/simple/integerToDouble"
+        | "normalization/success/simple/integerToDouble" => "bignums",
+        // These don't typecheck but we always tck before normalizing.
+        "alpha-normalization/success/unit/FunctionNestedBindingXXFree"
+        | "normalization/success/unit/Sort" => {
+            "normalizing ill-typed expressions"
+        }
 
-    // This is a mistake in the spec, we should make a PR for it.
-    let is_spec_error = false
+        // These are mistakes in the spec, we should make a PR for them.
         // The standard does not respect https://tools.ietf.org/html/rfc3986#section-5.2
-        || path == "import/success/unit/asLocation/RemoteCanonicalize4"
+        "import/success/unit/asLocation/RemoteCanonicalize4" => {
+            "spec error: canonicalization of remote imports"
+        }
         // The spec should specify how to print a Double
-        || path == "normalization/success/prelude/JSON/number/1";
+        "normalization/success/prelude/JSON/number/1" => {
+            "spec error: printing of Doubles"
+        }
 
-    // Failing for now, we should fix that.
-    let is_failing_for_now = false
+        // Failing for now, we should fix that.
         // TODO: fails because of caching issues.
-        || path == "type-inference/success/prelude"
+        "type-inference/success/prelude" => "caching",
         // TODO: do not recover from cyclic imports
-        || path == "import/failure/unit/DontRecoverCycle"
+        "import/failure/unit/DontRecoverCycle" => "cyclic import recovery",
         // TODO: import headers
-        || path == "import/success/customHeaders"
-        || path == "import/success/headerForwarding"
-        || path == "import/success/noHeaderForwarding"
-        || path == "import/failure/customHeadersUsingBoundVariable"
+        "import/success/customHeaders"
+        | "import/success/headerForwarding"
+        | "import/success/noHeaderForwarding"
+        | "import/failure/customHeadersUsingBoundVariable" => "import headers",
         // TODO: enable free variable checking
-        || path == "type-inference/failure/unit/MergeHandlerFreeVar";
+        "type-inference/failure/unit/MergeHandlerFreeVar" => {
+            "free variable checking"
+        }
 
-    (cfg!(debug_assertions) && is_too_slow)
-        || (cfg!(windows) && fails_on_windows)
-        || is_meant_to_fail
-        || is_spec_error
-        || is_failing_for_now
+        // Only include in release tests.
+        "parser/success/largeExpression"
+        | "normalization/success/remoteSystems"
+            if cfg!(debug_assertions) =>
+        {
+            "too slow for debug builds"
+        }
+        _ if cfg!(windows) && fails_on_windows => "windows",
+        _ => return None,
+    };
+    Some(reason)
 }
 
 fn run_test_stringy_error(test: &SpecTest) -> std::result::Result<(), String> {
@@ -660,13 +701,91 @@ fn run_test(test: &SpecTest) -> Result<()> {
     })
 }
 
+/// Groups the results of a run by section of the standard, and counts the tests skipped for each
+/// unsupported feature. `outcomes` records whether each test that was run passed.
+fn conformance_report(
+    tests: &[(String, SpecTestKind, Option<&'static str>)],
+    outcomes: &HashMap<String, bool>,
+) -> serde_json::Value {
+    let mut sections: Vec<_> = SECTIONS
+        .iter()
+        .map(|&section| (section, 0, 0, 0, Vec::new()))
+        .collect();
+    let mut unsupported: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, kind, skipped) in tests {
+        let section =
+            sections.iter_mut().find(|s| s.0 == kind.section()).unwrap();
+        match (outcomes.get(name), skipped) {
+            (Some(true), _) => section.1 += 1,
+            (Some(false), _) => {
+                section.2 += 1;
+                section.4.push(name.as_str());
+            }
+            (None, Some(reason)) => {
+                section.3 += 1;
+                unsupported.entry(reason).or_default().push(name);
+            }
+            // Filtered out
+            (None, None) => {}
+        }
+    }
+
+    println!(
+        "\n{:<20} {:>8} {:>8} {:>8}",
+        "section", "passed", "failed", "skipped"
+    );
+    for (section, passed, failed, skipped, _) in &sections {
+        println!("{:<20} {:>8} {:>8} {:>8}", section, passed, failed, skipped);
+    }
+    if !unsupported.is_empty() {
+        println!("\n{:<48} {:>8}", "skipped because of", "tests");
+        for (reason, tests) in &unsupported {
+            println!("{:<48} {:>8}", reason, tests.len());
+        }
+    }
+
+    let sections: Vec<_> = sections
+        .into_iter()
+        .map(|(section, passed, failed, skipped, mut failures)| {
+            failures.sort();
+            serde_json::json!({
+                "section": section,
+                "passed": passed,
+                "failed": failed,
+                "skipped": skipped,
+                "failures": failures,
+            })
+        })
+        .collect();
+    let unsupported: Vec<_> = unsupported
+        .into_iter()
+        .map(|(reason, mut tests)| {
+            tests.sort();
+            serde_json::json!({
+                "reason": reason,
+                "count": tests.len(),
+                "tests": tests,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "sections": sections,
+        "unsupported": unsupported,
+    })
+}
+
 fn main() {
-    let tests = FEATURES
+    let tests: Vec<_> = FEATURES
         .iter()
         .copied()
         .flat_map(discover_tests_for_feature)
         .collect();
+    let summary: Vec<_> = tests
+        .iter()
+        .map(|test| (test.name.clone(), test.data.kind, test.data.skipped))
+        .collect();
 
+    let invocation_dir = env::current_dir().unwrap();
     // Setup current directory to the root of the repository. Important for `as Location` tests.
     let root_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .parent()
@@ -691,8 +810,11 @@ fn main() {
     let cache_dir = format!("dhall-tests-{}", random_id);
     let cache_dir = env::temp_dir().join(cache_dir);
     std::fs::create_dir_all(&cache_dir).unwrap();
-    fs_extra::dir::copy(&dhall_cache_dir, &cache_dir, &Default::default())
-        .unwrap();
+    // The cache only exists if the dhall-lang submodule was checked out.
+    if dhall_cache_dir.is_dir() {
+        fs_extra::dir::copy(&dhall_cache_dir, &cache_dir, &Default::default())
+            .unwrap();
+    }
     env::set_var("XDG_CACHE_HOME", &cache_dir);
 
     // Whether to overwrite the output files when our own output differs.
@@ -702,11 +824,30 @@ fn main() {
         || env::var("UPDATE_TEST_FILES") == Ok("1".to_string());
     UPDATE_TEST_FILES.store(bless, Ordering::Release);
 
-    let args = Arguments::from_iter(env::args().filter(|arg| arg != "--bless"));
-    let res = libtest_mimic::run_tests(&args, tests, |test| {
-        let result = std::panic::catch_unwind(move || {
-            run_test_stringy_error(&test.data)
-        });
+    // Where to write a summary of the conformance to the standard, grouped by section. Eg:
+    // `cargo test --test spec -- -q --report conformance.json`.
+    let mut report_path = None;
+    let mut args = Vec::new();
+    let mut env_args = env::args();
+    while let Some(arg) = env_args.next() {
+        if arg == "--report" {
+            report_path = Some(env_args.next().expect("missing report path"));
+        } else if arg != "--bless" {
+            args.push(arg);
+        }
+    }
+
+    let outcomes = Arc::new(Mutex::new(HashMap::new()));
+    let outcomes_ = outcomes.clone();
+    let args = Arguments::from_iter(args);
+    let res = libtest_mimic::run_tests(&args, tests, move |test| {
+        let data = test.data.clone();
+        let result =
+            std::panic::catch_unwind(move || run_test_stringy_error(&data));
+        outcomes_
+            .lock()
+            .unwrap()
+            .insert(test.name.clone(), matches!(result, Ok(Ok(_))));
         match result {
             Ok(Ok(_)) => Outcome::Passed,
             Ok(Err(e)) => Outcome::Failed { msg: Some(e) },
@@ -718,5 +859,15 @@ fn main() {
 
     std::fs::remove_dir_all(&cache_dir).unwrap();
 
+    if let Some(report_path) = report_path {
+        let report = conformance_report(&summary, &outcomes.lock().unwrap());
+        let report = serde_json::to_string_pretty(&report).unwrap();
+        // Relative paths are relative to the directory the runner started in (`dhall/` under
+        // `cargo test`), not to the root of the repository.
+        let report_path = invocation_dir.join(report_path);
+        std::fs::write(&report_path, report + "\n").unwrap();
+        println!("\nconformance report written to {}", report_path.display());
+    }
+
     res.exit();
 }
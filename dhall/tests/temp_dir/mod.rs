@@ -0,0 +1,37 @@
+//! A directory of Dhall files for the tests that resolve imports, shared by the test files that
+//! need one.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// A fresh directory under the system's temporary directory, removed when dropped, so that it
+/// is cleaned up even when an assertion fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "dhall-{}-{}",
+            name,
+            std::process::id()
+        ));
+        // Leftovers from a run that was killed.
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+
+    pub fn write(&self, name: &str, contents: &str) {
+        std::fs::write(self.path(name), contents).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
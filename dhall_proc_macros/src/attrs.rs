@@ -0,0 +1,444 @@
+//! Parsing of the `#[serde(...)]` attributes that affect the shape of the (de)serialized data, and
+//! of our own `#[dhall(...)]` attributes. Other serde attributes are ignored.
+use syn::spanned::Spanned;
+use syn::{Attribute, Error, Lit, Meta, NestedMeta};
+
+/// How serde represents an enum.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Tagging {
+    External,
+    /// `#[serde(tag = "...")]`
+    Internal(String),
+    /// `#[serde(tag = "...", content = "...")]`
+    Adjacent(String, String),
+    /// `#[serde(untagged)]`
+    Untagged,
+}
+
+/// Attributes on a struct or enum.
+pub struct ContainerAttrs {
+    pub rename_all: RenameRule,
+    pub tagging: Tagging,
+    pub transparent: bool,
+}
+
+/// Attributes on a struct field or on the field of an enum variant.
+pub struct FieldAttrs {
+    pub rename: Option<String>,
+    pub skip: bool,
+    pub flatten: bool,
+}
+
+/// Attributes on an enum variant.
+pub struct VariantAttrs {
+    pub rename: Option<String>,
+    pub rename_all: RenameRule,
+    pub skip: bool,
+}
+
+/// The `rename_all` rules supported by serde.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RenameRule {
+    None,
+    LowerCase,
+    UpperCase,
+    PascalCase,
+    CamelCase,
+    SnakeCase,
+    ScreamingSnakeCase,
+    KebabCase,
+    ScreamingKebabCase,
+}
+
+impl RenameRule {
+    fn from_str(s: &str) -> Option<Self> {
+        Some(match s {
+            "lowercase" => RenameRule::LowerCase,
+            "UPPERCASE" => RenameRule::UpperCase,
+            "PascalCase" => RenameRule::PascalCase,
+            "camelCase" => RenameRule::CamelCase,
+            "snake_case" => RenameRule::SnakeCase,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnakeCase,
+            "kebab-case" => RenameRule::KebabCase,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebabCase,
+            _ => return None,
+        })
+    }
+
+    /// Renames an enum variant, which is assumed to be in PascalCase. This must match what serde
+    /// does.
+    pub fn apply_to_variant(self, variant: &str) -> String {
+        use RenameRule::*;
+        match self {
+            None | PascalCase => variant.to_owned(),
+            LowerCase => variant.to_ascii_lowercase(),
+            UpperCase => variant.to_ascii_uppercase(),
+            CamelCase => variant[..1].to_ascii_lowercase() + &variant[1..],
+            SnakeCase => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            ScreamingSnakeCase => {
+                SnakeCase.apply_to_variant(variant).to_ascii_uppercase()
+            }
+            KebabCase => SnakeCase.apply_to_variant(variant).replace('_', "-"),
+            ScreamingKebabCase => ScreamingSnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    /// Renames a struct field, which is assumed to be in snake_case. This must match what serde
+    /// does.
+    pub fn apply_to_field(self, field: &str) -> String {
+        use RenameRule::*;
+        match self {
+            None | LowerCase | SnakeCase => field.to_owned(),
+            UpperCase | ScreamingSnakeCase => field.to_ascii_uppercase(),
+            PascalCase => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            CamelCase => {
+                let pascal = PascalCase.apply_to_field(field);
+                pascal[..1].to_ascii_lowercase() + &pascal[1..]
+            }
+            KebabCase => field.replace('_', "-"),
+            ScreamingKebabCase => {
+                ScreamingSnakeCase.apply_to_field(field).replace('_', "-")
+            }
+        }
+    }
+}
+
+/// Iterates over the items of all the `#[<name>(...)]` attributes.
+fn meta_items(
+    attrs: &[Attribute],
+    name: &str,
+) -> Result<Vec<NestedMeta>, Error> {
+    let mut items = vec![];
+    for attr in attrs {
+        if !attr.path.is_ident(name) {
+            continue;
+        }
+        match attr.parse_meta()? {
+            Meta::List(list) => items.extend(list.nested),
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    format!("expected #[{}(...)]", name),
+                ))
+            }
+        }
+    }
+    Ok(items)
+}
+
+fn name_of(meta: &Meta) -> String {
+    meta.path()
+        .get_ident()
+        .map(|ident| ident.to_string())
+        .unwrap_or_default()
+}
+
+fn lit_str(lit: &Lit) -> Result<String, Error> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(Error::new(lit.span(), "expected a string literal")),
+    }
+}
+
+/// Parses the value of an attribute that can be given either as `name = "..."` or as
+/// `name(serialize = "...", deserialize = "...")`. Since a Dhall type describes both directions
+/// at once, both values must agree.
+fn ser_de_str(meta: &Meta) -> Result<Option<String>, Error> {
+    match meta {
+        Meta::NameValue(nv) => Ok(Some(lit_str(&nv.lit)?)),
+        Meta::List(list) => {
+            let mut ser = None;
+            let mut de = None;
+            for item in &list.nested {
+                match item {
+                    NestedMeta::Meta(Meta::NameValue(nv))
+                        if nv.path.is_ident("serialize") =>
+                    {
+                        ser = Some(lit_str(&nv.lit)?)
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv))
+                        if nv.path.is_ident("deserialize") =>
+                    {
+                        de = Some(lit_str(&nv.lit)?)
+                    }
+                    _ => {
+                        return Err(Error::new(
+                            item.span(),
+                            "expected `serialize` or `deserialize`",
+                        ))
+                    }
+                }
+            }
+            if ser != de {
+                return Err(Error::new(
+                    meta.span(),
+                    "a Dhall type cannot describe different serialized and \
+                     deserialized names",
+                ));
+            }
+            Ok(ser)
+        }
+        Meta::Path(_) => {
+            Err(Error::new(meta.span(), "expected a string value"))
+        }
+    }
+}
+
+fn rename_rule(meta: &Meta) -> Result<RenameRule, Error> {
+    match ser_de_str(meta)? {
+        None => Ok(RenameRule::None),
+        Some(s) => RenameRule::from_str(&s).ok_or_else(|| {
+            Error::new(meta.span(), format!("unknown rename rule `{}`", s))
+        }),
+    }
+}
+
+/// Returns the meta items of the serde attributes, skipping literals.
+fn metas(attrs: &[Attribute]) -> Result<Vec<Meta>, Error> {
+    Ok(meta_items(attrs, "serde")?
+        .into_iter()
+        .filter_map(|item| match item {
+            NestedMeta::Meta(meta) => Some(meta),
+            NestedMeta::Lit(_) => None,
+        })
+        .collect())
+}
+
+/// Returns the meta items of the `#[dhall(...)]` attributes. Unlike serde attributes, we own this
+/// namespace so we reject anything we don't understand.
+fn dhall_metas(attrs: &[Attribute]) -> Result<Vec<Meta>, Error> {
+    meta_items(attrs, "dhall")?
+        .into_iter()
+        .map(|item| match item {
+            NestedMeta::Meta(meta) => Ok(meta),
+            NestedMeta::Lit(lit) => {
+                Err(Error::new(lit.span(), "unexpected literal"))
+            }
+        })
+        .collect()
+}
+
+fn lit_path(lit: &Lit) -> Result<syn::Path, Error> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        _ => Err(Error::new(lit.span(), "expected a string literal")),
+    }
+}
+
+impl ContainerAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut rename_all = RenameRule::None;
+        let mut tag = None;
+        let mut content = None;
+        let mut untagged = None;
+        let mut transparent = false;
+        for meta in metas(attrs)? {
+            match name_of(&meta).as_str() {
+                "rename_all" => rename_all = rename_rule(&meta)?,
+                "tag" => tag = ser_de_str(&meta)?,
+                "content" => content = Some((ser_de_str(&meta)?, meta.span())),
+                "untagged" => untagged = Some(meta.span()),
+                "transparent" => transparent = true,
+                _ => {}
+            }
+        }
+        let tagging = match (tag, content, untagged) {
+            (None, None, None) => Tagging::External,
+            (Some(tag), None, None) => Tagging::Internal(tag),
+            (Some(tag), Some((Some(content), _)), None) => {
+                Tagging::Adjacent(tag, content)
+            }
+            (None, None, Some(_)) => Tagging::Untagged,
+            // Serde rejects these too.
+            (None, Some((_, span)), None) => return Err(Error::new(
+                span,
+                "#[serde(content = \"...\")] requires #[serde(tag = \"...\")]",
+            )),
+            (_, _, Some(span)) => {
+                return Err(Error::new(
+                    span,
+                    "#[serde(untagged)] cannot be combined with \
+                     #[serde(tag = \"...\")] or #[serde(content = \"...\")]",
+                ))
+            }
+            (_, Some((None, span)), _) => {
+                return Err(Error::new(span, "expected a string value"))
+            }
+        };
+        Ok(ContainerAttrs {
+            rename_all,
+            tagging,
+            transparent,
+        })
+    }
+}
+
+impl FieldAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut rename = None;
+        let mut skip_ser = false;
+        let mut skip_de = false;
+        let mut flatten = false;
+        let mut skip_span = None;
+        for meta in metas(attrs)? {
+            match name_of(&meta).as_str() {
+                "rename" => rename = ser_de_str(&meta)?,
+                "skip" => {
+                    skip_ser = true;
+                    skip_de = true;
+                }
+                "skip_serializing" => {
+                    skip_ser = true;
+                    skip_span = Some(meta.span());
+                }
+                "skip_deserializing" => {
+                    skip_de = true;
+                    skip_span = Some(meta.span());
+                }
+                "flatten" => flatten = true,
+                "skip_serializing_if" => {
+                    return Err(Error::new(
+                        meta.span(),
+                        "a field that is only sometimes serialized has no \
+                         Dhall equivalent; use `Option` without \
+                         `skip_serializing_if` instead",
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if skip_ser != skip_de {
+            return Err(Error::new(
+                skip_span.unwrap(),
+                "a field skipped in only one direction has no Dhall \
+                 equivalent; use `#[serde(skip)]` instead",
+            ));
+        }
+        Ok(FieldAttrs {
+            rename,
+            skip: skip_ser,
+            flatten,
+        })
+    }
+}
+
+impl VariantAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut rename = None;
+        let mut rename_all = RenameRule::None;
+        let mut skip = false;
+        for meta in metas(attrs)? {
+            match name_of(&meta).as_str() {
+                "rename" => rename = ser_de_str(&meta)?,
+                "rename_all" => rename_all = rename_rule(&meta)?,
+                "skip" => skip = true,
+                _ => {}
+            }
+        }
+        Ok(VariantAttrs {
+            rename,
+            rename_all,
+            skip,
+        })
+    }
+}
+
+/// What to do when a field is missing, as given by `#[dhall(default)]` or
+/// `#[dhall(default = "path")]`.
+pub enum DefaultValue {
+    Default,
+    Path(syn::Path),
+}
+
+/// `#[dhall(...)]` attributes on a field.
+pub struct DhallFieldAttrs {
+    pub rename: Option<String>,
+    pub default: Option<DefaultValue>,
+    /// A module with `from_dhall` and `to_dhall` functions.
+    pub with: Option<syn::Path>,
+}
+
+/// `#[dhall(...)]` attributes on an enum variant.
+pub struct DhallVariantAttrs {
+    pub rename: Option<String>,
+}
+
+impl DhallFieldAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut rename = None;
+        let mut default = None;
+        let mut with = None;
+        for meta in dhall_metas(attrs)? {
+            match (name_of(&meta).as_str(), &meta) {
+                ("rename", Meta::NameValue(nv)) => {
+                    rename = Some(lit_str(&nv.lit)?)
+                }
+                ("default", Meta::Path(_)) => {
+                    default = Some(DefaultValue::Default)
+                }
+                ("default", Meta::NameValue(nv)) => {
+                    default = Some(DefaultValue::Path(lit_path(&nv.lit)?))
+                }
+                ("with", Meta::NameValue(nv)) => {
+                    with = Some(lit_path(&nv.lit)?)
+                }
+                _ => {
+                    return Err(Error::new(
+                        meta.span(),
+                        "unknown dhall attribute",
+                    ))
+                }
+            }
+        }
+        Ok(DhallFieldAttrs {
+            rename,
+            default,
+            with,
+        })
+    }
+}
+
+impl DhallVariantAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self, Error> {
+        let mut rename = None;
+        for meta in dhall_metas(attrs)? {
+            match (name_of(&meta).as_str(), &meta) {
+                ("rename", Meta::NameValue(nv)) => {
+                    rename = Some(lit_str(&nv.lit)?)
+                }
+                _ => {
+                    return Err(Error::new(
+                        meta.span(),
+                        "unknown dhall attribute",
+                    ))
+                }
+            }
+        }
+        Ok(DhallVariantAttrs { rename })
+    }
+}
@@ -0,0 +1,417 @@
+//! `derive(FromDhall)` and `derive(ToDhall)`, which convert directly between a Rust type and a
+//! `SimpleValue` without going through serde. The encoding matches the one used by serde_dhall
+//! and `derive(StaticType)`: structs are records, tuple structs are records with fields `_1`,
+//! `_2`, etc., newtypes are transparent, and enums are unions.
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{parse_quote, DeriveInput, Error};
+
+use crate::attrs::{DefaultValue, DhallFieldAttrs, DhallVariantAttrs};
+
+pub fn derive_from_dhall(input: TokenStream) -> TokenStream {
+    TokenStream::from(match derive_from_dhall_inner(input) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    })
+}
+
+pub fn derive_to_dhall(input: TokenStream) -> TokenStream {
+    TokenStream::from(match derive_to_dhall_inner(input) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error(),
+    })
+}
+
+/// A field of a struct or variant, with its Dhall name.
+struct Field<'a> {
+    /// The name of the field in Dhall: the (possibly renamed) Rust name, or `_1`, `_2`, etc.
+    name: String,
+    /// The binding used to construct or destructure the field.
+    binding: syn::Ident,
+    /// The Rust name of the field, or its index for tuple-like fields.
+    member: syn::Member,
+    ty: &'a syn::Type,
+    attrs: DhallFieldAttrs,
+}
+
+fn fields_of(fields: &syn::Fields) -> Result<Vec<Field<'_>>, Error> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let attrs = DhallFieldAttrs::parse(&f.attrs)?;
+            let (name, member) = match &f.ident {
+                Some(ident) => (
+                    ident.unraw().to_string(),
+                    syn::Member::Named(ident.clone()),
+                ),
+                None => (format!("_{}", i + 1), syn::Member::Unnamed(i.into())),
+            };
+            Ok(Field {
+                name: attrs.rename.clone().unwrap_or(name),
+                binding: format_ident!("__field{}", i),
+                member,
+                ty: &f.ty,
+                attrs,
+            })
+        })
+        .collect()
+}
+
+fn is_newtype(fields: &syn::Fields) -> bool {
+    matches!(fields, syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1)
+}
+
+/// Builds `constructor` from the `SimpleValue` `value`, which sits at `path`.
+fn decode_fields(
+    fields: &syn::Fields,
+    constructor: proc_macro2::TokenStream,
+    value: proc_macro2::TokenStream,
+    path: proc_macro2::TokenStream,
+    constraints: &mut Vec<syn::Type>,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let private = quote!(::serde_dhall::__private);
+    let fields_info = fields_of(fields)?;
+    let decode = |f: &Field, x, path| match &f.attrs.with {
+        Some(with) => quote!( #private::at(#with::from_dhall(#x), #path)? ),
+        None => {
+            let ty = f.ty;
+            quote!(
+                <#ty as #private::FromSimpleValue>::from_simple_value(#x, #path)?
+            )
+        }
+    };
+    for f in &fields_info {
+        if f.attrs.with.is_none() {
+            constraints.push(f.ty.clone());
+        }
+    }
+
+    // Newtypes are transparent.
+    if is_newtype(fields) {
+        let f = &fields_info[0];
+        let decoded = decode(f, quote!(#value), quote!(#path));
+        return Ok(quote!( #constructor(#decoded) ));
+    }
+
+    let inits = fields_info.iter().map(|f| {
+        let name = &f.name;
+        let member = &f.member;
+        let decoded = decode(f, quote!(x), quote!(&path));
+        let missing = match (&f.attrs.default, &f.attrs.with) {
+            (Some(DefaultValue::Default), _) => {
+                quote!(::std::default::Default::default())
+            }
+            (Some(DefaultValue::Path(default)), _) => quote!(#default()),
+            (None, Some(_)) => {
+                quote!( return Err(#private::missing_field(&path)) )
+            }
+            (None, None) => {
+                let ty = f.ty;
+                quote!( <#ty as #private::FromSimpleValue>::missing_field(&path)? )
+            }
+        };
+        quote! {
+            #member: {
+                let path = #path.field(#name);
+                match fields.get(#name) {
+                    Some(x) => #decoded,
+                    None => #missing,
+                }
+            }
+        }
+    });
+    Ok(quote! {{
+        let fields = #private::expect_record(#value, #path)?;
+        #constructor { #(#inits),* }
+    }})
+}
+
+/// Builds the `SimpleValue` for the fields, bound to their `binding` names.
+fn encode_fields(
+    fields: &syn::Fields,
+    constraints: &mut Vec<syn::Type>,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let private = quote!(::serde_dhall::__private);
+    let fields_info = fields_of(fields)?;
+    let encode = |f: &Field| {
+        let binding = &f.binding;
+        match &f.attrs.with {
+            Some(with) => quote!( #with::to_dhall(#binding)? ),
+            None => {
+                let ty = f.ty;
+                quote!(
+                    <#ty as #private::ToSimpleValue>::to_simple_value(#binding)?
+                )
+            }
+        }
+    };
+    for f in &fields_info {
+        if f.attrs.with.is_none() {
+            constraints.push(f.ty.clone());
+        }
+    }
+
+    // Newtypes are transparent.
+    if is_newtype(fields) {
+        return Ok(encode(&fields_info[0]));
+    }
+
+    let inserts = fields_info.iter().map(|f| {
+        let name = &f.name;
+        let encoded = encode(f);
+        quote!( fields.insert(#name.to_owned(), #encoded); )
+    });
+    Ok(quote! {{
+        let mut fields = ::std::collections::BTreeMap::new();
+        #(#inserts)*
+        ::serde_dhall::SimpleValue::Record(fields)
+    }})
+}
+
+/// The pattern that binds the fields of a struct or variant to their `binding` names.
+fn fields_pattern(
+    path: proc_macro2::TokenStream,
+    fields: &syn::Fields,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let bindings = fields_of(fields)?.into_iter().map(|f| {
+        let member = f.member;
+        let binding = f.binding;
+        quote!( #member: #binding )
+    });
+    Ok(quote!( #path { #(#bindings),* } ))
+}
+
+fn check_data(input: &DeriveInput) -> Result<(), Error> {
+    match &input.data {
+        syn::Data::Enum(data) if data.variants.is_empty() => {
+            Err(Error::new(input.span(), "Empty enums are not supported"))
+        }
+        syn::Data::Union(x) => {
+            Err(Error::new(x.union_token.span(), "Unions are not supported"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn variant_name(v: &syn::Variant) -> Result<String, Error> {
+    let attrs = DhallVariantAttrs::parse(&v.attrs)?;
+    Ok(attrs.rename.unwrap_or_else(|| v.ident.to_string()))
+}
+
+/// Adds `ty: bound` to the where clause for every type in `constraints`.
+fn where_clause_with(
+    generics: &syn::Generics,
+    constraints: &[syn::Type],
+    bound: proc_macro2::TokenStream,
+) -> syn::WhereClause {
+    let mut generics = generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in constraints {
+        where_clause.predicates.push(parse_quote!(#ty: #bound));
+    }
+    where_clause.clone()
+}
+
+fn derive_from_dhall_inner(
+    input: TokenStream,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let input: DeriveInput = syn::parse_macro_input::parse(input)?;
+    check_data(&input)?;
+    let private = quote!(::serde_dhall::__private);
+
+    // List of types that must impl FromSimpleValue
+    let mut constraints = vec![];
+
+    let body = match &input.data {
+        syn::Data::Struct(data) => {
+            let decoded = decode_fields(
+                &data.fields,
+                quote!(Self),
+                quote!(v),
+                quote!(path),
+                &mut constraints,
+            )?;
+            quote!( Ok(#decoded) )
+        }
+        syn::Data::Enum(data) => {
+            let mut arms = vec![];
+            for v in data.variants.iter() {
+                let name = variant_name(v)?;
+                let ident = &v.ident;
+                let no_payload = quote! {
+                    (#name, Some(_)) => {
+                        Err(#private::variant_payload(#name, false, path))
+                    }
+                };
+                arms.push(match &v.fields {
+                    syn::Fields::Unit => quote! {
+                        (#name, None) => Ok(Self::#ident),
+                        #no_payload
+                    },
+                    syn::Fields::Unnamed(fields)
+                        if fields.unnamed.is_empty() =>
+                    {
+                        quote! {
+                            (#name, None) => Ok(Self::#ident()),
+                            #no_payload
+                        }
+                    }
+                    fields => {
+                        let decoded = decode_fields(
+                            fields,
+                            quote!(Self::#ident),
+                            quote!(x),
+                            quote!(&path),
+                            &mut constraints,
+                        )?;
+                        quote! {
+                            (#name, Some(x)) => {
+                                let path = path.field(#name);
+                                Ok(#decoded)
+                            }
+                            (#name, None) => {
+                                Err(#private::variant_payload(#name, true, path))
+                            }
+                        }
+                    }
+                });
+            }
+            quote! {
+                match #private::expect_union(v, path)? {
+                    #(#arms)*
+                    (name, _) => Err(#private::unknown_variant(name, path)),
+                }
+            }
+        }
+        syn::Data::Union(_) => unreachable!(),
+    };
+
+    let (impl_generics, ty_generics, orig_where_clause) =
+        input.generics.split_for_impl();
+    let where_clause = where_clause_with(
+        &input.generics,
+        &constraints,
+        quote!(#private::FromSimpleValue),
+    );
+    let ident = &input.ident;
+    Ok(quote! {
+        impl #impl_generics #private::FromDhallSealed
+                for #ident #ty_generics
+                #orig_where_clause {}
+
+        impl #impl_generics ::serde_dhall::FromDhall
+                for #ident #ty_generics
+                #where_clause {
+            fn from_dhall(
+                v: &::serde_dhall::Value,
+            ) -> ::serde_dhall::Result<Self> {
+                #private::from_dhall(v)
+            }
+        }
+
+        impl #impl_generics #private::FromSimpleValue
+                for #ident #ty_generics
+                #where_clause {
+            #[allow(clippy::needless_question_mark)]
+            fn from_simple_value(
+                v: &::serde_dhall::SimpleValue,
+                path: &#private::FieldPath,
+            ) -> ::serde_dhall::Result<Self> {
+                #body
+            }
+        }
+    })
+}
+
+fn derive_to_dhall_inner(
+    input: TokenStream,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let input: DeriveInput = syn::parse_macro_input::parse(input)?;
+    check_data(&input)?;
+    let private = quote!(::serde_dhall::__private);
+
+    // List of types that must impl ToSimpleValue
+    let mut constraints = vec![];
+
+    let body = match &input.data {
+        syn::Data::Struct(data) => {
+            let pattern = fields_pattern(quote!(Self), &data.fields)?;
+            let encoded = encode_fields(&data.fields, &mut constraints)?;
+            quote! {
+                let #pattern = self;
+                Ok(#encoded)
+            }
+        }
+        syn::Data::Enum(data) => {
+            let mut arms = vec![];
+            for v in data.variants.iter() {
+                let name = variant_name(v)?;
+                let ident = &v.ident;
+                let pattern = fields_pattern(quote!(Self::#ident), &v.fields)?;
+                let contents = match &v.fields {
+                    syn::Fields::Unit => quote!(None),
+                    syn::Fields::Unnamed(fields)
+                        if fields.unnamed.is_empty() =>
+                    {
+                        quote!(None)
+                    }
+                    fields => {
+                        let encoded = encode_fields(fields, &mut constraints)?;
+                        quote!( Some(Box::new(#encoded)) )
+                    }
+                };
+                arms.push(quote! {
+                    #pattern => ::serde_dhall::SimpleValue::Union(
+                        #name.to_owned(),
+                        #contents,
+                    ),
+                });
+            }
+            quote! {
+                Ok(match self {
+                    #(#arms)*
+                })
+            }
+        }
+        syn::Data::Union(_) => unreachable!(),
+    };
+
+    let (impl_generics, ty_generics, orig_where_clause) =
+        input.generics.split_for_impl();
+    let where_clause = where_clause_with(
+        &input.generics,
+        &constraints,
+        quote!(#private::ToSimpleValue),
+    );
+    let ident = &input.ident;
+    Ok(quote! {
+        impl #impl_generics #private::ToDhallSealed
+                for #ident #ty_generics
+                #orig_where_clause {}
+
+        impl #impl_generics ::serde_dhall::ToDhall
+                for #ident #ty_generics
+                #where_clause {
+            fn to_dhall(
+                &self,
+                ty: Option<&::serde_dhall::SimpleType>,
+            ) -> ::serde_dhall::Result<::serde_dhall::Value> {
+                #private::to_dhall(self, ty)
+            }
+        }
+
+        impl #impl_generics #private::ToSimpleValue
+                for #ident #ty_generics
+                #where_clause {
+            #[allow(clippy::needless_question_mark)]
+            fn to_simple_value(
+                &self,
+            ) -> ::serde_dhall::Result<::serde_dhall::SimpleValue> {
+                #body
+            }
+        }
+    })
+}
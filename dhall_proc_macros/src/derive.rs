@@ -1,9 +1,14 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::{quote, quote_spanned};
+use syn::ext::IdentExt;
 use syn::spanned::Spanned;
+use syn::DeriveInput;
 use syn::Error;
-use syn::{parse_quote, DeriveInput};
+
+use crate::attrs::{
+    ContainerAttrs, FieldAttrs, RenameRule, Tagging, VariantAttrs,
+};
 
 pub fn derive_static_type(input: TokenStream) -> TokenStream {
     TokenStream::from(match derive_static_type_inner(input) {
@@ -21,75 +26,190 @@ where
     )
 }
 
-fn derive_for_struct(
-    data: &syn::DataStruct,
-    constraints: &mut Vec<syn::Type>,
-) -> Result<proc_macro2::TokenStream, Error> {
-    let fields = match &data.fields {
-        syn::Fields::Named(fields) => fields
-            .named
-            .iter()
-            .map(|f| {
-                let name = f.ident.as_ref().unwrap().to_string();
-                let ty = &f.ty;
-                (name, ty)
-            })
-            .collect(),
-        syn::Fields::Unnamed(fields) => fields
-            .unnamed
-            .iter()
-            .enumerate()
-            .map(|(i, f)| {
-                let name = format!("_{}", i + 1);
-                let ty = &f.ty;
-                (name, ty)
-            })
-            .collect(),
-        syn::Fields::Unit => vec![],
+/// Requires `ty` to implement `StaticType`, or `StaticRecordType` if `record` is set. The bound
+/// gets the span of the type, so that errors point to the field.
+fn static_type_bound(ty: &syn::Type, record: bool) -> syn::WherePredicate {
+    let bound = if record {
+        quote_spanned!(ty.span()=>
+            #ty: ::serde_dhall::__private::StaticRecordType
+        )
+    } else {
+        quote_spanned!(ty.span()=> #ty: ::serde_dhall::StaticType)
     };
-    let entries = fields.into_iter().map(|(name, ty)| {
-        constraints.push(ty.clone());
+    syn::parse2(bound).unwrap()
+}
+
+/// Builds the fields of the record type made of the given fields, after applying the serde
+/// attributes. Unnamed fields are called `_1`, `_2`, etc., like for tuples. `extra` lists
+/// additional entries, like the tag of an internally tagged struct.
+fn record_fields(
+    fields: &syn::Fields,
+    rename_all: RenameRule,
+    extra: Vec<proc_macro2::TokenStream>,
+    constraints: &mut Vec<syn::WherePredicate>,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let mut entries = extra;
+    let mut flattened = vec![];
+    for f in fields.iter() {
+        let attrs = FieldAttrs::parse(&f.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        let ty = &f.ty;
+        if attrs.flatten {
+            // Checking the bound at compile time ensures the field's type is a record.
+            constraints.push(static_type_bound(ty, true));
+            flattened.push(quote! {
+                <#ty as ::serde_dhall::__private::StaticRecordType>
+                    ::static_record_fields()
+            });
+            continue;
+        }
+        constraints.push(static_type_bound(ty, false));
         let ty = static_type(ty);
-        quote!( (#name.to_owned(), #ty) )
-    });
+        let name = match (&attrs.rename, &f.ident) {
+            (Some(name), _) => name.clone(),
+            (None, Some(ident)) => {
+                rename_all.apply_to_field(&ident.unraw().to_string())
+            }
+            // Skipped fields are not counted.
+            (None, None) => format!("_{}", entries.len() + 1),
+        };
+        entries.push(quote!( (#name.to_owned(), #ty) ));
+    }
     Ok(quote! {
+        vec![ #(#entries),* ]
+            .into_iter()
+            #(.chain(#flattened))*
+            .collect()
+    })
+}
+
+/// Like `record_fields`, but builds the whole record type.
+fn record_type(
+    fields: &syn::Fields,
+    rename_all: RenameRule,
+    extra: Vec<proc_macro2::TokenStream>,
+    constraints: &mut Vec<syn::WherePredicate>,
+) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = record_fields(fields, rename_all, extra, constraints)?;
+    Ok(quote!( ::serde_dhall::SimpleType::Record(#fields) ))
+}
+
+/// Returns the type of the single field of a newtype, if `fields` is one.
+fn newtype_field(fields: &syn::Fields) -> Option<&syn::Type> {
+    match fields {
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            Some(&fields.unnamed.iter().next().unwrap().ty)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the type of the struct, and the fields of that type if the struct can be flattened into
+/// another one.
+fn derive_for_struct(
+    data: &syn::DataStruct,
+    attrs: &ContainerAttrs,
+    constraints: &mut Vec<syn::WherePredicate>,
+) -> Result<(proc_macro2::TokenStream, Option<proc_macro2::TokenStream>), Error>
+{
+    if attrs.transparent {
+        let mut fields = vec![];
+        for f in data.fields.iter() {
+            if !FieldAttrs::parse(&f.attrs)?.skip {
+                fields.push(f);
+            }
+        }
+        return match fields.as_slice() {
+            [f] => {
+                constraints.push(static_type_bound(&f.ty, false));
+                Ok((static_type(&f.ty), None))
+            }
+            _ => Err(Error::new(
+                data.fields.span(),
+                "#[serde(transparent)] requires exactly one non-skipped field",
+            )),
+        };
+    }
+    // Newtypes are transparent.
+    if let Some(ty) = newtype_field(&data.fields) {
+        constraints.push(static_type_bound(ty, false));
+        return Ok((static_type(ty), None));
+    }
+    // `#[serde(tag = "...")]` on a struct adds a field containing the name of the struct.
+    let extra = match &attrs.tagging {
+        Tagging::Internal(tag) => {
+            vec![quote!( (#tag.to_owned(), ::serde_dhall::SimpleType::Text) )]
+        }
+        _ => vec![],
+    };
+    let fields =
+        record_fields(&data.fields, attrs.rename_all, extra, constraints)?;
+    let ty = quote! {
         ::serde_dhall::SimpleType::Record(
-            vec![ #(#entries),* ].into_iter().collect()
+            <Self as ::serde_dhall::__private::StaticRecordType>
+                ::static_record_fields()
         )
-    })
+    };
+    // Serde can only flatten structs with named fields.
+    match data.fields {
+        syn::Fields::Named(_) => Ok((ty, Some(fields))),
+        _ => Ok((quote!( ::serde_dhall::SimpleType::Record(#fields) ), None)),
+    }
 }
 
 fn derive_for_enum(
     data: &syn::DataEnum,
-    constraints: &mut Vec<syn::Type>,
+    attrs: &ContainerAttrs,
+    constraints: &mut Vec<syn::WherePredicate>,
 ) -> Result<proc_macro2::TokenStream, Error> {
-    let entries: Vec<_> = data
-        .variants
-        .iter()
-        .map(|v| {
-            let name = v.ident.to_string();
-            match &v.fields {
-                syn::Fields::Unit => Ok(quote!( (#name.to_owned(), None) )),
-                syn::Fields::Unnamed(fields) if fields.unnamed.is_empty() => {
-                    Ok(quote!( (#name.to_owned(), None) ))
-                }
-                syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
-                    let ty = &fields.unnamed.iter().next().unwrap().ty;
-                    constraints.push(ty.clone());
-                    let ty = static_type(ty);
-                    Ok(quote!( (#name.to_owned(), Some(#ty)) ))
-                }
-                syn::Fields::Unnamed(_) => Err(Error::new(
-                    v.span(),
-                    "Variants with more than one field are not supported",
-                )),
-                syn::Fields::Named(_) => Err(Error::new(
-                    v.span(),
-                    "Named variants are not supported",
-                )),
+    let unsupported = match &attrs.tagging {
+        Tagging::External => None,
+        Tagging::Internal(_) => Some("Internally tagged enums"),
+        Tagging::Adjacent(..) => Some("Adjacently tagged enums"),
+        Tagging::Untagged => Some("Untagged enums"),
+    };
+    if let Some(unsupported) = unsupported {
+        return Err(Error::new(
+            data.enum_token.span(),
+            format!("{} have no Dhall equivalent", unsupported),
+        ));
+    }
+
+    let mut entries = vec![];
+    for v in data.variants.iter() {
+        let variant_attrs = VariantAttrs::parse(&v.attrs)?;
+        if variant_attrs.skip {
+            continue;
+        }
+        let name = match variant_attrs.rename {
+            Some(name) => name,
+            None => attrs.rename_all.apply_to_variant(&v.ident.to_string()),
+        };
+        entries.push(match &v.fields {
+            syn::Fields::Unit => quote!( (#name.to_owned(), None) ),
+            syn::Fields::Unnamed(fields) if fields.unnamed.is_empty() => {
+                quote!( (#name.to_owned(), None) )
+            }
+            fields => {
+                let ty = match newtype_field(fields) {
+                    Some(ty) => {
+                        constraints.push(static_type_bound(ty, false));
+                        static_type(ty)
+                    }
+                    // Tuple and struct variants carry a record.
+                    None => record_type(
+                        fields,
+                        variant_attrs.rename_all,
+                        vec![],
+                        constraints,
+                    )?,
+                };
+                quote!( (#name.to_owned(), Some(#ty)) )
             }
-        })
-        .collect::<Result<_, Error>>()?;
+        });
+    }
 
     Ok(quote! {
         ::serde_dhall::SimpleType::Union(
@@ -103,18 +223,24 @@ pub fn derive_static_type_inner(
 ) -> Result<proc_macro2::TokenStream, Error> {
     let input: DeriveInput = syn::parse_macro_input::parse(input)?;
 
-    // List of types that must impl StaticType
+    let attrs = ContainerAttrs::parse(&input.attrs)?;
+
+    // Bounds on the types of the fields, usually that they impl StaticType
     let mut constraints = vec![];
 
-    let get_type = match &input.data {
-        syn::Data::Struct(data) => derive_for_struct(data, &mut constraints)?,
+    let (get_type, record_fields) = match &input.data {
+        syn::Data::Struct(data) => {
+            derive_for_struct(data, &attrs, &mut constraints)?
+        }
         syn::Data::Enum(data) if data.variants.is_empty() => {
             return Err(Error::new(
                 input.span(),
                 "Empty enums are not supported",
             ))
         }
-        syn::Data::Enum(data) => derive_for_enum(data, &mut constraints)?,
+        syn::Data::Enum(data) => {
+            (derive_for_enum(data, &attrs, &mut constraints)?, None)
+        }
         syn::Data::Union(x) => {
             return Err(Error::new(
                 x.union_token.span(),
@@ -130,14 +256,12 @@ pub fn derive_static_type_inner(
     let orig_where_clause = orig_where_clause.unwrap();
 
     // Hygienic errors
-    let assertions = constraints.iter().enumerate().map(|(i, ty)| {
-        // Ensure that ty: StaticType, with an appropriate span
+    let assertions = constraints.iter().enumerate().map(|(i, pred)| {
+        // Ensure that the bound holds, with an appropriate span
         let assert_name =
-            syn::Ident::new(&format!("_AssertType{}", i), ty.span());
+            syn::Ident::new(&format!("_AssertType{}", i), pred.span());
         let mut local_where_clause = orig_where_clause.clone();
-        local_where_clause
-            .predicates
-            .push(parse_quote!(#ty: ::serde_dhall::StaticType));
+        local_where_clause.predicates.push(pred.clone());
         let phantoms = generics.params.iter().map(|param| match param {
             syn::GenericParam::Type(syn::TypeParam { ident, .. }) => {
                 quote!(#ident)
@@ -147,23 +271,35 @@ pub fn derive_static_type_inner(
             }) => quote!(&#lifetime ()),
             _ => unimplemented!(),
         });
-        quote_spanned! {ty.span()=>
+        quote_spanned! {pred.span()=>
             struct #assert_name #impl_generics #local_where_clause {
                 _phantom: std::marker::PhantomData<(#(#phantoms),*)>
-            };
+            }
         }
     });
 
-    // Ensure that all the fields have a StaticType impl
+    // Ensure that all the fields satisfy their bounds
     let mut where_clause = orig_where_clause.clone();
-    for ty in constraints.iter() {
-        where_clause
-            .predicates
-            .push(parse_quote!(#ty: ::serde_dhall::StaticType));
-    }
+    where_clause.predicates.extend(constraints.iter().cloned());
 
     let ident = &input.ident;
+    let record_impl = record_fields.map(|fields| {
+        quote! {
+            #[allow(unused_parens)]
+            impl #impl_generics ::serde_dhall::__private::StaticRecordType
+                    for #ident #ty_generics
+                    #where_clause {
+                fn static_record_fields() -> ::std::collections::HashMap<
+                    ::std::string::String,
+                    ::serde_dhall::SimpleType,
+                > {
+                    #fields
+                }
+            }
+        }
+    });
     let tokens = quote! {
+        #record_impl
         #[allow(unused_parens)]
         impl #impl_generics ::serde_dhall::StaticType
                 for #ident #ty_generics
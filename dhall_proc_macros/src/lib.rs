@@ -5,11 +5,23 @@
 //!
 //! [dhall-rust]: https://github.com/Nadrieril/dhall-rust
 
+mod attrs;
+mod convert;
 mod derive;
 
 use proc_macro::TokenStream;
 
-#[proc_macro_derive(StaticType)]
+#[proc_macro_derive(StaticType, attributes(serde))]
 pub fn derive_static_type(input: TokenStream) -> TokenStream {
     derive::derive_static_type(input)
 }
+
+#[proc_macro_derive(FromDhall, attributes(dhall))]
+pub fn derive_from_dhall(input: TokenStream) -> TokenStream {
+    convert::derive_from_dhall(input)
+}
+
+#[proc_macro_derive(ToDhall, attributes(dhall))]
+pub fn derive_to_dhall(input: TokenStream) -> TokenStream {
+    convert::derive_to_dhall(input)
+}
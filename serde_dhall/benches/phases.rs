@@ -0,0 +1,221 @@
+//! Runs the `dhall-lang` test corpus and the Prelude through each phase of evaluation, and reports
+//! the time spent and the memory allocated in each phase. Each phase is timed on its own: the
+//! inputs it needs are prepared outside the timed region.
+//!
+//! Run with `cargo bench -p serde_dhall --bench phases`. The test corpus comes from the
+//! `dhall-lang` submodule. A copy of part of the Prelude, and some data that uses it, live next to
+//! this file so that the benchmark never needs the network. Other directories can be given as arguments:
+//! `cargo bench -p serde_dhall --bench phases -- path/to/dir`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use dhall::syntax::binary;
+use dhall::{Ctxt, Parsed};
+
+/// Counts the allocations made by the benchmark.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// What a phase cost over the whole corpus.
+#[derive(Default)]
+struct Phase {
+    runs: usize,
+    failures: usize,
+    time: Duration,
+    allocations: usize,
+    allocated_bytes: usize,
+}
+
+impl Phase {
+    /// Runs `f` and adds its cost to the phase.
+    fn measure<T, E>(&mut self, f: impl FnOnce() -> Result<T, E>) -> Option<T> {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let res = f();
+        self.time += start.elapsed();
+        self.allocations += ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+        self.allocated_bytes +=
+            ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes;
+        self.runs += 1;
+        if res.is_err() {
+            self.failures += 1;
+        }
+        res.ok()
+    }
+}
+
+#[derive(Default)]
+struct Phases {
+    parse: Phase,
+    encode: Phase,
+    decode: Phase,
+    resolve: Phase,
+    typecheck: Phase,
+    normalize: Phase,
+    deserialize: Phase,
+}
+
+impl Phases {
+    /// Parses the file and encodes it to and from binary.
+    fn run_syntax(&mut self, path: &Path) {
+        let src = std::fs::read_to_string(path).unwrap();
+        let parsed = match self.parse.measure(|| Parsed::parse_str(&src)) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+        let expr = parsed.to_expr();
+        if let Some(data) = self.encode.measure(|| binary::encode(&expr)) {
+            self.decode.measure(|| Parsed::parse_binary(&data));
+        }
+    }
+
+    /// Evaluates the file, and deserializes the result with `serde_dhall`. Values that aren't plain
+    /// data, like functions, count as deserialization failures.
+    fn run_semantics(&mut self, path: &Path) {
+        self.run_syntax(path);
+        let parsed = match Parsed::parse_file(path) {
+            Ok(parsed) => parsed,
+            Err(_) => return,
+        };
+        let normal_form = Ctxt::with_new(|cx| {
+            let resolved = self.resolve.measure(|| parsed.resolve(cx))?;
+            let typed = self.typecheck.measure(|| resolved.typecheck(cx))?;
+            let nf = self.normalize.measure(|| typed.normalize_checked(cx))?;
+            Some(nf.to_expr(cx).to_string())
+        });
+        if let Some(nf) = normal_form {
+            self.deserialize.measure(|| {
+                serde_dhall::from_str(&nf).parse::<serde_json::Value>()
+            });
+        }
+    }
+
+    fn report(&self) {
+        println!(
+            "{:<12} {:>6} {:>8} {:>12} {:>12} {:>14}",
+            "phase", "runs", "failures", "time (ms)", "allocations", "bytes"
+        );
+        let phases = [
+            ("parse", &self.parse),
+            ("encode", &self.encode),
+            ("decode", &self.decode),
+            ("resolve", &self.resolve),
+            ("typecheck", &self.typecheck),
+            ("normalize", &self.normalize),
+            ("deserialize", &self.deserialize),
+        ];
+        for (name, phase) in &phases {
+            println!(
+                "{:<12} {:>6} {:>8} {:>12.1} {:>12} {:>14}",
+                name,
+                phase.runs,
+                phase.failures,
+                phase.time.as_secs_f64() * 1000.0,
+                phase.allocations,
+                phase.allocated_bytes,
+            );
+        }
+    }
+}
+
+/// Lists the `.dhall` files in a directory, recursively, in a stable order.
+fn dhall_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            dhall_files(&path, files);
+        } else if path.extension() == Some("dhall".as_ref()) {
+            files.push(path);
+        }
+    }
+}
+
+/// Whether the file mentions a URL, which would make evaluating it depend on the network.
+fn has_remote_imports(path: &Path) -> bool {
+    let src = std::fs::read_to_string(path).unwrap_or_default();
+    src.contains("http://") || src.contains("https://")
+}
+
+fn main() {
+    let dirs: Vec<PathBuf> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .collect();
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dhall_lang = manifest_dir.parent().unwrap().join("dhall-lang");
+    let benches = manifest_dir.join("benches");
+
+    // Files that are only parsed, and files that are evaluated too. The parser tests can contain
+    // remote imports, and aren't all meant to typecheck.
+    let mut syntax_files = Vec::new();
+    let mut semantics_files = Vec::new();
+    if dirs.is_empty() {
+        let tests = dhall_lang.join("tests");
+        dhall_files(&tests.join("parser").join("success"), &mut syntax_files);
+        for dir in &["normalization", "type-inference"] {
+            dhall_files(&tests.join(dir).join("success"), &mut semantics_files);
+        }
+        // A few of these tests import things from the network.
+        semantics_files.retain(|path| !has_remote_imports(path));
+        if syntax_files.is_empty() {
+            eprintln!(
+                "The dhall-lang test corpus is missing, only the files in `benches` will be run. Run \
+                 `git submodule update --init` to get it."
+            );
+        }
+        dhall_files(&benches.join("Prelude"), &mut semantics_files);
+        dhall_files(&benches.join("data"), &mut semantics_files);
+    } else {
+        for dir in &dirs {
+            dhall_files(dir, &mut semantics_files);
+        }
+    }
+    if syntax_files.is_empty() && semantics_files.is_empty() {
+        eprintln!("No dhall files found.");
+        return;
+    }
+
+    let mut phases = Phases::default();
+    for path in &syntax_files {
+        phases.run_syntax(path);
+    }
+    for path in &semantics_files {
+        phases.run_semantics(path);
+    }
+    phases.report();
+}
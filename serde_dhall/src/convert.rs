@@ -0,0 +1,463 @@
+//! Direct conversions between Rust types and [`SimpleValue`], without going through serde. This
+//! is what `derive(FromDhall)` and `derive(ToDhall)` build upon.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+
+use dhall::syntax::NumKind;
+
+use crate::error::FieldPath;
+use crate::{Error, ErrorKind, Result, SimpleType, SimpleValue, Value};
+
+/// A Rust type that can be built directly from a [`SimpleValue`].
+///
+/// Implemented for common types and by `derive(FromDhall)`.
+#[doc(hidden)]
+pub trait FromSimpleValue: Sized {
+    /// Converts the value, which lives at `path` inside the value being deserialized.
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self>;
+
+    /// Called when a record field of this type is absent.
+    fn missing_field(path: &FieldPath) -> Result<Self> {
+        Err(missing_field(path))
+    }
+}
+
+/// A Rust type that can be converted directly into a [`SimpleValue`].
+///
+/// Implemented for common types and by `derive(ToDhall)`.
+#[doc(hidden)]
+pub trait ToSimpleValue {
+    /// Converts the value.
+    fn to_simple_value(&self) -> Result<SimpleValue>;
+}
+
+/// Describes the kind of a value, for error messages.
+fn describe(v: &SimpleValue) -> &'static str {
+    match v {
+        SimpleValue::Num(NumKind::Bool(_)) => "a Bool",
+        SimpleValue::Num(NumKind::Natural(_)) => "a Natural",
+        SimpleValue::Num(NumKind::Integer(_)) => "an Integer",
+        SimpleValue::Num(NumKind::Double(_)) => "a Double",
+        SimpleValue::Text(_) => "a Text",
+        SimpleValue::Optional(_) => "an Optional",
+        SimpleValue::List(_) => "a List",
+        SimpleValue::Record(_) => "a record",
+        SimpleValue::Union(..) => "a union",
+    }
+}
+
+/// Builds a type mismatch error.
+pub fn invalid_type(
+    expected: &str,
+    found: &SimpleValue,
+    path: &FieldPath,
+) -> Error {
+    Error(ErrorKind::Deserialize(format!(
+        "expected {}, found {}",
+        expected,
+        describe(found)
+    )))
+    .at(path)
+}
+
+/// Builds the error for an absent record field.
+pub fn missing_field(path: &FieldPath) -> Error {
+    Error(ErrorKind::Deserialize("missing field".to_owned())).at(path)
+}
+
+/// Attaches the path to the error returned by a `#[dhall(with = ...)]` function.
+pub fn at<T>(res: Result<T>, path: &FieldPath) -> Result<T> {
+    res.map_err(|e| e.at(path))
+}
+
+/// Returns the fields of a record value.
+pub fn expect_record<'v>(
+    v: &'v SimpleValue,
+    path: &FieldPath,
+) -> Result<&'v BTreeMap<String, SimpleValue>> {
+    match v {
+        SimpleValue::Record(fields) => Ok(fields),
+        _ => Err(invalid_type("a record", v, path)),
+    }
+}
+
+/// Returns the name and contents of a union value.
+pub fn expect_union<'v>(
+    v: &'v SimpleValue,
+    path: &FieldPath,
+) -> Result<(&'v str, Option<&'v SimpleValue>)> {
+    match v {
+        SimpleValue::Union(name, x) => Ok((name, x.as_deref())),
+        _ => Err(invalid_type("a union", v, path)),
+    }
+}
+
+/// Builds the error for a union alternative the Rust type doesn't know about.
+pub fn unknown_variant(name: &str, path: &FieldPath) -> Error {
+    Error(ErrorKind::Deserialize(format!(
+        "unknown union alternative `{}`",
+        name
+    )))
+    .at(path)
+}
+
+/// Builds the error for a known union alternative that has a payload when the Rust variant has
+/// none, or the other way around.
+pub fn variant_payload(
+    name: &str,
+    expected_payload: bool,
+    path: &FieldPath,
+) -> Error {
+    let msg = if expected_payload {
+        "expected a payload"
+    } else {
+        "expected no payload"
+    };
+    Error(ErrorKind::Deserialize(format!(
+        "{} for union alternative `{}`",
+        msg, name
+    )))
+    .at(path)
+}
+
+/// Implementation of `FromDhall::from_dhall` for types that implement `FromSimpleValue`.
+pub fn from_dhall<T: FromSimpleValue>(v: &Value) -> Result<T> {
+    let sval = v.to_simple_value().ok_or_else(|| {
+        Error(ErrorKind::Deserialize(format!(
+            "this cannot be converted into a simple value: {}",
+            v
+        )))
+    })?;
+    T::from_simple_value(&sval, &FieldPath::root())
+}
+
+/// Implementation of `ToDhall::to_dhall` for types that implement `ToSimpleValue`.
+pub fn to_dhall<T: ToSimpleValue + ?Sized>(
+    x: &T,
+    ty: Option<&SimpleType>,
+) -> Result<Value> {
+    x.to_simple_value()?.into_value(ty)
+}
+
+impl FromSimpleValue for bool {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        match v {
+            SimpleValue::Num(NumKind::Bool(b)) => Ok(*b),
+            _ => Err(invalid_type("a Bool", v, path)),
+        }
+    }
+}
+impl ToSimpleValue for bool {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Num(NumKind::Bool(*self)))
+    }
+}
+
+// Like serde, we accept any number that fits in the target type.
+macro_rules! impl_integer {
+    ($ty:ty, $expected:expr, $num_kind:ident, $wide:ty) => {
+        impl FromSimpleValue for $ty {
+            fn from_simple_value(
+                v: &SimpleValue,
+                path: &FieldPath,
+            ) -> Result<Self> {
+                let n = match v {
+                    SimpleValue::Num(NumKind::Natural(n)) => {
+                        <$ty>::try_from(*n).ok()
+                    }
+                    SimpleValue::Num(NumKind::Integer(n)) => {
+                        <$ty>::try_from(*n).ok()
+                    }
+                    _ => return Err(invalid_type($expected, v, path)),
+                };
+                n.ok_or_else(|| {
+                    Error(ErrorKind::Deserialize(format!(
+                        "number out of range for {}",
+                        stringify!($ty)
+                    )))
+                    .at(path)
+                })
+            }
+        }
+        impl ToSimpleValue for $ty {
+            fn to_simple_value(&self) -> Result<SimpleValue> {
+                Ok(SimpleValue::Num(NumKind::$num_kind(<$wide>::from(*self))))
+            }
+        }
+    };
+}
+
+impl_integer!(u8, "a Natural", Natural, u64);
+impl_integer!(u16, "a Natural", Natural, u64);
+impl_integer!(u32, "a Natural", Natural, u64);
+impl_integer!(u64, "a Natural", Natural, u64);
+impl_integer!(i8, "an Integer", Integer, i64);
+impl_integer!(i16, "an Integer", Integer, i64);
+impl_integer!(i32, "an Integer", Integer, i64);
+impl_integer!(i64, "an Integer", Integer, i64);
+
+impl FromSimpleValue for usize {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        let n = u64::from_simple_value(v, path)?;
+        usize::try_from(n).map_err(|_| {
+            Error(ErrorKind::Deserialize(
+                "number out of range for usize".to_owned(),
+            ))
+            .at(path)
+        })
+    }
+}
+impl ToSimpleValue for usize {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Num(NumKind::Natural(*self as u64)))
+    }
+}
+impl FromSimpleValue for isize {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        let n = i64::from_simple_value(v, path)?;
+        isize::try_from(n).map_err(|_| {
+            Error(ErrorKind::Deserialize(
+                "number out of range for isize".to_owned(),
+            ))
+            .at(path)
+        })
+    }
+}
+impl ToSimpleValue for isize {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Num(NumKind::Integer(*self as i64)))
+    }
+}
+
+impl FromSimpleValue for f64 {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        match v {
+            SimpleValue::Num(NumKind::Double(x)) => Ok((*x).into()),
+            SimpleValue::Num(NumKind::Natural(x)) => Ok(*x as f64),
+            SimpleValue::Num(NumKind::Integer(x)) => Ok(*x as f64),
+            _ => Err(invalid_type("a Double", v, path)),
+        }
+    }
+}
+impl ToSimpleValue for f64 {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Num(NumKind::Double((*self).into())))
+    }
+}
+impl FromSimpleValue for f32 {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        Ok(f64::from_simple_value(v, path)? as f32)
+    }
+}
+impl ToSimpleValue for f32 {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        f64::from(*self).to_simple_value()
+    }
+}
+
+impl FromSimpleValue for String {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        match v {
+            SimpleValue::Text(s) => Ok(s.clone()),
+            _ => Err(invalid_type("a Text", v, path)),
+        }
+    }
+}
+impl ToSimpleValue for String {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        self.as_str().to_simple_value()
+    }
+}
+impl ToSimpleValue for str {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Text(self.to_owned()))
+    }
+}
+
+impl FromSimpleValue for SimpleValue {
+    fn from_simple_value(v: &SimpleValue, _path: &FieldPath) -> Result<Self> {
+        Ok(v.clone())
+    }
+}
+impl ToSimpleValue for SimpleValue {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(self.clone())
+    }
+}
+
+impl<T: FromSimpleValue> FromSimpleValue for Option<T> {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        match v {
+            SimpleValue::Optional(None) => Ok(None),
+            SimpleValue::Optional(Some(x)) => {
+                Ok(Some(T::from_simple_value(x, path)?))
+            }
+            _ => Err(invalid_type("an Optional", v, path)),
+        }
+    }
+    fn missing_field(_path: &FieldPath) -> Result<Self> {
+        Ok(None)
+    }
+}
+impl<T: ToSimpleValue> ToSimpleValue for Option<T> {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Optional(match self {
+            None => None,
+            Some(x) => Some(Box::new(x.to_simple_value()?)),
+        }))
+    }
+}
+
+impl<T: FromSimpleValue> FromSimpleValue for Box<T> {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        Ok(Box::new(T::from_simple_value(v, path)?))
+    }
+    fn missing_field(path: &FieldPath) -> Result<Self> {
+        Ok(Box::new(T::missing_field(path)?))
+    }
+}
+impl<T: ToSimpleValue + ?Sized> ToSimpleValue for Box<T> {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        (**self).to_simple_value()
+    }
+}
+impl<T: ToSimpleValue + ?Sized> ToSimpleValue for &T {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        (**self).to_simple_value()
+    }
+}
+
+impl<T: FromSimpleValue> FromSimpleValue for Vec<T> {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        match v {
+            SimpleValue::List(xs) => xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| T::from_simple_value(x, &path.index(i)))
+                .collect(),
+            _ => Err(invalid_type("a List", v, path)),
+        }
+    }
+}
+impl<T: ToSimpleValue> ToSimpleValue for Vec<T> {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        self.as_slice().to_simple_value()
+    }
+}
+impl<T: ToSimpleValue> ToSimpleValue for [T] {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::List(
+            self.iter()
+                .map(ToSimpleValue::to_simple_value)
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+impl<T: FromSimpleValue> FromSimpleValue for BTreeMap<String, T> {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        expect_record(v, path)?
+            .iter()
+            .map(|(k, x)| {
+                Ok((k.clone(), T::from_simple_value(x, &path.field(k))?))
+            })
+            .collect()
+    }
+}
+impl<T: ToSimpleValue> ToSimpleValue for BTreeMap<String, T> {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Record(
+            self.iter()
+                .map(|(k, x)| Ok((k.clone(), x.to_simple_value()?)))
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+impl<T, S> FromSimpleValue for HashMap<String, T, S>
+where
+    T: FromSimpleValue,
+    S: BuildHasher + Default,
+{
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        expect_record(v, path)?
+            .iter()
+            .map(|(k, x)| {
+                Ok((k.clone(), T::from_simple_value(x, &path.field(k))?))
+            })
+            .collect()
+    }
+}
+impl<T, S> ToSimpleValue for HashMap<String, T, S>
+where
+    T: ToSimpleValue,
+    S: BuildHasher,
+{
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Record(
+            self.iter()
+                .map(|(k, x)| Ok((k.clone(), x.to_simple_value()?)))
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+impl FromSimpleValue for () {
+    fn from_simple_value(v: &SimpleValue, path: &FieldPath) -> Result<Self> {
+        expect_record(v, path)?;
+        Ok(())
+    }
+}
+impl ToSimpleValue for () {
+    fn to_simple_value(&self) -> Result<SimpleValue> {
+        Ok(SimpleValue::Record(BTreeMap::new()))
+    }
+}
+
+// Tuples are records with fields `_1`, `_2`, etc., like in `StaticType`.
+macro_rules! impl_tuple {
+    ($($ty:ident $field:ident $idx:tt),*) => {
+        impl<$($ty),*> FromSimpleValue for ($($ty,)*)
+        where
+            $($ty: FromSimpleValue),*
+        {
+            fn from_simple_value(
+                v: &SimpleValue,
+                path: &FieldPath,
+            ) -> Result<Self> {
+                let fields = expect_record(v, path)?;
+                Ok(($(
+                    {
+                        let field = stringify!($field);
+                        let path = path.field(field);
+                        match fields.get(field) {
+                            Some(x) => $ty::from_simple_value(x, &path)?,
+                            None => $ty::missing_field(&path)?,
+                        }
+                    },
+                )*))
+            }
+        }
+        impl<$($ty),*> ToSimpleValue for ($($ty,)*)
+        where
+            $($ty: ToSimpleValue),*
+        {
+            fn to_simple_value(&self) -> Result<SimpleValue> {
+                let mut fields = BTreeMap::new();
+                $(
+                    fields.insert(
+                        stringify!($field).to_owned(),
+                        self.$idx.to_simple_value()?,
+                    );
+                )*
+                Ok(SimpleValue::Record(fields))
+            }
+        }
+    };
+}
+
+impl_tuple!(A _1 0);
+impl_tuple!(A _1 0, B _2 1);
+impl_tuple!(A _1 0, B _2 1, C _3 2);
+impl_tuple!(A _1 0, B _2 1, C _3 2, D _4 3);
@@ -9,16 +9,23 @@ use serde::de::VariantAccess as _;
 
 use dhall::syntax::NumKind;
 
-use crate::value::SimpleValue;
+use crate::error::FieldPath;
+use crate::function::{self, DhallFunction};
+use crate::value::{NestedValue, SimpleValue, ValueKind};
 use crate::{Error, ErrorKind, Value};
 
+/// Implemented by the types that implement [`FromDhall`].
 pub trait Sealed {}
 
 /// A data structure that can be deserialized from a Dhall expression.
 ///
 /// This is automatically implemented for any type that [serde] can deserialize.
-/// In fact, this trait cannot be implemented manually. To implement it for your type,
-/// use serde's derive mechanism.
+/// This trait cannot be implemented manually. To implement it for your type,
+/// use serde's derive mechanism, or `#[derive(FromDhall)]` (see below).
+///
+/// When deserialization fails, the error mentions the path of the offending value, e.g.
+/// `servers[2].port`. Its alternate form (`{:#}`) also shows where that value comes from in the
+/// source, when possible.
 ///
 /// # Example
 ///
@@ -39,6 +46,45 @@ pub trait Sealed {}
 /// # }
 /// ```
 ///
+/// # Deriving without serde
+///
+/// `#[derive(FromDhall)]` converts directly from Dhall values without going through serde.
+/// Errors then mention the path of the offending field, e.g. `servers[2].port: expected a
+/// Natural, found a Text`. Fields can be customized with `#[dhall(...)]` attributes:
+///
+/// - `rename = "name"`: use a different Dhall field (or union alternative) name;
+/// - `default`: use `Default::default()` if the field is missing;
+/// - `default = "path"`: call the given function if the field is missing;
+/// - `with = "module"`: convert the field with `module::from_dhall(&SimpleValue) ->
+///   Result<T>` (and `module::to_dhall(&T) -> Result<SimpleValue>` for `ToDhall`).
+///
+/// The types of the fields must themselves be standard types (numbers, strings, `Vec`, `Option`,
+/// maps, tuples, ...) or derive `FromDhall`. A type can't derive both `FromDhall` and serde's
+/// `Deserialize`.
+///
+/// ```rust
+/// # fn main() -> serde_dhall::Result<()> {
+/// use serde_dhall::FromDhall;
+///
+/// #[derive(Debug, FromDhall)]
+/// struct Server {
+///     host: String,
+///     #[dhall(default = "default_port")]
+///     port: u16,
+/// }
+/// fn default_port() -> u16 { 80 }
+///
+/// let server: Server = serde_dhall::from_str("{ host = \"localhost\" }").parse()?;
+/// assert_eq!(server.port, 80);
+///
+/// let err = serde_dhall::from_str("{ host = \"localhost\", port = True }")
+///     .parse::<Server>()
+///     .unwrap_err();
+/// assert_eq!(err.to_string(), "port: expected a Natural, found a Bool");
+/// # Ok(())
+/// # }
+/// ```
+///
 /// [serde]: https://serde.rs
 pub trait FromDhall: Sealed + Sized {
     #[doc(hidden)]
@@ -88,7 +134,10 @@ pub fn from_simple_value<T>(v: SimpleValue) -> crate::Result<T>
 where
     T: serde::de::DeserializeOwned,
 {
-    T::deserialize(Deserializer(Cow::Owned(v)))
+    T::deserialize(Deserializer {
+        value: Node::Simple(Cow::Owned(v)),
+        path: FieldPath::root(),
+    })
 }
 
 impl<T> FromDhall for T
@@ -96,17 +145,91 @@ where
     T: serde::de::DeserializeOwned,
 {
     fn from_dhall(v: &Value) -> crate::Result<Self> {
-        let sval = v.to_simple_value().ok_or_else(|| {
-            Error(ErrorKind::Deserialize(format!(
-                "this cannot be deserialized into the serde data model: {}",
-                v
-            )))
-        })?;
-        from_simple_value(sval)
+        T::deserialize(Deserializer::value(v, FieldPath::root()))
     }
 }
 
-struct Deserializer<'a>(Cow<'a, SimpleValue>);
+/// The part of a `Value` being deserialized.
+enum Node<'a> {
+    Simple(Cow<'a, SimpleValue>),
+    /// Any other kind of value, e.g. a function or a record that contains one.
+    Other(&'a Value),
+}
+
+struct Deserializer<'a> {
+    value: Node<'a>,
+    /// Where this value is inside the value being deserialized, for error messages.
+    path: FieldPath,
+}
+
+impl<'a> Deserializer<'a> {
+    fn child(x: &'a SimpleValue, path: FieldPath) -> Self {
+        Deserializer {
+            value: Node::Simple(Cow::Borrowed(x)),
+            path,
+        }
+    }
+    fn field(x: &'a SimpleValue, name: &str, parent: &FieldPath) -> Self {
+        Deserializer::child(x, parent.field(name))
+    }
+    fn value(x: &'a Value, path: FieldPath) -> Self {
+        match &x.kind {
+            ValueKind::Val(x, _) => Deserializer::child(x, path),
+            _ => Deserializer {
+                value: Node::Other(x),
+                path,
+            },
+        }
+    }
+
+    fn simple(&self) -> Option<&SimpleValue> {
+        match &self.value {
+            Node::Simple(x) => Some(x.as_ref()),
+            Node::Other(_) => None,
+        }
+    }
+    fn function(&self) -> Option<&'a DhallFunction> {
+        match self.value {
+            Node::Other(Value {
+                kind: ValueKind::Fun(f),
+            }) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// Deserializes a value that is not a `SimpleValue`.
+    fn deserialize_other<'de, V>(
+        x: &'a Value,
+        path: &FieldPath,
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        'de: 'a,
+        V: serde::de::Visitor<'de>,
+    {
+        let res =
+            match &x.kind {
+                ValueKind::Nested(NestedValue::Optional(x), _) => {
+                    visitor.visit_some(Deserializer::value(x, path.clone()))
+                }
+                ValueKind::Nested(NestedValue::List(xs), _) => visitor
+                    .visit_seq(SeqDeserializer::new(
+                        xs.iter().enumerate().map(|(i, x)| {
+                            Deserializer::value(x, path.index(i))
+                        }),
+                    )),
+                ValueKind::Nested(NestedValue::Record(m), _) => visitor
+                    .visit_map(MapDeserializer::new(m.iter().map(|(k, x)| {
+                        (k.as_str(), Deserializer::value(x, path.field(k)))
+                    }))),
+                _ => Err(Error(ErrorKind::Deserialize(format!(
+                    "this cannot be deserialized into the serde data model: {}",
+                    x
+                )))),
+            };
+        res.map_err(|e| e.at(path))
+    }
+}
 
 impl<'de: 'a, 'a> serde::de::IntoDeserializer<'de, Error> for Deserializer<'a> {
     type Deserializer = Deserializer<'a>;
@@ -125,24 +248,40 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
         use NumKind::*;
         use SimpleValue::*;
 
-        let val = |x| Deserializer(Cow::Borrowed(x));
-        match self.0.as_ref() {
+        let path = &self.path;
+        let value = match &self.value {
+            Node::Simple(x) => x.as_ref(),
+            Node::Other(x) => {
+                return Deserializer::deserialize_other(x, path, visitor)
+            }
+        };
+        let res = match value {
             Num(Bool(x)) => visitor.visit_bool(*x),
             Num(Natural(x)) => visitor.visit_u64(*x),
             Num(Integer(x)) => visitor.visit_i64(*x),
             Num(Double(x)) => visitor.visit_f64((*x).into()),
             Text(x) => visitor.visit_str(x),
-            List(xs) => {
-                visitor.visit_seq(SeqDeserializer::new(xs.iter().map(val)))
-            }
-            Optional(None) => visitor.visit_none(),
-            Optional(Some(x)) => visitor.visit_some(val(x)),
-            Record(m) => visitor.visit_map(MapDeserializer::new(
-                m.iter().map(|(k, v)| (k.as_str(), val(v))),
+            List(xs) => visitor.visit_seq(SeqDeserializer::new(
+                xs.iter()
+                    .enumerate()
+                    .map(|(i, x)| Deserializer::child(x, path.index(i))),
             )),
+            Optional(None) => visitor.visit_none(),
+            Optional(Some(x)) => {
+                visitor.visit_some(Deserializer::child(x, path.clone()))
+            }
+            Record(m) => {
+                visitor.visit_map(MapDeserializer::new(m.iter().map(
+                    |(k, x)| (k.as_str(), Deserializer::field(x, k, path)),
+                )))
+            }
             Union(field_name, Some(x)) => visitor.visit_enum(
                 MapAccessDeserializer::new(MapDeserializer::new(
-                    Some((field_name.as_str(), val(x))).into_iter(),
+                    Some((
+                        field_name.as_str(),
+                        Deserializer::field(x, field_name, path),
+                    ))
+                    .into_iter(),
                 )),
             ),
             Union(field_name, None) => visitor.visit_enum(
@@ -150,7 +289,8 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
                     Some((field_name.as_str(), ())).into_iter(),
                 )),
             ),
-        }
+        };
+        res.map_err(|e| e.at(&self.path))
     }
 
     fn deserialize_tuple<V>(
@@ -161,32 +301,97 @@ impl<'de: 'a, 'a> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        let val = |x| Deserializer(Cow::Borrowed(x));
-        match self.0.as_ref() {
-            // Blindly takes keys in sorted order.
-            SimpleValue::Record(m) => visitor
-                .visit_seq(SeqDeserializer::new(m.iter().map(|(_, v)| val(v)))),
+        match self.simple() {
+            Some(SimpleValue::Record(m)) => {
+                let path = &self.path;
+                visitor
+                    .visit_seq(SeqDeserializer::new(
+                        tuple_fields(m)
+                            .map(|(k, x)| Deserializer::field(x, k, path)),
+                    ))
+                    .map_err(|e| e.at(&self.path))
+            }
             _ => self.deserialize_any(visitor),
         }
     }
 
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    // Newtypes are transparent, except the one that `DhallFunction` deserializes through.
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        if name != function::NEWTYPE_NAME {
+            return visitor.visit_newtype_struct(self);
+        }
+        match self.function() {
+            Some(f) => {
+                function::hand_over(f, visitor).map_err(|e| e.at(&self.path))
+            }
+            None => self.deserialize_any(visitor),
+        }
+    }
+
     fn deserialize_unit<V>(self, visitor: V) -> crate::Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        match self.0.as_ref() {
-            SimpleValue::Record(m) if m.is_empty() => visitor.visit_unit(),
+        match self.simple() {
+            Some(SimpleValue::Record(m)) if m.is_empty() => {
+                visitor.visit_unit().map_err(|e: Error| e.at(&self.path))
+            }
             _ => self.deserialize_any(visitor),
         }
     }
 
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> crate::Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
     serde::forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit_struct newtype_struct seq
-        tuple_struct map struct enum identifier ignored_any
+        bytes byte_buf option seq map struct enum identifier ignored_any
     }
 }
 
+/// Iterates over the fields of a record that encodes a tuple. If the fields are `_1`, `_2`, etc.,
+/// they are returned in numeric order (so that `_10` comes after `_9`); otherwise this blindly
+/// takes keys in sorted order.
+fn tuple_fields(
+    m: &BTreeMap<String, SimpleValue>,
+) -> impl Iterator<Item = (&str, &SimpleValue)> {
+    let positional = (1..=m.len())
+        .map(|i| m.get_key_value(&format!("_{}", i)))
+        .collect::<Option<Vec<_>>>();
+    let fields = match positional {
+        Some(fields) => fields,
+        None => m.iter().collect(),
+    };
+    fields.into_iter().map(|(k, x)| (k.as_str(), x))
+}
+
 struct SimpleValueVisitor;
 
 impl<'de> serde::de::Visitor<'de> for SimpleValueVisitor {
@@ -1,4 +1,8 @@
-use dhall::error::Error as DhallError;
+use dhall::error::{Error as DhallError, ErrorBuilder};
+use dhall::operations::OpKind;
+use dhall::semantics::{Hir, HirKind};
+use dhall::syntax::{ExprKind, Span};
+use dhall::Ctxt;
 
 /// Alias for a `Result` with the error type `serde_dhall::Error`.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -12,6 +16,176 @@ pub(crate) enum ErrorKind {
     Dhall(DhallError),
     Deserialize(String),
     Serialize(String),
+    /// An error that occurred at the given position inside a value. The span points to where
+    /// that value comes from in the source, if it could be found.
+    Located(FieldPath, Option<Span>, Box<ErrorKind>),
+}
+
+/// The position of a subvalue inside a Dhall value, e.g. `servers[2].port`.
+#[doc(hidden)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldPath(Vec<PathSegment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PathSegment {
+    /// A record field or a union alternative.
+    Field(String),
+    /// A list element.
+    Index(usize),
+}
+
+impl FieldPath {
+    /// The path to the value itself.
+    pub fn root() -> Self {
+        FieldPath(Vec::new())
+    }
+    /// Whether this is the path to the value itself.
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// The path to a record field or union alternative of this value.
+    pub fn field(&self, name: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Field(name.to_owned()));
+        path
+    }
+    /// The path to a list element of this value.
+    pub fn index(&self, i: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Index(i));
+        path
+    }
+
+    /// Finds where the value at this path comes from in the source expression. Normalization
+    /// loses spans, so this follows the path through literals in the original expression and
+    /// returns the span of the innermost subexpression it could reach.
+    pub(crate) fn find_span<'cx>(&self, cx: Ctxt<'cx>, hir: &Hir<'cx>) -> Span {
+        let mut hir = hir;
+        for segment in &self.0 {
+            match find_child(cx, hir, segment) {
+                Some(child) => hir = child,
+                None => break,
+            }
+        }
+        hir.span()
+    }
+}
+
+/// Skips the nodes that don't change the shape of a value.
+fn skip_transparent<'h, 'cx: 'h>(
+    cx: Ctxt<'cx>,
+    hir: &'h Hir<'cx>,
+) -> &'h Hir<'cx> {
+    match hir.kind() {
+        HirKind::Import(import) => {
+            skip_transparent(cx, &cx[import].unwrap_result().hir)
+        }
+        HirKind::ImportAlternative(alt, left, right) => {
+            let hir = if cx[alt].unwrap_selected() {
+                left
+            } else {
+                right
+            };
+            skip_transparent(cx, hir)
+        }
+        HirKind::Expr(ExprKind::Annot(x, _))
+        | HirKind::Expr(ExprKind::SomeLit(x))
+        | HirKind::Expr(ExprKind::Let(_, _, _, x)) => skip_transparent(cx, x),
+        _ => hir,
+    }
+}
+
+/// Finds the subexpression that builds the given part of a value, if it is syntactically obvious.
+fn find_child<'h, 'cx: 'h>(
+    cx: Ctxt<'cx>,
+    hir: &'h Hir<'cx>,
+    segment: &PathSegment,
+) -> Option<&'h Hir<'cx>> {
+    let kind = match skip_transparent(cx, hir).kind() {
+        HirKind::Expr(kind) => kind,
+        _ => return None,
+    };
+    match (kind, segment) {
+        (ExprKind::RecordLit(kvs), PathSegment::Field(name)) => {
+            kvs.get(name.as_str())
+        }
+        // A union alternative applied to its contents.
+        (ExprKind::Op(OpKind::App(f, x)), PathSegment::Field(name)) => {
+            match skip_transparent(cx, f).kind() {
+                HirKind::Expr(ExprKind::Op(OpKind::Field(_, l)))
+                    if String::from(l) == *name =>
+                {
+                    Some(x)
+                }
+                _ => None,
+            }
+        }
+        // A `Prelude.Map`-style list of key-value records.
+        (ExprKind::NEListLit(xs), PathSegment::Field(name)) => xs
+            .iter()
+            .find_map(|x| match skip_transparent(cx, x).kind() {
+                HirKind::Expr(ExprKind::RecordLit(kvs)) => {
+                    let key = kvs.get("mapKey")?;
+                    match skip_transparent(cx, key).kind() {
+                        HirKind::Expr(ExprKind::TextLit(t))
+                            if t.tail().is_empty() && t.head() == name =>
+                        {
+                            kvs.get("mapValue")
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }),
+        (ExprKind::NEListLit(xs), PathSegment::Index(i)) => xs.get(*i),
+        _ => None,
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Records where in the value this error happened, unless that is already known.
+    pub(crate) fn at(self, path: &FieldPath) -> Self {
+        match self.0 {
+            kind @ ErrorKind::Located(..) => Error(kind),
+            kind if path.is_root() => Error(kind),
+            kind => {
+                Error(ErrorKind::Located(path.clone(), None, Box::new(kind)))
+            }
+        }
+    }
+
+    /// Records where in the source the offending value comes from, using the given function to
+    /// find the span of a subvalue.
+    pub(crate) fn with_span(
+        self,
+        find: impl FnOnce(&FieldPath) -> Span,
+    ) -> Self {
+        match self.0 {
+            ErrorKind::Located(path, None, err) => {
+                let span = find(&path);
+                Error(ErrorKind::Located(path, Some(span), err))
+            }
+            kind @ ErrorKind::Deserialize(_) => {
+                let path = FieldPath::root();
+                let span = find(&path);
+                Error(ErrorKind::Located(path, Some(span), Box::new(kind)))
+            }
+            kind => Error(kind),
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -20,16 +194,44 @@ impl From<ErrorKind> for Error {
     }
 }
 
-impl std::fmt::Display for Error {
+impl std::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match &self.0 {
+        match self {
             ErrorKind::Dhall(err) => write!(f, "{}", err),
             ErrorKind::Deserialize(err) => write!(f, "{}", err),
             ErrorKind::Serialize(err) => write!(f, "{}", err),
+            ErrorKind::Located(path, span, err) => {
+                let msg = if path.is_root() {
+                    err.to_string()
+                } else {
+                    format!("{}: {}", path, err)
+                };
+                match span {
+                    Some(span @ Span::Parsed(_)) if f.alternate() => {
+                        let label = if path.is_root() {
+                            "the value comes from here".to_owned()
+                        } else {
+                            format!("`{}` comes from here", path)
+                        };
+                        let mut builder = ErrorBuilder::new(msg);
+                        builder.span_err(span.clone(), label);
+                        write!(f, "{}", builder.format())
+                    }
+                    _ => write!(f, "{}", msg),
+                }
+            }
         }
     }
 }
 
+/// The alternate form (`{:#}`) additionally shows where the offending value comes from in the
+/// source, when that is known.
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 impl std::error::Error for Error {}
 
 impl serde::de::Error for Error {
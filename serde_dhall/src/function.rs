@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::result::Result as StdResult;
+
+use serde::de::{Unexpected, Visitor};
+
+use dhall::syntax::Expr;
+use dhall::Program;
+
+use crate::value::ValueKind;
+use crate::{Error, ErrorKind, FromDhall, Result, SimpleType, ToDhall, Value};
+
+/// A Dhall function, that can be called from Rust.
+///
+/// This can be obtained by deserializing a Dhall function, e.g. `λ(x : Natural) → x + 1`, with
+/// [`from_str()`] or [`from_file()`]. Calling it with [`call()`] converts the argument to Dhall,
+/// evaluates the application, and converts the result back to Rust.
+///
+/// A function with several arguments can be called one argument at a time, by deserializing the
+/// intermediate results into `DhallFunction`s.
+///
+/// Functions can also be found inside records, lists and optionals, e.g. as a field of a struct
+/// that derives `Deserialize`. They can't be deserialized by other serde formats.
+///
+/// # Example
+///
+/// ```rust
+/// # fn main() -> serde_dhall::Result<()> {
+/// use serde::Deserialize;
+/// use serde_dhall::DhallFunction;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     host: String,
+///     debug: bool,
+/// }
+///
+/// let data = r#"
+///     λ(env : Text) → { host = "${env}.example.com", debug = False }
+/// "#;
+/// let mk_config: DhallFunction = serde_dhall::from_str(data).parse()?;
+///
+/// let config: Config = mk_config.call(&"staging")?;
+/// assert_eq!(config.host, "staging.example.com");
+///
+/// // Arguments of the wrong type are caught by the typechecker.
+/// assert!(mk_config.call::<_, Config>(&true).is_err());
+///
+/// // Functions can be called one argument at a time.
+/// let add: DhallFunction = serde_dhall::from_str("λ(x : Natural) → λ(y : Natural) → x + y")
+///     .parse()?;
+/// let add_one: DhallFunction = add.call(&1u64)?;
+/// assert_eq!(add_one.call::<_, u64>(&2u64)?, 3);
+///
+/// // Functions can be fields of a struct.
+/// #[derive(Deserialize)]
+/// struct Hooks {
+///     on_start: DhallFunction,
+/// }
+/// let hooks: Hooks = serde_dhall::from_str("{ on_start = λ(n : Natural) → n + 1 }").parse()?;
+/// assert_eq!(hooks.on_start.call::<_, u64>(&1u64)?, 2);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`from_str()`]: crate::from_str()
+/// [`from_file()`]: crate::from_file()
+/// [`call()`]: DhallFunction::call()
+#[derive(Debug, Clone)]
+pub struct DhallFunction {
+    /// The normal form of the function, along with its type. It is closed and all its imports have
+    /// been resolved, so calling it only needs to typecheck the application.
+    program: Program,
+    /// The type of the argument, if it is simple. Used as a type annotation when converting the
+    /// argument.
+    input: Option<SimpleType>,
+}
+
+impl DhallFunction {
+    pub(crate) fn new(program: Program, input: Option<SimpleType>) -> Self {
+        DhallFunction { program, input }
+    }
+
+    /// Applies the function to the given argument and converts the result to `R`.
+    ///
+    /// The argument is given the type the function expects, so values like empty lists or enums
+    /// don't need any further annotation.
+    pub fn call<A, R>(&self, arg: &A) -> Result<R>
+    where
+        A: ToDhall,
+        R: FromDhall,
+    {
+        let arg = arg.to_dhall(self.input.as_ref())?.to_expr();
+        let val = (|| {
+            let app = self.program.apply(&Program::from_expr(arg)?)?;
+            app.with_typed(|cx, typed| {
+                Value::from_nir_and_ty(
+                    cx,
+                    typed.normalize(cx).as_nir(),
+                    typed.ty().as_nir(),
+                )
+            })
+        })()
+        .map_err(ErrorKind::Dhall)
+        .map_err(Error)??;
+        R::from_dhall(&val)
+    }
+
+    /// Converts back to the corresponding AST expression.
+    pub(crate) fn to_expr(&self) -> Expr {
+        self.program.to_expr()
+    }
+}
+
+impl PartialEq for DhallFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.program.as_expr() == other.program.as_expr()
+    }
+}
+impl Eq for DhallFunction {}
+
+/// The name of the newtype through which the deserializer hands functions over to
+/// `DhallFunction::deserialize`.
+pub(crate) const NEWTYPE_NAME: &str = "$serde_dhall::private::DhallFunction";
+
+thread_local! {
+    /// Serde can only pass plain data from a deserializer to a visitor, so the function itself
+    /// goes through here.
+    // `const` initializers need a newer Rust than we support.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static HANDOVER: RefCell<Option<DhallFunction>> = RefCell::new(None);
+}
+
+/// Hands the function over to a visitor created by `DhallFunction::deserialize`.
+pub(crate) fn hand_over<'de, V>(
+    f: &DhallFunction,
+    visitor: V,
+) -> Result<V::Value>
+where
+    V: Visitor<'de>,
+{
+    HANDOVER.with(|h| *h.borrow_mut() = Some(f.clone()));
+    let res = visitor.visit_unit();
+    HANDOVER.with(|h| h.borrow_mut().take());
+    res
+}
+
+struct FunctionVisitor;
+
+impl<'de> Visitor<'de> for FunctionVisitor {
+    type Value = DhallFunction;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Dhall function")
+    }
+
+    fn visit_unit<E>(self) -> StdResult<DhallFunction, E>
+    where
+        E: serde::de::Error,
+    {
+        HANDOVER
+            .with(|h| h.borrow_mut().take())
+            .ok_or_else(|| E::invalid_type(Unexpected::Unit, &self))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DhallFunction {
+    fn deserialize<D>(deserializer: D) -> StdResult<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(NEWTYPE_NAME, FunctionVisitor)
+    }
+}
+
+impl crate::serialize::Sealed for DhallFunction {}
+
+impl ToDhall for DhallFunction {
+    fn to_dhall(&self, _ty: Option<&SimpleType>) -> Result<Value> {
+        Ok(Value {
+            kind: ValueKind::Fun(self.clone()),
+        })
+    }
+}
+
+impl fmt::Display for DhallFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.program.as_expr().fmt(f)
+    }
+}
@@ -258,22 +258,38 @@ mod test_readme {
     doc_comment::doctest!("../../README.md");
 }
 
+mod convert;
 mod deserialize;
 mod error;
+mod function;
 mod options;
 mod serialize;
 mod static_type;
 /// Dhall values
 mod value;
+mod watch;
 
 #[doc(hidden)]
-pub use dhall_proc_macros::StaticType;
+pub use dhall_proc_macros::{FromDhall, StaticType, ToDhall};
+
+/// Used by the code generated by the derive macros. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::convert::*;
+    pub use crate::deserialize::Sealed as FromDhallSealed;
+    pub use crate::error::FieldPath;
+    pub use crate::serialize::Sealed as ToDhallSealed;
+    pub use crate::static_type::StaticRecordType;
+}
 
 pub use deserialize::{from_simple_value, FromDhall};
+pub use dhall::{CancellationToken, Limits};
 pub(crate) use error::ErrorKind;
 pub use error::{Error, Result};
+pub use function::DhallFunction;
 pub use options::de::{from_binary_file, from_file, from_str, Deserializer};
 pub use options::ser::{serialize, Serializer};
 pub use serialize::ToDhall;
 pub use static_type::StaticType;
 pub use value::{NumKind, SimpleType, SimpleValue, Value};
+pub use watch::Watcher;
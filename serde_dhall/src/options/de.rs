@@ -1,10 +1,10 @@
 use std::path::{Path, PathBuf};
 
-use dhall::{Ctxt, Parsed};
+use dhall::{Ctxt, Limits, Parsed, Resolved, Session};
 
 use crate::options::{HasAnnot, ManualAnnot, NoAnnot, StaticAnnot, TypeAnnot};
 use crate::SimpleType;
-use crate::{Error, ErrorKind, FromDhall, Result, Value};
+use crate::{Error, ErrorKind, FromDhall, Result, Value, Watcher};
 
 #[derive(Debug, Clone)]
 enum Source<'a> {
@@ -58,6 +58,7 @@ pub struct Deserializer<'a, A> {
     source: Source<'a>,
     annot: A,
     allow_imports: bool,
+    limits: Limits,
     // allow_remote_imports: bool,
     // use_cache: bool,
 }
@@ -68,6 +69,7 @@ impl<'a> Deserializer<'a, NoAnnot> {
             source,
             annot: NoAnnot,
             allow_imports: true,
+            limits: Limits::default(),
             // allow_remote_imports: true,
             // use_cache: true,
         }
@@ -131,6 +133,7 @@ impl<'a> Deserializer<'a, NoAnnot> {
             annot: ManualAnnot(ty),
             source: self.source,
             allow_imports: self.allow_imports,
+            limits: self.limits,
         }
     }
 
@@ -181,6 +184,7 @@ impl<'a> Deserializer<'a, NoAnnot> {
             annot: StaticAnnot,
             source: self.source,
             allow_imports: self.allow_imports,
+            limits: self.limits,
         }
     }
 }
@@ -214,6 +218,35 @@ impl<'a, A> Deserializer<'a, A> {
         }
     }
 
+    /// Sets limits on the resources that resolving and evaluating the value can use. Exceeding
+    /// them makes parsing fail.
+    ///
+    /// By default, there are no limits.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> serde_dhall::Result<()> {
+    /// use serde_dhall::Limits;
+    ///
+    /// let data = "Natural/fold 1000000000 Natural (λ(x : Natural) → x + 1) 0";
+    /// let limits = Limits {
+    ///     max_steps: Some(10_000),
+    ///     ..Limits::default()
+    /// };
+    /// assert!(
+    ///     serde_dhall::from_str(data)
+    ///         .limits(limits)
+    ///         .parse::<u64>()
+    ///         .is_err()
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn limits(self, limits: Limits) -> Self {
+        Deserializer { limits, ..self }
+    }
+
     // /// TODO
     // pub fn remote_imports(&mut self, imports: bool) -> &mut Self {
     //     self.allow_remote_imports = imports;
@@ -223,32 +256,59 @@ impl<'a, A> Deserializer<'a, A> {
     //     self
     // }
 
-    fn _parse<T>(&self) -> dhall::error::Result<Result<Value>>
+    fn parse_source(&self) -> dhall::error::Result<Parsed> {
+        Ok(match &self.source {
+            Source::Str(s) => Parsed::parse_str(s)?,
+            Source::File(p) => Parsed::parse_file(p.as_ref())?,
+            Source::BinaryFile(p) => Parsed::parse_binary_file(p.as_ref())?,
+        })
+    }
+
+    /// Parses the value. If a session is provided, files and imports are tracked by it and
+    /// imports resolved in previous runs are reused.
+    pub(crate) fn _parse<T>(
+        &self,
+        session: Option<&mut Session>,
+    ) -> dhall::error::Result<Result<T>>
     where
         A: TypeAnnot,
-        T: HasAnnot<A>,
+        T: FromDhall + HasAnnot<A>,
     {
-        Ctxt::with_new(|cx| {
-            let parsed = match &self.source {
-                Source::Str(s) => Parsed::parse_str(s)?,
-                Source::File(p) => Parsed::parse_file(p.as_ref())?,
-                Source::BinaryFile(p) => Parsed::parse_binary_file(p.as_ref())?,
+        Ctxt::with_limits(self.limits.clone(), |cx| {
+            let (parsed, session) = match (&self.source, session) {
+                (Source::File(p), Some(session)) => {
+                    (session.parse_file(p.as_ref())?, Some(session))
+                }
+                (_, session) => (self.parse_source()?, session),
             };
-            let resolved = if self.allow_imports {
-                parsed.resolve(cx)?
-            } else {
-                parsed.skip_resolve(cx)?
+            let resolved = match session {
+                _ if !self.allow_imports => parsed.skip_resolve(cx)?,
+                Some(session) => session.resolve(cx, parsed)?,
+                None => parsed.resolve(cx)?,
             };
-            let typed = match &T::get_annot(self.annot) {
-                None => resolved.typecheck(cx)?,
-                Some(ty) => resolved.typecheck_with(cx, &ty.to_hir())?,
-            };
-            Ok(Value::from_nir_and_ty(
-                cx,
-                typed.normalize(cx).as_nir(),
-                typed.ty().as_nir(),
-            ))
+            deserialize_resolved(cx, resolved, T::get_annot(self.annot))
+        })
+    }
+
+    async fn _parse_async<T>(&self) -> dhall::error::Result<Result<T>>
+    where
+        A: TypeAnnot,
+        T: FromDhall + HasAnnot<A>,
+    {
+        let parsed = self.parse_source()?;
+        let allow_imports = self.allow_imports;
+        let annot = T::get_annot(self.annot);
+        Ctxt::with_limits_async(self.limits.clone(), move |cx| {
+            Box::pin(async move {
+                let resolved = if allow_imports {
+                    parsed.resolve_async(cx).await?
+                } else {
+                    parsed.skip_resolve(cx)?
+                };
+                deserialize_resolved(cx, resolved, annot)
+            })
         })
+        .await
     }
 
     /// Parses the chosen dhall value with the options provided.
@@ -272,14 +332,80 @@ impl<'a, A> Deserializer<'a, A> {
         A: TypeAnnot,
         T: FromDhall + HasAnnot<A>,
     {
-        let val = self
-            ._parse::<T>()
+        self._parse::<T>(None)
+            .map_err(ErrorKind::Dhall)
+            .map_err(Error)?
+    }
+
+    /// Like [`parse()`], but resolves imports asynchronously, and fetches the imports of a same
+    /// file concurrently.
+    ///
+    /// Remote imports are downloaded without blocking the current thread. This uses the async
+    /// `reqwest` client, which requires a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> serde_dhall::Result<()> {
+    /// # futures::executor::block_on(async {
+    /// let data = serde_dhall::from_str("6 * 7").parse_async::<u64>().await?;
+    /// assert_eq!(data, 42);
+    /// # Ok(())
+    /// # })
+    /// # }
+    /// ```
+    ///
+    /// [`parse()`]: Deserializer::parse()
+    pub async fn parse_async<T>(&self) -> Result<T>
+    where
+        A: TypeAnnot,
+        T: FromDhall + HasAnnot<A>,
+    {
+        self._parse_async::<T>()
+            .await
             .map_err(ErrorKind::Dhall)
-            .map_err(Error)??;
-        T::from_dhall(&val)
+            .map_err(Error)?
+    }
+
+    /// Returns a [`Watcher`] that parses the value again whenever one of the local files it was
+    /// read from changes.
+    ///
+    /// See [`Watcher`] for an example.
+    pub fn watch<T>(&self) -> Watcher<'a, A, T>
+    where
+        A: TypeAnnot,
+        T: FromDhall + HasAnnot<A>,
+    {
+        Watcher::new(self.clone())
     }
 }
 
+/// Typechecks and deserializes a value once its imports have been resolved.
+fn deserialize_resolved<'cx, T: FromDhall>(
+    cx: Ctxt<'cx>,
+    resolved: Resolved<'cx>,
+    annot: Option<SimpleType>,
+) -> dhall::error::Result<Result<T>> {
+    let typed = match &annot {
+        None => resolved.typecheck(cx)?,
+        Some(ty) => resolved.typecheck_with(cx, &ty.to_hir())?,
+    };
+    let val = Value::from_nir_and_ty(
+        cx,
+        typed.normalize_checked(cx)?.as_nir(),
+        typed.ty().as_nir(),
+    );
+    let val = match val {
+        Ok(val) => val,
+        Err(err) => return Ok(Err(err)),
+    };
+    // Deserialize while we still have the source expression around, so that errors can point to
+    // it.
+    Ok(T::from_dhall(&val).map_err(|err| {
+        err.with_span(|path| path.find_span(cx, typed.as_hir()))
+    }))
+}
+
 /// Deserialize a value from a string of Dhall text.
 ///
 /// This returns a [`Deserializer`] object. Call the [`parse()`] method to get the deserialized
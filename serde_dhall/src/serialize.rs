@@ -7,13 +7,15 @@ use crate::value::SimpleValue;
 use crate::{Error, ErrorKind, Result, SimpleType, Value};
 use SimpleValue::*;
 
+/// Implemented by the types that implement [`ToDhall`].
 pub trait Sealed {}
 
 /// A data structure that can be serialized from a Dhall expression.
 ///
 /// This is automatically implemented for any type that [serde] can serialize.
-/// In fact, this trait cannot be implemented manually. To implement it for your type,
-/// use serde's derive mechanism.
+/// This trait cannot be implemented manually. To implement it for your type,
+/// use serde's derive mechanism, or `#[derive(ToDhall)]` which bypasses serde and accepts the
+/// same `#[dhall(...)]` attributes as [`FromDhall`](crate::FromDhall#deriving-without-serde).
 ///
 /// # Example
 ///
@@ -63,11 +65,11 @@ impl ser::Serializer for Serializer {
 
     type SerializeSeq = SeqSerializer;
     type SerializeTuple = TupleSerializer;
-    type SerializeTupleStruct = ser::Impossible<Self::Ok, Self::Error>;
-    type SerializeTupleVariant = ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = TupleSerializer;
+    type SerializeTupleVariant = TupleVariantSerializer;
     type SerializeMap = MapSerializer;
     type SerializeStruct = StructSerializer;
-    type SerializeStructVariant = ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = StructVariantSerializer;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
         Ok(Num(NumKind::Bool(v)))
@@ -137,18 +139,16 @@ impl ser::Serializer for Serializer {
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
         self.serialize_unit()
     }
+    // Newtypes are transparent.
     fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<Self::Ok>
     where
         T: ?Sized + ser::Serialize,
     {
-        Err(ErrorKind::Serialize(
-            "Unsupported data for serialization: newtype struct".to_owned(),
-        )
-        .into())
+        value.serialize(self)
     }
     fn serialize_struct(
         self,
@@ -179,29 +179,30 @@ impl ser::Serializer for Serializer {
         let value = value.serialize(self)?;
         Ok(Union(variant.to_owned(), Some(Box::new(value))))
     }
+    // Tuple and struct variants become a union alternative that carries a record.
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        Err(ErrorKind::Serialize(
-            "Unsupported data for serialization: tuple variant".to_owned(),
-        )
-        .into())
+        Ok(TupleVariantSerializer {
+            variant,
+            fields: TupleSerializer::default(),
+        })
     }
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        Err(ErrorKind::Serialize(
-            "Unsupported data for serialization: struct variant".to_owned(),
-        )
-        .into())
+        Ok(StructVariantSerializer {
+            variant,
+            fields: StructSerializer::default(),
+        })
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
@@ -212,10 +213,7 @@ impl ser::Serializer for Serializer {
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        Err(ErrorKind::Serialize(
-            "Unsupported data for serialization: tuple struct".to_owned(),
-        )
-        .into())
+        Ok(TupleSerializer::default())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
@@ -273,6 +271,44 @@ impl ser::SerializeTuple for TupleSerializer {
     }
 }
 
+impl ser::SerializeTupleStruct for TupleSerializer {
+    type Ok = SimpleValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeTuple::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        ser::SerializeTuple::end(self)
+    }
+}
+
+struct TupleVariantSerializer {
+    variant: &'static str,
+    fields: TupleSerializer,
+}
+
+impl ser::SerializeTupleVariant for TupleVariantSerializer {
+    type Ok = SimpleValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeTuple::serialize_element(&mut self.fields, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        let fields = ser::SerializeTuple::end(self.fields)?;
+        Ok(Union(self.variant.to_owned(), Some(Box::new(fields))))
+    }
+}
+
 #[derive(Default)]
 struct MapSerializer {
     map: BTreeMap<String, SimpleValue>,
@@ -339,6 +375,28 @@ impl ser::SerializeStruct for StructSerializer {
     }
 }
 
+struct StructVariantSerializer {
+    variant: &'static str,
+    fields: StructSerializer,
+}
+
+impl ser::SerializeStructVariant for StructVariantSerializer {
+    type Ok = SimpleValue;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, val: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        ser::SerializeStruct::serialize_field(&mut self.fields, key, val)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        let fields = ser::SerializeStruct::end(self.fields)?;
+        Ok(Union(self.variant.to_owned(), Some(Box::new(fields))))
+    }
+}
+
 impl serde::ser::Serialize for SimpleValue {
     fn serialize<S>(
         &self,
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::SimpleType;
 
 /// A Rust type that can be represented as a Dhall type.
@@ -14,6 +16,47 @@ use crate::SimpleType;
 ///
 /// [the table of type correspondances]: SimpleType#type-correspondence
 ///
+/// # Serde attributes
+///
+/// The derive understands the serde attributes that change the shape of the data, so that the
+/// derived type matches what `Serialize` and `Deserialize` produce: `rename`, `rename_all`,
+/// `skip`, `flatten` and `transparent`, as well as `tag` on structs. Fields marked
+/// `#[serde(default)]` stay in the type, since they are always serialized. Internally tagged,
+/// adjacently tagged and untagged enums have no Dhall equivalent and are rejected at compile time,
+/// as are `skip_serializing_if` and flattening a field whose type is not a struct with named
+/// fields.
+///
+/// ```compile_fail
+/// use serde_dhall::StaticType;
+///
+/// #[derive(StaticType)]
+/// struct Foo {
+///     #[serde(flatten)]
+///     x: u64,
+/// }
+/// ```
+///
+/// ```rust
+/// # fn main() -> serde_dhall::Result<()> {
+/// use serde::Deserialize;
+/// use serde_dhall::{SimpleType, StaticType};
+///
+/// #[derive(Deserialize, StaticType)]
+/// #[serde(rename_all = "camelCase")]
+/// struct Foo {
+///     some_field: bool,
+///     #[serde(skip)]
+///     cache: Vec<u64>,
+/// }
+///
+/// let ty: SimpleType =
+///     serde_dhall::from_str("{ someField: Bool }").parse()?;
+///
+/// assert_eq!(Foo::static_type(), ty);
+/// # Ok(())
+/// # }
+/// ```
+///
 /// # Example
 ///
 /// ```rust
@@ -43,9 +86,8 @@ pub trait StaticType {
     /// use serde::Deserialize;
     /// use serde_dhall::{SimpleType, StaticType};
     ///
-    /// // Using `derive(StaticType)` here would give it the type `{ _1: List Natural }`.
+    /// // Newtypes are transparent, so this is also what `derive(StaticType)` would give.
     /// #[derive(Deserialize)]
-    /// #[serde(transparent)]
     /// struct Foo(Vec<u64>);
     ///
     /// impl StaticType for Foo {
@@ -65,6 +107,13 @@ pub trait StaticType {
     fn static_type() -> SimpleType;
 }
 
+/// A type whose Dhall type is a record, so that it can be used with `#[serde(flatten)]`.
+/// `derive(StaticType)` implements it for structs with named fields.
+#[doc(hidden)]
+pub trait StaticRecordType: StaticType {
+    fn static_record_fields() -> HashMap<String, SimpleType>;
+}
+
 macro_rules! derive_builtin {
     ($rust_ty:ty, $dhall_ty:ident) => {
         impl StaticType for $rust_ty {
@@ -79,9 +128,13 @@ derive_builtin!(bool, Bool);
 derive_builtin!(usize, Natural);
 derive_builtin!(u64, Natural);
 derive_builtin!(u32, Natural);
+derive_builtin!(u16, Natural);
+derive_builtin!(u8, Natural);
 derive_builtin!(isize, Integer);
 derive_builtin!(i64, Integer);
 derive_builtin!(i32, Integer);
+derive_builtin!(i16, Integer);
+derive_builtin!(i8, Integer);
 derive_builtin!(f64, Double);
 derive_builtin!(f32, Double);
 derive_builtin!(String, Text);
@@ -6,22 +6,34 @@ use dhall::operations::OpKind;
 use dhall::semantics::{Hir, HirKind, Nir, NirKind};
 pub use dhall::syntax::NumKind;
 use dhall::syntax::{Expr, ExprKind, Span};
-use dhall::Ctxt;
+use dhall::{Ctxt, Program};
 
-use crate::{Error, ErrorKind, FromDhall, Result, ToDhall};
+use crate::{DhallFunction, Error, ErrorKind, FromDhall, Result, ToDhall};
 
 #[derive(Debug, Clone)]
-enum ValueKind {
+pub(crate) enum ValueKind {
     /// Invariant: the value must be printable with the given type.
     Val(SimpleValue, Option<SimpleType>),
     Ty(SimpleType),
+    Fun(DhallFunction),
+    /// A value that contains functions, so it can't be a `SimpleValue`. The expression is its
+    /// normal form.
+    Nested(NestedValue, Expr),
+}
+
+/// A record, list or optional value whose elements can be any kind of value.
+#[derive(Debug, Clone)]
+pub(crate) enum NestedValue {
+    Optional(Box<Value>),
+    List(Vec<Value>),
+    Record(BTreeMap<String, Value>),
 }
 
 #[doc(hidden)]
 /// An arbitrary Dhall value.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Value {
-    kind: ValueKind,
+    pub(crate) kind: ValueKind,
 }
 
 /// A value of the kind that can be decoded by `serde_dhall`, e.g. `{ x = True, y = [1, 2, 3] }`.
@@ -127,6 +139,7 @@ pub enum SimpleValue {
 ///
 /// [`Deserializer::static_type_annotation`]: crate::Deserializer::static_type_annotation()
 /// [`StaticType`]: crate::StaticType
+/// [`DhallFunction`]: crate::DhallFunction
 ///
 /// # Type correspondence
 ///
@@ -142,11 +155,13 @@ pub enum SimpleValue {
 /// `List T`  | `Vec<T>`
 /// `Optional T`  | `Option<T>`
 /// `{ x: T, y: U }`  | structs
-/// `{ _1: T, _2: U }`  | `(T, U)`, structs
+/// `{ _1: T, _2: U }`  | `(T, U)`, tuple structs
 /// `{ x: T, y: T }`  | `HashMap<String, T>`, structs
+/// `T`  | newtype structs, e.g. `struct Foo(T)`
 /// `< x: T \| y: U >`  | enums
+/// `< x: { _1: T, _2: U } \| y: { z: T } >`  | enums with tuple or struct variants
 /// `Prelude.Map.Type Text T`  | `HashMap<String, T>`, structs
-/// `T -> U`  | unsupported
+/// `T -> U`  | [`DhallFunction`], which only `serde_dhall` can deserialize
 /// `Prelude.JSON.Type`  | unsupported
 /// `Prelude.Map.Type T U`  | unsupported
 ///
@@ -223,6 +238,19 @@ impl Value {
             Value {
                 kind: ValueKind::Ty(ty),
             }
+        } else if let NirKind::PiClosure { annot, .. } = ty.kind() {
+            let program = Program::from_nir(cx, x, ty)
+                .map_err(ErrorKind::Dhall)
+                .map_err(Error)?;
+            let input = SimpleType::from_nir(annot).ok();
+            Value {
+                kind: ValueKind::Fun(DhallFunction::new(program, input)),
+            }
+        } else if let Some(nested) = NestedValue::from_nir(cx, x, ty)? {
+            let expr = x.to_hir_noenv().to_expr(cx, Default::default());
+            Value {
+                kind: ValueKind::Nested(nested, expr),
+            }
         } else {
             let expr = x.to_hir_noenv().to_expr(cx, Default::default());
             return Err(Error(ErrorKind::Deserialize(format!(
@@ -253,10 +281,51 @@ impl Value {
         match &self.kind {
             ValueKind::Val(val, ty) => val.to_expr(ty.as_ref()).unwrap(),
             ValueKind::Ty(ty) => ty.to_expr(),
+            ValueKind::Fun(f) => f.to_expr(),
+            ValueKind::Nested(_, expr) => expr.clone(),
         }
     }
 }
 
+impl NestedValue {
+    /// Converts a record, list or optional value whose elements are not all simple values, e.g.
+    /// because some are functions.
+    fn from_nir<'cx>(
+        cx: Ctxt<'cx>,
+        x: &Nir<'cx>,
+        ty: &Nir<'cx>,
+    ) -> Result<Option<Self>> {
+        Ok(Some(match (x.kind(), ty.kind()) {
+            (NirKind::NEOptionalLit(x), NirKind::OptionalType(t)) => {
+                NestedValue::Optional(Box::new(Value::from_nir_and_ty(
+                    cx, x, t,
+                )?))
+            }
+            (NirKind::NEListLit(xs), NirKind::ListType(t)) => {
+                NestedValue::List(
+                    xs.iter()
+                        .map(|x| Value::from_nir_and_ty(cx, x, t))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            (NirKind::RecordLit(kvs), NirKind::RecordType(kts)) => {
+                NestedValue::Record(
+                    kvs.iter()
+                        .map(|(k, x)| {
+                            let t = kts.get(k).unwrap();
+                            Ok((
+                                k.to_string(),
+                                Value::from_nir_and_ty(cx, x, t)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )
+            }
+            _ => return Ok(None),
+        }))
+    }
+}
+
 #[derive(Debug)]
 struct NotSimpleValue;
 
@@ -564,6 +633,8 @@ impl PartialEq for ValueKind {
         match (self, other) {
             (Val(a, _), Val(b, _)) => a == b,
             (Ty(a), Ty(b)) => a == b,
+            (Fun(a), Fun(b)) => a == b,
+            (Nested(_, a), Nested(_, b)) => a == b,
             _ => false,
         }
     }
@@ -0,0 +1,102 @@
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use dhall::Session;
+
+use crate::options::{HasAnnot, TypeAnnot};
+use crate::{Deserializer, Error, ErrorKind, FromDhall, Result};
+
+/// Reloads a Dhall value whenever one of the files it was read from changes.
+///
+/// This is created with [`Deserializer::watch()`]. It keeps track of the root file and of every
+/// local file that was imported while resolving it, and polls them for changes. Only the files
+/// that changed and the files that import them are loaded again.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() {
+/// use std::time::Duration;
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Config {
+///     port: u64,
+/// }
+///
+/// let mut watcher = serde_dhall::from_file("config.dhall").watch::<Config>();
+/// watcher.run(Duration::from_secs(1), |config| {
+///     match config {
+///         Ok(config) => println!("New config: {:?}", config),
+///         Err(err) => eprintln!("Invalid config: {}", err),
+///     }
+///     // Keep watching.
+///     true
+/// });
+/// # }
+/// ```
+///
+/// [`Deserializer::watch()`]: crate::Deserializer::watch()
+#[derive(Debug)]
+pub struct Watcher<'a, A, T> {
+    deserializer: Deserializer<'a, A>,
+    session: Session,
+    loaded: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, A, T> Watcher<'a, A, T>
+where
+    A: TypeAnnot,
+    T: FromDhall + HasAnnot<A>,
+{
+    pub(crate) fn new(deserializer: Deserializer<'a, A>) -> Self {
+        Watcher {
+            deserializer,
+            session: Session::new(),
+            loaded: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Checks whether any of the files changed, and if so parses the value again.
+    ///
+    /// The first call always parses the value. Subsequent calls return `None` if no file changed
+    /// since the previous parse.
+    pub fn poll(&mut self) -> Option<Result<T>> {
+        if self.loaded && self.session.refresh() == 0 {
+            return None;
+        }
+        self.loaded = true;
+        Some(
+            self.deserializer
+                ._parse::<T>(Some(&mut self.session))
+                .map_err(ErrorKind::Dhall)
+                .map_err(Error)
+                .and_then(|res| res),
+        )
+    }
+
+    /// Polls for changes every `interval`, and calls `callback` with the initial value, then with
+    /// the new value or error each time a file changes. Stops when `callback` returns `false`.
+    pub fn run(
+        &mut self,
+        interval: Duration,
+        mut callback: impl FnMut(Result<T>) -> bool,
+    ) {
+        loop {
+            if let Some(res) = self.poll() {
+                if !callback(res) {
+                    return;
+                }
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    /// The local files that are being watched.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.session.local_files()
+    }
+}
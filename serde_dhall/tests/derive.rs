@@ -0,0 +1,228 @@
+mod derive {
+    use serde_dhall::{
+        from_str, serialize, FromDhall, SimpleValue, StaticType, ToDhall,
+    };
+
+    fn assert_de<T>(s: &str, x: T)
+    where
+        T: FromDhall + StaticType + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(
+            from_str(s)
+                .static_type_annotation()
+                .parse::<T>()
+                .map_err(|e| e.to_string()),
+            Ok(x)
+        );
+    }
+    fn assert_ser<T>(s: &str, x: T)
+    where
+        T: ToDhall + StaticType + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(
+            serialize(&x)
+                .static_type_annotation()
+                .to_string()
+                .map_err(|e| e.to_string()),
+            Ok(s.to_string())
+        );
+    }
+    fn assert_serde<T>(s: &str, x: T)
+    where
+        T: ToDhall
+            + FromDhall
+            + StaticType
+            + PartialEq
+            + std::fmt::Debug
+            + Clone,
+    {
+        assert_de(s, x.clone());
+        assert_ser(s, x);
+    }
+
+    #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+    struct Config {
+        servers: Vec<Server>,
+        debug: Option<bool>,
+    }
+
+    #[test]
+    fn structs() {
+        assert_serde(
+            r#"{ debug = Some True, servers = [{ host = "localhost", port = 80 }] }"#,
+            Config {
+                servers: vec![Server {
+                    host: "localhost".to_owned(),
+                    port: 80,
+                }],
+                debug: Some(true),
+            },
+        );
+
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+        struct Unit;
+        assert_serde("{=}", Unit);
+
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+        struct Newtype(Vec<u64>);
+        assert_serde("[1, 2]", Newtype(vec![1, 2]));
+
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+        struct Tuple(u64, String);
+        assert_serde(r#"{ _1 = 1, _2 = "foo" }"#, Tuple(1, "foo".to_owned()));
+    }
+
+    #[test]
+    fn enums() {
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall, StaticType)]
+        enum Foo {
+            X,
+            Y(i64),
+            Z { z: bool },
+        }
+        let ty = "< X | Y: Integer | Z: { z : Bool } >";
+        assert_serde(&format!("{}.X", ty), Foo::X);
+        assert_serde(&format!("{}.Y +1", ty), Foo::Y(1));
+        assert_serde(&format!("{}.Z {{ z = True }}", ty), Foo::Z { z: true });
+    }
+
+    mod hex {
+        use serde_dhall::SimpleValue;
+
+        pub fn from_dhall(v: &SimpleValue) -> serde_dhall::Result<u64> {
+            match v {
+                SimpleValue::Text(s) => {
+                    u64::from_str_radix(s, 16).map_err(serde::de::Error::custom)
+                }
+                _ => Err(serde::de::Error::custom("expected hex text")),
+            }
+        }
+        pub fn to_dhall(x: &u64) -> serde_dhall::Result<SimpleValue> {
+            Ok(SimpleValue::Text(format!("{:x}", x)))
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        fn default_port() -> u16 {
+            8080
+        }
+
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall)]
+        struct Foo {
+            #[dhall(rename = "type")]
+            kind: String,
+            #[dhall(default)]
+            tags: Vec<String>,
+            #[dhall(default = "default_port")]
+            port: u16,
+            #[dhall(with = "hex")]
+            color: u64,
+        }
+
+        assert_eq!(
+            from_str(r#"{ type = "a", color = "ff" }"#)
+                .parse::<Foo>()
+                .map_err(|e| e.to_string()),
+            Ok(Foo {
+                kind: "a".to_owned(),
+                tags: vec![],
+                port: 8080,
+                color: 255,
+            })
+        );
+        assert_eq!(
+            serialize(&Foo {
+                kind: "a".to_owned(),
+                tags: vec!["b".to_owned()],
+                port: 1,
+                color: 255,
+            })
+            .to_string()
+            .map_err(|e| e.to_string()),
+            Ok(r#"{ color = "ff", port = 1, tags = ["b"], type = "a" }"#
+                .to_owned())
+        );
+
+        #[derive(Debug, Clone, PartialEq, FromDhall, ToDhall)]
+        enum Bar {
+            #[dhall(rename = "x")]
+            X,
+        }
+        assert_eq!(
+            from_str("< x >.x")
+                .parse::<Bar>()
+                .map_err(|e| e.to_string()),
+            Ok(Bar::X)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        fn parse<T: FromDhall>(s: &str) -> Result<T, String> {
+            from_str(s).parse::<T>().map_err(|e| e.to_string())
+        }
+
+        assert_eq!(
+            parse::<Config>(r#"{ servers = [{ host = "a", port = "1" }] }"#),
+            Err("servers[0].port: expected a Natural, found a Text".to_owned())
+        );
+        #[derive(Debug, PartialEq, FromDhall)]
+        struct Servers(Vec<Server>);
+        assert_eq!(
+            parse::<Servers>(
+                r#"[
+                    { host = "a", port = 1 },
+                    { host = "b", port = 2 },
+                    { host = "c", port = 100000 },
+                ]"#
+            ),
+            Err("[2].port: number out of range for u16".to_owned())
+        );
+        assert_eq!(
+            parse::<Config>(r#"{ servers = [{ host = "a" }] }"#),
+            Err("servers[0].port: missing field".to_owned())
+        );
+        assert_eq!(
+            parse::<Config>(r#"{ servers = [{ host = "a", port = 100000 }] }"#),
+            Err("servers[0].port: number out of range for u16".to_owned())
+        );
+        assert_eq!(
+            parse::<Server>("[1, 2]"),
+            Err("expected a record, found a List".to_owned())
+        );
+
+        #[derive(Debug, PartialEq, FromDhall)]
+        enum Foo {
+            X(Server),
+            Z,
+        }
+        assert_eq!(
+            parse::<Foo>(
+                r#"< X: { host: Text, port: Bool } >.X { host = "", port = True }"#
+            ),
+            Err("X.port: expected a Natural, found a Bool".to_owned())
+        );
+        assert_eq!(
+            parse::<Foo>("< Y >.Y"),
+            Err("unknown union alternative `Y`".to_owned())
+        );
+        assert_eq!(
+            parse::<Foo>("< X | Z >.X"),
+            Err("expected a payload for union alternative `X`".to_owned())
+        );
+        assert_eq!(
+            parse::<Foo>("< X | Z : Natural >.Z 1"),
+            Err("expected no payload for union alternative `Z`".to_owned())
+        );
+
+        // The simple value can still be recovered.
+        assert!(parse::<SimpleValue>("{ x = 1 }").is_ok());
+    }
+}
@@ -0,0 +1,112 @@
+mod function {
+    use serde::{Deserialize, Serialize};
+    use serde_dhall::{from_str, serialize, DhallFunction};
+
+    fn function(s: &str) -> DhallFunction {
+        from_str(s).parse().unwrap()
+    }
+
+    #[test]
+    fn call() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            host: String,
+            port: u64,
+        }
+        let f = function(
+            r#"λ(env : Text) → { host = "${env}.example.com", port = 80 }"#,
+        );
+        assert_eq!(
+            f.call::<_, Config>(&"dev").map_err(|e| e.to_string()),
+            Ok(Config {
+                host: "dev.example.com".to_owned(),
+                port: 80,
+            })
+        );
+
+        // Curried functions and builtins.
+        let f = function("λ(x : Natural) → λ(y : Natural) → x * y");
+        let g: DhallFunction = f.call(&6u64).unwrap();
+        assert_eq!(g.call::<_, u64>(&7u64).unwrap(), 42);
+        assert_eq!(g.to_string(), "λ(y : Natural) → 6 * y");
+        let f = function("Natural/even");
+        assert!(!f.call::<_, bool>(&3u64).unwrap());
+    }
+
+    #[test]
+    fn argument_type() {
+        // The argument gets the type the function expects.
+        #[derive(Serialize)]
+        enum Env {
+            Dev,
+            Prod,
+        }
+        let f =
+            function("λ(e : < Dev | Prod >) → merge { Dev = 1, Prod = 2 } e");
+        assert_eq!(f.call::<_, u64>(&Env::Prod).unwrap(), 2);
+        assert_eq!(f.call::<_, u64>(&Env::Dev).unwrap(), 1);
+
+        let f = function("λ(xs : List Natural) → List/length Natural xs");
+        assert_eq!(f.call::<_, u64>(&Vec::<u64>::new()).unwrap(), 0);
+        assert_eq!(f.call::<_, u64>(&vec![1u64, 2]).unwrap(), 2);
+
+        assert!(f.call::<_, u64>(&true).is_err());
+    }
+
+    #[test]
+    fn nested() {
+        #[derive(Deserialize)]
+        struct Hooks {
+            name: String,
+            on_start: DhallFunction,
+            filters: Vec<DhallFunction>,
+            fallback: Option<DhallFunction>,
+        }
+        let hooks: Hooks = from_str(
+            r#"{
+                name = "hooks",
+                on_start = λ(n : Natural) → n + 1,
+                filters = [ Natural/even, Natural/odd ],
+                fallback = Some (λ(n : Natural) → n)
+            }"#,
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(hooks.name, "hooks");
+        assert_eq!(hooks.on_start.call::<_, u64>(&1u64).unwrap(), 2);
+        assert!(hooks.filters[0].call::<_, bool>(&2u64).unwrap());
+        assert!(hooks.filters[1].call::<_, bool>(&3u64).unwrap());
+        assert_eq!(hooks.fallback.unwrap().call::<_, u64>(&4u64).unwrap(), 4);
+
+        // Functions can return records of functions.
+        #[derive(Deserialize)]
+        struct Counter {
+            add: DhallFunction,
+        }
+        let f = function("λ(x : Natural) → { add = λ(y : Natural) → x + y }");
+        let counter: Counter = f.call(&1u64).unwrap();
+        assert_eq!(counter.add.call::<_, u64>(&2u64).unwrap(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(from_str("1").parse::<DhallFunction>().is_err());
+        assert!(from_str("{ f = λ(x : Bool) → x }")
+            .parse::<DhallFunction>()
+            .is_err());
+        assert_eq!(
+            from_str("{ f = λ(x : Bool) → x }")
+                .parse::<std::collections::HashMap<String, bool>>()
+                .map_err(|e| e.to_string()),
+            Err("f: this cannot be deserialized into the serde data model: \
+                 λ(x : Bool) → x"
+                .to_owned())
+        );
+        assert_eq!(
+            serialize(&function("λ(x : Bool) → x"))
+                .to_string()
+                .map_err(|e| e.to_string()),
+            Ok("λ(x : Bool) → x".to_owned())
+        );
+    }
+}
@@ -1,7 +1,8 @@
 mod serde {
     use serde::{Deserialize, Serialize};
     use serde_dhall::{
-        from_str, serialize, FromDhall, StaticType, ToDhall, Value,
+        from_simple_value, from_str, serialize, FromDhall, SimpleValue,
+        StaticType, ToDhall, Value,
     };
 
     fn assert_de<T>(s: &str, x: T)
@@ -101,17 +102,36 @@ mod serde {
 
     #[test]
     fn structs() {
-        // #[derive(
-        //     Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
-        // )]
-        // struct Foo;
-        // assert_serde::<Foo>("{=}", Foo);
-
-        // #[derive(
-        //     Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
-        // )]
-        // struct Bar(u64);
-        // assert_serde::<Bar>("{ _1 = 1 }", Bar (1));
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        struct Foo;
+        assert_serde::<Foo>("{=}", Foo);
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        struct Bar(u64);
+        assert_serde::<Bar>("1", Bar(1));
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        struct Qux(u64, String);
+        assert_serde::<Qux>(
+            r#"{ _1 = 1, _2 = "foo" }"#,
+            Qux(1, "foo".to_owned()),
+        );
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        struct Many(u64, u64, u64, u64, u64, u64, u64, u64, u64, u64, u64);
+        assert_serde::<Many>(
+            "{ _1 = 1, _10 = 10, _11 = 11, _2 = 2, _3 = 3, _4 = 4, _5 = 5, \
+             _6 = 6, _7 = 7, _8 = 8, _9 = 9 }",
+            Many(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11),
+        );
 
         #[derive(
             Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
@@ -147,6 +167,106 @@ mod serde {
             .static_type_annotation()
             .parse::<Bar>()
             .is_err());
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        enum Baz {
+            X(u64, String),
+            Y { y: i64 },
+            Z(Vec<u64>),
+        }
+        assert_serde::<Baz>(
+            r#"< X: { _1 : Natural, _2 : Text } | Y: { y : Integer } | Z: List Natural >.X { _1 = 1, _2 = "foo" }"#,
+            Baz::X(1, "foo".to_owned()),
+        );
+        assert_serde::<Baz>(
+            "< X: { _1 : Natural, _2 : Text } | Y: { y : Integer } | Z: List Natural >.Y { y = +1 }",
+            Baz::Y { y: 1 },
+        );
+        assert_serde::<Baz>(
+            "< X: { _1 : Natural, _2 : Text } | Y: { y : Integer } | Z: List Natural >.Z [1, 2]",
+            Baz::Z(vec![1, 2]),
+        );
+    }
+
+    #[test]
+    fn serde_attributes() {
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        #[serde(rename_all = "camelCase")]
+        struct Foo {
+            field_one: u64,
+            #[serde(rename = "renamed")]
+            field_two: bool,
+            #[serde(skip)]
+            skipped: Vec<String>,
+            #[serde(default)]
+            defaulted: Option<u64>,
+            #[serde(flatten)]
+            inner: Inner,
+        }
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        struct Inner {
+            x: i64,
+        }
+        assert_serde::<Foo>(
+            "{ defaulted = None Natural, fieldOne = 1, renamed = True, x = +2 }",
+            Foo {
+                field_one: 1,
+                field_two: true,
+                skipped: vec![],
+                defaulted: None,
+                inner: Inner { x: 2 },
+            },
+        );
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        #[serde(transparent)]
+        struct Transparent {
+            x: u64,
+        }
+        assert_serde::<Transparent>("1", Transparent { x: 1 });
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        #[serde(tag = "type")]
+        struct Tagged {
+            x: u64,
+        }
+        assert_serde::<Tagged>(
+            r#"{ type = "Tagged", x = 1 }"#,
+            Tagged { x: 1 },
+        );
+
+        #[derive(
+            Debug, Clone, PartialEq, Eq, Deserialize, Serialize, StaticType,
+        )]
+        #[serde(rename_all = "snake_case")]
+        enum Bar {
+            FirstVariant,
+            #[serde(rename = "second", rename_all = "SCREAMING_SNAKE_CASE")]
+            SecondVariant {
+                some_field: u64,
+            },
+            #[serde(skip)]
+            #[allow(dead_code)]
+            Skipped,
+        }
+        assert_serde::<Bar>(
+            "< first_variant | second: { SOME_FIELD : Natural } >.first_variant",
+            Bar::FirstVariant,
+        );
+        assert_serde::<Bar>(
+            "< first_variant | second: { SOME_FIELD : Natural } >.second { SOME_FIELD = 1 }",
+            Bar::SecondVariant { some_field: 1 },
+        );
     }
 
     #[test]
@@ -207,6 +327,65 @@ mod serde {
         assert!(from_str("List/length [True, 42]").parse::<bool>().is_err());
     }
 
+    #[test]
+    fn test_error_location() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            servers: Vec<Server>,
+        }
+        fn parse<T: FromDhall>(s: &str) -> Result<T, String> {
+            from_str(s).parse::<T>().map_err(|e| e.to_string())
+        }
+
+        // The alternate form shows where the value comes from.
+        assert_eq!(
+            from_str(
+                "{ servers =\n\
+                 \x20 [ { host = \"a\", port = 1 }\n\
+                 \x20 , { host = \"b\", port = 100000 }\n\
+                 \x20 ]\n\
+                 }"
+            )
+            .parse::<Config>()
+            .map_err(|e| format!("{:#}", e)),
+            Err(r#"error: servers[1].port: invalid value: integer `100000`, expected u16
+ --> <current file>:1:26
+  |
+...
+3 |   , { host = "b", port = 100000 }
+  |                          ^^^^^^ `servers[1].port` comes from here
+  |"#
+            .to_owned())
+        );
+        assert_eq!(
+            parse::<Config>(r#"{ servers = [{ host = "a" }] }"#),
+            Err("servers[0]: missing field `port`".to_owned())
+        );
+        // The path is followed through `let`s.
+        assert!(parse::<Config>(
+            r#"let port = "80" in { servers = [{ host = "a", port }] }"#
+        )
+        .unwrap_err()
+        .contains("servers[0].port: invalid type: string \"80\""));
+        assert_eq!(
+            from_simple_value::<Config>(
+                from_str(r#"{ servers = [{ host = "a", port = True }] }"#)
+                    .parse::<SimpleValue>()
+                    .unwrap()
+            )
+            .map_err(|e| e.to_string()),
+            Err(
+                "servers[0].port: invalid type: boolean `true`, expected u16"
+                    .to_owned()
+            )
+        );
+    }
+
     #[test]
     fn test_file() {
         assert_eq!(
@@ -247,6 +426,48 @@ mod serde {
         );
     }
 
+    #[test]
+    fn test_parse_async() {
+        use futures::executor::block_on;
+        use serde_dhall::from_file;
+
+        let dir = std::env::temp_dir()
+            .join(format!("serde-dhall-parse-async-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("x.dhall"), "1").unwrap();
+        std::fs::write(dir.join("y.dhall"), "./x.dhall + 1").unwrap();
+        std::fs::write(
+            dir.join("main.dhall"),
+            "{ x = ./x.dhall, y = ./y.dhall }",
+        )
+        .unwrap();
+
+        #[derive(Debug, PartialEq, Deserialize, StaticType)]
+        struct Point {
+            x: u64,
+            y: u64,
+        }
+        let main = dir.join("main.dhall");
+        assert_eq!(
+            block_on(
+                from_file(&main)
+                    .static_type_annotation()
+                    .parse_async::<Point>()
+            )
+            .map_err(|e| e.to_string()),
+            Ok(Point { x: 1, y: 2 })
+        );
+        assert!(block_on(
+            from_file(&main).imports(false).parse_async::<Point>()
+        )
+        .is_err());
+        assert!(
+            block_on(from_str("./missing.dhall").parse_async::<u64>()).is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     #[ignore] // Way too slow
     fn test_prelude() {
@@ -64,4 +64,52 @@ fn test_static_type() {
         B(bool),
     };
     assert_eq!(F::static_type(), parse("< A | B: Bool >"));
+
+    #[derive(StaticType)]
+    #[allow(dead_code)]
+    struct G(Vec<bool>);
+    assert_eq!(G::static_type(), parse("List Bool"));
+
+    #[derive(StaticType)]
+    #[allow(dead_code)]
+    struct H;
+    assert_eq!(H::static_type(), parse("{}"));
+
+    #[derive(StaticType)]
+    #[allow(dead_code)]
+    enum I {
+        A(bool, String),
+        B { x: bool },
+        C(G),
+    }
+    assert_eq!(
+        I::static_type(),
+        parse("< A: { _1: Bool, _2: Text } | B: { x: Bool } | C: List Bool >")
+    );
+
+    #[derive(StaticType)]
+    #[serde(rename_all = "kebab-case")]
+    #[allow(dead_code)]
+    struct J {
+        r#type: bool,
+        some_field: bool,
+        #[serde(rename = "other")]
+        other_field: bool,
+        #[serde(skip)]
+        skipped: String,
+        #[serde(flatten)]
+        flattened: A,
+    }
+    assert_eq!(
+        J::static_type(),
+        parse(
+            "{ type: Bool, some-field: Bool, other: Bool, \
+               field1: Bool, field2: Optional Bool }"
+        )
+    );
+
+    #[derive(StaticType)]
+    #[allow(dead_code)]
+    struct K(bool, #[serde(skip)] String, String);
+    assert_eq!(K::static_type(), parse("{ _1: Bool, _2: Text }"));
 }
@@ -0,0 +1,109 @@
+mod watch {
+    use serde::Deserialize;
+    use serde_dhall::{from_file, StaticType};
+    use std::path::PathBuf;
+
+    /// A fresh temporary directory, removed when dropped so that it is cleaned up even when an
+    /// assertion fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "serde-dhall-{}-{}",
+                name,
+                std::process::id()
+            ));
+            // Leftovers from a run that was killed.
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            std::fs::write(self.path(name), contents).unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, StaticType)]
+    struct Config {
+        host: String,
+        port: u64,
+    }
+
+    #[test]
+    fn poll() {
+        let dir = TempDir::new("watch");
+        dir.write("host.dhall", r#""localhost""#);
+        dir.write("port.dhall", "8080");
+        dir.write(
+            "config.dhall",
+            "{ host = ./host.dhall, port = ./port.dhall }",
+        );
+
+        let mut watcher = from_file(dir.path("config.dhall"))
+            .static_type_annotation()
+            .watch::<Config>();
+        let mut poll =
+            || watcher.poll().map(|res| res.map_err(|e| e.to_string()));
+        assert_eq!(
+            poll(),
+            Some(Ok(Config {
+                host: "localhost".to_owned(),
+                port: 8080
+            }))
+        );
+        assert_eq!(poll(), None);
+
+        dir.write("port.dhall", "8081");
+        assert_eq!(
+            poll(),
+            Some(Ok(Config {
+                host: "localhost".to_owned(),
+                port: 8081
+            }))
+        );
+        assert_eq!(poll(), None);
+
+        // Errors are reported, and changes to the files that failed are picked up.
+        dir.write("host.dhall", "./missing.dhall");
+        assert!(matches!(poll(), Some(Err(_))));
+        assert_eq!(poll(), None);
+        dir.write("missing.dhall", r#""example.com""#);
+        assert_eq!(
+            poll(),
+            Some(Ok(Config {
+                host: "example.com".to_owned(),
+                port: 8081
+            }))
+        );
+
+        // So are changes to the root file.
+        dir.write("config.dhall", "{ host = ./host.dhall, port = 80 }");
+        assert_eq!(
+            poll(),
+            Some(Ok(Config {
+                host: "example.com".to_owned(),
+                port: 80
+            }))
+        );
+        let files: Vec<_> = watcher
+            .files()
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        assert!(files.contains(&"config.dhall".to_owned()));
+        assert!(files.contains(&"missing.dhall".to_owned()));
+    }
+}